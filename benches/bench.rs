@@ -1,10 +1,115 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use dakv_skiplist::SkipList;
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, RandomGenerator, SkipList};
+use std::time::{Duration, Instant};
+
+/// Keys inserted per run of the pattern matrix.
+const N: u64 = 10_000;
+
+/// Ascending inserts resume from the path of the previous insert, which makes
+/// them at least this many times faster than random ones. Checked after the
+/// matrix so that a regression of the fast path fails the bench.
+const ASCENDING_SPEEDUP: f64 = 2.0;
+
+#[derive(Clone, Copy)]
+enum Pattern {
+    Ascending,
+    Descending,
+    Random,
+    /// Runs of 64 consecutive keys starting at random places.
+    Clustered,
+}
+
+const PATTERNS: [(&str, Pattern); 4] = [
+    ("ascending", Pattern::Ascending),
+    ("descending", Pattern::Descending),
+    ("random", Pattern::Random),
+    ("clustered", Pattern::Clustered),
+];
+
+fn keys(pattern: Pattern) -> Vec<Vec<u8>> {
+    let rnd = Random::new(301);
+    let mut base = 0;
+    (0..N)
+        .map(|i| {
+            let key = match pattern {
+                Pattern::Ascending => i,
+                Pattern::Descending => N - i,
+                Pattern::Random => u64::from(rnd.next()),
+                Pattern::Clustered => {
+                    if i % 64 == 0 {
+                        base = u64::from(rnd.next()) << 8;
+                    }
+                    base + i % 64
+                }
+            };
+            key.to_be_bytes().to_vec()
+        })
+        .collect()
+}
+
+fn new_list() -> SkipList<Random, DefaultComparator, ArenaImpl> {
+    SkipList::new(
+        Random::new(0xdead_beef),
+        DefaultComparator::default(),
+        ArenaImpl::new(),
+    )
+}
+
+fn insert_all(keys: Vec<Vec<u8>>) -> SkipList<Random, DefaultComparator, ArenaImpl> {
+    let sl = new_list();
+    for key in keys {
+        sl.insert(black_box(key));
+    }
+    sl
+}
 
 fn criterion_benchmark(c: &mut Criterion) {
-    let mut sl: SkipList<usize> = SkipList::default();
-    c.bench_function("SkipList insert", |b| b.iter(|| sl.insert(black_box(&1))));
+    let sl = new_list();
+    let mut i = 0u64;
+    c.bench_function("SkipList insert", |b| {
+        b.iter(|| {
+            i += 1;
+            sl.insert(black_box(i.to_be_bytes().to_vec()))
+        })
+    });
+}
+
+fn pattern_matrix(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert pattern");
+    for &(name, pattern) in PATTERNS.iter() {
+        let keys = keys(pattern);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &keys, |b, keys| {
+            b.iter_batched(|| keys.clone(), insert_all, BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+
+    let ascending = fastest_run(Pattern::Ascending);
+    let random = fastest_run(Pattern::Random);
+    let speedup = random.as_secs_f64() / ascending.as_secs_f64();
+    assert!(
+        speedup >= ASCENDING_SPEEDUP,
+        "ascending inserts are only {:.2}x faster than random ones ({:?} vs {:?})",
+        speedup,
+        ascending,
+        random
+    );
+}
+
+/// Time of the fastest of a few runs, the least disturbed by the machine.
+fn fastest_run(pattern: Pattern) -> Duration {
+    (0..5)
+        .map(|_| {
+            let keys = keys(pattern);
+            let start = Instant::now();
+            let sl = insert_all(keys);
+            let elapsed = start.elapsed();
+            drop(sl);
+            elapsed
+        })
+        .min()
+        .unwrap()
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(benches, criterion_benchmark, pattern_matrix);
 criterion_main!(benches);
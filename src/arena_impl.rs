@@ -0,0 +1,389 @@
+use crate::arena::{ArenaCheckpoint, K_BLOCK_SIZE};
+use crate::{Arena, SharedArena};
+use std::mem;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub struct ArenaInner {
+    /// Where the next allocation is carved from. Handles sharing the arena may
+    /// allocate concurrently, so the pointer and the bytes left behind it are
+    /// only ever updated together, under the lock.
+    bump: Mutex<Bump>,
+    /// Copy of `bump.remaining`, read without locking.
+    remaining_bytes: AtomicUsize,
+    memory_usage: AtomicU64,
+    blocks: Arc<Mutex<Vec<Vec<u8>>>>,
+    block_size: usize,
+}
+
+struct Bump {
+    ptr: *mut u8,
+    remaining: usize,
+}
+
+// The pointer goes into a block owned by the arena, and is only followed under
+// the lock.
+unsafe impl Send for Bump {}
+
+// Each allocation only moves the bump pointer once the block it points into
+// exists, so a panic (e.g. a poisoned block list) never hands out memory twice.
+impl UnwindSafe for ArenaInner {}
+impl RefUnwindSafe for ArenaInner {}
+
+impl ArenaInner {
+    fn new(block_size: usize) -> Self {
+        Self {
+            bump: Mutex::new(Bump {
+                ptr: ptr::null_mut(),
+                remaining: 0,
+            }),
+            remaining_bytes: AtomicUsize::default(),
+            memory_usage: AtomicU64::default(),
+            blocks: Arc::default(),
+            block_size,
+        }
+    }
+
+    fn remaining_bytes(&self) -> usize {
+        self.remaining_bytes.load(Ordering::Acquire)
+    }
+
+    /// Take `bytes` from the current block, which must have them left.
+    fn take(&self, bump: &mut Bump, bytes: usize) -> *mut u8 {
+        debug_assert!(bytes <= bump.remaining);
+        let result = bump.ptr;
+        bump.ptr = unsafe { bump.ptr.add(bytes) };
+        bump.remaining -= bytes;
+        self.remaining_bytes
+            .store(bump.remaining, Ordering::Release);
+        result
+    }
+
+    fn alloc_fallback(&self, bump: &mut Bump, bytes: usize) -> *mut u8 {
+        if bytes > self.block_size / 4 {
+            // Object is more than a quarter of our block size.  Allocate it separately
+            // to avoid wasting too much space in leftover bytes.
+            return self.allocate_new_block(bytes);
+        }
+
+        // We waste the remaining space in the current block.
+        bump.ptr = self.allocate_new_block(self.block_size);
+        bump.remaining = self.block_size;
+        self.take(bump, bytes)
+    }
+
+    fn allocate_new_block(&self, bytes: usize) -> *mut u8 {
+        let mut v = vec![0; bytes];
+
+        let result = v.as_mut_ptr();
+        self.blocks.lock().unwrap().push(v);
+        let charged = (bytes as u64).saturating_add(mem::size_of::<usize>() as u64);
+        let _ = self
+            .memory_usage
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |usage| {
+                Some(usage.saturating_add(charged))
+            });
+        result
+    }
+
+    fn memory_usage(&self) -> u64 {
+        self.memory_usage.load(Ordering::Acquire)
+    }
+}
+
+#[derive(Clone)]
+pub struct ArenaImpl {
+    inner: Arc<ArenaInner>,
+}
+
+impl Default for ArenaImpl {
+    fn default() -> Self {
+        Self::with_block_size(K_BLOCK_SIZE)
+    }
+}
+
+impl ArenaImpl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an arena allocating blocks of `block_size` bytes. Allocations larger
+    /// than a quarter of the block size get a block of their own.
+    /// # Panics
+    /// If `block_size` is 0.
+    pub fn with_block_size(block_size: usize) -> Self {
+        assert!(block_size > 0);
+        Self {
+            inner: Arc::new(ArenaInner::new(block_size)),
+        }
+    }
+}
+
+// The blocks belong to the inner arena, shared by the clones.
+unsafe impl SharedArena for ArenaImpl {}
+
+impl Arena for ArenaImpl {
+    fn alloc(&self, bytes: usize) -> NonNull<u8> {
+        if bytes == 0 {
+            return NonNull::dangling();
+        }
+
+        let mut bump = self.inner.bump.lock().unwrap();
+        let result = if bytes <= bump.remaining {
+            self.inner.take(&mut bump, bytes)
+        } else {
+            self.inner.alloc_fallback(&mut bump, bytes)
+        };
+        // Points into a block, which is never empty.
+        unsafe { NonNull::new_unchecked(result) }
+    }
+
+    // 0-byte allocations don't touch the blocks, they get a dangling pointer
+    // which is fine for an empty slice.
+    fn allocate(&self, bytes: usize) -> NonNull<[u8]> {
+        NonNull::slice_from_raw_parts(self.alloc(bytes), bytes)
+    }
+
+    fn allocate_aligned(&self, bytes: usize) -> NonNull<[u8]> {
+        if bytes == 0 {
+            return NonNull::slice_from_raw_parts(NonNull::dangling(), 0);
+        }
+        let ptr_size = mem::size_of::<usize>();
+        let align = if ptr_size > 8 { ptr_size } else { 8 };
+
+        let mut bump = self.inner.bump.lock().unwrap();
+        let current_mod = bump.ptr as usize & (align - 1);
+        let slop = if current_mod == 0 {
+            0
+        } else {
+            align - current_mod
+        };
+
+        let needed = bytes + slop;
+        let result = if needed <= bump.remaining {
+            unsafe { self.inner.take(&mut bump, needed).add(slop) }
+        } else {
+            // AllocateFallback always returned aligned memory
+            self.inner.alloc_fallback(&mut bump, bytes)
+        };
+        debug_assert_eq!(result as usize & (align - 1), 0);
+        NonNull::slice_from_raw_parts(unsafe { NonNull::new_unchecked(result) }, bytes)
+    }
+
+    fn memory_usage(&self) -> u64 {
+        self.inner.memory_usage()
+    }
+
+    fn remain_bytes(&self) -> usize {
+        self.inner.remaining_bytes()
+    }
+
+    fn checkpoint(&self) -> Option<ArenaCheckpoint> {
+        let bump = self.inner.bump.lock().unwrap();
+        let blocks = self.inner.blocks.lock().unwrap().len();
+        Some(ArenaCheckpoint {
+            ptr: bump.ptr as usize,
+            remaining: bump.remaining,
+            blocks,
+            memory_usage: self.inner.memory_usage(),
+        })
+    }
+
+    unsafe fn rollback(&self, checkpoint: ArenaCheckpoint) {
+        let mut bump = self.inner.bump.lock().unwrap();
+        // Blocks are pushed in allocation order, the ones after the checkpoint
+        // only hold memory allocated after it.
+        self.inner
+            .blocks
+            .lock()
+            .unwrap()
+            .truncate(checkpoint.blocks);
+        bump.ptr = checkpoint.ptr as *mut u8;
+        bump.remaining = checkpoint.remaining;
+        self.inner
+            .remaining_bytes
+            .store(bump.remaining, Ordering::Release);
+        self.inner
+            .memory_usage
+            .store(checkpoint.memory_usage, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Arena, ArenaImpl, Random, RandomGenerator};
+    use std::mem;
+    use std::panic::{RefUnwindSafe, UnwindSafe};
+    use std::ptr::NonNull;
+    use std::thread;
+
+    /// The bytes of an allocation, owned by an arena alive for `'a`.
+    fn memory<'a>(block: NonNull<[u8]>) -> &'a mut [u8] {
+        unsafe { &mut *block.as_ptr() }
+    }
+
+    #[test]
+    fn test_unwind_safe() {
+        fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe>() {}
+        assert_unwind_safe::<ArenaImpl>();
+    }
+
+    #[test]
+    fn test_alloc() {
+        let arena = ArenaImpl::new();
+
+        let _ = arena.allocate_aligned(104);
+        assert_eq!(arena.memory_usage(), 4104);
+    }
+
+    #[test]
+    fn test_zero_bytes() {
+        let arena = ArenaImpl::new();
+
+        assert!(arena.allocate(0).is_empty());
+        assert_eq!(arena.memory_usage(), 0);
+        let _ = arena.allocate(1);
+        let remain = arena.remain_bytes();
+        assert!(arena.allocate(0).is_empty());
+        assert_eq!(arena.remain_bytes(), remain);
+    }
+
+    #[test]
+    fn test_block_size() {
+        let arena = ArenaImpl::with_block_size(1024);
+
+        let _ = arena.allocate(100);
+        assert_eq!(arena.memory_usage(), 1032);
+        assert_eq!(arena.remain_bytes(), 924);
+        // Doesn't fit, but more than a quarter of the block size gets its own block.
+        let _ = arena.allocate(1000);
+        assert_eq!(arena.memory_usage(), 1032 + 1008);
+        assert_eq!(arena.remain_bytes(), 924);
+        // Doesn't fit either, the rest of the current block is wasted.
+        let _ = arena.allocate(200);
+        let _ = arena.allocate(200);
+        let _ = arena.allocate(200);
+        let _ = arena.allocate(200);
+        let _ = arena.allocate(200);
+        assert_eq!(arena.memory_usage(), 1032 + 1008 + 1032);
+        assert_eq!(arena.remain_bytes(), 824);
+    }
+
+    #[test]
+    fn test_rollback() {
+        let arena = ArenaImpl::with_block_size(1024);
+        let kept = memory(arena.allocate(100));
+        kept.fill(1);
+        let checkpoint = arena.checkpoint().unwrap();
+        let (usage, remain) = (arena.memory_usage(), arena.remain_bytes());
+        let first = arena.allocate(100).as_ptr();
+        let _ = arena.allocate(1000);
+        let _ = arena.allocate(800);
+        assert!(arena.memory_usage() > usage);
+
+        unsafe { arena.rollback(checkpoint) };
+        assert_eq!(arena.memory_usage(), usage);
+        assert_eq!(arena.remain_bytes(), remain);
+        assert_eq!(arena.inner.blocks.lock().unwrap().len(), 1);
+        // The same memory is handed out again, the rest is untouched.
+        assert_eq!(arena.allocate(100).as_ptr(), first);
+        assert!(kept.iter().all(|&b| b == 1));
+    }
+
+    #[test]
+    fn test_simple() {
+        let mut allocated = vec![];
+        let arena = ArenaImpl::new();
+
+        let n = 100000;
+        let mut bytes = 0;
+        let rnd = Random::new(301);
+        for i in 0..n {
+            let mut s;
+            if i % (n / 10) == 0 {
+                s = i;
+            } else {
+                s = if rnd.one_in(4000) {
+                    rnd.uniform(6000) as usize
+                } else {
+                    if rnd.one_in(10) {
+                        rnd.uniform(100) as usize
+                    } else {
+                        rnd.uniform(20) as usize
+                    }
+                }
+            }
+            if s == 0 {
+                s = 1;
+            }
+            let r = memory(if rnd.one_in(10) {
+                arena.allocate_aligned(s)
+            } else {
+                arena.allocate(s)
+            });
+            for b in r.iter_mut() {
+                *b = (i % 256) as u8;
+            }
+            bytes += s;
+            allocated.push((s, r));
+            assert!(arena.memory_usage() >= bytes as u64);
+            if i > n / 10 {
+                assert!((arena.memory_usage() as f64) <= (bytes as f64) * 1.10);
+            }
+        }
+
+        for (i, (num_bytes, p)) in allocated.iter().enumerate() {
+            assert_eq!(p.len(), *num_bytes);
+            for b in p.iter() {
+                assert_eq!(*b, (i % 256) as u8);
+            }
+        }
+    }
+
+    #[test]
+    fn test_concurrent_alloc() {
+        let arena = ArenaImpl::with_block_size(1024);
+        let requested: usize = thread::scope(|s| {
+            let workers: Vec<_> = (0..8u8)
+                .map(|t| {
+                    let arena = &arena;
+                    s.spawn(move || {
+                        let rnd = Random::new(u32::from(t) + 1);
+                        let mut allocated = vec![];
+                        for _ in 0..2000 {
+                            let size = if rnd.one_in(50) {
+                                300 + rnd.uniform(700) as usize
+                            } else {
+                                1 + rnd.uniform(64) as usize
+                            };
+                            let block = memory(if rnd.one_in(4) {
+                                arena.allocate_aligned(size)
+                            } else {
+                                arena.allocate(size)
+                            });
+                            block.fill(t);
+                            allocated.push(block);
+                        }
+                        // Nobody else was handed the same memory.
+                        assert!(allocated.iter().all(|b| b.iter().all(|&x| x == t)));
+                        allocated.iter().map(|b| b.len()).sum::<usize>()
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).sum()
+        });
+        let blocks: usize = arena
+            .inner
+            .blocks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|b| b.len() + mem::size_of::<usize>())
+            .sum();
+        assert_eq!(arena.memory_usage(), blocks as u64);
+        assert!(arena.memory_usage() >= requested as u64);
+        assert!(arena.remain_bytes() <= 1024);
+    }
+}
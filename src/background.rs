@@ -0,0 +1,47 @@
+use std::sync::RwLock;
+
+/// A unit of maintenance work handed to the spawn function registered with
+/// `SkipList::set_spawn_fn`.
+pub type BackgroundTask = Box<dyn FnOnce() + Send>;
+
+/// Maintenance work a writer can leave to a background task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Job {
+    /// Recompute the bloom filter of the segment starting at this node address.
+    RebuildBloom(usize),
+    /// Unlink the removed node at this address.
+    Purge(usize),
+    /// Lower the max height to the highest level that still has a node.
+    ShrinkHeight,
+}
+
+type Scheduler = Box<dyn Fn(Job) + Send + Sync>;
+
+/// Where writers send the work they don't want to do inline. Nothing is
+/// scheduled until the user provides a spawn function.
+#[derive(Default)]
+pub(crate) struct Background {
+    scheduler: RwLock<Option<Scheduler>>,
+}
+
+impl Background {
+    pub(crate) fn set(&self, scheduler: Scheduler) {
+        *self.scheduler.write().unwrap() = Some(scheduler);
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.scheduler.read().unwrap().is_some()
+    }
+
+    /// Hand `job` to the spawn function, returns `false` if there is none and the
+    /// caller has to do the work itself.
+    pub(crate) fn schedule(&self, job: Job) -> bool {
+        match &*self.scheduler.read().unwrap() {
+            Some(scheduler) => {
+                scheduler(job);
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,281 @@
+//! Inspects a memtable dumped by this crate, without writing a program for it.
+//!
+//! ```text
+//! skiplist-cli <snapshot|wal> <file> get <key>
+//! skiplist-cli <snapshot|wal> <file> scan [<start> [<end>]] [--limit <n>]
+//! skiplist-cli <snapshot|wal> <file> stats
+//! skiplist-cli <snapshot|wal> <file> verify
+//! ```
+//!
+//! A snapshot is a sequence of entries, each its key then its value prefixed
+//! with their length as a varint, as written by
+//! `ImmutableSkipList::export_descending`. A WAL is a log written by
+//! `wal::LogWriter`, each record holding entries encoded the same way. Entries
+//! are loaded in file order into a list sorted bytewise, a later entry
+//! replacing an equal key.
+//!
+//! Keys on the command line are read as UTF-8, or as hex after `0x`. Keys and
+//! values are printed with the bytes that aren't printable ASCII escaped.
+use dakv_skiplist::encoding::get_length_prefixed_slice;
+use dakv_skiplist::wal::LogReader;
+use dakv_skiplist::{
+    ArenaImpl, DefaultComparator, DuplicatePolicy, Random, SkipList, SkipListConfig, SkipMap,
+};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::ops::Bound;
+use std::process;
+
+const USAGE: &str = "usage: skiplist-cli <snapshot|wal> <file> <command>
+
+commands:
+    get <key>                               print the value of <key>
+    scan [<start> [<end>]] [--limit <n>]    print the entries from <start> to <end>, excluded
+    stats                                   print the size of the memtable
+    verify                                  check the file and the list, exit with 1 on errors";
+
+type Map = SkipMap<Random, DefaultComparator, ArenaImpl>;
+
+/// A memtable read back from a file.
+struct Dump {
+    map: Map,
+    /// The number of entries read, replaced ones included.
+    entries: usize,
+    /// The number of records of a WAL.
+    records: Option<usize>,
+    /// Everything that couldn't be read.
+    problems: Vec<String>,
+}
+
+impl Dump {
+    fn new() -> Self {
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::Replace,
+            ..SkipListConfig::default()
+        };
+        let list = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .expect("the default config is valid");
+        Dump {
+            map: SkipMap::from_list(list),
+            entries: 0,
+            records: None,
+            problems: vec![],
+        }
+    }
+
+    fn read_snapshot(path: &str) -> io::Result<Self> {
+        let mut dump = Dump::new();
+        let data = fs::read(path)?;
+        if let Err(offset) = dump.add_entries(&data) {
+            dump.problems
+                .push(format!("truncated entry at offset {}", offset));
+        }
+        Ok(dump)
+    }
+
+    fn read_wal(path: &str) -> io::Result<Self> {
+        let mut dump = Dump::new();
+        let mut reader = LogReader::new(BufReader::new(File::open(path)?));
+        let mut records = 0;
+        while let Some(record) = reader.read_record()? {
+            if let Err(offset) = dump.add_entries(&record) {
+                dump.problems.push(format!(
+                    "truncated entry at offset {} of record {}",
+                    offset, records
+                ));
+            }
+            records += 1;
+        }
+        for corruption in reader.corruptions() {
+            dump.problems.push(format!(
+                "skipped {} bytes: {}",
+                corruption.bytes, corruption.reason
+            ));
+        }
+        dump.records = Some(records);
+        Ok(dump)
+    }
+
+    /// Inserts the entries encoded in `src`, or returns the offset of the first
+    /// one cut short, after inserting those before it.
+    fn add_entries(&mut self, src: &[u8]) -> Result<(), usize> {
+        let mut rest = src;
+        while !rest.is_empty() {
+            let offset = src.len() - rest.len();
+            let key = get_length_prefixed_slice(&mut rest).ok_or(offset)?;
+            let value = get_length_prefixed_slice(&mut rest).ok_or(offset)?;
+            self.map.insert(key.to_vec(), value.to_vec());
+            self.entries += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `arg` as a key: the bytes of the hex digits after `0x`, or else the
+/// UTF-8 bytes of `arg`.
+fn parse_key(arg: &str) -> Result<Vec<u8>, String> {
+    let hex = match arg.strip_prefix("0x") {
+        Some(hex) => hex,
+        None => return Ok(arg.as_bytes().to_vec()),
+    };
+    if hex.len() % 2 != 0 {
+        return Err(format!("odd number of hex digits in {}", arg));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| format!("invalid hex key {}", arg))
+        })
+        .collect()
+}
+
+fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .flat_map(|&b| std::ascii::escape_default(b))
+        .map(char::from)
+        .collect()
+}
+
+fn scan(map: &Map, args: &[String]) -> Result<(), String> {
+    let mut bounds = vec![];
+    let mut limit = usize::MAX;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--limit" {
+            let n = args.next().ok_or("--limit needs a number")?;
+            limit = n.parse().map_err(|_| format!("invalid limit {}", n))?;
+        } else {
+            bounds.push(parse_key(arg)?);
+        }
+    }
+    if bounds.len() > 2 {
+        return Err("scan takes at most a start and an end key".into());
+    }
+    let start = bounds
+        .first()
+        .map_or(Bound::Unbounded, |key| Bound::Included(&key[..]));
+    let end = bounds
+        .get(1)
+        .map_or(Bound::Unbounded, |key| Bound::Excluded(&key[..]));
+    for node in map.as_list().range((start, end)).take(limit) {
+        println!("{} => {}", escape(&node.data), escape(node.value()));
+    }
+    Ok(())
+}
+
+fn stats(dump: &Dump) {
+    let list = dump.map.as_list();
+    println!("keys:         {}", list.len());
+    println!("entries read: {}", dump.entries);
+    if let Some(records) = dump.records {
+        println!("records:      {}", records);
+    }
+    println!("memory usage: {}", list.memory_usage());
+    println!("max height:   {}", list.get_max_height());
+    println!("problems:     {}", dump.problems.len());
+}
+
+/// Prints what's wrong with the dump, and returns `true` if nothing is.
+fn verify(dump: &Dump) -> bool {
+    let mut ok = true;
+    for problem in &dump.problems {
+        println!("{}", problem);
+        ok = false;
+    }
+    if let Err(err) = dump.map.as_list().self_check() {
+        println!("self check: {}", err);
+        ok = false;
+    }
+    let keys: Vec<&[u8]> = dump.map.as_list().keys().collect();
+    if let Some(pair) = keys.windows(2).find(|pair| pair[0] >= pair[1]) {
+        println!("out of order: {} then {}", escape(pair[0]), escape(pair[1]));
+        ok = false;
+    }
+    if ok {
+        println!("ok");
+    }
+    ok
+}
+
+fn run(args: &[String]) -> Result<bool, String> {
+    let (kind, path, command, rest) = match args {
+        [kind, path, command, rest @ ..] => (kind, path, command, rest),
+        _ => return Err(USAGE.into()),
+    };
+    let dump = match kind.as_str() {
+        "snapshot" => Dump::read_snapshot(path),
+        "wal" => Dump::read_wal(path),
+        _ => return Err(USAGE.into()),
+    }
+    .map_err(|err| format!("{}: {}", path, err))?;
+
+    match (command.as_str(), rest) {
+        ("get", [key]) => match dump
+            .map
+            .get(&parse_key(key)?)
+            .map_err(|err| err.to_string())?
+        {
+            Some(value) => println!("{}", escape(&value)),
+            None => return Ok(false),
+        },
+        ("scan", rest) => scan(&dump.map, rest)?,
+        ("stats", []) => stats(&dump),
+        ("verify", []) => return Ok(verify(&dump)),
+        _ => return Err(USAGE.into()),
+    }
+    Ok(true)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dakv_skiplist::encoding::put_length_prefixed_slice;
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key("abc"), Ok(b"abc".to_vec()));
+        assert_eq!(parse_key("0x00ff"), Ok(vec![0, 0xff]));
+        assert_eq!(parse_key("0x"), Ok(vec![]));
+        assert!(parse_key("0x0").is_err());
+        assert!(parse_key("0xzz").is_err());
+        assert_eq!(escape(b"a\0\n"), "a\\x00\\n");
+    }
+
+    #[test]
+    fn test_add_entries() {
+        let mut src = vec![];
+        for (key, value) in &[(b"b", b"1"), (b"a", b"2"), (b"b", b"3")] {
+            put_length_prefixed_slice(&mut src, &key[..]);
+            put_length_prefixed_slice(&mut src, &value[..]);
+        }
+        let complete = src.len();
+        put_length_prefixed_slice(&mut src, b"c");
+
+        let mut dump = Dump::new();
+        assert_eq!(dump.add_entries(&src), Err(complete));
+        assert_eq!(dump.entries, 3);
+        assert_eq!(dump.map.len(), 2);
+        assert_eq!(dump.map.get(b"b").unwrap().unwrap().as_ref(), b"3");
+        assert!(verify(&dump));
+    }
+}
@@ -0,0 +1,208 @@
+use crate::PrefixBloomConfig;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// A fixed size bloom filter, LevelDB style: a single 32-bit hash is turned into
+/// `probes` bit positions with double hashing.
+#[derive(Clone)]
+pub(crate) struct Bloom {
+    bits: Vec<u64>,
+    probes: u32,
+}
+
+impl Bloom {
+    /// Create a filter of `bits` bits tuned for about `expected` keys.
+    pub(crate) fn new(bits: usize, expected: usize) -> Self {
+        let bits = bits.div_ceil(64) * 64;
+        // 0.69 =~ ln(2), which minimizes the false positive rate.
+        let probes = (bits as f64 / expected.max(1) as f64 * 0.69) as u32;
+        Bloom {
+            bits: vec![0; bits / 64],
+            probes: probes.clamp(1, 30),
+        }
+    }
+
+    pub(crate) fn add(&mut self, key: &[u8]) {
+        let len = self.len();
+        for pos in self.positions(key, len) {
+            self.bits[pos / 64] |= 1 << (pos % 64);
+        }
+    }
+
+    pub(crate) fn may_contain(&self, key: &[u8]) -> bool {
+        self.positions(key, self.len())
+            .all(|pos| self.bits[pos / 64] & (1 << (pos % 64)) != 0)
+    }
+
+    fn len(&self) -> usize {
+        self.bits.len() * 64
+    }
+
+    fn positions(&self, key: &[u8], len: usize) -> impl Iterator<Item = usize> {
+        let mut h = hash(key, 0xbc9f_1d34);
+        let delta = h.rotate_right(17);
+        (0..self.probes).map(move |_| {
+            let pos = h as usize % len;
+            h = h.wrapping_add(delta);
+            pos
+        })
+    }
+}
+
+/// The prefix bloom filters of a skiplist, keyed by the address of the node that
+/// starts each segment. The head node starts the first segment.
+pub(crate) struct SegmentBlooms {
+    config: PrefixBloomConfig,
+    expected: usize,
+    filters: RwLock<HashMap<usize, Bloom>>,
+}
+
+impl SegmentBlooms {
+    pub(crate) fn new(config: PrefixBloomConfig, branching_factor: u32) -> Self {
+        let expected = (branching_factor as usize).saturating_pow(config.segment_level as u32);
+        SegmentBlooms {
+            config,
+            expected,
+            filters: RwLock::default(),
+        }
+    }
+
+    pub(crate) fn segment_level(&self) -> usize {
+        self.config.segment_level
+    }
+
+    pub(crate) fn prefix_len(&self) -> usize {
+        self.config.prefix_len
+    }
+
+    /// Returns the part of `key` hashed into the filters.
+    pub(crate) fn prefix<'a>(&self, key: &'a [u8]) -> &'a [u8] {
+        &key[..key.len().min(self.config.prefix_len)]
+    }
+
+    /// Record `key` in the filter of the segment starting at `segment`.
+    pub(crate) fn add(&self, segment: usize, key: &[u8]) {
+        let prefix = self.prefix(key);
+        let mut filters = self.filters.write().unwrap();
+        filters
+            .entry(segment)
+            .or_insert_with(|| Bloom::new(self.config.bits_per_segment, self.expected))
+            .add(prefix);
+    }
+
+    /// Start a new segment at `segment`, holding `keys`. The filter of the segment
+    /// being split isn't touched: it still holds the keys moved to the new one,
+    /// which only costs false positives.
+    pub(crate) fn split<'a>(&self, segment: usize, keys: impl Iterator<Item = &'a [u8]>) {
+        let mut bloom = Bloom::new(self.config.bits_per_segment, self.expected);
+        for key in keys {
+            bloom.add(self.prefix(key));
+        }
+        self.filters.write().unwrap().insert(segment, bloom);
+    }
+
+    /// Start a new segment at `segment` without walking its keys: it gets a copy of
+    /// the filter of `parent`, the segment it is split from, which already holds
+    /// them, plus `key`. The filter is only an approximation until `rebuild` runs.
+    pub(crate) fn fork(&self, parent: usize, segment: usize, key: &[u8]) {
+        let mut filters = self.filters.write().unwrap();
+        let mut bloom = match filters.get(&parent) {
+            Some(bloom) => bloom.clone(),
+            None => Bloom::new(self.config.bits_per_segment, self.expected),
+        };
+        bloom.add(self.prefix(key));
+        filters.insert(segment, bloom);
+    }
+
+    /// Replace the filter of `segment` with one holding exactly `keys`, unless the
+    /// segment is gone (e.g. the list was cleared). Writers are blocked while
+    /// `keys` is consumed, so no key added to the segment meanwhile gets lost.
+    pub(crate) fn rebuild<'a>(&self, segment: usize, keys: impl Iterator<Item = &'a [u8]>) {
+        let mut filters = self.filters.write().unwrap();
+        if let Some(slot) = filters.get_mut(&segment) {
+            let mut bloom = Bloom::new(self.config.bits_per_segment, self.expected);
+            for key in keys {
+                bloom.add(self.prefix(key));
+            }
+            *slot = bloom;
+        }
+    }
+
+    /// Returns `false` if no key of the segment starting at `segment` begins with
+    /// `prefix`, which must be exactly `prefix_len` bytes long (or a whole key).
+    pub(crate) fn may_contain(&self, segment: usize, prefix: &[u8]) -> bool {
+        match self.filters.read().unwrap().get(&segment) {
+            Some(bloom) => bloom.may_contain(prefix),
+            // Without a filter, e.g. the segment of an empty list, nothing is
+            // known about the keys.
+            None => true,
+        }
+    }
+
+    /// Forget the segment starting at `segment`, whose node was unlinked.
+    pub(crate) fn remove(&self, segment: usize) {
+        self.filters.write().unwrap().remove(&segment);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.filters.write().unwrap().clear();
+    }
+
+    pub(crate) fn memory_usage(&self) -> u64 {
+        let filters = self.filters.read().unwrap();
+        filters.values().map(|b| b.bits.len() as u64 * 8).sum()
+    }
+}
+
+/// Murmur-like hash used by LevelDB.
+pub(crate) fn hash(data: &[u8], seed: u32) -> u32 {
+    const M: u32 = 0xc6a4_a793;
+    const R: u32 = 24;
+    let mut h = seed ^ (data.len() as u32).wrapping_mul(M);
+
+    let mut chunks = data.chunks_exact(4);
+    for w in &mut chunks {
+        h = h.wrapping_add(u32::from_le_bytes([w[0], w[1], w[2], w[3]]));
+        h = h.wrapping_mul(M);
+        h ^= h >> 16;
+    }
+    let rest = chunks.remainder();
+    if !rest.is_empty() {
+        for (i, b) in rest.iter().enumerate() {
+            h = h.wrapping_add((*b as u32) << (8 * i));
+        }
+        h = h.wrapping_mul(M);
+        h ^= h >> R;
+    }
+    h
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hash, Bloom};
+
+    #[test]
+    fn test_hash() {
+        // Values computed by LevelDB's Hash().
+        assert_eq!(hash(&[], 0xbc9f_1d34), 0xbc9f_1d34);
+        assert_eq!(hash(&[0x62], 0xbc9f_1d34), 0xef13_45c4);
+        assert_eq!(hash(&[0xc3, 0x97], 0xbc9f_1d34), 0x5b66_3814);
+        assert_eq!(hash(&[0xe2, 0x99, 0xa5], 0xbc9f_1d34), 0x323c_078f);
+        assert_eq!(hash(&[0xe1, 0x80, 0xb9, 0x32], 0xbc9f_1d34), 0xed21_633a);
+    }
+
+    #[test]
+    fn test_bloom() {
+        let mut bloom = Bloom::new(1024, 64);
+        for i in 0..64u32 {
+            bloom.add(&i.to_be_bytes());
+        }
+        for i in 0..64u32 {
+            assert!(bloom.may_contain(&i.to_be_bytes()));
+        }
+        let false_positives = (1000..11000u32)
+            .filter(|i| bloom.may_contain(&i.to_be_bytes()))
+            .count();
+        assert!(false_positives < 500, "{}", false_positives);
+    }
+}
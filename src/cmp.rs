@@ -1,8 +1,66 @@
 use std::cmp::Ordering;
+use std::sync::Arc;
 
+/// Orders the keys of a skiplist.
+///
+/// A comparator can carry state, e.g. a schema describing the type and order of
+/// the columns encoded in a key. To pick the comparator at runtime, a list can be
+/// built with an `Arc<dyn BaseComparator + Send + Sync>` or a `Box` of one.
+///
+/// Only keys are compared. The metadata byte of an entry can change after it is
+/// inserted (see `SkipList::set_meta`), which would leave it out of order.
+///
+/// A `MemTableSet` doesn't pass the sequence number or the kind of an entry
+/// either: searches compare a bare key, which has neither, with the stored
+/// ones. Versions of a key are ordered by stamping the sequence number into the
+/// key itself, see `SkipMap::insert_seq`, and a tombstone shadows the entries
+/// of the older lists whatever the comparator says.
+/// # Examples
+#[cfg_attr(feature = "arena", doc = "```")]
+#[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+/// use std::cmp::Ordering;
+/// use std::sync::Arc;
+/// use dakv_skiplist::{ArenaImpl, BaseComparator, Random, SkipList};
+///
+/// /// Keys are made of fixed-width columns, some sorted in descending order.
+/// struct Schema {
+///     widths: Vec<usize>,
+///     descending: Vec<bool>,
+/// }
+///
+/// impl BaseComparator for Schema {
+///     fn compare(&self, mut a: &[u8], mut b: &[u8]) -> Ordering {
+///         for (&width, &desc) in self.widths.iter().zip(&self.descending) {
+///             let ord = a[..width].cmp(&b[..width]);
+///             if ord != Ordering::Equal {
+///                 return if desc { ord.reverse() } else { ord };
+///             }
+///             a = &a[width..];
+///             b = &b[width..];
+///         }
+///         Ordering::Equal
+///     }
+/// }
+///
+/// let schema = Schema { widths: vec![1, 1], descending: vec![false, true] };
+/// let cmp: Arc<dyn BaseComparator + Send + Sync> = Arc::new(schema);
+/// let sl = SkipList::new(Random::new(0xdead_beef), cmp, ArenaImpl::new());
+/// sl.insert(vec![1u8, 1]);
+/// sl.insert(vec![1u8, 2]);
+/// sl.insert(vec![0u8, 1]);
+/// assert_eq!(format!("{}", sl), "[[0, 1] [1, 2] [1, 1] ]");
+/// ```
 pub trait BaseComparator {
     fn compare(&self, a: &[u8], b: &[u8]) -> Ordering;
 
+    /// Names the order of the keys, e.g. to tell whether two lists are sorted
+    /// the same way before comparing them with `==`. Comparators of one type ordering keys
+    /// differently, like `Schema` above for different columns, should return
+    /// different names. Defaults to the name of the type.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
     /// Less than
     fn lt(&self, a: &[u8], b: &[u8]) -> bool {
         self.compare(a, b) == Ordering::Less
@@ -34,7 +92,27 @@ pub trait BaseComparator {
     }
 }
 
-#[derive(Default)]
+impl<T: BaseComparator + ?Sized> BaseComparator for Arc<T> {
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        (**self).compare(a, b)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+}
+
+impl<T: BaseComparator + ?Sized> BaseComparator for Box<T> {
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        (**self).compare(a, b)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+}
+
+#[derive(Default, Clone, Copy)]
 pub struct DefaultComparator {}
 
 impl BaseComparator for DefaultComparator {
@@ -49,11 +127,13 @@ impl BaseComparator for DefaultComparator {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "arena"))]
 mod tests {
     use super::BaseComparator;
     use crate::cmp::DefaultComparator;
+    use crate::{ArenaImpl, Random, SkipList};
     use std::cmp::Ordering;
+    use std::sync::Arc;
 
     #[test]
     fn test_basic() {
@@ -73,4 +153,27 @@ mod tests {
         assert!(cmp.gt(&[2], &[1]));
         assert!(cmp.ne(&[2], &[1]));
     }
+
+    #[test]
+    fn test_dyn_comparator() {
+        struct Reverse;
+
+        impl BaseComparator for Reverse {
+            fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+                b.cmp(a)
+            }
+        }
+
+        let comparators: Vec<Box<dyn BaseComparator>> =
+            vec![Box::new(DefaultComparator::default()), Box::new(Reverse)];
+        assert_eq!(comparators[0].compare(&[1], &[2]), Ordering::Less);
+        assert_eq!(comparators[1].compare(&[1], &[2]), Ordering::Greater);
+
+        let shared: Arc<dyn BaseComparator + Send + Sync> = Arc::new(Reverse);
+        let mut sl = SkipList::new(Random::new(0xdead_beef), shared.clone(), ArenaImpl::new());
+        sl.extend(0..10);
+        assert_eq!(sl.is_sorted(), Ok(()));
+        assert_eq!((&sl).into_iter().next().unwrap().data.as_ref(), &[9]);
+        assert_eq!(Arc::strong_count(&shared), 2);
+    }
 }
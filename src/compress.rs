@@ -0,0 +1,160 @@
+//! Transparent compression of large values.
+//!
+//! A `ValueCodec` wraps a user provided `Compressor` (e.g. lz4 or snappy) and a
+//! size threshold. Stored values start with a tag byte telling whether the rest
+//! is compressed, so values below the threshold, or that don't shrink, are kept
+//! as is and reads never have to guess.
+use crate::CompressionError;
+use bytes::{BufMut, Bytes, BytesMut};
+use std::fmt;
+use std::sync::Arc;
+
+const RAW: u8 = 0;
+const COMPRESSED: u8 = 1;
+
+/// A compression algorithm.
+pub trait Compressor: Send + Sync {
+    /// Append the compressed form of `data` to `dst`.
+    fn compress(&self, data: &[u8], dst: &mut Vec<u8>);
+
+    /// Returns the data compressed by `compress`, `None` if `data` is corrupted.
+    fn decompress(&self, data: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// Compresses the values of at least `threshold` bytes with a `Compressor`.
+#[derive(Clone)]
+pub struct ValueCodec {
+    compressor: Arc<dyn Compressor>,
+    threshold: usize,
+}
+
+impl ValueCodec {
+    pub fn new(compressor: impl Compressor + 'static, threshold: usize) -> Self {
+        ValueCodec {
+            compressor: Arc::new(compressor),
+            threshold,
+        }
+    }
+
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Returns the stored form of `value`.
+    pub fn encode(&self, value: &[u8]) -> Bytes {
+        if value.len() >= self.threshold {
+            let mut compressed = Vec::with_capacity(value.len() + 1);
+            compressed.push(COMPRESSED);
+            self.compressor.compress(value, &mut compressed);
+            if compressed.len() <= value.len() {
+                return compressed.into();
+            }
+        }
+        let mut raw = BytesMut::with_capacity(value.len() + 1);
+        raw.put_u8(RAW);
+        raw.put_slice(value);
+        raw.freeze()
+    }
+
+    /// Returns the value whose stored form is `stored`. Values kept as is are
+    /// returned without copying.
+    pub fn decode(&self, stored: &Bytes) -> Result<Bytes, CompressionError> {
+        match stored.first() {
+            Some(&RAW) => Ok(stored.slice(1..)),
+            Some(&COMPRESSED) => self
+                .compressor
+                .decompress(&stored[1..])
+                .map(Bytes::from)
+                .ok_or(CompressionError::Corrupted),
+            Some(&tag) => Err(CompressionError::UnknownTag(tag)),
+            None => Err(CompressionError::Corrupted),
+        }
+    }
+}
+
+/// Codecs are equal when they share the same compressor and threshold.
+impl PartialEq for ValueCodec {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.compressor, &other.compressor) && self.threshold == other.threshold
+    }
+}
+
+impl Eq for ValueCodec {}
+
+impl fmt::Debug for ValueCodec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ValueCodec")
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Compressor, ValueCodec};
+    use crate::CompressionError;
+    use bytes::Bytes;
+
+    /// Run length encoding, as (count, byte) pairs.
+    struct Rle;
+
+    impl Compressor for Rle {
+        fn compress(&self, data: &[u8], dst: &mut Vec<u8>) {
+            let mut i = 0;
+            while i < data.len() {
+                let run = data[i..]
+                    .iter()
+                    .take(255)
+                    .take_while(|&&b| b == data[i])
+                    .count();
+                dst.push(run as u8);
+                dst.push(data[i]);
+                i += run;
+            }
+        }
+
+        fn decompress(&self, data: &[u8]) -> Option<Vec<u8>> {
+            if !data.len().is_multiple_of(2) {
+                return None;
+            }
+            let mut out = vec![];
+            for pair in data.chunks(2) {
+                out.extend(std::iter::repeat_n(pair[1], pair[0] as usize));
+            }
+            Some(out)
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let codec = ValueCodec::new(Rle, 16);
+        let small = vec![7u8; 8];
+        let large = vec![7u8; 1000];
+        let noisy: Vec<u8> = (0..100u8).collect();
+        for value in [&small, &large, &noisy] {
+            let stored = codec.encode(value);
+            assert_eq!(codec.decode(&stored).unwrap().as_ref(), value.as_slice());
+        }
+        // Compressed only above the threshold, and when it pays off.
+        assert_eq!(codec.encode(&small).len(), 9);
+        assert!(codec.encode(&large).len() < 20);
+        assert_eq!(codec.encode(&noisy).len(), 101);
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let codec = ValueCodec::new(Rle, 16);
+        assert_eq!(
+            codec.decode(&Bytes::from_static(&[9, 1])),
+            Err(CompressionError::UnknownTag(9))
+        );
+        assert_eq!(
+            codec.decode(&Bytes::from_static(&[1, 1])),
+            Err(CompressionError::Corrupted)
+        );
+        assert_eq!(
+            codec.decode(&Bytes::new()),
+            Err(CompressionError::Corrupted)
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use crate::arena::K_BLOCK_SIZE;
+use crate::compress::ValueCodec;
+use crate::{ConfigError, K_MAX_HEIGHT};
+
+/// What `SkipList::insert` does when an equal key is already in the list.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DuplicatePolicy {
+    /// Insert the key anyway, equal keys end up next to each other.
+    #[default]
+    AllowDuplicates,
+    /// Keep the existing entry and drop the new one.
+    Ignore,
+    /// Remove the existing entry and insert the new one, e.g. to overwrite the
+    /// value of a `SkipMap` key.
+    Replace,
+    /// Keep the existing entry and report the new one, see `SkipList::try_insert`.
+    Error,
+    /// Keep the existing entry and count one more insert of its key in it,
+    /// making the list a multiset, see `SkipList::count` and
+    /// `SkipList::remove_one`.
+    Count,
+}
+
+/// What `SkipList::insert` does when the comparator orders a new key and its
+/// neighbours in the list inconsistently, e.g. `a < b` but also `b < a`, which
+/// would otherwise corrupt the order of the list silently.
+///
+/// Checking costs four more comparisons per insert.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ComparatorCheck {
+    /// Don't check.
+    #[default]
+    Off,
+    /// Panic with the `ComparatorViolation`.
+    Panic,
+    /// Drop the key and report it, see `SkipList::checked_insert`. Inserts that
+    /// can't report it, like `VacantEntry::insert`, panic.
+    Error,
+    /// `Panic` in debug builds, `Error` in release builds.
+    DebugPanic,
+}
+
+/// What `SkipList::insert` does with a new entry once the list holds
+/// `max_entries` of them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum OverflowPolicy {
+    /// Drop the new entry, see `InsertOutcome::Full`.
+    #[default]
+    Reject,
+    /// Remove the smallest entry to make room, keeping the largest ones like a
+    /// top-K. A key that would be the smallest is dropped instead.
+    EvictSmallest,
+    /// Remove the largest entry to make room, keeping the smallest ones like a
+    /// bottom-K. A key that would be the largest is dropped instead.
+    EvictLargest,
+}
+
+/// Bloom filters over key prefixes, one per segment of the list.
+///
+/// The nodes reaching `segment_level` split the list into segments of about
+/// `branching_factor ^ segment_level` entries. Each segment gets a filter of the
+/// first `prefix_len` bytes of its keys, so point lookups and prefix scans can
+/// give up as soon as they know which segment a key would be in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PrefixBloomConfig {
+    /// Number of leading bytes of a key hashed into the filters. Shorter keys are
+    /// hashed whole.
+    pub prefix_len: usize,
+    /// Level whose nodes start a new segment, at least 1.
+    pub segment_level: usize,
+    /// Size of the filter of each segment.
+    pub bits_per_segment: usize,
+}
+
+impl Default for PrefixBloomConfig {
+    fn default() -> Self {
+        PrefixBloomConfig {
+            prefix_len: 8,
+            segment_level: 2,
+            bits_per_segment: 256,
+        }
+    }
+}
+
+/// Tunables of a skiplist, e.g. read from the config file of a database.
+///
+/// Every field has a default, so a config only needs to mention what it changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct SkipListConfig {
+    /// Highest tower a node can get, at most `K_MAX_HEIGHT`.
+    pub max_height: usize,
+    /// A node reaching level `l` also reaches level `l + 1` with a probability of
+    /// `1 / branching_factor`.
+    pub branching_factor: u32,
+    pub duplicate_policy: DuplicatePolicy,
+    /// Memory usage, as reported by `SkipList::memory_usage`, above which the list
+    /// reports being full. `None` means unbounded.
+    pub memory_budget: Option<u64>,
+    /// Size of the blocks allocated by the arena, used by `SkipList::from_config`.
+    pub arena_block_size: usize,
+    /// Per-segment prefix bloom filters, disabled when `None`.
+    pub prefix_bloom: Option<PrefixBloomConfig>,
+    pub comparator_check: ComparatorCheck,
+    /// Most entries the list holds, new ones past it go through the
+    /// `overflow_policy`. `None` means unbounded.
+    pub max_entries: Option<u64>,
+    pub overflow_policy: OverflowPolicy,
+    /// Name of the list, e.g. the column family of a memtable, to tell lists
+    /// apart in logs and metrics labels. Shown along with `SkipList::id` in the
+    /// `Debug` output of the list, and in the errors it returns.
+    pub name: Option<String>,
+    /// Compresses the values of a `SkipMap` over the list, see `compress`. It
+    /// is fixed when the list is created, so that every handle, and every map
+    /// made with `SkipMap::from_list`, stores and reads values the same way.
+    /// Not read from config files.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub value_codec: Option<ValueCodec>,
+    /// Count one successful lookup out of this many per thread, reported by
+    /// `SkipList::hot_keys`. Disabled when `None`.
+    #[cfg(feature = "metrics")]
+    pub hot_key_sampling: Option<u32>,
+}
+
+impl Default for SkipListConfig {
+    fn default() -> Self {
+        SkipListConfig {
+            max_height: K_MAX_HEIGHT,
+            branching_factor: 4,
+            duplicate_policy: DuplicatePolicy::default(),
+            memory_budget: None,
+            arena_block_size: K_BLOCK_SIZE,
+            prefix_bloom: None,
+            comparator_check: ComparatorCheck::default(),
+            max_entries: None,
+            overflow_policy: OverflowPolicy::default(),
+            name: None,
+            value_codec: None,
+            #[cfg(feature = "metrics")]
+            hot_key_sampling: None,
+        }
+    }
+}
+
+impl SkipListConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_height == 0 || self.max_height > K_MAX_HEIGHT {
+            return Err(ConfigError::InvalidMaxHeight(self.max_height));
+        }
+        if self.branching_factor < 2 {
+            return Err(ConfigError::InvalidBranchingFactor(self.branching_factor));
+        }
+        if self.arena_block_size == 0 {
+            return Err(ConfigError::InvalidArenaBlockSize(self.arena_block_size));
+        }
+        if self.max_entries == Some(0) {
+            return Err(ConfigError::InvalidMaxEntries(0));
+        }
+        #[cfg(feature = "metrics")]
+        if self.hot_key_sampling == Some(0) {
+            return Err(ConfigError::InvalidSamplingRate(0));
+        }
+        if let Some(bloom) = &self.prefix_bloom {
+            if bloom.segment_level == 0 || bloom.segment_level >= self.max_height {
+                return Err(ConfigError::InvalidSegmentLevel(bloom.segment_level));
+            }
+            if bloom.bits_per_segment == 0 {
+                return Err(ConfigError::InvalidBloomSize(bloom.bits_per_segment));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PrefixBloomConfig, SkipListConfig};
+    use crate::{ConfigError, K_MAX_HEIGHT};
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(SkipListConfig::default().validate(), Ok(()));
+
+        let config = SkipListConfig {
+            max_height: K_MAX_HEIGHT + 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::InvalidMaxHeight(K_MAX_HEIGHT + 1))
+        );
+        let config = SkipListConfig {
+            branching_factor: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::InvalidBranchingFactor(1))
+        );
+        let config = SkipListConfig {
+            arena_block_size: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::InvalidArenaBlockSize(0))
+        );
+        let config = SkipListConfig {
+            max_entries: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::InvalidMaxEntries(0)));
+        let config = SkipListConfig {
+            prefix_bloom: Some(PrefixBloomConfig {
+                segment_level: K_MAX_HEIGHT,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(ConfigError::InvalidSegmentLevel(K_MAX_HEIGHT))
+        );
+        #[cfg(feature = "metrics")]
+        {
+            let config = SkipListConfig {
+                hot_key_sampling: Some(0),
+                ..Default::default()
+            };
+            assert_eq!(config.validate(), Err(ConfigError::InvalidSamplingRate(0)));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn test_deserialize() {
+        use super::{ComparatorCheck, DuplicatePolicy, OverflowPolicy};
+
+        let config: SkipListConfig = serde_json::from_str(
+            r#"{"max_height": 8, "duplicate_policy": "ignore", "memory_budget": 4194304}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            SkipListConfig {
+                max_height: 8,
+                duplicate_policy: DuplicatePolicy::Ignore,
+                memory_budget: Some(4 << 20),
+                ..Default::default()
+            }
+        );
+        let policy: DuplicatePolicy = serde_json::from_str(r#""replace""#).unwrap();
+        assert_eq!(policy, DuplicatePolicy::Replace);
+        let check: ComparatorCheck = serde_json::from_str(r#""debug_panic""#).unwrap();
+        assert_eq!(check, ComparatorCheck::DebugPanic);
+        let policy: OverflowPolicy = serde_json::from_str(r#""evict_smallest""#).unwrap();
+        assert_eq!(policy, OverflowPolicy::EvictSmallest);
+    }
+}
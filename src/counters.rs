@@ -0,0 +1,62 @@
+//! Work counters of the `deterministic` feature.
+//!
+//! Without the feature the counters are empty and every method compiles to
+//! nothing, so the hot paths can call them unconditionally.
+#[cfg(feature = "deterministic")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Work done by a skiplist since it was created or since the counters were
+/// last reset, see `SkipList::op_counters`.
+#[cfg(feature = "deterministic")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct OpCounters {
+    /// Calls to the comparator.
+    pub comparisons: u64,
+    /// Nodes whose key was read while searching.
+    pub nodes_visited: u64,
+}
+
+#[cfg(feature = "deterministic")]
+#[derive(Default)]
+pub(crate) struct Counters {
+    comparisons: AtomicU64,
+    nodes_visited: AtomicU64,
+}
+
+#[cfg(feature = "deterministic")]
+impl Counters {
+    #[inline]
+    pub(crate) fn compared(&self) {
+        self.comparisons.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn visited(&self) {
+        self.nodes_visited.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> OpCounters {
+        OpCounters {
+            comparisons: self.comparisons.load(Ordering::Relaxed),
+            nodes_visited: self.nodes_visited.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.comparisons.store(0, Ordering::Relaxed);
+        self.nodes_visited.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(not(feature = "deterministic"))]
+#[derive(Default)]
+pub(crate) struct Counters {}
+
+#[cfg(not(feature = "deterministic"))]
+impl Counters {
+    #[inline(always)]
+    pub(crate) fn compared(&self) {}
+
+    #[inline(always)]
+    pub(crate) fn visited(&self) {}
+}
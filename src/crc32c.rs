@@ -0,0 +1,90 @@
+//! CRC32C (Castagnoli), as used by LevelDB to checksum log records.
+
+const POLY: u32 = 0x82f6_3b78;
+
+const TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Returns the crc32c of `concat(A, data)` where `init_crc` is the crc32c of A.
+pub fn extend(init_crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !init_crc;
+    for &b in data {
+        crc = TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// Returns the crc32c of `data`.
+pub fn value(data: &[u8]) -> u32 {
+    extend(0, data)
+}
+
+const MASK_DELTA: u32 = 0xa282_ead8;
+
+/// Returns a masked representation of `crc`.
+///
+/// Computing the crc of a string that contains embedded crcs is problematic,
+/// so crcs are masked before being stored.
+pub fn mask(crc: u32) -> u32 {
+    // Rotate right by 15 bits and add a constant.
+    crc.rotate_right(15).wrapping_add(MASK_DELTA)
+}
+
+/// Returns the crc whose masked representation is `masked_crc`.
+pub fn unmask(masked_crc: u32) -> u32 {
+    masked_crc.wrapping_sub(MASK_DELTA).rotate_left(15)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_standard_results() {
+        // From rfc3720 section B.4.
+        assert_eq!(value(&[0; 32]), 0x8a91_36aa);
+        assert_eq!(value(&[0xff; 32]), 0x62a8_ab43);
+        let ascending: Vec<u8> = (0..32).collect();
+        assert_eq!(value(&ascending), 0x46dd_794e);
+        let descending: Vec<u8> = (0..32).rev().collect();
+        assert_eq!(value(&descending), 0x113f_db5c);
+    }
+
+    #[test]
+    fn test_values() {
+        assert_ne!(value(b"a"), value(b"foo"));
+    }
+
+    #[test]
+    fn test_extend() {
+        assert_eq!(value(b"hello world"), extend(value(b"hello "), b"world"));
+    }
+
+    #[test]
+    fn test_mask() {
+        let crc = value(b"foo");
+        assert_ne!(crc, mask(crc));
+        assert_ne!(crc, mask(mask(crc)));
+        assert_eq!(crc, unmask(mask(crc)));
+        assert_eq!(crc, unmask(unmask(mask(mask(crc)))));
+    }
+}
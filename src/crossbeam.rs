@@ -0,0 +1,200 @@
+//! Conversions to and from the lists of crossbeam-skiplist, to migrate either
+//! way or to check a list against `crossbeam_skiplist`.
+//!
+//! Crossbeam orders keys by `Ord`, which for `Bytes` is the bytewise order of
+//! `DefaultComparator`, so only lists with that comparator convert. Crossbeam
+//! keeps a single entry per key: converting a list holding equal keys, e.g.
+//! values shadowed by a later insert, fails rather than dropping them.
+use crate::{
+    Arena, ConversionError, DefaultComparator, DuplicateKeyError, RandomGenerator, SkipList,
+    SkipMap,
+};
+use bytes::Bytes;
+use std::convert::TryFrom;
+
+impl<R, A> SkipList<R, DefaultComparator, A>
+where
+    R: RandomGenerator,
+    A: Arena,
+{
+    /// Returns a list holding the keys of `set`, built in O(n) like
+    /// `from_sorted_iter`.
+    /// # Examples
+    /// ```
+    /// use dakv_skiplist::{ArenaImpl, Random, SkipList};
+    ///
+    /// let set = crossbeam_skiplist::SkipSet::new();
+    /// set.insert(bytes::Bytes::from_static(b"b"));
+    /// set.insert(bytes::Bytes::from_static(b"a"));
+    /// let sl = SkipList::from_crossbeam(Random::new(0xdead_beef), ArenaImpl::new(), &set);
+    /// assert_eq!(format!("{}", sl), "[[97] [98] ]");
+    /// ```
+    pub fn from_crossbeam(rnd: R, arena: A, set: &crossbeam_skiplist::SkipSet<Bytes>) -> Self {
+        Self::from_sorted_iter(
+            rnd,
+            DefaultComparator::default(),
+            arena,
+            set.iter().map(|entry| entry.value().clone()),
+        )
+        .expect("crossbeam sets are sorted bytewise")
+    }
+}
+
+impl<R, A> SkipMap<R, DefaultComparator, A>
+where
+    R: RandomGenerator,
+    A: Arena,
+{
+    /// Returns a map holding the entries of `map`.
+    /// # Examples
+    /// ```
+    /// use dakv_skiplist::{ArenaImpl, Random, SkipMap};
+    ///
+    /// let map = crossbeam_skiplist::SkipMap::new();
+    /// map.insert(bytes::Bytes::from_static(b"key"), bytes::Bytes::from_static(b"value"));
+    /// let sm = SkipMap::from_crossbeam(Random::new(0xdead_beef), ArenaImpl::new(), &map);
+    /// assert_eq!(sm.get(b"key").unwrap().unwrap().as_ref(), b"value");
+    /// ```
+    pub fn from_crossbeam(
+        rnd: R,
+        arena: A,
+        map: &crossbeam_skiplist::SkipMap<Bytes, Bytes>,
+    ) -> Self {
+        let sm = Self::new(rnd, DefaultComparator::default(), arena);
+        for entry in map.iter() {
+            sm.insert(entry.key().clone(), entry.value().clone());
+        }
+        sm
+    }
+}
+
+impl<R, A> TryFrom<&SkipList<R, DefaultComparator, A>> for crossbeam_skiplist::SkipSet<Bytes>
+where
+    R: RandomGenerator,
+    A: Arena,
+{
+    type Error = DuplicateKeyError;
+
+    /// Returns a set holding the keys of `list`, or the first key found twice.
+    fn try_from(list: &SkipList<R, DefaultComparator, A>) -> Result<Self, Self::Error> {
+        let set = crossbeam_skiplist::SkipSet::new();
+        for node in list {
+            if set.contains(&node.data) {
+                return Err(DuplicateKeyError {
+                    key: node.data.clone(),
+                    list: list.name().map(String::from),
+                });
+            }
+            set.insert(node.data.clone());
+        }
+        Ok(set)
+    }
+}
+
+impl<R, A> TryFrom<&SkipMap<R, DefaultComparator, A>> for crossbeam_skiplist::SkipMap<Bytes, Bytes>
+where
+    R: RandomGenerator,
+    A: Arena,
+{
+    type Error = ConversionError;
+
+    /// Returns a map holding the entries of `map`, with their values
+    /// decompressed, or the first key found twice or value that can't be
+    /// decompressed.
+    fn try_from(map: &SkipMap<R, DefaultComparator, A>) -> Result<Self, Self::Error> {
+        let converted = crossbeam_skiplist::SkipMap::new();
+        for entry in map.iter_bytes() {
+            let (key, value) = entry?;
+            if converted.contains_key(&key) {
+                return Err(DuplicateKeyError {
+                    key,
+                    list: map.as_list().name().map(String::from),
+                }
+                .into());
+            }
+            converted.insert(key, value);
+        }
+        Ok(converted)
+    }
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use crate::{
+        ArenaImpl, ConversionError, DefaultComparator, DuplicatePolicy, Random, RandomGenerator,
+        SkipList, SkipListConfig, SkipMap,
+    };
+    use bytes::Bytes;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_set_round_trip() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let rnd = Random::new(7);
+        let reference = crossbeam_skiplist::SkipSet::new();
+        for _ in 0..500 {
+            let key = Bytes::from(rnd.next().to_be_bytes().to_vec());
+            if !sl.contains(&key) {
+                sl.insert(key.clone());
+            }
+            reference.insert(key);
+        }
+        let set = crossbeam_skiplist::SkipSet::try_from(&sl).unwrap();
+        assert!(set
+            .iter()
+            .map(|e| e.value().clone())
+            .eq(sl.keys().map(Bytes::copy_from_slice)));
+        assert!(set
+            .iter()
+            .map(|e| e.value().clone())
+            .eq(reference.iter().map(|e| e.value().clone())));
+
+        let back = SkipList::from_crossbeam(Random::new(1), ArenaImpl::new(), &set);
+        assert!(back == sl);
+
+        sl.insert(set.front().unwrap().value().clone());
+        let err = crossbeam_skiplist::SkipSet::try_from(&sl).err().unwrap();
+        assert_eq!(&err.key, set.front().unwrap().value());
+    }
+
+    #[test]
+    fn test_map_round_trip() {
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::Replace,
+            name: Some("memtable".into()),
+            ..SkipListConfig::default()
+        };
+        let list = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        let sm = SkipMap::from_list(list);
+        for i in 0..100u32 {
+            sm.insert(i.to_be_bytes().to_vec(), vec![0u8; i as usize % 7]);
+            sm.insert(i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec());
+        }
+        let map = crossbeam_skiplist::SkipMap::try_from(&sm).unwrap();
+        assert_eq!(map.len(), 100);
+        for entry in map.iter() {
+            assert_eq!(sm.get(entry.key()).unwrap().as_ref(), Some(entry.value()));
+        }
+
+        let back = SkipMap::from_crossbeam(Random::new(1), ArenaImpl::new(), &map);
+        assert!(back.iter().eq(sm.iter()));
+
+        // Shadowed values don't fit in a crossbeam map.
+        back.insert(&b"\0\0\0\x05"[..], &b"new"[..]);
+        match crossbeam_skiplist::SkipMap::try_from(&back) {
+            Err(ConversionError::DuplicateKey(err)) => {
+                assert_eq!(err.key.as_ref(), b"\0\0\0\x05")
+            }
+            _ => panic!("the shadowed value converted"),
+        }
+    }
+}
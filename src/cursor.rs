@@ -0,0 +1,311 @@
+use crate::skipnode::{live_from, Nav};
+use crate::{Arena, BaseComparator, RandomGenerator, SkipList, K_MAX_HEIGHT};
+use std::cmp::Ordering;
+use std::iter;
+
+/// A position in a skiplist that moves both ways, see `SkipList::lower_bound`.
+///
+/// Besides the entries, a cursor can be before the first one or after the last
+/// one, where `key` returns `None`. Moving on from there comes back to the
+/// entries: `next` before the first one goes to the first, `prev` after the last
+/// one goes to the last.
+pub struct Cursor<'a, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    list: &'a SkipList<R, C, A>,
+    /// The current live node, the head before the first entry, `None` after the
+    /// last one.
+    node: Option<Nav<'a>>,
+}
+
+impl<'a, R, C, A> Cursor<'a, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    pub(crate) fn new(list: &'a SkipList<R, C, A>, node: Option<Nav<'a>>) -> Self {
+        Cursor { list, node }
+    }
+
+    /// Returns `true` if the cursor is on an entry.
+    pub fn valid(&self) -> bool {
+        self.node.is_some_and(|node| node != self.list.head())
+    }
+
+    /// Returns the key of the current entry, `None` before the first entry or
+    /// after the last one.
+    pub fn key(&self) -> Option<&'a [u8]> {
+        self.node.filter(|_| self.valid()).map(Nav::key)
+    }
+
+    /// Move to the first entry greater than or equal to `key`, like
+    /// `SkipList::lower_bound`, and returns its key, `None` past the last one.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![1, 3, 5]);
+    ///
+    /// let mut cursor = sl.cursor_back();
+    /// assert_eq!(cursor.seek(&[2]), Some(&[3u8][..]));
+    /// assert_eq!(cursor.seek(&[6]), None);
+    /// assert_eq!(cursor.prev(), Some(&[5u8][..]));
+    /// ```
+    pub fn seek(&mut self, key: &[u8]) -> Option<&'a [u8]> {
+        self.node = live_from(self.list.seek(key, &mut []));
+        self.key()
+    }
+
+    /// Move to the next entry and returns its key, `None` past the last one.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&'a [u8]> {
+        self.node = self.node.and_then(Nav::next_live);
+        self.key()
+    }
+
+    /// Move to the previous entry and returns its key, `None` before the first
+    /// one.
+    pub fn prev(&mut self) -> Option<&'a [u8]> {
+        let head = self.list.head();
+        self.node = Some(match self.node {
+            None => self.list.last_live_up_to(self.list.tail()),
+            Some(node) if node == head => head,
+            Some(node) => {
+                // Entries equal to the current one may come before it.
+                let before = self.list.less_than(node.key());
+                let prev = iter::successors(before.next(0), |x| x.next(0))
+                    .take_while(|&x| x != node)
+                    .filter(|x| !x.is_deleted())
+                    .last()
+                    .unwrap_or(before);
+                self.list.last_live_up_to(prev)
+            }
+        });
+        self.key()
+    }
+}
+
+/// Answers `contains` probes made in increasing key order, see
+/// `SkipList::membership_cursor`.
+///
+/// The cursor keeps the path to the last probe and resumes from it, climbing
+/// only as high as the distance to the next probe needs, so that a probe costs
+/// O(1 + log gap) comparisons instead of a walk from the head. This suits
+/// joining a sorted stream of keys against the list. A probe before the previous
+/// one is still answered, starting over from the head.
+pub struct MembershipCursor<'a, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    list: &'a SkipList<R, C, A>,
+    /// For every level, the last node linked there whose key is less than the
+    /// last probe.
+    prev: [Nav<'a>; K_MAX_HEIGHT],
+}
+
+impl<'a, R, C, A> MembershipCursor<'a, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    pub(crate) fn new(list: &'a SkipList<R, C, A>) -> Self {
+        MembershipCursor {
+            list,
+            prev: [list.head(); K_MAX_HEIGHT],
+        }
+    }
+
+    /// Returns `true` if the list holds an entry equal to `key`.
+    pub fn contains(&mut self, key: &[u8]) -> bool {
+        let head = self.list.head();
+        if self.prev[0] != head && !self.less(self.prev[0], key) {
+            self.prev = [head; K_MAX_HEIGHT];
+        }
+
+        // Climb while the probe is past the next node of the level above.
+        let height = self.list.get_max_height();
+        let mut top = 0;
+        while top + 1 < height && self.next_before(self.prev[top + 1], top + 1, key).is_some() {
+            top += 1;
+        }
+        let mut x = self.prev[top];
+        let mut advanced = false;
+        for level in (0..=top).rev() {
+            // A node reached above is past the kept one of the lower levels.
+            if !advanced {
+                x = self.prev[level];
+            }
+            while let Some(next) = self.next_before(x, level, key) {
+                x = next;
+                advanced = true;
+            }
+            self.prev[level] = x;
+        }
+
+        iter::successors(x.next(0), |x| x.next(0))
+            .take_while(|x| self.list.compare(x.key(), key) == Ordering::Equal)
+            .any(|x| !x.is_deleted())
+    }
+
+    /// Returns the next node of `x` at `level` if it is less than `key`.
+    fn next_before(&self, x: Nav<'a>, level: usize, key: &[u8]) -> Option<Nav<'a>> {
+        x.next(level).filter(|&next| self.less(next, key))
+    }
+
+    fn less(&self, node: Nav<'_>, key: &[u8]) -> bool {
+        self.list.compare(node.key(), key) == Ordering::Less
+    }
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use crate::{ArenaImpl, DefaultComparator, Random, SkipList};
+
+    #[test]
+    fn test_seek() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let mut cursor = sl.cursor_front();
+        assert_eq!(cursor.seek(&[1]), None);
+
+        for i in 0..50u8 {
+            sl.insert(vec![i * 2]);
+        }
+        sl.remove(&[20]);
+        let mut cursor = sl.cursor_back();
+        assert_eq!(cursor.seek(&[20]), Some(&[22u8][..]));
+        assert_eq!(cursor.prev(), Some(&[18u8][..]));
+        // Backwards too.
+        assert_eq!(cursor.seek(&[0]), Some(&[0u8][..]));
+        assert_eq!(cursor.seek(&[99]), None);
+        assert_eq!(cursor.prev(), Some(&[98u8][..]));
+
+        let found = unsafe { sl.find_raw(&[19], &mut []) };
+        assert_eq!(unsafe { &*found }.data.as_ref(), &[20]);
+    }
+
+    #[test]
+    fn test_bounds() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        assert_eq!(sl.lower_bound(&[1]).key(), None);
+        assert_eq!(sl.upper_bound(&[1]).prev(), None);
+        assert_eq!(sl.cursor_front().prev(), None);
+        assert_eq!(sl.cursor_back().next(), None);
+
+        for i in 0..50u8 {
+            sl.insert(vec![i * 2]);
+        }
+        sl.insert(vec![10u8]);
+        sl.insert(vec![10u8]);
+        sl.remove(&[20]);
+
+        assert_eq!(sl.lower_bound(&[10]).key(), Some(&[10u8][..]));
+        assert_eq!(sl.lower_bound(&[11]).key(), Some(&[12u8][..]));
+        assert_eq!(sl.lower_bound(&[19]).key(), Some(&[22u8][..]));
+        assert_eq!(sl.upper_bound(&[10]).key(), Some(&[12u8][..]));
+        assert_eq!(sl.upper_bound(&[18]).key(), Some(&[22u8][..]));
+        assert_eq!(sl.upper_bound(&[98]).key(), None);
+
+        // Both ways over the duplicates and the removed key.
+        let mut cursor = sl.upper_bound(&[8]);
+        let mut forward = vec![];
+        while let Some(key) = cursor.key() {
+            if key[0] > 24 {
+                break;
+            }
+            forward.push(key[0]);
+            cursor.next();
+        }
+        assert_eq!(forward, vec![10, 10, 10, 12, 14, 16, 18, 22, 24]);
+        let mut backward = vec![];
+        while let Some(key) = cursor.prev() {
+            if key[0] <= 8 {
+                break;
+            }
+            backward.push(key[0]);
+        }
+        forward.reverse();
+        assert_eq!(backward, forward);
+
+        assert_eq!(sl.cursor_front().key(), Some(&[0u8][..]));
+        assert_eq!(sl.cursor_back().key(), Some(&[98u8][..]));
+
+        // Off both ends and back.
+        let mut cursor = sl.lower_bound(&[0]);
+        assert_eq!(cursor.prev(), None);
+        assert!(!cursor.valid());
+        assert_eq!(cursor.prev(), None);
+        assert_eq!(cursor.next(), Some(&[0u8][..]));
+        let mut cursor = sl.lower_bound(&[99]);
+        assert_eq!(cursor.next(), None);
+        assert_eq!(cursor.prev(), Some(&[98u8][..]));
+        sl.remove(&[98]);
+        assert_eq!(sl.cursor_back().key(), Some(&[96u8][..]));
+    }
+
+    #[test]
+    fn test_membership_cursor() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..1000u32 {
+            sl.insert((i * 3).to_be_bytes().to_vec());
+        }
+        sl.insert(30u32.to_be_bytes().to_vec());
+        sl.remove(&60u32.to_be_bytes());
+        sl.remove(&30u32.to_be_bytes());
+
+        #[cfg(feature = "deterministic")]
+        sl.reset_op_counters();
+        let mut cursor = sl.membership_cursor();
+        for i in 0..3000u32 {
+            let key = i.to_be_bytes();
+            assert_eq!(cursor.contains(&key), sl.contains_key(&key), "{}", i);
+        }
+        // Going back starts over.
+        assert!(cursor.contains(&0u32.to_be_bytes()));
+        assert!(!cursor.contains(&60u32.to_be_bytes()));
+        assert!(cursor.contains(&2997u32.to_be_bytes()));
+        assert!(!cursor.contains(&3000u32.to_be_bytes()));
+
+        #[cfg(feature = "deterministic")]
+        {
+            // Dense probes cost a few comparisons each, lookups from the head
+            // a few per level.
+            let comparisons = |probe: &mut dyn FnMut(&[u8]) -> bool| {
+                sl.reset_op_counters();
+                for i in 0..3000u32 {
+                    probe(&i.to_be_bytes());
+                }
+                sl.op_counters().comparisons
+            };
+            let mut cursor = sl.membership_cursor();
+            let joined = comparisons(&mut |key| cursor.contains(key));
+            let looked_up = comparisons(&mut |key| sl.contains_key(key));
+            assert!(joined < 5 * 3000, "{}", joined);
+            assert!(joined * 3 < looked_up, "{} {}", joined, looked_up);
+        }
+    }
+}
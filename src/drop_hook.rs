@@ -0,0 +1,57 @@
+use std::sync::Mutex;
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+/// Callbacks to run once the owning skiplist is dropped.
+///
+/// This is stored as a field of the list so the callbacks fire exactly once, after
+/// the last `Arc` handle is gone, and move along with the list when it's frozen.
+#[derive(Default)]
+pub(crate) struct DropHooks {
+    callbacks: Mutex<Vec<Callback>>,
+}
+
+impl DropHooks {
+    pub(crate) fn push(&self, f: Callback) {
+        self.callbacks.lock().unwrap().push(f);
+    }
+
+    /// Move every registered callback into a new set of hooks.
+    pub(crate) fn take(&self) -> DropHooks {
+        let callbacks = self.callbacks.lock().unwrap().drain(..).collect();
+        DropHooks {
+            callbacks: Mutex::new(callbacks),
+        }
+    }
+}
+
+impl Drop for DropHooks {
+    fn drop(&mut self) {
+        let callbacks = match self.callbacks.get_mut() {
+            Ok(callbacks) => callbacks,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for f in callbacks.drain(..) {
+            f();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DropHooks;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_run_in_order() {
+        let order = Arc::new(Mutex::new(vec![]));
+        let hooks = DropHooks::default();
+        for i in 0..3 {
+            let order = order.clone();
+            hooks.push(Box::new(move || order.lock().unwrap().push(i)));
+        }
+        assert!(order.lock().unwrap().is_empty());
+        drop(hooks);
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,297 @@
+//! Endian-neutral encoding, the equivalent of LevelDB's `coding.h`.
+//!
+//! * Fixed-length numbers are encoded with the least significant byte first.
+//! * Varints store 7 bits per byte, the high bit set on every byte but the last.
+//! * Slices are prefixed with their length as a varint64, which reads like the
+//!   varint32 of LevelDB for slices shorter than 4GB.
+//! * `put_fixed64_ordered` encodes most significant byte first, so that encoded
+//!   numbers sort like the numbers under a bytewise comparator.
+//!
+//! The `get_*` functions read from the front of `src` and advance it past what
+//! they consumed. They return `None` on truncated or malformed input, in which
+//! case `src` is left as it was.
+use std::convert::TryFrom;
+
+pub fn put_fixed32(dst: &mut Vec<u8>, value: u32) {
+    dst.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn put_fixed64(dst: &mut Vec<u8>, value: u64) {
+    dst.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn put_fixed64_ordered(dst: &mut Vec<u8>, value: u64) {
+    dst.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn decode_fixed32(src: &[u8]) -> u32 {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&src[..4]);
+    u32::from_le_bytes(buf)
+}
+
+pub fn decode_fixed64(src: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&src[..8]);
+    u64::from_le_bytes(buf)
+}
+
+pub fn decode_fixed64_ordered(src: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&src[..8]);
+    u64::from_be_bytes(buf)
+}
+
+pub fn get_fixed32(src: &mut &[u8]) -> Option<u32> {
+    if src.len() < 4 {
+        return None;
+    }
+    let value = decode_fixed32(src);
+    *src = &src[4..];
+    Some(value)
+}
+
+pub fn get_fixed64(src: &mut &[u8]) -> Option<u64> {
+    if src.len() < 8 {
+        return None;
+    }
+    let value = decode_fixed64(src);
+    *src = &src[8..];
+    Some(value)
+}
+
+pub fn put_varint32(dst: &mut Vec<u8>, value: u32) {
+    put_varint64(dst, value as u64)
+}
+
+pub fn put_varint64(dst: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        dst.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    dst.push(value as u8);
+}
+
+/// Returns the number of bytes `put_varint64` uses for `value`.
+pub fn varint_length(mut value: u64) -> usize {
+    let mut len = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        len += 1;
+    }
+    len
+}
+
+pub fn get_varint32(src: &mut &[u8]) -> Option<u32> {
+    let (value, len) = decode_varint(src, 28)?;
+    *src = &src[len..];
+    Some(value as u32)
+}
+
+pub fn get_varint64(src: &mut &[u8]) -> Option<u64> {
+    let (value, len) = decode_varint(src, 63)?;
+    *src = &src[len..];
+    Some(value)
+}
+
+/// Decode a varint whose last byte is shifted by at most `max_shift` bits,
+/// returning it with its encoded length.
+fn decode_varint(src: &[u8], max_shift: u32) -> Option<(u64, usize)> {
+    let mut result = 0u64;
+    for (i, &byte) in src.iter().enumerate() {
+        let shift = 7 * i as u32;
+        if shift > max_shift {
+            return None;
+        }
+        result |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some((result, i + 1));
+        }
+    }
+    None
+}
+
+pub fn put_length_prefixed_slice(dst: &mut Vec<u8>, value: &[u8]) {
+    put_varint64(dst, value.len() as u64);
+    dst.extend_from_slice(value);
+}
+
+pub fn get_length_prefixed_slice<'a>(src: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let mut input = *src;
+    let len = usize::try_from(get_varint64(&mut input)?).ok()?;
+    if input.len() < len {
+        return None;
+    }
+    let (value, rest) = input.split_at(len);
+    *src = rest;
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed() {
+        let mut dst = vec![];
+        for v in 0..100_000u32 {
+            put_fixed32(&mut dst, v);
+        }
+        let mut src = &dst[..];
+        for v in 0..100_000u32 {
+            assert_eq!(get_fixed32(&mut src), Some(v));
+        }
+        assert!(src.is_empty());
+        assert_eq!(get_fixed32(&mut src), None);
+
+        let mut dst = vec![];
+        for power in 0..64 {
+            let v = 1u64 << power;
+            put_fixed64(&mut dst, v - 1);
+            put_fixed64(&mut dst, v);
+            put_fixed64(&mut dst, v + 1);
+        }
+        let mut src = &dst[..];
+        for power in 0..64 {
+            let v = 1u64 << power;
+            assert_eq!(get_fixed64(&mut src), Some(v - 1));
+            assert_eq!(get_fixed64(&mut src), Some(v));
+            assert_eq!(get_fixed64(&mut src), Some(v + 1));
+        }
+    }
+
+    #[test]
+    fn test_encoding_output() {
+        let mut dst = vec![];
+        put_fixed32(&mut dst, 0x0403_0201);
+        assert_eq!(dst, [0x01, 0x02, 0x03, 0x04]);
+
+        let mut dst = vec![];
+        put_fixed64(&mut dst, 0x0807_0605_0403_0201);
+        assert_eq!(dst, [0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08]);
+    }
+
+    #[test]
+    fn test_fixed64_ordered() {
+        let values = [0u64, 1, 255, 256, 1 << 32, u64::MAX - 1, u64::MAX];
+        let encoded: Vec<Vec<u8>> = values
+            .iter()
+            .map(|v| {
+                let mut dst = vec![];
+                put_fixed64_ordered(&mut dst, *v);
+                dst
+            })
+            .collect();
+        assert!(encoded.windows(2).all(|w| w[0] < w[1]));
+        for (v, e) in values.iter().zip(&encoded) {
+            assert_eq!(decode_fixed64_ordered(e), *v);
+        }
+    }
+
+    #[test]
+    fn test_varint32() {
+        let mut dst = vec![];
+        for i in 0..32 * 32u32 {
+            let v = (i / 32) << (i % 32);
+            put_varint32(&mut dst, v);
+        }
+        let mut src = &dst[..];
+        for i in 0..32 * 32u32 {
+            let expected = (i / 32) << (i % 32);
+            let before = src.len();
+            assert_eq!(get_varint32(&mut src), Some(expected));
+            assert_eq!(before - src.len(), varint_length(expected as u64));
+        }
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn test_varint64() {
+        let mut values = vec![0, 100, !0u64, !0u64 - 1];
+        for k in 0..64 {
+            let power = 1u64 << k;
+            values.push(power);
+            values.push(power - 1);
+            values.push(power + 1);
+        }
+        let mut dst = vec![];
+        for v in &values {
+            put_varint64(&mut dst, *v);
+        }
+        let mut src = &dst[..];
+        for v in &values {
+            let before = src.len();
+            assert_eq!(get_varint64(&mut src), Some(*v));
+            assert_eq!(before - src.len(), varint_length(*v));
+        }
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn test_varint32_overflow() {
+        let input: &[u8] = &[0x81, 0x82, 0x83, 0x84, 0x85, 0x11];
+        let mut src = input;
+        assert_eq!(get_varint32(&mut src), None);
+        assert_eq!(src, input);
+    }
+
+    #[test]
+    fn test_varint32_truncation() {
+        let large = (1u32 << 31) + 100;
+        let mut dst = vec![];
+        put_varint32(&mut dst, large);
+        for len in 0..dst.len() {
+            let mut src = &dst[..len];
+            assert_eq!(get_varint32(&mut src), None);
+        }
+        let mut src = &dst[..];
+        assert_eq!(get_varint32(&mut src), Some(large));
+    }
+
+    #[test]
+    fn test_varint64_overflow() {
+        let input: &[u8] = &[
+            0x81, 0x82, 0x83, 0x84, 0x85, 0x81, 0x82, 0x83, 0x84, 0x85, 0x11,
+        ];
+        let mut src = input;
+        assert_eq!(get_varint64(&mut src), None);
+    }
+
+    #[test]
+    fn test_varint64_truncation() {
+        let large = (1u64 << 63) + 100;
+        let mut dst = vec![];
+        put_varint64(&mut dst, large);
+        for len in 0..dst.len() {
+            let mut src = &dst[..len];
+            assert_eq!(get_varint64(&mut src), None);
+        }
+        let mut src = &dst[..];
+        assert_eq!(get_varint64(&mut src), Some(large));
+    }
+
+    #[test]
+    fn test_strings() {
+        let mut dst = vec![];
+        put_length_prefixed_slice(&mut dst, b"");
+        put_length_prefixed_slice(&mut dst, b"foo");
+        put_length_prefixed_slice(&mut dst, b"bar");
+        let long = vec![b'x'; 200];
+        put_length_prefixed_slice(&mut dst, &long);
+
+        let mut src = &dst[..];
+        assert_eq!(get_length_prefixed_slice(&mut src), Some(&b""[..]));
+        assert_eq!(get_length_prefixed_slice(&mut src), Some(&b"foo"[..]));
+        assert_eq!(get_length_prefixed_slice(&mut src), Some(&b"bar"[..]));
+        assert_eq!(get_length_prefixed_slice(&mut src), Some(&long[..]));
+        assert!(src.is_empty());
+        let mut prefix = vec![];
+        put_varint32(&mut prefix, 200);
+        assert_eq!(&dst[9..11], &prefix[..]);
+
+        let truncated: &[u8] = &[3, b'a', b'b'];
+        let mut src = truncated;
+        assert_eq!(get_length_prefixed_slice(&mut src), None);
+        assert_eq!(src, truncated);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::skipnode::{Node, NodeRef, Splice};
+use crate::{
+    Arena, BaseComparator, ComparatorViolation, InsertOutcome, RandomGenerator, SkipList,
+};
+use bytes::Bytes;
+use std::fmt;
+use std::ptr::NonNull;
+use std::sync::MutexGuard;
+
+/// A view into a single entry of a skiplist, which may either be vacant or
+/// occupied, see `SkipList::entry`.
+///
+/// The search done by `SkipList::entry` is kept along with the entry, so
+/// inserting into a vacant entry doesn't search again.
+pub enum Entry<'a, 'k, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    Occupied(OccupiedEntry<'a, R, C, A>),
+    Vacant(VacantEntry<'a, 'k, R, C, A>),
+}
+
+impl<'a, 'k, R, C, A> Entry<'a, 'k, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    /// Returns the key of the entry: the stored one if occupied, the searched one
+    /// if vacant.
+    pub fn key(&self) -> &[u8] {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Insert a copy of the searched key if the entry is vacant, and returns the
+    /// occupied entry.
+    pub fn or_insert(self) -> OccupiedEntry<'a, R, C, A> {
+        match self {
+            Entry::Occupied(entry) => entry,
+            Entry::Vacant(entry) => entry.insert(),
+        }
+    }
+
+    /// Insert the key returned by `f` if the entry is vacant, and returns the
+    /// occupied entry. This avoids building an owned key when it is already
+    /// stored. The key returned by `f` must be equal to the searched one.
+    pub fn or_insert_with<K, F>(self, f: F) -> OccupiedEntry<'a, R, C, A>
+    where
+        K: Into<Bytes>,
+        F: FnOnce() -> K,
+    {
+        match self {
+            Entry::Occupied(entry) => entry,
+            Entry::Vacant(entry) => entry.insert_key(f()),
+        }
+    }
+
+    /// Call `f` with the entry if it is occupied, e.g. to update its metadata
+    /// byte, and returns the entry.
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut OccupiedEntry<'a, R, C, A>),
+    {
+        if let Entry::Occupied(entry) = &mut self {
+            f(entry);
+        }
+        self
+    }
+}
+
+impl<'a, 'k, R, C, A> fmt::Debug for Entry<'a, 'k, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Entry::Occupied(entry) => f.debug_tuple("Occupied").field(&entry.key()).finish(),
+            Entry::Vacant(entry) => f.debug_tuple("Vacant").field(&entry.key()).finish(),
+        }
+    }
+}
+
+/// An entry of a skiplist holding a key, see `Entry`.
+pub struct OccupiedEntry<'a, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    list: &'a SkipList<R, C, A>,
+    node: &'a Node,
+}
+
+impl<'a, R, C, A> OccupiedEntry<'a, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    pub(crate) fn new(list: &'a SkipList<R, C, A>, node: &'a Node) -> Self {
+        OccupiedEntry { list, node }
+    }
+
+    /// Returns the stored key.
+    pub fn key(&self) -> &'a [u8] {
+        self.node.data.as_ref()
+    }
+
+    /// Returns a handle to the entry, see `NodeRef`.
+    pub fn handle(&self) -> NodeRef {
+        NodeRef::new(self.list.id(), NonNull::from(self.node))
+    }
+
+    /// Returns the metadata byte of the entry, see `SkipList::meta`.
+    pub fn meta(&self) -> u8 {
+        self.node.get_meta()
+    }
+
+    pub fn set_meta(&mut self, meta: u8) {
+        self.node.set_meta(meta);
+    }
+}
+
+/// An entry of a skiplist with no key equal to the searched one, see `Entry`.
+pub struct VacantEntry<'a, 'k, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    list: &'a SkipList<R, C, A>,
+    key: &'k [u8],
+    /// The writer lock of the list, holding the search path of the key, where
+    /// it gets linked.
+    writer: MutexGuard<'a, Splice>,
+}
+
+impl<'a, 'k, R, C, A> VacantEntry<'a, 'k, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    pub(crate) fn new(
+        list: &'a SkipList<R, C, A>,
+        key: &'k [u8],
+        writer: MutexGuard<'a, Splice>,
+    ) -> Self {
+        VacantEntry { list, key, writer }
+    }
+
+    /// Returns the searched key.
+    pub fn key(&self) -> &'k [u8] {
+        self.key
+    }
+
+    /// Insert a copy of the searched key.
+    pub fn insert(self) -> OccupiedEntry<'a, R, C, A> {
+        let key = Bytes::copy_from_slice(self.key);
+        self.insert_key(key)
+    }
+
+    /// Insert `key`, which must be equal to the searched key, without copying it.
+    /// # Panics
+    /// If `ComparatorCheck::Error` drops the key, or if the list is full and its
+    /// `OverflowPolicy` drops it.
+    pub fn insert_key(self, key: impl Into<Bytes>) -> OccupiedEntry<'a, R, C, A> {
+        let key = key.into();
+        let list = self.list;
+        let (node, outcome) = self.insert_outcome(key.clone());
+        // There is no entry to return.
+        match outcome {
+            InsertOutcome::Violation => {
+                let neighbour = unsafe { node.as_ref() }.data.clone();
+                let list = list.name().map(String::from);
+                panic!(
+                    "{}",
+                    ComparatorViolation {
+                        key,
+                        neighbour,
+                        list
+                    }
+                );
+            }
+            InsertOutcome::Full => panic!("{:?} is full", list),
+            _ => OccupiedEntry::new(list, unsafe { &*node.as_ptr() }),
+        }
+    }
+
+    /// Insert `key` like `insert_key`, returning the node and what the list did
+    /// instead of panicking.
+    pub(crate) fn insert_outcome(mut self, key: Bytes) -> (NonNull<Node>, InsertOutcome) {
+        debug_assert!(self.list.inner().cmp.eq(&key, self.key));
+        self.list.insert_at(&mut self.writer, key, Bytes::new())
+    }
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use super::Entry;
+    use crate::{ArenaImpl, DefaultComparator, Random, SkipList};
+
+    fn list() -> SkipList<Random, DefaultComparator, ArenaImpl> {
+        SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        )
+    }
+
+    #[test]
+    fn test_entry() {
+        let sl = list();
+        for round in 0..2 {
+            for i in (0..100u32).rev() {
+                let key = i.to_be_bytes();
+                let mut built = false;
+                let entry = sl.entry(&key).or_insert_with(|| {
+                    built = true;
+                    key.to_vec()
+                });
+                assert_eq!(entry.key(), &key);
+                // The key is only built when it is missing.
+                assert_eq!(built, round == 0);
+            }
+        }
+        assert_eq!(sl.len(), 100);
+        assert!(sl.is_sorted().is_ok());
+
+        match sl.entry(&[1]) {
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.key(), &[1]);
+                let entry = entry.insert();
+                assert_eq!(entry.key(), &[1]);
+            }
+            Entry::Occupied(_) => panic!("[1] isn't in the list"),
+        }
+        let entry = sl.entry(&[1]);
+        assert_eq!(format!("{:?}", entry), "Occupied([1])");
+        let handle = entry.and_modify(|e| e.set_meta(7)).or_insert().handle();
+        assert_eq!(sl.meta(handle), Some(7));
+        assert_eq!(sl.len(), 101);
+
+        // Removed entries are vacant.
+        sl.remove(&[1]);
+        assert!(matches!(sl.entry(&[1]), Entry::Vacant(_)));
+        let entry = sl.entry(&[1]).or_insert();
+        assert_eq!(entry.meta(), 0);
+        assert_eq!(sl.len(), 101);
+    }
+
+    #[test]
+    #[cfg(feature = "deterministic")]
+    fn test_single_search() {
+        let keys: Vec<_> = (0..1000u32)
+            .map(|i| (i * 7919 % 1000).to_be_bytes())
+            .collect();
+
+        let sl = list();
+        for key in &keys {
+            if !sl.contains_key(key) {
+                sl.insert(key.to_vec());
+            }
+        }
+        let twice = sl.op_counters().comparisons;
+
+        let sl = list();
+        for key in &keys {
+            sl.entry(key).or_insert();
+        }
+        let once = sl.op_counters().comparisons;
+        assert!(once * 3 < twice * 2, "{} vs {}", once, twice);
+    }
+}
@@ -0,0 +1,299 @@
+use bytes::Bytes;
+use std::error::Error;
+use std::fmt;
+
+/// Errors returned by `SkipListIter` when it is used in a way that has no meaning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IterError {
+    /// The iterator isn't positioned at an entry, either because it was never
+    /// positioned or because it moved past either end of the list.
+    Invalid,
+}
+
+impl fmt::Display for IterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IterError::Invalid => write!(f, "iterator is not positioned at an entry"),
+        }
+    }
+}
+
+impl Error for IterError {}
+
+/// Errors returned when a `SkipListConfig` can't be used to build a skiplist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    /// `max_height` must be between 1 and `K_MAX_HEIGHT`.
+    InvalidMaxHeight(usize),
+    /// `branching_factor` must be at least 2.
+    InvalidBranchingFactor(u32),
+    /// `arena_block_size` must not be 0.
+    InvalidArenaBlockSize(usize),
+    /// The `segment_level` of a prefix bloom must be between 1 and `max_height - 1`.
+    InvalidSegmentLevel(usize),
+    /// The `bits_per_segment` of a prefix bloom must not be 0.
+    InvalidBloomSize(usize),
+    /// `hot_key_sampling` must not be 0.
+    InvalidSamplingRate(u32),
+    /// `max_entries` must not be 0.
+    InvalidMaxEntries(usize),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::InvalidMaxHeight(h) => write!(f, "invalid max height: {}", h),
+            ConfigError::InvalidBranchingFactor(b) => write!(f, "invalid branching factor: {}", b),
+            ConfigError::InvalidArenaBlockSize(s) => write!(f, "invalid arena block size: {}", s),
+            ConfigError::InvalidSegmentLevel(l) => write!(f, "invalid bloom segment level: {}", l),
+            ConfigError::InvalidBloomSize(s) => write!(f, "invalid bloom size: {}", s),
+            ConfigError::InvalidSamplingRate(r) => write!(f, "invalid sampling rate: {}", r),
+            ConfigError::InvalidMaxEntries(n) => write!(f, "invalid max entries: {}", n),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Names the list an error comes from, if it has one, see `SkipListConfig::name`.
+struct InList<'a>(&'a Option<String>);
+
+impl fmt::Display for InList<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(name) => write!(f, " in {}", name),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Two neighbours on the bottom level of a skiplist that the comparator says are in
+/// the wrong order, see `SkipList::is_sorted`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderError {
+    pub prev: Bytes,
+    pub next: Bytes,
+    /// The name of the list.
+    pub list: Option<String>,
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "keys out of order{}: {:?} is before {:?}",
+            InList(&self.list),
+            self.prev.as_ref(),
+            self.next.as_ref()
+        )
+    }
+}
+
+impl Error for OrderError {}
+
+/// A key rejected by `DuplicatePolicy::Error` because an equal one is already in
+/// the list, see `SkipList::try_insert`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateKeyError {
+    pub key: Bytes,
+    /// The name of the list.
+    pub list: Option<String>,
+}
+
+impl fmt::Display for DuplicateKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "duplicate key{}: {:?}",
+            InList(&self.list),
+            self.key.as_ref()
+        )
+    }
+}
+
+impl Error for DuplicateKeyError {}
+
+/// A key that the comparator orders inconsistently with `neighbour`, the entry
+/// next to the place found for it in the list, see `ComparatorCheck`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComparatorViolation {
+    pub key: Bytes,
+    pub neighbour: Bytes,
+    /// The name of the list.
+    pub list: Option<String>,
+}
+
+impl fmt::Display for ComparatorViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "comparator violation{}: {:?} is ordered inconsistently with {:?}",
+            InList(&self.list),
+            self.key.as_ref(),
+            self.neighbour.as_ref()
+        )
+    }
+}
+
+impl Error for ComparatorViolation {}
+
+/// A list given its own entries to `SkipList::ingest` or `SkipList::merge`,
+/// through the same handle or another one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SameListError {
+    /// The name of the list.
+    pub list: Option<String>,
+}
+
+impl fmt::Display for SameListError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "list merged into itself{}", InList(&self.list))
+    }
+}
+
+impl Error for SameListError {}
+
+/// Errors returned when a stored value can't be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionError {
+    /// The value doesn't start with a known tag.
+    UnknownTag(u8),
+    /// The compressor rejected the compressed data.
+    Corrupted,
+}
+
+impl fmt::Display for CompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressionError::UnknownTag(t) => write!(f, "unknown compression tag: {}", t),
+            CompressionError::Corrupted => write!(f, "corrupted compressed value"),
+        }
+    }
+}
+
+impl Error for CompressionError {}
+
+/// Errors returned when a `SkipMap` can't be converted to a crossbeam map.
+#[cfg(feature = "crossbeam")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversionError {
+    /// A key is in the map twice.
+    DuplicateKey(DuplicateKeyError),
+    /// A value can't be decompressed.
+    Compression(CompressionError),
+}
+
+#[cfg(feature = "crossbeam")]
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConversionError::DuplicateKey(err) => err.fmt(f),
+            ConversionError::Compression(err) => err.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "crossbeam")]
+impl Error for ConversionError {}
+
+#[cfg(feature = "crossbeam")]
+impl From<DuplicateKeyError> for ConversionError {
+    fn from(err: DuplicateKeyError) -> Self {
+        ConversionError::DuplicateKey(err)
+    }
+}
+
+#[cfg(feature = "crossbeam")]
+impl From<CompressionError> for ConversionError {
+    fn from(err: CompressionError) -> Self {
+        ConversionError::Compression(err)
+    }
+}
+
+/// Returned by `MemTableSet::try_insert` when a write should wait for a flush,
+/// see `StallLimits`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WouldStall {
+    /// The lists of the set use `usage` bytes, at least `limit`.
+    Memory { usage: u64, limit: u64 },
+    /// `count` frozen lists wait to be flushed, at least `limit`.
+    Immutables { count: usize, limit: usize },
+}
+
+impl fmt::Display for WouldStall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WouldStall::Memory { usage, limit } => {
+                write!(f, "memtables use {} bytes, limit is {}", usage, limit)
+            }
+            WouldStall::Immutables { count, limit } => {
+                write!(
+                    f,
+                    "{} memtables wait for a flush, limit is {}",
+                    count, limit
+                )
+            }
+        }
+    }
+}
+
+impl Error for WouldStall {}
+
+/// A problem found by `SkipList::self_check` in the comparator or the random
+/// generator of a list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelfCheckError {
+    /// The comparator doesn't find `key` equal to itself.
+    NotReflexive { key: Bytes },
+    /// Comparing `a` with `b` doesn't give the reverse of comparing `b` with `a`.
+    NotAntisymmetric { a: Bytes, b: Bytes },
+    /// `a` was sorted before `b` although the comparator puts it after, so the
+    /// comparator isn't transitive.
+    NotTransitive { a: Bytes, b: Bytes },
+    /// A key inserted in the scratch list can't be found again.
+    LostKey { key: Bytes },
+    /// Iterating over the scratch list returned `iterated` keys out of `inserted`.
+    LostEntries { inserted: usize, iterated: usize },
+    /// `tall` towers out of `draws` got more than one level, too far from what
+    /// the branching factor gives for the generator to be random.
+    SkewedHeights { draws: usize, tall: usize },
+}
+
+impl fmt::Display for SelfCheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SelfCheckError::NotReflexive { key } => {
+                write!(f, "key not equal to itself: {:?}", key.as_ref())
+            }
+            SelfCheckError::NotAntisymmetric { a, b } => write!(
+                f,
+                "comparing {:?} and {:?} depends on the order of the arguments",
+                a.as_ref(),
+                b.as_ref()
+            ),
+            SelfCheckError::NotTransitive { a, b } => write!(
+                f,
+                "comparator isn't transitive: {:?} sorted before {:?}",
+                a.as_ref(),
+                b.as_ref()
+            ),
+            SelfCheckError::LostKey { key } => {
+                write!(f, "inserted key not found: {:?}", key.as_ref())
+            }
+            SelfCheckError::LostEntries { inserted, iterated } => {
+                write!(
+                    f,
+                    "iterated over {} of {} inserted keys",
+                    iterated, inserted
+                )
+            }
+            SelfCheckError::SkewedHeights { draws, tall } => write!(
+                f,
+                "{} of {} random towers are taller than one level",
+                tall, draws
+            ),
+        }
+    }
+}
+
+impl Error for SelfCheckError {}
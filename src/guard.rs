@@ -0,0 +1,163 @@
+use crate::skipnode::{live_from, Nav, Node};
+use crate::{Arena, BaseComparator, RandomGenerator, SkipList};
+use bytes::Bytes;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::atomic::Ordering;
+
+/// Keeps the nodes of a skiplist alive while it exists.
+///
+/// Keys returned by the lookups of a guard are `StableRef`s borrowing from the
+/// guard, so the type system checks they aren't used after the guard is dropped.
+/// Nodes are never reclaimed while a guard exists, even if the list is cleared
+/// or an entry removed: the keys simply stop being reachable through the list.
+pub struct Guard<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    list: SkipList<R, C, A>,
+}
+
+impl<R, C, A> Guard<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    pub(crate) fn new(list: SkipList<R, C, A>) -> Self {
+        list.inner()
+            .guards
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Guard { list }
+    }
+
+    /// Returns the stored key equal to `key`.
+    pub fn get(&self, key: &[u8]) -> Option<StableRef<'_>> {
+        self.list.find_equal(key).map(StableRef::new)
+    }
+
+    /// Returns the first key greater than or equal to `key`.
+    pub fn lower_bound(&self, key: &[u8]) -> Option<StableRef<'_>> {
+        let x = live_from(self.list.seek(key, &mut []))?;
+        Some(StableRef::new(x.node()))
+    }
+
+    /// Iterate over every key of the list, in order.
+    pub fn iter(&self) -> GuardIter<'_> {
+        GuardIter {
+            node: self.list.head(),
+        }
+    }
+}
+
+/// Iterator over the keys of a list, see `Guard::iter`.
+pub struct GuardIter<'g> {
+    node: Nav<'g>,
+}
+
+impl<'g> Iterator for GuardIter<'g> {
+    type Item = StableRef<'g>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.node = self.node.next_live()?;
+        Some(StableRef::new(self.node.node()))
+    }
+}
+
+impl<R, C, A> Drop for Guard<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    fn drop(&mut self) {
+        self.list.inner().guards.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A key borrowed from a skiplist, valid as long as the `Guard` it came from.
+#[derive(Clone, Copy)]
+pub struct StableRef<'g> {
+    node: &'g Node,
+}
+
+impl<'g> StableRef<'g> {
+    fn new(node: &'g Node) -> Self {
+        StableRef { node }
+    }
+
+    /// Returns the key as `Bytes` sharing the buffer of the node, which can
+    /// outlive the guard.
+    pub fn to_bytes(&self) -> Bytes {
+        self.node.data.clone()
+    }
+
+    /// Returns the metadata byte of the entry, see `SkipList::meta`.
+    pub fn meta(&self) -> u8 {
+        self.node.get_meta()
+    }
+}
+
+impl<'g> Deref for StableRef<'g> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.node.data.as_ref()
+    }
+}
+
+impl<'g> AsRef<[u8]> for StableRef<'g> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<'g> PartialEq for StableRef<'g> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<'g> fmt::Debug for StableRef<'g> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", &**self)
+    }
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use crate::{ArenaImpl, DefaultComparator, Random, SkipList};
+
+    #[test]
+    fn test_guard() {
+        let mut sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        sl.extend(vec![1, 3, 5]);
+
+        let guard = sl.pin();
+        assert_eq!(sl.active_guards(), 1);
+        let three = guard.get(&[3]).unwrap();
+        assert_eq!(&*three, &[3]);
+        assert!(guard.get(&[4]).is_none());
+        assert_eq!(&*guard.lower_bound(&[4]).unwrap(), &[5]);
+        assert!(guard.lower_bound(&[6]).is_none());
+        let keys: Vec<_> = guard.iter().map(|k| k.to_vec()).collect();
+        assert_eq!(keys, vec![vec![1], vec![3], vec![5]]);
+        let shared = three.to_bytes();
+        assert_eq!(shared.as_ptr(), three.as_ptr());
+
+        // The key stays readable after the list is cleared and the last list
+        // handle is gone, as long as the guard is alive.
+        sl.clear();
+        assert!(!sl.contains_key(&[3]));
+        drop(sl);
+        assert_eq!(three.as_ref(), &[3]);
+        drop(guard);
+        assert_eq!(shared.as_ref(), &[3]);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::skipnode::Node;
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+thread_local! {
+    /// Lookups made by this thread, whichever the list.
+    static LOOKUPS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Sampled access counts of the entries of a skiplist, kept in the nodes.
+///
+/// Only one lookup out of `rate` made by a thread is recorded, so readers mostly
+/// pay for a thread local increment, and a sampled one for an atomic increment
+/// in the node found, shared with no other entry. The counts go away with their
+/// nodes once unlinked, and take no memory besides the nodes. Counts are scaled
+/// back by `rate` when reported.
+pub(crate) struct AccessCounts {
+    rate: u64,
+}
+
+impl AccessCounts {
+    pub(crate) fn new(rate: u32) -> Self {
+        AccessCounts {
+            rate: u64::from(rate),
+        }
+    }
+
+    /// Count a lookup that found the entry `node`.
+    pub(crate) fn record(&self, node: &Node) {
+        let lookup = LOOKUPS.with(|lookups| {
+            let lookup = lookups.get();
+            lookups.set(lookup.wrapping_add(1));
+            lookup
+        });
+        if lookup.is_multiple_of(self.rate) {
+            node.add_hit();
+        }
+    }
+
+    /// Returns the `k` most accessed entries among `nodes` and their estimated
+    /// access counts, most accessed first. Entries accessed as often keep the
+    /// order of `nodes`.
+    pub(crate) fn top<'a>(
+        &self,
+        nodes: impl Iterator<Item = &'a Node>,
+        k: usize,
+    ) -> Vec<(&'a Node, u64)> {
+        // The `k` most accessed so far, the least accessed on top.
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        for (i, node) in nodes.enumerate() {
+            let hits = node.hits();
+            if hits > 0 {
+                heap.push((Reverse(hits), i, node as *const Node));
+                if heap.len() > k {
+                    heap.pop();
+                }
+            }
+        }
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|(Reverse(hits), _, node)| (unsafe { &*node }, hits * self.rate))
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use super::AccessCounts;
+    use crate::skipnode::Node;
+    use crate::ArenaImpl;
+    use bytes::Bytes;
+
+    #[test]
+    fn test_sampling() {
+        const KEYS: [u8; 3] = [0, 1, 2];
+        let arena = ArenaImpl::new();
+        let nodes: Vec<_> = (0..KEYS.len())
+            .map(|i| unsafe { Node::new(Bytes::from_static(&KEYS[i..=i]), 1, &arena).as_ref() })
+            .collect();
+        let counts = AccessCounts::new(4);
+        for _ in 0..400 {
+            counts.record(nodes[1]);
+        }
+        for _ in 0..40 {
+            counts.record(nodes[2]);
+        }
+        let top = |k| -> Vec<_> {
+            counts
+                .top(nodes.iter().copied(), k)
+                .into_iter()
+                .map(|(node, count)| (node.data[0], count))
+                .collect()
+        };
+        assert_eq!(top(1), vec![(1, 400)]);
+        assert_eq!(top(5), vec![(1, 400), (2, 40)]);
+        assert!(top(0).is_empty());
+    }
+}
@@ -0,0 +1,615 @@
+use crate::drop_hook::DropHooks;
+use crate::encoding::put_length_prefixed_slice;
+use crate::skiplist::{fmt_keys, Iter};
+use crate::skipnode::{Nav, Node, NodePtr, Nodes};
+use crate::{Arena, BaseComparator, K_MAX_HEIGHT};
+use bytes::Bytes;
+use std::cmp;
+use std::fmt;
+use std::io::{self, Write};
+use std::iter;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::Arc;
+
+/// A read-only skiplist produced by `SkipList::freeze`.
+///
+/// It only keeps what readers need: the node graph, the comparator and the arena
+/// backing the nodes. The random generator used to pick tower heights is dropped,
+/// and no write method exists, so a frozen memtable can be shared with readers
+/// while it is waiting to be flushed.
+pub struct ImmutableSkipListInner<C, A>
+where
+    C: BaseComparator,
+    A: Arena,
+{
+    id: u64,
+    name: Option<String>,
+    /// Drops the keys and values of the nodes, so it comes before the arena and
+    /// `on_drop`, which may keep the arenas of merged lists.
+    nodes: Arc<Nodes>,
+    max_height: usize,
+    len: u64,
+    data_size: u64,
+    cmp: C,
+    arena: A,
+    on_drop: DropHooks,
+}
+
+// Nothing is ever written once frozen, so a panic can't leave the list half
+// updated. Only the injected comparator and arena need to be checked.
+impl<C, A> UnwindSafe for ImmutableSkipListInner<C, A>
+where
+    C: BaseComparator + RefUnwindSafe,
+    A: Arena + RefUnwindSafe,
+{
+}
+
+impl<C, A> RefUnwindSafe for ImmutableSkipListInner<C, A>
+where
+    C: BaseComparator + RefUnwindSafe,
+    A: Arena + RefUnwindSafe,
+{
+}
+
+pub struct ImmutableSkipList<C, A>
+where
+    C: BaseComparator,
+    A: Arena,
+{
+    inner: Arc<ImmutableSkipListInner<C, A>>,
+}
+
+impl<C, A> Clone for ImmutableSkipList<C, A>
+where
+    C: BaseComparator,
+    A: Arena,
+{
+    fn clone(&self) -> Self {
+        ImmutableSkipList {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<C, A> ImmutableSkipList<C, A>
+where
+    C: BaseComparator,
+    A: Arena,
+{
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        id: u64,
+        name: Option<String>,
+        nodes: Arc<Nodes>,
+        max_height: usize,
+        len: u64,
+        data_size: u64,
+        cmp: C,
+        arena: A,
+        on_drop: DropHooks,
+    ) -> Self {
+        ImmutableSkipList {
+            inner: Arc::new(ImmutableSkipListInner {
+                id,
+                name,
+                nodes,
+                max_height,
+                len,
+                data_size,
+                cmp,
+                arena,
+                on_drop,
+            }),
+        }
+    }
+
+    /// Returns the id of the list it was frozen from, see `SkipList::id`.
+    pub fn id(&self) -> u64 {
+        self.inner.id
+    }
+
+    /// Returns the name of the list it was frozen from, see `SkipList::name`.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name.as_deref()
+    }
+
+    /// Returns the number of elements in the skiplist.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.inner.len
+    }
+
+    /// Returns `true` if the skiplist is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn comparator(&self) -> &C {
+        &self.inner.cmp
+    }
+
+    #[inline]
+    pub fn get_max_height(&self) -> usize {
+        self.inner.max_height
+    }
+
+    /// Returns the memory allocated by the arena, which holds the nodes.
+    pub fn memory_size(&self) -> u64 {
+        self.inner.arena.memory_usage()
+    }
+
+    /// Returns an estimate of the total memory used by the skiplist, see
+    /// `SkipList::memory_usage`.
+    pub fn memory_usage(&self) -> u64 {
+        self.memory_size() + self.inner.data_size
+    }
+
+    /// Register a callback that runs exactly once, after the last handle to the
+    /// list has been dropped. See `SkipList::on_drop`.
+    pub fn on_drop(&self, f: impl FnOnce() + Send + 'static) {
+        self.inner.on_drop.push(Box::new(f));
+    }
+
+    /// Returns `true` if the skiplist contains an entry equal to `key`.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.find_equal(key).is_some()
+    }
+
+    /// Returns the stored key equal to `key`, if any. See `SkipList::get`.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.find_equal(key).map(|node| node.data.as_ref())
+    }
+
+    /// Returns the stored key equal to `key` as `Bytes`, without copying it.
+    pub fn get_bytes(&self, key: &[u8]) -> Option<Bytes> {
+        self.find_equal(key).map(|node| node.data.clone())
+    }
+
+    /// Returns the first live node equal to `key`.
+    fn find_equal(&self, key: &[u8]) -> Option<&Node> {
+        iter::successors(self.find_greater_or_equal(key), |x| x.next(0))
+            .take_while(|x| self.is_equal(key, x.key()))
+            .find(|x| !x.is_deleted())
+            .map(Nav::node)
+    }
+
+    /// Returns the newest node equal to `key`, even if it was removed, so that a
+    /// tombstone can shadow older lists.
+    #[cfg(feature = "memtable")]
+    pub(crate) fn find_newest(&self, key: &[u8]) -> Option<&Node> {
+        self.find_greater_or_equal(key)
+            .filter(|x| self.is_equal(key, x.key()))
+            .map(Nav::node)
+    }
+
+    fn is_equal(&self, a: &[u8], b: &[u8]) -> bool {
+        self.inner.cmp.compare(a, b) == cmp::Ordering::Equal
+    }
+
+    fn find_greater_or_equal(&self, key: &[u8]) -> Option<Nav<'_>> {
+        let mut x = self.head();
+        let mut level = self.get_max_height() - 1;
+        loop {
+            let next = x.next(level);
+            match next {
+                Some(next) if self.inner.cmp.compare(next.key(), key) == cmp::Ordering::Less => {
+                    x = next
+                }
+                _ if level == 0 => return next,
+                _ => level -= 1,
+            }
+        }
+    }
+
+    /// Iterate over the keys in order, see `SkipList::keys`.
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.into_iter().map(|node| node.data.as_ref())
+    }
+
+    /// Every node of the list, removed ones included.
+    #[cfg(feature = "memtable")]
+    pub(crate) fn nodes(&self) -> impl Iterator<Item = &Node> {
+        iter::successors(self.head().next(0), |x| x.next(0)).map(Nav::node)
+    }
+
+    fn head(&self) -> Nav<'_> {
+        unsafe { self.inner.nodes.head().nav() }
+    }
+
+    /// Copy every entry into a freshly created arena and rebuild the towers with
+    /// deterministic heights: the i-th entry gets one level for every factor of 4
+    /// in `i`, which is the shape a perfectly balanced list with a branching
+    /// factor of 4 would have. Space wasted at the tail of arena blocks by the
+    /// mutable list is not carried over. Drop callbacks move to the compacted list,
+    /// see `optimize_for_scan`.
+    ///
+    /// Like `optimize_for_scan`, the nodes end up in key order in the arena.
+    pub fn compact(self) -> Self
+    where
+        C: Clone,
+        A: Default,
+    {
+        self.relocate(|i, _| balanced_height(i as u64 + 1, 4, K_MAX_HEIGHT))
+    }
+
+    /// Copy every entry into a freshly created arena, in key order, keeping the
+    /// height of its tower. Nodes of the mutable list sit in the arena in the
+    /// order they were inserted, so a scan jumps all over it; once relocated a
+    /// full scan reads the nodes mostly sequentially, block after block. Keys
+    /// and values are shared, not copied. Removed entries are left behind.
+    ///
+    /// Drop callbacks move to the new list if this was the last handle. Otherwise
+    /// they stay with the other handles, which may still read nodes kept alive
+    /// by the callbacks, and run once they are all dropped.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+    /// sl.extend((0..100).rev());
+    /// let imm = sl.freeze().ok().unwrap().optimize_for_scan();
+    /// let nodes: Vec<*const _> = (&imm).into_iter().map(|n| n as *const _).collect();
+    /// assert!(nodes.windows(2).all(|w| w[0] < w[1]));
+    /// ```
+    pub fn optimize_for_scan(self) -> Self
+    where
+        C: Clone,
+        A: Default,
+    {
+        self.relocate(|_, node| node.height())
+    }
+
+    /// Copy the live entries in key order into a new arena, the i-th (0-based)
+    /// one with a tower of `height(i, node)` levels.
+    fn relocate(mut self, mut height: impl FnMut(usize, &Node) -> usize) -> Self
+    where
+        C: Clone,
+        A: Default,
+    {
+        let arena = A::default();
+        let nodes = Arc::new(Nodes::new(Node::head(&arena)));
+        let head = nodes.head();
+        // The nodes are read through the new list, which takes the arena.
+        let mut tails = [unsafe { head.nav() }; K_MAX_HEIGHT];
+        let mut max_height = 1;
+        for (i, node) in (&self).into_iter().enumerate() {
+            let height = height(i, node);
+            max_height = cmp::max(max_height, height);
+            let n = Node::with_value(node.data.clone(), node.value().clone(), height, &arena);
+            // Nobody else sees the node before it is linked.
+            #[cfg(feature = "timestamps")]
+            unsafe {
+                std::ptr::addr_of_mut!((*n.as_ptr()).inserted).write(node.inserted)
+            };
+            let n = unsafe { NodePtr::new(n).nav() };
+            n.set_meta(node.get_meta());
+            n.set_count(node.count());
+            for (level, tail) in tails.iter_mut().enumerate().take(height) {
+                tail.set_next(level, Some(n));
+                *tail = n;
+            }
+        }
+        let on_drop = match Arc::get_mut(&mut self.inner) {
+            Some(inner) => inner.on_drop.take(),
+            None => DropHooks::default(),
+        };
+        ImmutableSkipList::new(
+            self.id(),
+            self.inner.name.clone(),
+            nodes,
+            max_height,
+            self.len(),
+            self.inner.data_size,
+            self.inner.cmp.clone(),
+            arena,
+            on_drop,
+        )
+    }
+
+    /// Write the entries from the largest key to the smallest, e.g. for an on-disk
+    /// format storing keys in descending order. Returns the number of entries
+    /// written. Each entry is its key then its value, both prefixed with their
+    /// length as a varint, see `encoding::get_length_prefixed_slice`.
+    ///
+    /// Nodes only link forward, so the list is cut into about `sqrt(len)`
+    /// segments starting at the nodes of a high level. Segments are exported last
+    /// to first, each one collected then written backwards, which takes a single
+    /// pass over the entries and memory for `O(sqrt(len))` of them.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::encoding::get_length_prefixed_slice;
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+    /// sl.extend(vec![1, 2, 3]);
+    /// let imm = sl.freeze().ok().unwrap();
+    /// let mut out = vec![];
+    /// assert_eq!(imm.export_descending(&mut out).unwrap(), 3);
+    /// let mut src = &out[..];
+    /// assert_eq!(get_length_prefixed_slice(&mut src), Some(&[3u8][..]));
+    /// assert_eq!(get_length_prefixed_slice(&mut src), Some(&[][..]));
+    /// ```
+    pub fn export_descending<W: Write>(&self, mut dst: W) -> io::Result<usize> {
+        let head = self.head();
+        // Segments start at the nodes of the highest level holding at least
+        // `sqrt(len)` of them, and at the head.
+        let mut starts = vec![head];
+        for level in (1..self.get_max_height()).rev() {
+            starts = iter::successors(Some(head), |x| x.next(level)).collect();
+            if (starts.len() as u64).pow(2) >= self.len() {
+                break;
+            }
+        }
+
+        let mut written = 0;
+        let mut segment: Vec<&Node> = vec![];
+        let mut buf = vec![];
+        let mut end = None;
+        for &start in starts.iter().rev() {
+            segment.clear();
+            let first = if start == head {
+                head.next(0)
+            } else {
+                Some(start)
+            };
+            let nodes = iter::successors(first, |x| x.next(0)).take_while(|&x| Some(x) != end);
+            segment.extend(nodes.filter(|x| !x.is_deleted()).map(Nav::node));
+            end = Some(start);
+            buf.clear();
+            for node in segment.iter().rev() {
+                put_length_prefixed_slice(&mut buf, node.data.as_ref());
+                put_length_prefixed_slice(&mut buf, node.value().as_ref());
+            }
+            dst.write_all(&buf)?;
+            written += segment.len();
+        }
+        Ok(written)
+    }
+}
+
+/// Height of the `i`-th (1-based) entry of a perfectly balanced list with a
+/// branching factor of `branching`.
+pub(crate) fn balanced_height(i: u64, branching: usize, max_height: usize) -> usize {
+    let branching = branching as u64;
+    let mut height = 1;
+    let mut i = i;
+    while height < max_height && i.is_multiple_of(branching) {
+        height += 1;
+        i /= branching;
+    }
+    height
+}
+
+impl<C, A> fmt::Debug for ImmutableSkipList<C, A>
+where
+    C: BaseComparator,
+    A: Arena,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ImmutableSkipList")
+            .field("id", &self.id())
+            .field("name", &self.name())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<C, A> fmt::Display for ImmutableSkipList<C, A>
+where
+    C: BaseComparator,
+    A: Arena,
+{
+    /// Writes the keys of the list, with the same limit as `SkipList`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_keys(f, self.into_iter().map(|node| node.data.as_ref()))
+    }
+}
+
+impl<'a, C, A> iter::IntoIterator for &'a ImmutableSkipList<C, A>
+where
+    C: BaseComparator,
+    A: Arena,
+{
+    type Item = &'a Node;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        Iter::new(unsafe { self.inner.nodes.head().nav() }, self.len())
+    }
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use crate::encoding::get_length_prefixed_slice;
+    use crate::{
+        ArenaImpl, DefaultComparator, ImmutableSkipList, Random, RandomGenerator, SkipList,
+    };
+    use std::panic::{RefUnwindSafe, UnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_freeze() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in (0..100u8).rev() {
+            sl.insert(vec![i]);
+        }
+        let imm = sl.freeze().ok().unwrap();
+        assert_eq!(imm.len(), 100);
+        for i in 0..100 {
+            assert!(imm.contains(&[i]));
+        }
+        assert!(!imm.contains(&[100]));
+        assert_eq!(imm.get(&[42]), Some(&[42u8][..]));
+        assert_eq!(imm.get_bytes(&[42]).unwrap().as_ref(), &[42]);
+        assert_eq!(imm.get(&[100]), None);
+        for (count, node) in (&imm).into_iter().enumerate() {
+            assert_eq!(node.data.as_ref(), &[count as u8]);
+        }
+        assert!(imm.keys().eq((0..100u8).map(|i| vec![i])));
+    }
+
+    #[test]
+    fn test_unwind_safe() {
+        fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe>() {}
+        assert_unwind_safe::<ImmutableSkipList<DefaultComparator, ArenaImpl>>();
+    }
+
+    #[test]
+    fn test_freeze_shared() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let writer = sl.clone();
+        // Another handle can still write, so the list can't be frozen yet.
+        let sl = sl.freeze().err().unwrap();
+        drop(writer);
+        assert!(sl.freeze().is_ok());
+    }
+
+    #[test]
+    fn test_on_drop() {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let counter = dropped.clone();
+        sl.on_drop(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let imm = sl.freeze().ok().unwrap();
+        assert_eq!(dropped.load(Ordering::SeqCst), 0);
+        let imm = imm.compact();
+        let reader = imm.clone();
+        drop(imm);
+        assert_eq!(dropped.load(Ordering::SeqCst), 0);
+        drop(reader);
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+
+        // Callbacks stay with the handles left on the original list.
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let counter = dropped.clone();
+        sl.on_drop(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let imm = sl.freeze().ok().unwrap();
+        let reader = imm.clone();
+        drop(imm.optimize_for_scan());
+        assert_eq!(dropped.load(Ordering::SeqCst), 1);
+        drop(reader);
+        assert_eq!(dropped.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_compact() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..200u8 {
+            sl.insert(vec![i; 100]);
+        }
+        let imm = sl.freeze().ok().unwrap();
+        let expected = format!("{}", imm);
+        let before = imm.memory_size();
+
+        let imm = imm.compact();
+        assert_eq!(imm.len(), 200);
+        assert_eq!(format!("{}", imm), expected);
+        assert!(imm.memory_size() <= before);
+        assert_eq!(imm.memory_usage(), imm.memory_size() + 200 * 100);
+        assert_eq!(imm.get_max_height(), 4); // 4^3 <= 200 < 4^4
+        for i in 0..200 {
+            assert!(imm.contains(&[i; 100]));
+        }
+        assert!(!imm.contains(&[200; 100]));
+    }
+
+    #[test]
+    fn test_optimize_for_scan() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let rnd = Random::new(301);
+        for _ in 0..2000 {
+            sl.insert(rnd.next().to_be_bytes().to_vec());
+        }
+        sl.remove(sl.first().unwrap());
+        let heights = |imm: &ImmutableSkipList<_, _>| {
+            imm.into_iter()
+                .map(|n| (n.data.clone(), n.height()))
+                .collect::<Vec<_>>()
+        };
+        let imm = sl.freeze().ok().unwrap();
+        let expected = heights(&imm);
+        let height = imm.get_max_height();
+
+        let imm = imm.optimize_for_scan();
+        assert_eq!(heights(&imm), expected);
+        assert_eq!(imm.len(), expected.len() as u64);
+        assert_eq!(imm.get_max_height(), height);
+        for (key, _) in &expected {
+            assert!(imm.contains(key));
+        }
+        // Only moving on to the next block goes backwards, if ever.
+        let nodes: Vec<usize> = (&imm).into_iter().map(|n| n as *const _ as usize).collect();
+        let backwards = nodes.windows(2).filter(|w| w[1] < w[0]).count();
+        assert!(backwards * 50 < nodes.len(), "{}", backwards);
+    }
+
+    #[test]
+    fn test_export_descending() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..1000u32 {
+            sl.insert(i.to_be_bytes().to_vec());
+        }
+        sl.remove(&0u32.to_be_bytes());
+        sl.remove(&500u32.to_be_bytes());
+        let imm = sl.freeze().ok().unwrap();
+
+        let mut out = vec![];
+        assert_eq!(imm.export_descending(&mut out).unwrap(), 998);
+        let mut src = &out[..];
+        let mut keys = vec![];
+        while !src.is_empty() {
+            keys.push(get_length_prefixed_slice(&mut src).unwrap().to_vec());
+            assert_eq!(get_length_prefixed_slice(&mut src), Some(&[][..]));
+        }
+        let mut expected: Vec<_> = (&imm).into_iter().map(|n| n.data.to_vec()).collect();
+        expected.reverse();
+        assert_eq!(keys, expected);
+
+        let empty = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let mut out = vec![];
+        let imm = empty.freeze().ok().unwrap();
+        assert_eq!(imm.export_descending(&mut out).unwrap(), 0);
+        assert!(out.is_empty());
+    }
+}
@@ -1,14 +1,77 @@
 mod arena;
+#[cfg(feature = "arena")]
+mod arena_impl;
+mod background;
+mod bloom;
 mod cmp;
+pub mod compress;
+mod config;
+mod counters;
+#[cfg(feature = "wal")]
+pub mod crc32c;
+#[cfg(feature = "crossbeam")]
+mod crossbeam;
+mod cursor;
+mod drop_hook;
+pub mod encoding;
+mod entry;
+mod error;
+mod guard;
+#[cfg(feature = "metrics")]
+mod hot_keys;
+mod immutable;
+#[cfg(feature = "memtable")]
+mod memtable;
+mod merging;
 mod random;
+mod range_lock;
+mod readahead;
 mod skiplist;
+#[cfg(feature = "iter")]
 mod skiplist_iter;
+mod skipmap;
 mod skipnode;
+#[cfg(feature = "wal")]
+pub mod wal;
+#[cfg(feature = "windowed")]
+mod windowed;
+mod write_batch;
 
-pub use arena::{Arena, ArenaImpl};
+pub use arena::{Arena, ArenaCheckpoint, SharedArena};
+#[cfg(feature = "arena")]
+pub use arena_impl::ArenaImpl;
+pub use background::BackgroundTask;
 pub use cmp::{BaseComparator, DefaultComparator};
+pub use config::{
+    ComparatorCheck, DuplicatePolicy, OverflowPolicy, PrefixBloomConfig, SkipListConfig,
+};
+#[cfg(feature = "deterministic")]
+pub use counters::OpCounters;
+pub use cursor::{Cursor, MembershipCursor};
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+#[cfg(feature = "crossbeam")]
+pub use error::ConversionError;
+pub use error::{
+    ComparatorViolation, CompressionError, ConfigError, DuplicateKeyError, IterError, OrderError,
+    SameListError, SelfCheckError, WouldStall,
+};
+pub use guard::{Guard, GuardIter, StableRef};
+pub use immutable::ImmutableSkipList;
+#[cfg(feature = "memtable")]
+pub use memtable::{MemTableSet, StallLimits};
+pub use merging::MergingIterator;
 pub use random::{Random, RandomGenerator};
-pub use skiplist::SkipList;
-pub use skiplist_iter::SkipListIter;
+pub use range_lock::RangeLockGuard;
+pub use skiplist::{
+    BytesIter, InsertOutcome, KeyFilter, LevelIter, Page, RangeIter, RevIter, SkipList,
+    DISPLAY_LIMIT,
+};
+#[cfg(feature = "iter")]
+pub use skiplist_iter::{ResumeToken, SkipListIter, StdEntries, StdIter};
+pub use skipmap::{EntryMeta, SkipMap};
+pub use skipnode::{NodeRef, Splice};
+#[cfg(feature = "windowed")]
+pub use windowed::WindowedSkipList;
+pub use write_batch::WriteBatch;
 
 pub const K_MAX_HEIGHT: usize = 12;
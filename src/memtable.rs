@@ -0,0 +1,373 @@
+use crate::skipnode::Node;
+use crate::{
+    Arena, BaseComparator, ImmutableSkipList, MergingIterator, RandomGenerator, SkipList,
+    WouldStall,
+};
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::iter;
+
+/// Limits past which writes to a `MemTableSet` should wait for the frozen lists
+/// to be flushed, like the write stalls of LevelDB. `None` disables a limit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StallLimits {
+    /// Memory used by every list of the set, see `MemTableSet::memory_usage`.
+    pub max_memory: Option<u64>,
+    /// Number of frozen lists waiting to be flushed.
+    pub max_immutables: Option<usize>,
+}
+
+/// The memtables of an LSM tree: the active skiplist taking writes, and the
+/// frozen ones waiting to be flushed, newest first.
+///
+/// Reads go through every list from the newest to the oldest, so an entry in the
+/// active list shadows the entries of the frozen ones.
+/// # Examples
+/// ```
+/// use dakv_skiplist::{ArenaImpl, DefaultComparator, MemTableSet, Random, SkipList};
+///
+/// fn memtable() -> SkipList<Random, DefaultComparator, ArenaImpl> {
+///     SkipList::new(
+///         Random::new(0xdead_beef),
+///         DefaultComparator::default (),
+///         ArenaImpl::new(),
+///     )
+/// }
+///
+/// let mut set = MemTableSet::new(memtable());
+/// set.insert(vec![1u8]);
+/// set.rotate(memtable()).ok().unwrap();
+/// set.insert(vec![2u8]);
+/// assert_eq!(set.get(&[1u8]).unwrap().as_ref(), &[1u8]);
+/// assert_eq!(set.get(&[2u8]).unwrap().as_ref(), &[2u8]);
+///
+/// let flushed = set.pop_oldest().unwrap();
+/// assert!(flushed.contains(&[1u8]));
+/// assert_eq!(set.get(&[1u8]), None);
+/// ```
+pub struct MemTableSet<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    active: SkipList<R, C, A>,
+    immutables: VecDeque<ImmutableSkipList<C, A>>,
+    limits: StallLimits,
+    backpressure: Option<Box<dyn Fn(WouldStall) + Send + Sync>>,
+}
+
+impl<R, C, A> MemTableSet<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    pub fn new(active: SkipList<R, C, A>) -> Self {
+        MemTableSet {
+            active,
+            immutables: VecDeque::new(),
+            limits: StallLimits::default(),
+            backpressure: None,
+        }
+    }
+
+    /// Set the limits past which writes stall, see `try_insert`.
+    pub fn set_stall_limits(&mut self, limits: StallLimits) {
+        self.limits = limits;
+    }
+
+    /// Call `f` before every `insert` or `remove` made while the set is over its
+    /// `StallLimits`, e.g. to sleep a little like LevelDB slows writes down, or
+    /// to wake up the flush. The write goes through once `f` returns.
+    pub fn set_backpressure(&mut self, f: impl Fn(WouldStall) + Send + Sync + 'static) {
+        self.backpressure = Some(Box::new(f));
+    }
+
+    /// Returns the first `StallLimits` the set is over, `None` if writes can go
+    /// on.
+    pub fn stall(&self) -> Option<WouldStall> {
+        if let Some(limit) = self.limits.max_immutables {
+            let count = self.immutables.len();
+            if count >= limit {
+                return Some(WouldStall::Immutables { count, limit });
+            }
+        }
+        let limit = self.limits.max_memory?;
+        let usage = self.memory_usage();
+        if usage >= limit {
+            Some(WouldStall::Memory { usage, limit })
+        } else {
+            None
+        }
+    }
+
+    fn apply_backpressure(&self) {
+        if let Some(f) = &self.backpressure {
+            if let Some(stall) = self.stall() {
+                f(stall);
+            }
+        }
+    }
+
+    /// Returns the list taking writes.
+    pub fn active(&self) -> &SkipList<R, C, A> {
+        &self.active
+    }
+
+    pub fn active_mut(&mut self) -> &mut SkipList<R, C, A> {
+        &mut self.active
+    }
+
+    /// Returns the frozen lists, newest first.
+    pub fn immutables(&self) -> impl Iterator<Item = &ImmutableSkipList<C, A>> {
+        self.immutables.iter()
+    }
+
+    /// Returns the number of frozen lists waiting to be flushed.
+    pub fn num_immutables(&self) -> usize {
+        self.immutables.len()
+    }
+
+    pub fn insert(&mut self, key: impl Into<Bytes>) {
+        self.apply_backpressure();
+        self.active.insert(key);
+    }
+
+    /// Insert `key` unless the set is over its `StallLimits`, in which case
+    /// nothing is written and the caller should hold the write until a flush
+    /// brings the set back under them.
+    pub fn try_insert(&mut self, key: impl Into<Bytes>) -> Result<(), WouldStall> {
+        match self.stall() {
+            Some(stall) => Err(stall),
+            None => {
+                self.active.insert(key);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the stored entry equal to `key` from the newest list holding one,
+    /// `None` if that list removed it.
+    pub fn get(&self, key: &[u8]) -> Option<Bytes> {
+        let newest = self
+            .active
+            .find_newest(key)
+            .or_else(|| self.immutables.iter().find_map(|imm| imm.find_newest(key)))?;
+        if newest.is_deleted() {
+            None
+        } else {
+            Some(newest.data.clone())
+        }
+    }
+
+    /// Returns the entries of the set in order, each key from the newest list
+    /// holding it, like `get`. Keys whose newest entry was removed are skipped.
+    pub fn iter(&self) -> impl Iterator<Item = &Node> {
+        let active: Box<dyn Iterator<Item = &Node>> = Box::new(self.active.nodes());
+        let lists = iter::once(active).chain(self.immutables.iter().map(|imm| {
+            let nodes: Box<dyn Iterator<Item = &Node>> = Box::new(imm.nodes());
+            nodes
+        }));
+        MergingIterator::new(self.active.comparator(), lists)
+            .newest_only()
+            .filter(|node| !node.is_deleted())
+    }
+
+    /// Remove `key` from the set: a tombstone goes into the active list, hiding
+    /// the entries of the frozen ones until they are flushed.
+    pub fn remove(&mut self, key: impl Into<Bytes>) {
+        self.apply_backpressure();
+        let key = key.into();
+        self.active.remove(&key);
+        self.active.insert_tombstone(key);
+    }
+
+    /// Returns `true` once the active list reached its memory budget and should be
+    /// rotated.
+    pub fn should_rotate(&self) -> bool {
+        self.active.exceeds_memory_budget()
+    }
+
+    /// Freeze the active list, queue it as the newest immutable one, and make
+    /// `next` the active list.
+    ///
+    /// Freezing needs the last handle to the active list, see `SkipList::freeze`.
+    /// If other handles are alive nothing changes and `next` is handed back.
+    pub fn rotate(&mut self, next: SkipList<R, C, A>) -> Result<(), SkipList<R, C, A>> {
+        let active = std::mem::replace(&mut self.active, next);
+        match active.freeze() {
+            Ok(imm) => {
+                self.immutables.push_front(imm);
+                Ok(())
+            }
+            Err(active) => Err(std::mem::replace(&mut self.active, active)),
+        }
+    }
+
+    /// Remove the oldest frozen list, typically once it has been flushed.
+    pub fn pop_oldest(&mut self) -> Option<ImmutableSkipList<C, A>> {
+        self.immutables.pop_back()
+    }
+
+    /// Returns the estimated memory used by every list of the set.
+    pub fn memory_usage(&self) -> u64 {
+        self.active.memory_usage()
+            + self
+                .immutables
+                .iter()
+                .map(|imm| imm.memory_usage())
+                .sum::<u64>()
+    }
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use super::{MemTableSet, StallLimits};
+    use crate::{ArenaImpl, DefaultComparator, Random, SkipList, SkipListConfig, WouldStall};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn memtable() -> SkipList<Random, DefaultComparator, ArenaImpl> {
+        let config = SkipListConfig {
+            memory_budget: Some(16 << 10),
+            ..Default::default()
+        };
+        SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rotate() {
+        let mut set = MemTableSet::new(memtable());
+        for i in 0..1000u32 {
+            set.insert(i.to_be_bytes().to_vec());
+            if set.should_rotate() {
+                set.rotate(memtable()).ok().unwrap();
+            }
+        }
+        assert!(set.num_immutables() > 1);
+        let total = set.active().len() + set.immutables().map(|imm| imm.len()).sum::<u64>();
+        assert_eq!(total, 1000);
+        for i in 0..1000u32 {
+            let key = i.to_be_bytes();
+            assert_eq!(set.get(&key).unwrap().as_ref(), &key);
+        }
+        assert_eq!(set.get(&1000u32.to_be_bytes()), None);
+
+        // Older lists come out first, and hold the smallest keys.
+        let oldest = set.pop_oldest().unwrap();
+        assert!(oldest.contains(&0u32.to_be_bytes()));
+        assert_eq!(set.get(&0u32.to_be_bytes()), None);
+        assert!(set.memory_usage() > set.active().memory_usage());
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut set = MemTableSet::new(memtable());
+        set.insert(vec![1u8]);
+        set.insert(vec![2u8]);
+        set.rotate(memtable()).ok().unwrap();
+        set.insert(vec![3u8]);
+
+        // The tombstone in the active list shadows the frozen entry.
+        set.remove(vec![1u8]);
+        set.remove(vec![3u8]);
+        assert_eq!(set.get(&[1]), None);
+        assert_eq!(set.get(&[3]), None);
+        assert_eq!(set.get(&[2]).unwrap().as_ref(), &[2]);
+        assert!(set.active().is_empty());
+
+        set.rotate(memtable()).ok().unwrap();
+        assert_eq!(set.get(&[1]), None);
+        set.insert(vec![1u8]);
+        assert_eq!(set.get(&[1]).unwrap().as_ref(), &[1]);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut set = MemTableSet::new(memtable());
+        for i in 0..10u8 {
+            set.insert(vec![i]);
+        }
+        set.rotate(memtable()).ok().unwrap();
+        for i in 5..15u8 {
+            set.insert(vec![i]);
+        }
+        set.remove(vec![2u8]);
+        set.remove(vec![12u8]);
+        set.rotate(memtable()).ok().unwrap();
+        set.insert(vec![2u8]);
+        set.remove(vec![7u8]);
+
+        let keys: Vec<u8> = set.iter().map(|node| node.data[0]).collect();
+        let expected: Vec<u8> = (0..15).filter(|&i| i != 7 && i != 12).collect();
+        assert_eq!(keys, expected);
+        for key in 0..16u8 {
+            assert_eq!(set.get(&[key]).is_some(), keys.contains(&key), "{}", key);
+        }
+    }
+
+    #[test]
+    fn test_rotate_shared() {
+        let mut set = MemTableSet::new(memtable());
+        set.insert(vec![1u8]);
+        let reader = set.active().clone();
+        let next = set.rotate(memtable()).err().unwrap();
+        assert!(next.is_empty());
+        assert_eq!(set.num_immutables(), 0);
+        assert!(set.active().contains_key(&[1u8]));
+        drop(reader);
+        assert!(set.rotate(next).is_ok());
+        assert_eq!(set.num_immutables(), 1);
+    }
+
+    #[test]
+    fn test_stall() {
+        let mut set = MemTableSet::new(memtable());
+        let stalls = Arc::new(AtomicUsize::new(0));
+        let counter = stalls.clone();
+        set.set_backpressure(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        set.set_stall_limits(StallLimits {
+            max_memory: Some(64 << 10),
+            max_immutables: Some(2),
+        });
+        assert_eq!(set.stall(), None);
+
+        set.insert(vec![1u8]);
+        set.rotate(memtable()).ok().unwrap();
+        assert!(set.try_insert(vec![2u8]).is_ok());
+        set.rotate(memtable()).ok().unwrap();
+        assert_eq!(
+            set.try_insert(vec![3u8]),
+            Err(WouldStall::Immutables { count: 2, limit: 2 })
+        );
+        assert_eq!(set.get(&[3]), None);
+        set.insert(vec![3u8]);
+        assert_eq!(stalls.load(Ordering::SeqCst), 1);
+        assert_eq!(set.get(&[3]).unwrap().as_ref(), &[3]);
+
+        // Flushing frees the writes, until the memory runs out.
+        set.pop_oldest();
+        let mut i = 0u32;
+        let stall = loop {
+            match set.try_insert(i.to_be_bytes().to_vec()) {
+                Ok(()) => i += 1,
+                Err(stall) => break stall,
+            }
+        };
+        assert!(matches!(stall, WouldStall::Memory { limit, usage } if usage >= limit));
+        set.remove(vec![1u8]);
+        assert_eq!(stalls.load(Ordering::SeqCst), 2);
+        set.pop_oldest();
+        set.rotate(memtable()).ok().unwrap();
+        set.pop_oldest();
+        assert_eq!(set.stall(), None);
+    }
+}
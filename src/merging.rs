@@ -0,0 +1,202 @@
+use crate::skipnode::Node;
+use crate::BaseComparator;
+use std::cmp::Ordering;
+use std::iter::Peekable;
+
+/// Merges the entries of several lists sorted by the same comparator, e.g. the
+/// active and frozen memtables of an LSM tree, into a single sorted iterator.
+///
+/// Sources are given newest first. Equal keys come out from the newest source
+/// to the oldest, and within a list from the newest entry to the oldest, since
+/// a list links a new entry before the equal ones. With `newest_only`, only the
+/// first of them comes out, the one shadowing the others. Keys carrying a
+/// sequence number that the comparator orders, newest first, need neither: the
+/// comparator alone sorts the versions of an entry.
+///
+/// Like LevelDB's merging iterator, every step compares the heads of all the
+/// sources, which is the cheapest for the handful of memtables an LSM reads.
+/// # Examples
+#[cfg_attr(feature = "arena", doc = "```")]
+#[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+/// use dakv_skiplist::{ArenaImpl, DefaultComparator, MergingIterator, Random, SkipList};
+///
+/// let new_list = || {
+///     SkipList::new(
+///         Random::new(0xdead_beef),
+///         DefaultComparator::default (),
+///         ArenaImpl::new(),
+///     )
+/// };
+/// let older = new_list();
+/// let newer = new_list();
+/// older.insert(vec![1u8]);
+/// older.insert(vec![3u8]);
+/// newer.insert(vec![2u8]);
+/// newer.insert(vec![3u8]);
+///
+/// let merged = MergingIterator::new(newer.comparator(), vec![&newer, &older]);
+/// let keys: Vec<u8> = merged.map(|node| node.data[0]).collect();
+/// assert_eq!(keys, vec![1, 2, 3, 3]);
+///
+/// let merged = MergingIterator::new(newer.comparator(), vec![&newer, &older]).newest_only();
+/// assert_eq!(merged.count(), 3);
+/// ```
+pub struct MergingIterator<'a, C, I>
+where
+    C: BaseComparator,
+    I: Iterator<Item = &'a Node>,
+{
+    cmp: &'a C,
+    sources: Vec<Peekable<I>>,
+    newest_only: bool,
+}
+
+impl<'a, C, I> MergingIterator<'a, C, I>
+where
+    C: BaseComparator,
+    I: Iterator<Item = &'a Node>,
+{
+    /// Merges `sources`, newest first, which all have to be sorted by `cmp`.
+    pub fn new<S>(cmp: &'a C, sources: impl IntoIterator<Item = S>) -> Self
+    where
+        S: IntoIterator<IntoIter = I>,
+    {
+        MergingIterator {
+            cmp,
+            sources: sources
+                .into_iter()
+                .map(|source| source.into_iter().peekable())
+                .collect(),
+            newest_only: false,
+        }
+    }
+
+    /// Yield only the newest of equal keys, skipping the entries it shadows.
+    pub fn newest_only(mut self) -> Self {
+        self.newest_only = true;
+        self
+    }
+}
+
+impl<'a, C, I> Iterator for MergingIterator<'a, C, I>
+where
+    C: BaseComparator,
+    I: Iterator<Item = &'a Node>,
+{
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cmp = self.cmp;
+        // Ties go to the first source, the newest.
+        let mut smallest: Option<(usize, &'a Node)> = None;
+        for (i, source) in self.sources.iter_mut().enumerate() {
+            if let Some(&node) = source.peek() {
+                if smallest.is_none_or(|(_, s)| cmp.lt(&node.data, &s.data)) {
+                    smallest = Some((i, node));
+                }
+            }
+        }
+        let (i, node) = smallest?;
+        self.sources[i].next();
+        if self.newest_only {
+            for source in &mut self.sources {
+                while source
+                    .next_if(|x| cmp.compare(&x.data, &node.data) == Ordering::Equal)
+                    .is_some()
+                {}
+            }
+        }
+        Some(node)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.sources.iter().map(Peekable::size_hint).fold(
+            (0, Some(0)),
+            |(lower, upper): (usize, Option<usize>), (l, u)| {
+                (
+                    lower.saturating_add(l),
+                    upper.zip(u).and_then(|(a, b)| a.checked_add(b)),
+                )
+            },
+        );
+        if self.newest_only {
+            // All the entries but one may be shadowed.
+            (lower.min(1), upper)
+        } else {
+            (lower, upper)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use super::MergingIterator;
+    use crate::{ArenaImpl, BaseComparator, DefaultComparator, Random, SkipList};
+    use std::cmp::Ordering;
+
+    fn list(keys: &[u8]) -> SkipList<Random, DefaultComparator, ArenaImpl> {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for &key in keys {
+            sl.insert(vec![key]);
+        }
+        sl
+    }
+
+    #[test]
+    fn test_merge() {
+        let lists = vec![
+            list(&[2, 4, 6]),
+            list(&[]),
+            list(&[1, 4, 7, 9]),
+            list(&[0, 9]),
+        ];
+        let cmp = lists[0].comparator();
+        let merged = MergingIterator::new(cmp, &lists);
+        assert_eq!(merged.size_hint(), (9, Some(9)));
+        let keys: Vec<u8> = merged.map(|node| node.data[0]).collect();
+        assert_eq!(keys, vec![0, 1, 2, 4, 4, 6, 7, 9, 9]);
+
+        let merged = MergingIterator::new(cmp, &lists).newest_only();
+        assert_eq!(merged.size_hint(), (1, Some(9)));
+        let keys: Vec<u8> = merged.map(|node| node.data[0]).collect();
+        assert_eq!(keys, vec![0, 1, 2, 4, 6, 7, 9]);
+
+        let empty: Vec<SkipList<Random, DefaultComparator, ArenaImpl>> = vec![list(&[])];
+        assert_eq!(
+            MergingIterator::new(cmp, &empty)
+                .next()
+                .map(|n| n.data.clone()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_precedence() {
+        // The second byte tells the entries with equal keys apart.
+        struct FirstByte;
+        impl BaseComparator for FirstByte {
+            fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+                a[0].cmp(&b[0])
+            }
+        }
+        let list = || SkipList::new(Random::new(0xdead_beef), FirstByte, ArenaImpl::new());
+        let newer = list();
+        let older = list();
+        newer.insert(vec![1u8, 0]);
+        older.insert(vec![1u8, 1]);
+        older.insert(vec![1u8, 2]);
+        older.insert(vec![2u8, 1]);
+
+        let merged = MergingIterator::new(&FirstByte, vec![&newer, &older]);
+        let keys: Vec<&[u8]> = merged.map(|node| node.data.as_ref()).collect();
+        assert_eq!(keys, vec![&[1, 0], &[1, 2], &[1, 1], &[2, 1]]);
+
+        let merged = MergingIterator::new(&FirstByte, vec![&older, &newer]).newest_only();
+        let keys: Vec<&[u8]> = merged.map(|node| node.data.as_ref()).collect();
+        assert_eq!(keys, vec![&[1, 2], &[2, 1]]);
+    }
+}
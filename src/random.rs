@@ -11,7 +11,7 @@ pub trait RandomGenerator {
     // Randomly returns true ~"1/n" of the time, and false otherwise.
     // REQUIRES: n > 0
     fn one_in(&self, n: u32) -> bool {
-        (self.next() % n) == 0
+        self.next().is_multiple_of(n)
     }
     // Skewed: pick "base" uniformly from range [0,max_log] and then
     // return "base" random bits.  The effect is to pick a number in the
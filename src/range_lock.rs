@@ -0,0 +1,260 @@
+use crate::BaseComparator;
+use bytes::Bytes;
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+type Range = (Bound<Bytes>, Bound<Bytes>);
+
+/// Key ranges locked through the handles of a list, see `SkipList::lock_range`.
+#[derive(Default)]
+pub(crate) struct RangeLocks {
+    held: Mutex<Held>,
+    released: Condvar,
+}
+
+/// The held ranges, which never overlap, sorted by their start along with their
+/// ids. Sorted by start, they are sorted by end too: the ones overlapping a
+/// range follow each other and a binary search finds the first.
+#[derive(Default)]
+struct Held {
+    last_id: u64,
+    ranges: Vec<(Range, u64)>,
+}
+
+impl Held {
+    /// Returns where `range` goes among the held ranges, or `None` if it overlaps
+    /// one of them.
+    fn slot<C: BaseComparator>(&self, cmp: &C, range: &Range) -> Option<usize> {
+        let i = self
+            .ranges
+            .partition_point(|(held, _)| ends_before(cmp, &held.1, &range.0));
+        match self.ranges.get(i) {
+            Some((next, _)) if !ends_before(cmp, &range.1, &next.0) => None,
+            _ => Some(i),
+        }
+    }
+}
+
+impl RangeLocks {
+    /// Lock `range`, waiting until it no longer overlaps any held one if `wait`,
+    /// or else giving up. Returns the id of the lock.
+    pub(crate) fn lock<'k, C: BaseComparator>(
+        &self,
+        cmp: &C,
+        range: impl RangeBounds<&'k [u8]>,
+        wait: bool,
+    ) -> Option<u64> {
+        let range = (to_owned(range.start_bound()), to_owned(range.end_bound()));
+        let mut held = self.held();
+        let i = loop {
+            match held.slot(cmp, &range) {
+                Some(i) => break i,
+                None if !wait => return None,
+                None => {
+                    held = self
+                        .released
+                        .wait(held)
+                        .unwrap_or_else(PoisonError::into_inner)
+                }
+            }
+        };
+        held.last_id += 1;
+        let id = held.last_id;
+        held.ranges.insert(i, (range, id));
+        Some(id)
+    }
+
+    pub(crate) fn unlock(&self, id: u64) {
+        let mut held = self.held();
+        if let Some(i) = held.ranges.iter().position(|&(_, i)| i == id) {
+            held.ranges.remove(i);
+        }
+        self.released.notify_all();
+    }
+
+    /// The held ranges only change once checked against the comparator, a panic
+    /// of it leaves them as they were.
+    fn held(&self) -> MutexGuard<'_, Held> {
+        self.held.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn to_owned(bound: Bound<&&[u8]>) -> Bound<Bytes> {
+    match bound {
+        Bound::Included(key) => Bound::Included(Bytes::copy_from_slice(key)),
+        Bound::Excluded(key) => Bound::Excluded(Bytes::copy_from_slice(key)),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// Returns `true` if no key is both before `end` and after `start`.
+fn ends_before<C: BaseComparator>(cmp: &C, end: &Bound<Bytes>, start: &Bound<Bytes>) -> bool {
+    match (end, start) {
+        (Bound::Unbounded, _) | (_, Bound::Unbounded) => false,
+        (Bound::Included(end), Bound::Included(start)) => cmp.lt(end, start),
+        (Bound::Included(end), Bound::Excluded(start))
+        | (Bound::Excluded(end), Bound::Included(start))
+        | (Bound::Excluded(end), Bound::Excluded(start)) => cmp.le(end, start),
+    }
+}
+
+/// A key range locked with `SkipList::lock_range`, unlocked when dropped.
+pub struct RangeLockGuard<'a> {
+    locks: &'a RangeLocks,
+    id: u64,
+}
+
+impl<'a> RangeLockGuard<'a> {
+    pub(crate) fn new(locks: &'a RangeLocks, id: u64) -> Self {
+        RangeLockGuard { locks, id }
+    }
+}
+
+impl<'a> Drop for RangeLockGuard<'a> {
+    fn drop(&mut self) {
+        self.locks.unlock(self.id);
+    }
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use super::{ends_before, Range, RangeLocks};
+    use crate::{ArenaImpl, BaseComparator, DefaultComparator, Random, RandomGenerator, SkipList};
+    use std::cmp;
+    use std::ops::Bound::{Excluded, Included, Unbounded};
+    use std::panic;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    fn overlap<C: BaseComparator>(cmp: &C, a: &Range, b: &Range) -> bool {
+        !ends_before(cmp, &a.1, &b.0) && !ends_before(cmp, &b.1, &a.0)
+    }
+
+    #[test]
+    fn test_overlap() {
+        let cmp = DefaultComparator::default();
+        let key = |k: u8| bytes::Bytes::from(vec![k]);
+        let cases: Vec<(Range, Range, bool)> = vec![
+            (
+                (Included(key(1)), Excluded(key(3))),
+                (Included(key(3)), Unbounded),
+                false,
+            ),
+            (
+                (Included(key(1)), Included(key(3))),
+                (Included(key(3)), Unbounded),
+                true,
+            ),
+            (
+                (Included(key(1)), Included(key(3))),
+                (Excluded(key(3)), Unbounded),
+                false,
+            ),
+            (
+                (Unbounded, Unbounded),
+                (Included(key(9)), Included(key(9))),
+                true,
+            ),
+            (
+                (Included(key(5)), Included(key(6))),
+                (Included(key(1)), Included(key(2))),
+                false,
+            ),
+            (
+                (Included(key(5)), Included(key(5))),
+                (Included(key(5)), Included(key(5))),
+                true,
+            ),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(overlap(&cmp, &a, &b), expected, "{:?} {:?}", a, b);
+            assert_eq!(overlap(&cmp, &b, &a), expected, "{:?} {:?}", b, a);
+        }
+    }
+
+    #[test]
+    fn test_lock_range() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let guard = sl.lock_range(&[10u8][..]..&[20u8][..]);
+        assert!(sl.try_lock_range(&[15u8][..]..).is_none());
+        // Disjoint ranges don't wait.
+        let other = sl.try_lock_range(&[20u8][..]..&[30u8][..]).unwrap();
+        drop(other);
+
+        let released = AtomicBool::new(false);
+        thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                let _guard = sl.lock_range(..=&[10u8][..]);
+                assert!(released.load(Ordering::SeqCst));
+            });
+            thread::sleep(Duration::from_millis(50));
+            released.store(true, Ordering::SeqCst);
+            drop(guard);
+            waiter.join().unwrap();
+        });
+        assert!(sl.try_lock_range(..).is_some());
+    }
+
+    #[test]
+    fn test_many_ranges() {
+        let cmp = DefaultComparator::default();
+        let locks = RangeLocks::default();
+        let rnd = Random::new(301);
+        let mut held: Vec<(Range, u64)> = vec![];
+        for _ in 0..2000 {
+            let (a, b) = (rnd.next() as u8 % 200, rnd.next() as u8 % 200);
+            let (start, end) = ([cmp::min(a, b)], [cmp::max(a, b)]);
+            let range = (Included(&start[..]), Excluded(&end[..]));
+            let expected = (
+                Included(start.to_vec().into()),
+                Excluded(end.to_vec().into()),
+            );
+            let free = !held.iter().any(|(r, _)| overlap(&cmp, r, &expected));
+            match locks.lock(&cmp, range, false) {
+                Some(id) => {
+                    assert!(free, "{:?}", expected);
+                    held.push((expected, id));
+                }
+                None => assert!(!free, "{:?}", expected),
+            }
+            if rnd.next().is_multiple_of(3) && !held.is_empty() {
+                let (_, id) = held.swap_remove(rnd.next() as usize % held.len());
+                locks.unlock(id);
+            }
+        }
+    }
+
+    #[test]
+    fn test_poisoned() {
+        #[derive(Clone)]
+        struct PanickingComparator;
+
+        impl BaseComparator for PanickingComparator {
+            fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+                if a == [13] || b == [13] {
+                    panic!("unlucky key");
+                }
+                a.cmp(b)
+            }
+        }
+
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            PanickingComparator,
+            ArenaImpl::new(),
+        );
+        let guard = sl.lock_range(&[1u8][..]..&[5u8][..]);
+        let r = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            sl.try_lock_range(&[13u8][..]..).is_some()
+        }));
+        assert!(r.is_err());
+        // Unlocking after the panic doesn't panic in turn.
+        drop(guard);
+        assert!(sl.try_lock_range(&[1u8][..]..&[5u8][..]).is_some());
+    }
+}
@@ -0,0 +1,155 @@
+use crate::skiplist::Iter;
+use crate::skipnode::{Nav, Node};
+use std::cmp;
+
+/// Largest number of entries whose values are prefetched ahead of a scan.
+const MAX_WINDOW: usize = 16;
+/// Bytes prefetched from the start of every value.
+const MAX_PREFETCH: usize = 1024;
+const CACHE_LINE: usize = 64;
+
+/// Iterates over the entries of a list and prefetches the values of the next
+/// ones, so that scans over large values don't stall on every entry.
+///
+/// The window grows as the scan goes on, like the read-ahead of a file: short
+/// lookups don't touch the memory of entries they won't read, long scans get
+/// `MAX_WINDOW` entries ahead.
+pub(crate) struct ReadAhead<'a> {
+    nodes: Iter<'a>,
+    /// The last prefetched entry, `distance` live entries ahead of the scan,
+    /// which skips the removed ones.
+    ahead: Option<Nav<'a>>,
+    distance: usize,
+    window: usize,
+    /// Entries returned since the window last grew.
+    served: usize,
+}
+
+impl<'a> ReadAhead<'a> {
+    pub(crate) fn new(nodes: Iter<'a>) -> Self {
+        ReadAhead {
+            nodes,
+            ahead: None,
+            distance: 0,
+            window: 0,
+            served: 0,
+        }
+    }
+
+    fn fill(&mut self, current: &'a Node) {
+        if self.distance == 0 {
+            self.ahead = Some(Nav::from(current));
+        }
+        while self.distance < self.window {
+            let next = match self.ahead.and_then(Nav::next_live) {
+                Some(next) => next,
+                None => break,
+            };
+            self.ahead = Some(next);
+            self.distance += 1;
+            prefetch_value(&next);
+        }
+    }
+}
+
+impl<'a> Iterator for ReadAhead<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.nodes.next()?;
+        self.distance = self.distance.saturating_sub(1);
+        self.served += 1;
+        if self.served > self.window {
+            // The whole window was read, the scan is sequential.
+            self.window = (self.window * 2).clamp(1, MAX_WINDOW);
+            self.served = 0;
+        }
+        self.fill(node);
+        Some(node)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.nodes.size_hint()
+    }
+}
+
+fn prefetch_value(node: &Node) {
+    let value = node.value();
+    let len = cmp::min(value.len(), MAX_PREFETCH);
+    for offset in (0..len).step_by(CACHE_LINE) {
+        prefetch(unsafe { value.as_ptr().add(offset) });
+    }
+}
+
+#[inline(always)]
+fn prefetch(ptr: *const u8) {
+    #[cfg(target_arch = "x86_64")]
+    unsafe {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        _mm_prefetch(ptr as *const i8, _MM_HINT_T0);
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = ptr;
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use super::{ReadAhead, MAX_WINDOW};
+    use crate::skipnode::Nav;
+    use crate::{ArenaImpl, DefaultComparator, Random, SkipMap};
+    use std::iter;
+
+    #[test]
+    fn test_window() {
+        let map = SkipMap::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..100u32 {
+            map.insert(i.to_be_bytes().to_vec(), vec![0u8; 4096]);
+        }
+        let mut iter = ReadAhead::new(map.as_list().into_iter());
+        let mut windows = vec![];
+        let mut i = 0u32;
+        while let Some(node) = iter.next() {
+            assert_eq!(node.data.as_ref(), &i.to_be_bytes());
+            windows.push(iter.window);
+            assert!(iter.distance <= iter.window);
+            i += 1;
+        }
+        assert_eq!(windows.len(), 100);
+        assert_eq!(&windows[..6], &[1, 1, 2, 2, 2, 4]);
+        assert_eq!(windows[99], MAX_WINDOW);
+        assert_eq!(iter.distance, 0);
+    }
+
+    #[test]
+    fn test_removed_entries() {
+        let map = SkipMap::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..200u32 {
+            map.insert(i.to_be_bytes().to_vec(), vec![0u8; 4096]);
+        }
+        for i in (0..200u32).filter(|i| i % 4 != 0) {
+            map.remove(&i.to_be_bytes());
+        }
+        let mut iter = ReadAhead::new(map.as_list().into_iter());
+        let mut served = 0;
+        while let Some(node) = iter.next() {
+            served += 1;
+            // The window is counted in entries the scan will return.
+            let ahead = iter.ahead.unwrap();
+            let live = iter::successors(Some(Nav::from(node)), |&x| x.next_live())
+                .position(|x| x == ahead)
+                .unwrap();
+            assert_eq!(live, iter.distance);
+            assert!(iter.distance <= iter.window);
+        }
+        assert_eq!(served, 50);
+        assert_eq!(iter.window, MAX_WINDOW);
+    }
+}
@@ -1,14 +1,69 @@
-use crate::skipnode::Node;
-use crate::{Arena, BaseComparator, RandomGenerator, K_MAX_HEIGHT};
+use crate::background::{Background, Job};
+use crate::bloom::SegmentBlooms;
+use crate::counters::Counters;
+#[cfg(feature = "deterministic")]
+use crate::counters::OpCounters;
+use crate::drop_hook::DropHooks;
+#[cfg(feature = "metrics")]
+use crate::hot_keys::AccessCounts;
+use crate::immutable::balanced_height;
+use crate::range_lock::RangeLocks;
+use crate::skipnode::{live_from, raw, Nav, Node, NodePtr, NodeRef, Nodes, Splice};
+#[cfg(feature = "arena")]
+use crate::ArenaImpl;
+use crate::{
+    Arena, ArenaCheckpoint, BackgroundTask, BaseComparator, ComparatorCheck, ComparatorViolation,
+    ConfigError, Cursor, DuplicateKeyError, DuplicatePolicy, Entry, Guard, ImmutableSkipList,
+    MembershipCursor, OccupiedEntry, OrderError, OverflowPolicy, RandomGenerator, RangeLockGuard,
+    SameListError, SelfCheckError, SharedArena, SkipListConfig, VacantEntry, WriteBatch,
+    K_MAX_HEIGHT,
+};
+#[cfg(feature = "iter")]
+use crate::{ResumeToken, SkipListIter};
 use bytes::Bytes;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use std::cmp;
+use std::convert::TryFrom;
 use std::fmt;
 use std::iter;
-use std::marker::PhantomData;
 use std::mem;
-use std::ptr::{null_mut, NonNull};
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::ops::{Bound, ControlFlow, RangeBounds};
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// The last node before a key on every level, see `SkipList::seek`.
+type Path<'a> = [Nav<'a>; K_MAX_HEIGHT];
+
+/// An entry evicted by a `try_insert_batch`, copied out of its node.
+struct Evicted {
+    key: Bytes,
+    value: Bytes,
+    meta: u8,
+    count: usize,
+}
+
+impl From<&Node> for Evicted {
+    fn from(node: &Node) -> Self {
+        Evicted {
+            key: node.data.clone(),
+            value: node.value().clone(),
+            meta: node.get_meta(),
+            count: node.count(),
+        }
+    }
+}
+
+/// Whether a list at `max_entries` takes a new entry, see `SkipList::room`.
+enum Room<'a> {
+    /// The list isn't full.
+    Free,
+    /// The entry goes in, and the node makes room for it.
+    Evict(Nav<'a>),
+    /// The entry is dropped, in favour of the node.
+    Full(Nav<'a>),
+}
 
 /// Skip list is a data structure that allows O(log n) search complexity as well as
 /// O(log n) insertion complexity within an ordered sequence of n elements.
@@ -24,38 +79,105 @@ where
     C: BaseComparator,
     A: Arena,
 {
-    head: NonNull<Node>,
+    /// Drops the keys and values of the nodes, so it comes before the arena and
+    /// `adopted`. Shared with `adopted` once some of the nodes were split off
+    /// from another list, see `split_off`.
+    nodes: Arc<Nodes>,
     max_height: AtomicUsize,
-    len: AtomicUsize,
+    len: AtomicU64,
+    data_size: AtomicU64,
+    pub(crate) guards: AtomicUsize,
     rnd: R,
-    cmp: C,
+    pub(crate) cmp: C,
     arena: A,
+    on_drop: DropHooks,
+    config: SkipListConfig,
+    blooms: Option<SegmentBlooms>,
+    #[cfg(feature = "metrics")]
+    access: Option<AccessCounts>,
+    counters: Counters,
+    background: Background,
+    range_locks: RangeLocks,
+    /// The last sequence number handed out by `next_seq`.
+    seq: AtomicU64,
+    /// Lists merged into this one, whose arenas hold some of its nodes. Shared
+    /// with the lists split off from this one.
+    adopted: Arc<Mutex<Vec<Box<dyn Send>>>>,
+    /// Held while the spans of the links change, so that handles removing
+    /// entries don't race with the one inserting.
+    span_lock: Mutex<()>,
+    /// Changes whenever the node graph is reset, to invalidate `Splice`s.
+    generation: AtomicUsize,
+    id: u64,
+    /// Held by the writers of every handle, one at a time, along with the path
+    /// of the last insert, where the next one resumes. Readers never take it.
+    writer: Mutex<Splice>,
 }
 
-unsafe impl<R, C, A> Send for SkipListInner<R, C, A>
+/// A handle to a skiplist, see `SkipListInner`. Clones share the same list.
+///
+/// A list is `Send` and `Sync` exactly when its random generator, comparator and
+/// arena are, so a comparator with interior mutability keeps it on one thread:
+#[cfg_attr(feature = "arena", doc = "```compile_fail")]
+#[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+/// use dakv_skiplist::{ArenaImpl, BaseComparator, Random, SkipList};
+/// use std::cell::Cell;
+/// use std::cmp::Ordering;
+///
+/// #[derive(Default)]
+/// struct CountingComparator(Cell<usize>);
+///
+/// impl BaseComparator for CountingComparator {
+///     fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+///         self.0.set(self.0.get() + 1);
+///         a.cmp(b)
+///     }
+/// }
+///
+/// fn assert_sync<T: Sync>(_: &T) {}
+///
+/// let sl = SkipList::new(Random::new(0xdead_beef), CountingComparator::default (), ArenaImpl::new());
+/// assert_sync(&sl);
+/// ```
+///
+/// A list isn't `UnwindSafe`. A panic in the comparator or in a callback, e.g.
+/// the spawn function, can stop a write half way: after the new node is linked
+/// but before `DuplicatePolicy::Replace` removed the older entries or an
+/// eviction made room for it. The links stay consistent and the list remains
+/// usable, but its length, spans and policies may be off. Wrap the calls in
+/// `AssertUnwindSafe` where that is acceptable.
+pub struct SkipList<R, C, A>
 where
     R: RandomGenerator,
     C: BaseComparator,
     A: Arena,
 {
+    inner: Arc<SkipListInner<R, C, A>>,
 }
 
-unsafe impl<R, C, A> Sync for SkipListInner<R, C, A>
-where
-    R: RandomGenerator,
-    C: BaseComparator,
-    A: Arena,
-{
+/// Returns an id never given to another list.
+fn next_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    NEXT.fetch_add(1, Ordering::SeqCst)
 }
 
-#[derive(Clone)]
-pub struct SkipList<R, C, A>
+/// Returns a generation number never given to another list or `clear`.
+fn next_generation() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(1);
+    NEXT.fetch_add(1, Ordering::SeqCst)
+}
+
+impl<R, C, A> Clone for SkipList<R, C, A>
 where
     R: RandomGenerator,
     C: BaseComparator,
     A: Arena,
 {
-    inner: Arc<SkipListInner<R, C, A>>,
+    fn clone(&self) -> Self {
+        SkipList {
+            inner: self.inner.clone(),
+        }
+    }
 }
 
 impl<R, C, A> SkipList<R, C, A>
@@ -65,24 +187,106 @@ where
     A: Arena,
 {
     pub fn new(rnd: R, cmp: C, arena: A) -> Self {
-        SkipList {
-            inner: Arc::new(SkipListInner {
-                head: NonNull::from(Node::head(&arena)),
-                max_height: AtomicUsize::new(1), // max height in all of the nodes except head node
-                len: AtomicUsize::new(0),
-                rnd,
-                cmp,
-                arena,
-            }),
-        }
+        Self::with_config_unchecked(rnd, cmp, arena, SkipListConfig::default())
     }
 
-    /// Returns the number of elements in the skiplist.
+    /// Create a skiplist tuned by `config`. The arena is provided by the caller, so
+    /// `arena_block_size` isn't used, see `SkipList::from_config` for that.
     /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, SkipListConfig, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let config = SkipListConfig {
+    ///     max_height: 4,
+    ///     ..Default::default()
+    /// };
+    /// let mut sl = SkipList::with_config(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    ///     config,
+    /// ).unwrap();
+    /// sl.extend(0..100);
+    /// assert!(sl.get_max_height() <= 4);
     /// ```
+    pub fn with_config(
+        rnd: R,
+        cmp: C,
+        arena: A,
+        config: SkipListConfig,
+    ) -> Result<Self, ConfigError> {
+        config.validate()?;
+        Ok(Self::with_config_unchecked(rnd, cmp, arena, config))
+    }
+
+    pub(crate) fn with_config_unchecked(rnd: R, cmp: C, arena: A, config: SkipListConfig) -> Self {
+        let blooms = config
+            .prefix_bloom
+            .clone()
+            .map(|bloom| SegmentBlooms::new(bloom, config.branching_factor));
+        #[cfg(feature = "metrics")]
+        let access = config.hot_key_sampling.map(AccessCounts::new);
+        Self::from_inner(Arc::new(SkipListInner {
+            nodes: Arc::new(Nodes::new(Node::head(&arena))),
+            max_height: AtomicUsize::new(1), // max height in all of the nodes except head node
+            len: AtomicU64::new(0),
+            data_size: AtomicU64::new(0),
+            guards: AtomicUsize::new(0),
+            rnd,
+            cmp,
+            arena,
+            on_drop: DropHooks::default(),
+            config,
+            blooms,
+            #[cfg(feature = "metrics")]
+            access,
+            counters: Counters::default(),
+            background: Background::default(),
+            range_locks: RangeLocks::default(),
+            seq: AtomicU64::new(0),
+            adopted: Arc::default(),
+            span_lock: Mutex::new(()),
+            generation: AtomicUsize::new(next_generation()),
+            id: next_id(),
+            writer: Mutex::default(),
+        }))
+    }
+
+    fn from_inner(inner: Arc<SkipListInner<R, C, A>>) -> Self {
+        SkipList { inner }
+    }
+
+    /// Wait for the other writers of the list, whatever their handle, and
+    /// returns the path of the last insert.
+    pub(crate) fn writer(&self) -> MutexGuard<'_, Splice> {
+        // A panicking writer never leaves a node half linked, see `SkipList`.
+        self.inner
+            .writer
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Wait for the other handles changing the spans of the links.
+    fn spans(&self) -> MutexGuard<'_, ()> {
+        self.inner
+            .span_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn config(&self) -> &SkipListConfig {
+        &self.inner.config
+    }
+
+    /// Returns the number of elements in the skiplist. With
+    /// `DuplicatePolicy::Count` a key counts once, see `count`.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
     /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
     ///
-    /// let mut sl = SkipList::new(
+    /// let sl = SkipList::new(
     ///     Random::new(0xdead_beef),
     ///     DefaultComparator::default (),
     ///     ArenaImpl::new(),
@@ -93,16 +297,38 @@ where
     /// assert_eq!(sl.len(), 1);
     /// ```
     #[inline]
-    pub fn len(&self) -> usize {
+    pub fn len(&self) -> u64 {
         self.inner.len.load(Ordering::SeqCst)
     }
 
-    /// Returns `true` if the skiplist is empty.
+    /// Like `len`, without synchronizing with concurrent writers: the count may
+    /// lag behind inserts made by other handles. Good enough for size hints and
+    /// statistics, and cheaper on hot paths.
     /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.insert(vec![1u8]);
+    /// assert_eq!(sl.len_relaxed(), 1);
     /// ```
+    #[inline]
+    pub fn len_relaxed(&self) -> u64 {
+        self.inner.len.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if the skiplist is empty.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
     /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
     ///
-    /// let mut sl = SkipList::new(
+    /// let sl = SkipList::new(
     ///     Random::new(0xdead_beef),
     ///     DefaultComparator::default (),
     ///     ArenaImpl::new(),
@@ -117,14 +343,55 @@ where
         self.len() == 0
     }
 
-    pub fn memory_size(&self) -> usize {
+    /// Returns the memory allocated by the arena, which holds the nodes.
+    pub fn memory_size(&self) -> u64 {
         self.inner.arena.memory_usage()
     }
 
+    /// Returns an estimate of the total memory used by the skiplist: the arena
+    /// blocks holding the nodes (including the per-block overhead and the space
+    /// left unused in the current block) plus the bytes of every stored key and
+    /// value.
+    ///
+    /// This is the number to compare against a write buffer limit.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// let empty = sl.memory_usage();
+    /// sl.insert(vec![0u8; 100]);
+    /// assert_eq!(sl.memory_usage(), empty + 100);
+    /// ```
+    pub fn memory_usage(&self) -> u64 {
+        let blooms = self.inner.blooms.as_ref().map_or(0, |b| b.memory_usage());
+        self.memory_size() + self.inner.data_size.load(Ordering::Relaxed) + blooms
+    }
+
+    /// Returns `true` once `memory_usage` reached the `memory_budget` of the
+    /// config. Always `false` without a budget.
+    pub fn exceeds_memory_budget(&self) -> bool {
+        match self.inner.config.memory_budget {
+            Some(budget) => self.memory_usage() >= budget,
+            None => false,
+        }
+    }
+
     pub fn remain_bytes(&self) -> usize {
         self.inner.arena.remain_bytes()
     }
 
+    /// Returns the comparator ordering the keys, e.g. to merge the list with
+    /// others, see `MergingIterator`.
+    pub fn comparator(&self) -> &C {
+        &self.inner.cmp
+    }
+
     #[inline]
     pub fn get_max_height(&self) -> usize {
         self.inner.max_height.load(Ordering::SeqCst)
@@ -135,9 +402,72 @@ where
         self.inner.max_height.store(h, Ordering::SeqCst);
     }
 
-    /// Clear every single node and reset the head node.
+    /// Raise the max height to at least `height`, never lowering it even if
+    /// another writer raised it meanwhile.
+    ///
+    /// It is fine to publish the new height before the new node is linked, without
+    /// any synchronization with concurrent readers: a reader seeing the new height
+    /// either finds a null pointer on the new levels of the head, which sorts after
+    /// every key and makes it drop to the next level right away, or the new node.
+    fn raise_max_height(&self, height: usize) {
+        let mut current = self.get_max_height();
+        while height > current {
+            match self.inner.max_height.compare_exchange_weak(
+                current,
+                height,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => break,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Lower the max height to the highest level that still has a node, and
+    /// returns it.
+    ///
+    /// Searches start from the max height, which only ever grows while inserting.
+    /// Levels left empty, e.g. after `set_max_height`, cost a comparison-free hop
+    /// per search until this is called. Takes the writer lock, as writers raise
+    /// the height before linking a taller node and would otherwise see it lowered
+    /// under them.
     /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.set_max_height(12);
+    /// assert_eq!(sl.shrink_height(), 1);
+    /// assert_eq!(sl.get_max_height(), 1);
     /// ```
+    pub fn shrink_height(&mut self) -> usize {
+        self.lower_max_height()
+    }
+
+    fn lower_max_height(&self) -> usize {
+        let _writer = self.writer();
+        let head = self.head();
+        let height = (1..self.get_max_height())
+            .rev()
+            .find(|&level| head.next(level).is_some())
+            .map_or(1, |level| level + 1);
+        self.inner.max_height.store(height, Ordering::SeqCst);
+        height
+    }
+
+    /// Clear every single node and reset the head node.
+    ///
+    /// The nodes are unlinked but their memory stays in the arena until the list
+    /// is dropped, so keys borrowed through a `Guard` remain valid.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
     /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
     ///
     /// let mut sl = SkipList::new(
@@ -151,216 +481,4104 @@ where
     /// ```
     #[inline]
     pub fn clear(&mut self) {
-        // let new_head = Node::head(&self.inner.herd);
+        let _writer = self.writer();
+        let head = self.head();
+        if let Some(first) = head.next(0) {
+            let len = iter::successors(Some(first), |x| x.next(0)).count();
+            self.inner.nodes.retire(first, len);
+        }
+        for level in 0..K_MAX_HEIGHT {
+            head.set_next(level, None);
+            head.set_span(level, 0);
+        }
+        self.inner.max_height.store(1, Ordering::SeqCst);
+        self.inner
+            .generation
+            .store(next_generation(), Ordering::SeqCst);
+        if let Some(blooms) = &self.inner.blooms {
+            blooms.clear();
+        }
         self.inner.len.store(0, Ordering::SeqCst);
-        // unsafe { mem::replace(&mut self.inner.head.as_ptr(), new_head) }
     }
 
-    /// 1/4 probability
-    fn random_height(&mut self) -> usize {
-        let k_branching = 4;
+    /// Pin the nodes of the list, see `Guard`.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.insert(vec![1u8]);
+    ///
+    /// let guard = sl.pin();
+    /// let key = guard.get(&[1u8]).unwrap();
+    /// sl.clear();
+    /// assert_eq!(&*key, &[1u8]);
+    /// ```
+    pub fn pin(&self) -> Guard<R, C, A> {
+        Guard::new(self.clone())
+    }
+
+    /// Returns the number of guards currently pinning the list.
+    pub fn active_guards(&self) -> usize {
+        self.inner.guards.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn inner(&self) -> &SkipListInner<R, C, A> {
+        &self.inner
+    }
+
+    /// 1/branching_factor probability, 1/4 by default
+    fn random_height(&self) -> usize {
+        let k_branching = self.inner.config.branching_factor;
+        let max_height = self.inner.config.max_height;
         let mut height = 1;
-        while height < K_MAX_HEIGHT && (self.inner.rnd.next() % k_branching == 0) {
+        while height < max_height && self.inner.rnd.next().is_multiple_of(k_branching) {
             height += 1;
         }
-        assert!(height > 0);
-        assert!(height <= K_MAX_HEIGHT);
+        debug_assert!(height > 0);
+        debug_assert!(height <= K_MAX_HEIGHT);
         height
     }
 
-    /// Look for the node greater than or equal to key
+    /// Returns the first node greater than or equal to `key`, removed or not,
+    /// null if there is none, and leaves in `prev`, unless it is empty, the last node before
+    /// `key` on every level of the list. `lower_bound` returns a `Cursor` there
+    /// without handing out pointers.
     /// # Safety
-    /// todo doc
-    pub fn find(&self, key: &[u8], prev: &mut Vec<*mut Node>) -> *mut Node {
-        // const pointer
-        let mut const_ptr: *const Node = unsafe { self.inner.head.as_ref() };
-        let mut height = self.get_max_height() - 1;
+    /// The pointers are only valid as long as the list, and the nodes are shared
+    /// with the readers and writers of all its handles: dereferencing them
+    /// mutably, or after the list is dropped, is undefined behavior.
+    pub unsafe fn find_raw(&self, key: &[u8], prev: &mut [*mut Node]) -> *mut Node {
+        let mut path = [self.head(); K_MAX_HEIGHT];
+        let found = self.seek(key, if prev.is_empty() { &mut [] } else { &mut path });
+        for (prev, node) in prev.iter_mut().zip(path) {
+            *prev = node.as_ptr();
+        }
+        raw(found)
+    }
+
+    /// Returns the first node greater than or equal to `key`, and leaves in
+    /// `path`, unless it is empty, the last node before `key` on every level of
+    /// the list.
+    pub(crate) fn seek<'a>(&'a self, key: &[u8], path: &mut [Nav<'a>]) -> Option<Nav<'a>> {
+        let mut x = self.head();
+        let mut level = self.get_max_height() - 1;
         loop {
-            let next_ptr = unsafe { (*const_ptr).get_next(height) };
-            // if key > next_ptr => now = next
-            if self.key_is_after_node(key, next_ptr) {
-                const_ptr = next_ptr as *const Node;
+            let next = x.next(level);
+            if let Some(next) = self.before_key(next, key) {
+                x = next;
             } else {
-                if !prev.is_empty() {
-                    prev[height] = const_ptr as *mut Node;
+                if !path.is_empty() {
+                    path[level] = x;
                 }
-                if height == 0 {
-                    return next_ptr;
-                } else {
-                    height -= 1;
+                if level == 0 {
+                    return next;
                 }
+                level -= 1;
             }
         }
     }
 
-    fn key_is_after_node(&self, key: &[u8], node: *mut Node) -> bool {
-        if node.is_null() {
-            false
-        } else {
-            self.lt(unsafe { (*node).data.as_ref() }, key)
-        }
-    }
-
-    /// 1. Find the node greater than or equal to the key and return the mutable reference
-    /// 2. Randomly generate level
-    /// 3. Create new node
-    /// 4. Insert and set forwards
-    pub fn insert(&mut self, key: impl Into<Bytes>) {
-        let key: Bytes = key.into();
-
-        let mut prev = iter::repeat(null_mut()).take(K_MAX_HEIGHT).collect();
-        self.find(key.as_ref(), &mut prev);
-        // random height
-        let height = self.random_height();
-        // record all previous node that are higher than the current
-        if height > self.get_max_height() {
-            for node in prev.iter_mut().take(height).skip(self.get_max_height()) {
-                *node = self.inner.head.as_ptr();
-            }
-            self.set_max_height(height);
-        }
-        // Accelerate memory allocation
-        let n = Node::new(key, height, &self.inner.arena);
-        for (i, &mut node) in prev.iter_mut().enumerate().take(height) {
-            unsafe {
-                let tmp = (*node).get_next(i);
-                n.set_next(i, tmp);
-                (*node).set_next(i, n);
-            }
-        }
-        self.inner.len.fetch_add(1, Ordering::SeqCst);
-    }
-
-    pub fn contains(&mut self, key: &[u8]) -> bool {
-        let mut prev = iter::repeat(null_mut()).take(K_MAX_HEIGHT).collect();
-        let x = self.find(key, &mut prev);
-        !x.is_null() && self.eq(key, unsafe { (*x).data.as_ref() })
+    /// Returns `node` if its key is less than `key`.
+    fn before_key<'a>(&self, node: Option<Nav<'a>>, key: &[u8]) -> Option<Nav<'a>> {
+        node.filter(|node| {
+            self.inner.counters.visited();
+            self.lt(node.key(), key)
+        })
     }
 
-    fn eq(&self, a: &[u8], b: &[u8]) -> bool {
-        self.inner.cmp.compare(a, b) == cmp::Ordering::Equal
+    /// Returns the sentinel node before the first entry. Its key is empty and its
+    /// tower spans every level.
+    #[inline]
+    pub(crate) fn head(&self) -> Nav<'_> {
+        unsafe { self.inner.nodes.head().nav() }
     }
 
-    fn lt(&self, a: &[u8], b: &[u8]) -> bool {
-        self.inner.cmp.compare(a, b) == cmp::Ordering::Less
+    /// 1. Find the node greater than or equal to the key and return the mutable reference
+    /// 2. Apply the duplicate policy if that node is equal to the key
+    /// 3. Randomly generate level
+    /// 4. Create new node
+    /// 5. Insert and set forwards
+    ///
+    /// The search resumes from the path of the previous insert, see `Splice`,
+    /// which makes ascending or nearly ascending inserts cheap. Inserts take a
+    /// shared reference: writers through any handle wait for each other, while
+    /// readers never wait.
+    ///
+    /// The key is stored as `Bytes`, and the conversion decides whether it gets
+    /// copied:
+    /// - `Bytes`, `BytesMut`, `Vec<u8>`, `Box<[u8]>` and `String` hand their buffer
+    ///   over to the list without copying it. Spare capacity is kept along with
+    ///   the buffer but isn't counted by `memory_usage`.
+    /// - `&'static [u8]` and `&'static str` are referenced, not copied.
+    /// - A borrowed `&[u8]` has to be copied first, e.g. with
+    ///   `Bytes::copy_from_slice`, which makes the copy visible at the call site.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use bytes::BytesMut;
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// let mut buf = BytesMut::with_capacity(64);
+    /// buf.extend_from_slice(b"key");
+    /// let ptr = buf.as_ptr();
+    /// sl.insert(buf);
+    /// let stored = sl.front_entry().unwrap();
+    /// assert_eq!(stored.key().as_ptr(), ptr);
+    /// assert!(sl.insert(&b"key"[..]).existed());
+    /// ```
+    pub fn insert(&self, key: impl Into<Bytes>) -> InsertOutcome {
+        self.insert_node(key.into(), Bytes::new()).1
     }
 
-    fn gte(&self, a: &[u8], b: &[u8]) -> bool {
-        let r = self.inner.cmp.compare(a, b);
-        r == cmp::Ordering::Greater || r == cmp::Ordering::Equal
+    /// Insert `key` like `insert`, but fail if `DuplicatePolicy::Error` rejected it.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, DefaultComparator, DuplicatePolicy, SkipListConfig};
+    ///
+    /// let config = SkipListConfig {
+    ///     duplicate_policy: DuplicatePolicy::Error,
+    ///     ..Default::default()
+    /// };
+    /// let sl = SkipList::from_config(Random::new(0xdead_beef), DefaultComparator::default (), config).unwrap();
+    /// assert!(sl.try_insert(vec![1u8]).is_ok());
+    /// let err = sl.try_insert(vec![1u8]).unwrap_err();
+    /// assert_eq!(err.key.as_ref(), &[1]);
+    /// assert_eq!(sl.len(), 1);
+    /// ```
+    pub fn try_insert(&self, key: impl Into<Bytes>) -> Result<InsertOutcome, DuplicateKeyError> {
+        let key = key.into();
+        match self.insert_node(key.clone(), Bytes::new()).1 {
+            InsertOutcome::Rejected => Err(DuplicateKeyError {
+                key,
+                list: self.name().map(String::from),
+            }),
+            outcome => Ok(outcome),
+        }
     }
 
-    pub fn get_head(&self) -> &Node {
-        unsafe { self.inner.head.as_ref() }
+    /// Insert `key` like `insert`, but fail if `ComparatorCheck::Error` found the
+    /// comparator ordering it inconsistently with the entries around its place.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{BaseComparator, ComparatorCheck, Random, SkipList, SkipListConfig};
+    /// use std::cmp::Ordering;
+    ///
+    /// // Every key is less than every other one.
+    /// struct Broken;
+    /// impl BaseComparator for Broken {
+    ///     fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+    ///         if a == b { Ordering::Equal } else { Ordering::Less }
+    ///     }
+    /// }
+    ///
+    /// let config = SkipListConfig {
+    ///     comparator_check: ComparatorCheck::Error,
+    ///     ..Default::default()
+    /// };
+    /// let sl = SkipList::from_config(Random::new(0xdead_beef), Broken, config).unwrap();
+    /// assert!(sl.checked_insert(vec![1u8]).is_ok());
+    /// let err = sl.checked_insert(vec![2u8]).unwrap_err();
+    /// assert_eq!(err.neighbour.as_ref(), &[1]);
+    /// assert_eq!(sl.len(), 1);
+    /// ```
+    pub fn checked_insert(
+        &self,
+        key: impl Into<Bytes>,
+    ) -> Result<InsertOutcome, ComparatorViolation> {
+        let key = key.into();
+        match self.insert_node(key.clone(), Bytes::new()) {
+            (neighbour, InsertOutcome::Violation) => Err(ComparatorViolation {
+                key,
+                neighbour: unsafe { neighbour.as_ref() }.data.clone(),
+                list: self.name().map(String::from),
+            }),
+            (_, outcome) => Ok(outcome),
+        }
     }
 
-    #[allow(clippy::unnecessary_unwrap)]
-    pub fn find_less_than(&self, key: &[u8]) -> *const Node {
-        let mut x: *const Node = unsafe { mem::transmute_copy(&self.inner.head) };
-        let mut level = self.get_max_height() - 1;
-        unsafe {
-            loop {
-                let next = (*x).get_next(level);
-                if next.is_null() || self.gte((*next).data.as_ref(), key) {
-                    if level == 0 {
-                        return x;
-                    } else {
-                        level -= 1;
+    /// Insert all the keys, or none of them if `DuplicatePolicy::Error` rejects
+    /// one, be it because of an entry of the list or of an earlier key of the
+    /// batch. Returns the number of keys stored, leaving out the ones the
+    /// `DuplicatePolicy` ignored or counted in an equal entry, and the ones
+    /// dropped by `ComparatorCheck` or by the `OverflowPolicy`.
+    ///
+    /// The keys are all taken from `keys` before the first one is inserted, so
+    /// the iterator may read or write the list. The keys inserted before the rejected one are unlinked again, and the
+    /// entries they evicted under an evicting `OverflowPolicy` inserted back.
+    /// If no other handle shares the list and it has no prefix bloom filters,
+    /// the memory the keys took is given back to the arena with
+    /// `Arena::rollback`, otherwise readers may still be on them and it stays
+    /// allocated.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, DefaultComparator, DuplicatePolicy, SkipListConfig};
+    ///
+    /// let config = SkipListConfig {
+    ///     duplicate_policy: DuplicatePolicy::Error,
+    ///     ..Default::default()
+    /// };
+    /// let sl = SkipList::from_config(Random::new(0xdead_beef), DefaultComparator::default (), config).unwrap();
+    /// sl.insert(vec![3u8]);
+    /// let memory = sl.memory_usage();
+    ///
+    /// let err = sl.try_insert_batch(vec![vec![1u8], vec![2], vec![3]]).unwrap_err();
+    /// assert_eq!(err.key.as_ref(), &[3]);
+    /// assert_eq!(sl.len(), 1);
+    /// assert_eq!(sl.memory_usage(), memory);
+    ///
+    /// assert_eq!(sl.try_insert_batch(vec![vec![1u8], vec![2]]), Ok(2));
+    /// ```
+    pub fn try_insert_batch<I>(&self, keys: I) -> Result<usize, DuplicateKeyError>
+    where
+        I: IntoIterator,
+        I::Item: Into<Bytes>,
+    {
+        // No user code runs once a key is linked: nothing can borrow the keys
+        // `unstage` frees.
+        let keys: Vec<Bytes> = keys.into_iter().map(Into::into).collect();
+        let mut splice = self.writer();
+        let checkpoint = self.inner.arena.checkpoint();
+        let data_size = self.inner.data_size.load(Ordering::SeqCst);
+        let mut staged = vec![];
+        let mut evicted = vec![];
+        let mut stored = 0;
+        for key in keys {
+            let victim = self.next_victim();
+            match self.insert_at(&mut splice, key.clone(), Bytes::new()) {
+                (_, InsertOutcome::Rejected) => {
+                    self.unstage(staged, checkpoint, data_size);
+                    self.restore(&mut splice, evicted);
+                    return Err(DuplicateKeyError {
+                        key,
+                        list: self.name().map(String::from),
+                    });
+                }
+                (node, InsertOutcome::Inserted) => {
+                    staged.push(node);
+                    stored += 1;
+                    // The writer lock is held, only the insert removed it.
+                    if let Some(victim) = victim.filter(|victim| victim.is_deleted()) {
+                        evicted.push(Evicted::from(victim.node()));
                     }
-                } else {
-                    x = next;
                 }
+                // Never rejected by `DuplicatePolicy::Error`, nothing to undo.
+                (_, InsertOutcome::Duplicate) | (_, InsertOutcome::Replaced) => stored += 1,
+                _ => {}
             }
         }
+        Ok(stored)
     }
 
-    pub fn find_last(&self) -> *const Node {
-        let mut x = self.inner.head.as_ptr() as *const Node;
-        let mut level = self.get_max_height() - 1;
-
-        loop {
-            let next = unsafe { (*x).get_next(level) };
-            if !next.is_null() {
-                x = next;
-            } else if level == 0 {
-                return x;
-            } else {
-                level -= 1;
-            }
+    /// Insert back the entries evicted by a failed `try_insert_batch`, once its
+    /// keys are taken out again.
+    fn restore(&self, splice: &mut Splice, evicted: Vec<Evicted>) {
+        for entry in evicted {
+            let size = (entry.key.len() + entry.value.len()) as u64;
+            let (node, _) = self.insert_at(splice, entry.key, entry.value);
+            let node = unsafe { node.as_ref() };
+            node.set_meta(entry.meta);
+            node.set_count(entry.count);
+            // The evicted node still counts in `data_size`.
+            self.inner.data_size.fetch_sub(size, Ordering::SeqCst);
         }
     }
-}
 
-impl<R, C, A> fmt::Display for SkipList<R, C, A>
-where
-    R: RandomGenerator,
-    C: BaseComparator,
-    A: Arena,
-{
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[")?;
-        unsafe {
-            let mut head: *const Node = mem::transmute_copy(&self.inner.head);
-            loop {
-                let next = (*head).get_next(0);
-                if next.is_null() {
-                    break;
-                } else {
-                    write!(f, "{:?} ", (*next).data.as_ref())?;
-                    head = next as *const Node;
-                }
-            }
+    /// Returns the entry the `OverflowPolicy` would evict to make room for a
+    /// new one, if the list is full and the policy evicts, see `room`.
+    fn next_victim(&self) -> Option<Nav<'_>> {
+        match self.inner.config.max_entries {
+            Some(max) if self.len() >= max => {}
+            _ => return None,
+        }
+        match self.inner.config.overflow_policy {
+            OverflowPolicy::Reject => None,
+            OverflowPolicy::EvictSmallest => self.head().next_live(),
+            OverflowPolicy::EvictLargest => self.back(),
         }
-        write!(f, "]")
     }
-}
 
-impl<R, C, A, T> Extend<T> for SkipList<R, C, A>
-where
-    T: Into<u8>,
-    R: RandomGenerator,
-    C: BaseComparator,
-    A: Arena,
-{
-    #[inline]
-    fn extend<I: iter::IntoIterator<Item = T>>(&mut self, iterable: I) {
-        let iterator = iterable.into_iter();
-        for element in iterator {
-            self.insert(Bytes::from(vec![element.into()]));
+    /// Take out the nodes inserted by a failed `try_insert_batch`, and free
+    /// their memory if no reader can be on them.
+    fn unstage(
+        &self,
+        staged: Vec<NonNull<Node>>,
+        checkpoint: Option<ArenaCheckpoint>,
+        data_size: u64,
+    ) {
+        let retired = self.inner.nodes.retired();
+        for node in staged.iter().rev() {
+            self.pop(unsafe { node.as_ref() });
+        }
+        // What `Arc::get_mut` checks: the weak handles of background tasks
+        // could upgrade and read the nodes too.
+        let exclusive = Arc::strong_count(&self.inner) == 1
+            && Arc::weak_count(&self.inner) == 0
+            && self.inner.blooms.is_none();
+        if let (true, Some(checkpoint)) = (exclusive, checkpoint) {
+            // Every handle is `self`, and the nodes are unlinked: nothing
+            // allocated since the checkpoint is reachable.
+            unsafe {
+                self.inner.nodes.drop_retired_since(retired);
+                self.inner.arena.rollback(checkpoint)
+            };
+            self.inner.data_size.store(data_size, Ordering::SeqCst);
         }
     }
-}
-
-pub struct Iter<'a> {
-    head: *const Node,
-    size: usize,
-    _lifetime: PhantomData<&'a Node>,
-}
 
-impl<'a> Iterator for Iter<'a> {
-    type Item = &'a Node;
+    /// Insert `key` like `insert` and return a handle to its node. If the key was
+    /// ignored because of the duplicate policy, the handle points to the entry
+    /// already in the list, if it was dropped by `ComparatorCheck::Error`, to the
+    /// entry it was ordered inconsistently with, and if it was dropped by the
+    /// `OverflowPolicy`, to the smallest entry with `EvictSmallest`, the largest
+    /// otherwise.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// let one = sl.insert_ref(vec![1u8]);
+    /// let two = sl.insert_ref(vec![2u8]);
+    /// assert_eq!(sl.key_of(one), Some(&[1u8][..]));
+    /// assert_eq!(sl.next(one), Some(two));
+    /// assert_eq!(sl.next(two), None);
+    /// ```
+    pub fn insert_ref(&self, key: impl Into<Bytes>) -> NodeRef {
+        NodeRef::new(self.id(), self.insert_node(key.into(), Bytes::new()).0)
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        unsafe {
-            // If the lowest forward node is None, return None.
-            let next = (*self.head).get_next(0);
-            if !next.is_null() {
-                self.head = next;
-                if self.size > 0 {
-                    self.size -= 1;
-                }
-                return Some(&&*self.head);
-            }
-            None
-        }
+    /// Insert `key` like `insert`, searching from the path left in `splice` by the
+    /// previous insert instead of from the head, and leaving the path of this one.
+    ///
+    /// This is the insert with a hint of LevelDB and RocksDB: a batch of nearby
+    /// keys only searches the levels below the lowest one where the path still
+    /// brackets the next key.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator, Splice};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// let mut splice = Splice::new();
+    /// for i in 0..100u8 {
+    ///     sl.insert_with_splice(&mut splice, vec![i]);
+    /// }
+    /// assert_eq!(sl.len(), 100);
+    /// ```
+    #[doc(alias = "insert_with_hint")]
+    pub fn insert_with_splice(&self, splice: &mut Splice, key: impl Into<Bytes>) -> InsertOutcome {
+        let _writer = self.writer();
+        self.insert_at(splice, key.into(), Bytes::new()).1
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.size, Some(self.size))
+    /// Merge a batch of keys sorted with the comparator of the list, e.g. a sorted
+    /// flush or replication batch, in a single forward pass: every search starts
+    /// from the path of the previous insert instead of from the head.
+    ///
+    /// Keys out of order are still inserted at the right place, only slower.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![1, 4]);
+    /// sl.extend_sorted(vec![vec![0u8], vec![2], vec![3], vec![5]]);
+    /// assert_eq!(format!("{}", sl), "[[0] [1] [2] [3] [4] [5] ]");
+    /// ```
+    pub fn extend_sorted<I>(&self, keys: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<Bytes>,
+    {
+        let _writer = self.writer();
+        let mut splice = Splice::new();
+        for key in keys {
+            self.insert_at(&mut splice, key.into(), Bytes::new());
+        }
     }
-}
 
-impl<'a, R, C, A> iter::IntoIterator for &'a SkipList<R, C, A>
+    /// Build a list out of keys sorted with `cmp` in O(n), e.g. a memtable out of
+    /// a sorted log replay, see `append_sorted`.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let keys = (0..1000u32).map(|i| i.to_be_bytes().to_vec());
+    /// let sl = SkipList::from_sorted_iter(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    ///     keys,
+    /// )
+    /// .unwrap();
+    /// assert_eq!(sl.len(), 1000);
+    /// assert_eq!(sl.select(500), Some(&500u32.to_be_bytes()[..]));
+    ///
+    /// let err = SkipList::from_sorted_iter(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    ///     vec![vec![1u8], vec![0]],
+    /// );
+    /// assert_eq!(err.err().unwrap().next.as_ref(), &[0]);
+    /// ```
+    pub fn from_sorted_iter<I>(rnd: R, cmp: C, arena: A, keys: I) -> Result<Self, OrderError>
+    where
+        I: IntoIterator,
+        I::Item: Into<Bytes>,
+    {
+        let sl = Self::new(rnd, cmp, arena);
+        sl.append_sorted(keys)?;
+        Ok(sl)
+    }
+
+    /// Append keys sorted with the comparator of the list, none of them less
+    /// than the last key of the list, in O(n): every node is linked after the
+    /// last one of each level, without any search, and gets the height of its
+    /// position in a perfectly balanced list, one level for every factor of the
+    /// branching factor in its index. Returns the number of keys inserted.
+    ///
+    /// A key equal to the last one is inserted like by `insert`, going through
+    /// the duplicate policy, and so is every key through the `OverflowPolicy`.
+    /// At the first key less than the one before it, the keys before it stay
+    /// appended and the pair is returned.
+    pub fn append_sorted<I>(&self, keys: I) -> Result<usize, OrderError>
+    where
+        I: IntoIterator,
+        I::Item: Into<Bytes>,
+    {
+        let mut splice = self.writer();
+        let branching = self.inner.config.branching_factor as usize;
+        let max_height = self.inner.config.max_height;
+        let mut inserted = 0;
+        let mut tails = self.last_before(|_| true);
+        for key in keys {
+            let key = key.into();
+            let last = tails[0];
+            if last != self.head() {
+                match self.compare(&key, last.key()) {
+                    cmp::Ordering::Less => {
+                        return Err(OrderError {
+                            prev: last.data.clone(),
+                            next: key,
+                            list: self.name().map(String::from),
+                        });
+                    }
+                    cmp::Ordering::Equal => {
+                        let (_, outcome) = self.insert_at(&mut splice, key, Bytes::new());
+                        if !matches!(
+                            outcome,
+                            InsertOutcome::Ignored
+                                | InsertOutcome::Rejected
+                                | InsertOutcome::Violation
+                                | InsertOutcome::Full
+                        ) {
+                            inserted += 1;
+                        }
+                        tails = self.last_before(|_| true);
+                        continue;
+                    }
+                    cmp::Ordering::Greater => {}
+                }
+            }
+            let evict = match self.room(last, &key) {
+                Room::Free => None,
+                Room::Evict(victim) => Some(victim),
+                Room::Full(_) => continue,
+            };
+            let height = balanced_height(self.len() + 1, branching, max_height);
+            self.raise_max_height(height);
+            let n = self.new_node(key, Bytes::new(), height);
+            self.link(&mut tails, n, true);
+            self.inner
+                .data_size
+                .fetch_add(n.data.len() as u64, Ordering::SeqCst);
+            self.inner.len.fetch_add(1, Ordering::SeqCst);
+            if let Some(blooms) = &self.inner.blooms {
+                self.schedule_rebuilds(blooms, &tails, n);
+            }
+            for tail in tails.iter_mut().take(height) {
+                *tail = n;
+            }
+            if let Some(victim) = evict {
+                self.pop(victim.node());
+                // The victim may end a level, unlinked now.
+                tails = self.last_before(|_| true);
+            }
+            inserted += 1;
+        }
+        Ok(inserted)
+    }
+
+    /// Load a large batch of unsorted keys: they are sorted on the rayon thread
+    /// pool, then merged in a single pass like with `extend_sorted`. Equal keys
+    /// keep the order of the batch.
+    /// # Examples
+    /// ```
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.build_par((0..1000u32).rev().map(|i| i.to_be_bytes().to_vec()).collect::<Vec<_>>());
+    /// assert_eq!(sl.len(), 1000);
+    /// assert!(sl.is_sorted().is_ok());
+    /// ```
+    #[cfg(feature = "parallel")]
+    pub fn build_par<I>(&self, keys: I)
+    where
+        I: IntoParallelIterator,
+        I::Item: Into<Bytes>,
+        C: Sync,
+    {
+        let mut keys: Vec<Bytes> = keys.into_par_iter().map(Into::into).collect();
+        let cmp = &self.inner.cmp;
+        keys.par_sort_by(|a, b| cmp.compare(a, b));
+        self.extend_sorted(keys);
+    }
+
+    /// Merge the entries of another list, e.g. when flushing or merging shards,
+    /// keeping the height of their nodes instead of drawing new ones. The merged
+    /// list is as balanced as the source and doesn't depend on the random
+    /// generator of the destination. Heights above the `max_height` of the
+    /// destination are capped.
+    ///
+    /// Keys and values are shared with the source, not copied.
+    /// Like `extend_sorted`, every search starts from the path of the previous
+    /// insert, so nodes in the order of the list are ingested in a single pass.
+    ///
+    /// Fails if `src` is a handle of this list, which would be walked while
+    /// growing.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut src = SkipList::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+    /// src.extend(0..100);
+    /// let dst = SkipList::new(Random::new(42), DefaultComparator::default (), ArenaImpl::new());
+    /// dst.ingest(&src).unwrap();
+    /// let heights = |sl: &SkipList<_, _, _>| sl.into_iter().map(|n| n.height()).collect::<Vec<_>>();
+    /// assert_eq!(heights(&dst), heights(&src));
+    /// assert!(src.ingest(&src.clone()).is_err());
+    /// ```
+    pub fn ingest<R2, C2, A2>(&self, src: &SkipList<R2, C2, A2>) -> Result<(), SameListError>
+    where
+        R2: RandomGenerator,
+        C2: BaseComparator,
+        A2: Arena,
+    {
+        // Handles of one list share its inner state, and only them.
+        if ptr::eq(
+            Arc::as_ptr(&self.inner).cast::<u8>(),
+            Arc::as_ptr(&src.inner).cast::<u8>(),
+        ) {
+            return Err(self.same_list_error());
+        }
+        let _writer = self.writer();
+        self.ingest_locked(src);
+        Ok(())
+    }
+
+    fn same_list_error(&self) -> SameListError {
+        SameListError {
+            list: self.name().map(String::from),
+        }
+    }
+
+    /// `ingest`, with the writer lock already held.
+    fn ingest_locked<'a, I>(&self, nodes: I)
+    where
+        I: IntoIterator<Item = &'a Node>,
+    {
+        let max_height = self.inner.config.max_height;
+        let mut splice = Splice::new();
+        for node in nodes {
+            let height = cmp::min(node.height(), max_height);
+            let (n, outcome) = self.insert_at_height(
+                &mut splice,
+                node.data.clone(),
+                node.value().clone(),
+                Some(height),
+                None,
+            );
+            // Counts of a multiset carry over.
+            let n = unsafe { n.as_ref() };
+            match outcome {
+                InsertOutcome::Counted => {
+                    n.add_count(node.count() - 1);
+                }
+                InsertOutcome::Ignored
+                | InsertOutcome::Rejected
+                | InsertOutcome::Violation
+                | InsertOutcome::Full => {}
+                _ => n.set_count(node.count()),
+            }
+        }
+    }
+
+    /// Insert an entry, resuming from the path of the last insert.
+    pub(crate) fn insert_node(&self, key: Bytes, value: Bytes) -> (NonNull<Node>, InsertOutcome) {
+        let mut splice = self.writer();
+        self.insert_at(&mut splice, key, value)
+    }
+
+    /// Insert an entry, with the writer lock held.
+    pub(crate) fn insert_at(
+        &self,
+        splice: &mut Splice,
+        key: Bytes,
+        value: Bytes,
+    ) -> (NonNull<Node>, InsertOutcome) {
+        self.insert_at_height(splice, key, value, None, None)
+    }
+
+    /// Insert an entry with a tower of `height` levels, or of a random height,
+    /// with the writer lock held. Returns the inserted node, or the equal one
+    /// kept instead.
+    ///
+    /// With a `pending_on` node, the entry is inserted whatever the duplicate
+    /// policy, hidden until that node is removed, and isn't counted by `len`.
+    fn insert_at_height(
+        &self,
+        splice: &mut Splice,
+        key: Bytes,
+        value: Bytes,
+        height: Option<usize>,
+        pending_on: Option<&Node>,
+    ) -> (NonNull<Node>, InsertOutcome) {
+        let head = self.head();
+        let generation = self.inner.generation.load(Ordering::SeqCst);
+        let mut path = if splice.generation == generation {
+            // The generation didn't change, the nodes of the path are still linked.
+            splice
+                .prev
+                .map(|node| unsafe { Nav::from_ptr(node) }.unwrap_or(head))
+        } else {
+            splice.generation = generation;
+            [head; K_MAX_HEIGHT]
+        };
+        let max_height = self.get_max_height();
+        let prev = &mut path;
+        // The nodes of the path get closer to the previous key as the level goes
+        // down. Resume from the lowest level where the path still brackets `key`,
+        // only the levels below it need a search.
+        let mut level = 0;
+        // Once a node of the path is before `key`, so are those above it. A next
+        // node already found before `key` on the level below needs no comparison.
+        let mut path_before = false;
+        let mut passed = None;
+        while level < max_height {
+            path_before = path_before || self.node_is_before_key(prev[level], key.as_ref());
+            if path_before {
+                let next = prev[level].next(level);
+                if next.is_none() || next != passed {
+                    match self.before_key(next, key.as_ref()) {
+                        None => break,
+                        next => passed = next,
+                    }
+                }
+            }
+            level += 1;
+        }
+        // The path is up to date on the levels below `valid`.
+        let valid = if level == max_height && !path_before {
+            *prev = [head; K_MAX_HEIGHT];
+            self.find_from(key.as_ref(), prev, max_height);
+            max_height
+        } else {
+            if level == max_height {
+                // The key is past the next node of the path on every level, e.g.
+                // an ascending insert far after the previous one: move on from the
+                // path rather than from the head.
+                level = max_height - 1;
+                self.catch_up(prev, level, key.as_ref());
+            }
+            for l in (0..level).rev() {
+                prev[l] = prev[l + 1];
+                self.catch_up(prev, l, key.as_ref());
+            }
+            level + 1
+        };
+        if pending_on.is_none() {
+            if let Some(neighbour) = self.check_order(prev[0], &key) {
+                splice.prev = path.map(Nav::as_ptr);
+                return (NonNull::from(neighbour.node()), InsertOutcome::Violation);
+            }
+        }
+        let outcome = match self.live_equal(prev[0].next(0), key.as_ref()) {
+            None => InsertOutcome::Inserted,
+            Some(_) if pending_on.is_some() => InsertOutcome::Duplicate,
+            Some(existing) => match self.inner.config.duplicate_policy {
+                DuplicatePolicy::AllowDuplicates => InsertOutcome::Duplicate,
+                DuplicatePolicy::Ignore => {
+                    splice.prev = path.map(Nav::as_ptr);
+                    return (NonNull::from(existing.node()), InsertOutcome::Ignored);
+                }
+                DuplicatePolicy::Replace => InsertOutcome::Replaced,
+                DuplicatePolicy::Error => {
+                    splice.prev = path.map(Nav::as_ptr);
+                    return (NonNull::from(existing.node()), InsertOutcome::Rejected);
+                }
+                DuplicatePolicy::Count if existing.add_count(1) => {
+                    splice.prev = path.map(Nav::as_ptr);
+                    return (NonNull::from(existing.node()), InsertOutcome::Counted);
+                }
+                // The last count was just removed, so is the entry.
+                DuplicatePolicy::Count => InsertOutcome::Inserted,
+            },
+        };
+        let mut evict = None;
+        if pending_on.is_none() && outcome != InsertOutcome::Replaced {
+            match self.room(prev[0], &key) {
+                Room::Free => {}
+                Room::Evict(victim) => evict = Some(victim),
+                Room::Full(kept) => {
+                    splice.prev = path.map(Nav::as_ptr);
+                    return (NonNull::from(kept.node()), InsertOutcome::Full);
+                }
+            }
+        }
+        let height = height.unwrap_or_else(|| self.random_height());
+        // Levels above the bracket only hold nodes before `key`, move them up to
+        // it where they are needed: the new tower and the bloom segment level.
+        let needed = cmp::max(
+            height,
+            self.inner
+                .blooms
+                .as_ref()
+                .map_or(0, |blooms| blooms.segment_level() + 1),
+        );
+        for l in valid..needed {
+            if l >= max_height {
+                prev[l] = head;
+            } else {
+                self.catch_up(prev, l, key.as_ref());
+            }
+        }
+        self.raise_max_height(height);
+        // Accelerate memory allocation
+        let n = self.new_node(key, value, height);
+        if let Some(source) = pending_on {
+            n.mark_pending(source);
+        }
+        self.link(prev, n, pending_on.is_none());
+        self.inner
+            .data_size
+            .fetch_add((n.data.len() + n.value().len()) as u64, Ordering::SeqCst);
+        if pending_on.is_none() {
+            self.inner.len.fetch_add(1, Ordering::SeqCst);
+        }
+        if let Some(blooms) = &self.inner.blooms {
+            self.schedule_rebuilds(blooms, prev, n);
+        }
+        if outcome == InsertOutcome::Replaced {
+            // The new node comes first, readers never miss the key.
+            self.remove_from(n.next(0), n.key());
+        }
+        if let Some(victim) = evict {
+            // `None` if a concurrent `remove` made room already.
+            self.pop(victim.node());
+        }
+        // The next key is likely to come right after this one.
+        for node in prev.iter_mut().take(height) {
+            *node = n;
+        }
+        splice.prev = path.map(Nav::as_ptr);
+        (NonNull::from(n.node()), outcome)
+    }
+
+    /// Whether a new entry for `key`, linked after `prev`, fits in `max_entries`,
+    /// see `OverflowPolicy`.
+    fn room<'a>(&'a self, prev: Nav<'a>, key: &[u8]) -> Room<'a> {
+        match self.inner.config.max_entries {
+            Some(max) if self.len() >= max => {}
+            _ => return Room::Free,
+        }
+        let head = self.head();
+        match self.inner.config.overflow_policy {
+            OverflowPolicy::Reject => Room::Full(self.back().unwrap_or(head)),
+            OverflowPolicy::EvictSmallest => match head.next_live() {
+                // The new entry goes before the equal ones.
+                Some(first) if self.compare(key, first.key()) == cmp::Ordering::Greater => {
+                    Room::Evict(first)
+                }
+                first => Room::Full(first.unwrap_or(head)),
+            },
+            OverflowPolicy::EvictLargest => match (prev.next_live(), self.back()) {
+                (Some(_), Some(last)) => Room::Evict(last),
+                (_, last) => Room::Full(last.unwrap_or(head)),
+            },
+        }
+    }
+
+    /// Allocate a node in the arena of the list, see `Node::with_value`.
+    fn new_node(&self, key: Bytes, value: Bytes, height: usize) -> Nav<'_> {
+        let node = Node::with_value(key, value, height, &self.inner.arena);
+        // The arena lives as long as the list.
+        unsafe { NodePtr::new(node).nav() }
+    }
+
+    /// Check the comparator against the neighbours of `key` on the bottom level,
+    /// `prev` and the node after it, as `comparator_check` asks. Returns the
+    /// neighbour it orders inconsistently with `key`, unless that panicked.
+    fn check_order<'a>(&'a self, prev: Nav<'a>, key: &Bytes) -> Option<Nav<'a>> {
+        let check = self.inner.config.comparator_check;
+        if check == ComparatorCheck::Off {
+            return None;
+        }
+        // `node` should come before `key` if `before`, not after it otherwise,
+        // whichever side is compared first.
+        let misplaced = |node: Nav<'a>, before: bool| {
+            let ord = self.compare(node.key(), key);
+            let misplaced = if before {
+                ord != cmp::Ordering::Less
+            } else {
+                ord == cmp::Ordering::Less
+            };
+            misplaced || self.compare(key, node.key()) != ord.reverse()
+        };
+        let neighbour = Some(prev)
+            .filter(|&prev| prev != self.head() && misplaced(prev, true))
+            .or_else(|| prev.next(0).filter(|&next| misplaced(next, false)))?;
+        let violation = ComparatorViolation {
+            key: key.clone(),
+            neighbour: neighbour.data.clone(),
+            list: self.name().map(String::from),
+        };
+        match check {
+            ComparatorCheck::Panic => panic!("{}", violation),
+            ComparatorCheck::DebugPanic if cfg!(debug_assertions) => panic!("{}", violation),
+            _ => Some(neighbour),
+        }
+    }
+
+    /// Link `n` after the nodes of `prev`, the path to its key, and count it in
+    /// the spans of the links over it if it is `live`.
+    fn link<'a>(&'a self, prev: &mut Path<'a>, n: Nav<'a>, live: bool) {
+        let _spans = self.spans();
+        let before = self.settle_path(prev);
+        if let Some(blooms) = &self.inner.blooms {
+            // Readers finding the node must find its key in the filters.
+            self.update_blooms(blooms, prev, n);
+        }
+        for (level, &node) in prev.iter().enumerate() {
+            if level < n.height() {
+                n.set_span(level, node.span(level) - before[level]);
+                node.set_span(level, before[level] + usize::from(live));
+                n.set_next(level, node.next(level));
+                node.set_next(level, Some(n));
+            } else if live {
+                node.add_span(level, 1);
+            }
+        }
+    }
+
+    /// Move the levels of `prev`, a path to a key which may lag behind above
+    /// level 0, up to the last node before the key, following the level below
+    /// without comparing keys. Levels above the list get the head. Returns, for
+    /// every level, the number of live entries after `prev[level]` up to `prev[0]`
+    /// included.
+    fn settle_path<'a>(&'a self, prev: &mut Path<'a>) -> [usize; K_MAX_HEIGHT] {
+        let head = self.head();
+        for node in prev.iter_mut().skip(self.get_max_height()) {
+            *node = head;
+        }
+        let mut before = [0; K_MAX_HEIGHT];
+        for level in 1..K_MAX_HEIGHT {
+            let mut x = prev[level];
+            let mut between = 0;
+            loop {
+                if x.height() > level {
+                    prev[level] = x;
+                    between = 0;
+                }
+                if x == prev[level - 1] {
+                    break;
+                }
+                between += x.span(level - 1);
+                // `prev[level - 1]` comes later on the level.
+                x = x.next(level - 1).unwrap();
+            }
+            before[level] = before[level - 1] + between;
+        }
+        before
+    }
+
+    /// Add `delta` to the spans of the links over `node`, when it is removed or
+    /// shows up.
+    fn count_live(&self, node: &Node, delta: isize) {
+        let _spans = self.spans();
+        let mut path = [self.head(); K_MAX_HEIGHT];
+        let mut x = self.seek(node.data.as_ref(), &mut path);
+        // `path` leads to the first node equal to `node`, which may come after
+        // other equal ones.
+        loop {
+            match x {
+                Some(x) if x.is(node) => break,
+                Some(x) if self.eq(x.key(), &node.data) => path
+                    .iter_mut()
+                    .take(x.height())
+                    .for_each(|level| *level = x),
+                // Unlinked by `pop` or `delete_range`.
+                _ => return,
+            }
+            x = x.and_then(|x| x.next(0));
+        }
+        for (level, x) in path.iter().enumerate() {
+            x.add_span(level, delta);
+        }
+    }
+
+    /// Record the key of `n` in the filter of its segment, or start the segment
+    /// `n` begins. Runs before `n` is linked after `prev`, a settled path.
+    fn update_blooms(&self, blooms: &SegmentBlooms, prev: &Path<'_>, n: Nav<'_>) {
+        let level = blooms.segment_level();
+        let parent = prev[level].as_ptr() as usize;
+        if n.height() <= level {
+            blooms.add(parent, n.key());
+        } else if self.inner.background.is_enabled() {
+            // Leave the walk over the keys of the new segment to a background
+            // task, see `schedule_rebuilds`.
+            blooms.fork(parent, n.as_ptr() as usize, n.key());
+        } else {
+            // The new node starts a segment, made of its key and the ones after
+            // it up to the next node of the segment level.
+            let end = prev[level].next(level);
+            let rest = iter::successors(prev[0].next(0), |x| x.next(0))
+                .take_while(|&x| Some(x) != end)
+                .map(Nav::key);
+            blooms.split(n.as_ptr() as usize, iter::once(n.key()).chain(rest));
+        }
+    }
+
+    /// Have a background task compute the filters of the segment `n` starts
+    /// and of the one it was split from, once `n` is linked after `prev`.
+    fn schedule_rebuilds(&self, blooms: &SegmentBlooms, prev: &Path<'_>, n: Nav<'_>) {
+        let level = blooms.segment_level();
+        if n.height() > level && self.inner.background.is_enabled() {
+            self.inner
+                .background
+                .schedule(Job::RebuildBloom(n.as_ptr() as usize));
+            // The parent no longer holds the keys moved to the new segment.
+            self.inner
+                .background
+                .schedule(Job::RebuildBloom(prev[level].as_ptr() as usize));
+        }
+    }
+
+    fn run_job(&self, job: Job) {
+        match job {
+            Job::RebuildBloom(segment) => {
+                if let Some(blooms) = &self.inner.blooms {
+                    self.rebuild_bloom(blooms, segment);
+                }
+            }
+            Job::Purge(node) => self.purge(node),
+            Job::ShrinkHeight => {
+                self.lower_max_height();
+            }
+        }
+    }
+
+    fn rebuild_bloom(&self, blooms: &SegmentBlooms, segment: usize) {
+        blooms.rebuild(segment, self.segment_keys(blooms, segment));
+    }
+
+    /// Returns the keys of the segment starting at `segment`.
+    fn segment_keys(&self, blooms: &SegmentBlooms, segment: usize) -> impl Iterator<Item = &[u8]> {
+        // Segments are keyed by the address of a node of this list.
+        let start = unsafe { Nav::from_ptr(segment as *const Node) }.unwrap();
+        let end = start.next(blooms.segment_level());
+        let first = if start == self.head() {
+            start.next(0)
+        } else {
+            Some(start)
+        };
+        iter::successors(first, |x| x.next(0))
+            .take_while(move |&x| Some(x) != end)
+            .map(Nav::key)
+    }
+
+    /// Provide a way to run maintenance work off the write path, e.g. on a thread
+    /// pool. Without it writers do that work inline: with prefix blooms enabled,
+    /// every node starting a new segment costs `insert` a walk over the keys of the
+    /// segment. With it, the new segment starts with a copy of the filter it is
+    /// split from and `spawn_fn` gets a task computing the exact filter.
+    ///
+    /// Tasks also purge the entries tombstoned by `remove` and `remove_one`,
+    /// unlinking them like `pop_first` does, and lower the max height once its top
+    /// levels are left empty, like `shrink_height`. Those take the writer lock, so
+    /// `spawn_fn` has to hand the tasks over to another thread rather than run
+    /// them in place.
+    ///
+    /// Tasks only hold a weak reference to the list and do nothing once it has
+    /// been dropped or frozen. With the `deterministic` feature `spawn_fn` is
+    /// ignored, so the work done by an insert doesn't depend on thread scheduling.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{
+    ///     ArenaImpl, DefaultComparator, PrefixBloomConfig, Random, SkipList, SkipListConfig,
+    /// };
+    ///
+    /// let config = SkipListConfig {
+    ///     prefix_bloom: Some(PrefixBloomConfig::default()),
+    ///     ..Default::default()
+    /// };
+    /// let mut sl = SkipList::with_config(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    ///     config,
+    /// ).unwrap();
+    /// sl.set_spawn_fn(|task| {
+    ///     std::thread::spawn(task);
+    /// });
+    /// sl.extend(0..100);
+    /// assert!(sl.contains_key(&[42]));
+    /// ```
+    pub fn set_spawn_fn<F>(&self, spawn_fn: F)
+    where
+        F: Fn(BackgroundTask) + Send + Sync + 'static,
+        R: Send + Sync + 'static,
+        C: Send + Sync + 'static,
+        A: Send + Sync + 'static,
+    {
+        if cfg!(feature = "deterministic") {
+            return;
+        }
+        let list = Arc::downgrade(&self.inner);
+        self.inner.background.set(Box::new(move |job| {
+            let list = list.clone();
+            spawn_fn(Box::new(move || {
+                if let Some(inner) = list.upgrade() {
+                    SkipList::from_inner(inner).run_job(job);
+                }
+            }))
+        }));
+    }
+
+    /// Returns the first live node equal to `key`, counting the access for
+    /// `hot_keys`.
+    pub(crate) fn find_equal(&self, key: &[u8]) -> Option<&Node> {
+        let x = self.live_equal(self.seek(key, &mut []), key)?;
+        self.record_access(x.node());
+        Some(x.node())
+    }
+
+    /// Returns the newest node equal to `key`, even if it was removed, so that a
+    /// tombstone can shadow older lists.
+    #[cfg(feature = "memtable")]
+    pub(crate) fn find_newest(&self, key: &[u8]) -> Option<&Node> {
+        self.seek(key, &mut [])
+            .filter(|x| self.eq(key, x.key()))
+            .map(Nav::node)
+    }
+
+    /// Replace the value of `node` with `value`, as long as `accept` returns
+    /// `true` for the current value. Returns `false` as soon as it doesn't.
+    ///
+    /// Readers may still be on the replaced value, which is only dropped with
+    /// the list, but it no longer counts in `memory_usage`.
+    pub(crate) fn update_value(
+        &self,
+        node: &Node,
+        value: Bytes,
+        mut accept: impl FnMut(&Bytes) -> bool,
+    ) -> bool {
+        let len = value.len();
+        let mut value = Some(value);
+        let mut slot: *mut Bytes = ptr::null_mut();
+        loop {
+            let current = node.value_ptr();
+            let current_value = if current.is_null() {
+                Bytes::new()
+            } else {
+                unsafe { (*current).clone() }
+            };
+            if !accept(&current_value) {
+                if !slot.is_null() {
+                    // Nobody else saw the slot allocated for a previous try.
+                    unsafe { ptr::drop_in_place(slot) };
+                }
+                return false;
+            }
+            // Allocated once, retries reuse it.
+            if let Some(value) = value.take() {
+                slot = Node::alloc_value(value, &self.inner.arena);
+            }
+            if node.compare_exchange_value(current, slot) {
+                self.inner.nodes.retire_value(current);
+                self.inner.data_size.fetch_add(len as u64, Ordering::SeqCst);
+                self.inner
+                    .data_size
+                    .fetch_sub(current_value.len() as u64, Ordering::SeqCst);
+                return true;
+            }
+        }
+    }
+
+    /// Returns the first node from `x` on that is equal to `key` and wasn't
+    /// removed, or null.
+    fn live_equal<'a>(&self, mut x: Option<Nav<'a>>, key: &[u8]) -> Option<Nav<'a>> {
+        while let Some(node) = x.filter(|x| self.eq(key, x.key())) {
+            if !node.is_deleted() {
+                return Some(node);
+            }
+            x = node.next(0);
+        }
+        None
+    }
+
+    /// Remove every entry equal to `key`, returns `false` if there was none.
+    ///
+    /// Entries are tombstoned rather than unlinked: the nodes stay where they are,
+    /// so iterators, guards and handles sitting on them remain valid, and every
+    /// read skips them from now on. Their memory goes away with the arena. With a
+    /// spawn function, see `set_spawn_fn`, a background task unlinks them later
+    /// so that reads stop walking over them.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![1, 2, 3]);
+    /// assert!(sl.remove(&[2]));
+    /// assert!(!sl.remove(&[2]));
+    /// assert_eq!(sl.len(), 2);
+    /// assert_eq!(format!("{}", sl), "[[1] [3] ]");
+    /// ```
+    pub fn remove(&self, key: &[u8]) -> bool {
+        self.remove_from(self.seek(key, &mut []), key) > 0
+    }
+
+    /// Returns the number of times `key` is in the list: the count of its entry
+    /// with `DuplicatePolicy::Count`, the number of entries equal to it
+    /// otherwise.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, DefaultComparator, DuplicatePolicy, InsertOutcome, SkipListConfig};
+    ///
+    /// let config = SkipListConfig {
+    ///     duplicate_policy: DuplicatePolicy::Count,
+    ///     ..Default::default()
+    /// };
+    /// let sl = SkipList::from_config(Random::new(0xdead_beef), DefaultComparator::default (), config).unwrap();
+    /// sl.insert(vec![1u8]);
+    /// assert_eq!(sl.insert(vec![1u8]), InsertOutcome::Counted);
+    /// sl.insert(vec![2u8]);
+    /// assert_eq!(sl.count(&[1]), 2);
+    /// assert_eq!(sl.len(), 2);
+    ///
+    /// assert!(sl.remove_one(&[1]));
+    /// assert_eq!(sl.count(&[1]), 1);
+    /// assert!(sl.remove_one(&[1]));
+    /// assert!(!sl.contains_key(&[1]));
+    /// assert!(!sl.remove_one(&[1]));
+    /// ```
+    pub fn count(&self, key: &[u8]) -> usize {
+        iter::successors(self.seek(key, &mut []), |x| x.next(0))
+            .take_while(|x| self.eq(key, x.key()))
+            .filter(|x| !x.is_deleted())
+            .fold(0, |sum: usize, x| sum.saturating_add(x.count()))
+    }
+
+    /// Take one off the count of `key`, removing its entry once the count drops
+    /// to 0. Without `DuplicatePolicy::Count`, remove one of the entries equal
+    /// to `key`. Returns `false` if there was none.
+    pub fn remove_one(&self, key: &[u8]) -> bool {
+        let mut x = self.seek(key, &mut []);
+        while let Some(node) = self.live_equal(x, key) {
+            match node.decrement_count() {
+                Some(1) => {
+                    if node.mark_deleted() {
+                        self.count_live(&node, -1);
+                        self.inner.len.fetch_sub(1, Ordering::SeqCst);
+                        self.schedule_purge(&node);
+                    }
+                    return true;
+                }
+                Some(_) => return true,
+                // A concurrent `remove_one` took the last count.
+                None => x = node.next(0),
+            }
+        }
+        false
+    }
+
+    /// Remove the entries equal to `key` from `x` on, returns how many there were.
+    fn remove_from(&self, mut x: Option<Nav<'_>>, key: &[u8]) -> usize {
+        let mut removed = 0;
+        while let Some(node) = x.filter(|x| self.eq(key, x.key())) {
+            if node.mark_deleted() {
+                self.count_live(&node, -1);
+                self.schedule_purge(&node);
+                removed += 1;
+            }
+            x = node.next(0);
+        }
+        self.inner.len.fetch_sub(removed as u64, Ordering::SeqCst);
+        removed
+    }
+
+    /// Remove the smallest key and return it, e.g. to use the list as an ordered
+    /// work queue. `None` if the list is empty.
+    ///
+    /// Unlike `remove`, the entry is unlinked so that a queue drained from the
+    /// front doesn't pile up removed entries to skip. Readers sitting on it can
+    /// still move on, and the returned key shares its buffer with the node.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![2, 3, 1]);
+    /// assert_eq!(sl.pop_first().unwrap().as_ref(), &[1]);
+    /// assert_eq!(sl.pop_last().unwrap().as_ref(), &[3]);
+    /// assert_eq!(sl.pop_first().unwrap().as_ref(), &[2]);
+    /// assert_eq!(sl.pop_first(), None);
+    /// ```
+    pub fn pop_first(&self) -> Option<Bytes> {
+        let _writer = self.writer();
+        loop {
+            let x = NonNull::from(self.head().next_live()?.node());
+            // Lost to a concurrent `remove`, try the next one.
+            if let Some(key) = self.pop(unsafe { x.as_ref() }) {
+                return Some(key);
+            }
+        }
+    }
+
+    /// Remove the largest key and return it, see `pop_first`.
+    pub fn pop_last(&self) -> Option<Bytes> {
+        let _writer = self.writer();
+        loop {
+            let x = NonNull::from(self.back()?.node());
+            if let Some(key) = self.pop(unsafe { x.as_ref() }) {
+                return Some(key);
+            }
+        }
+    }
+
+    /// Remove `node` and unlink it, returns its key, or `None` if it was already
+    /// removed. The caller holds the writer lock.
+    fn pop(&self, node: &Node) -> Option<Bytes> {
+        if !node.mark_deleted() {
+            return None;
+        }
+        self.count_live(node, -1);
+        self.inner.len.fetch_sub(1, Ordering::SeqCst);
+        if !self.in_segments(node) {
+            self.unlink(node);
+        }
+        Some(node.data.clone())
+    }
+
+    /// Have a background task unlink `node`, just removed, like `pop` does.
+    fn schedule_purge(&self, node: &Node) {
+        if !self.inner.background.is_enabled() || self.in_segments(node) {
+            return;
+        }
+        self.inner
+            .background
+            .schedule(Job::Purge(node as *const Node as usize));
+    }
+
+    /// Unlink the removed node at `node`, unless a writer got to it first. The
+    /// address stays valid while the list is alive, nodes are only freed with the
+    /// arena.
+    fn purge(&self, node: usize) {
+        let _writer = self.writer();
+        let node = unsafe { &*(node as *const Node) };
+        // `split_off` may have moved it to another list since, then it is not
+        // found here.
+        let linked = iter::successors(self.seek(node.data.as_ref(), &mut []), |x| x.next(0))
+            .take_while(|x| self.eq(x.key(), &node.data))
+            .any(|x| x.is(node));
+        if linked {
+            self.unlink(node);
+        }
+    }
+
+    /// Have a background task lower the max height once the top level is left
+    /// empty by unlinking nodes. The caller holds the writer lock.
+    fn schedule_shrink(&self) {
+        let top = self.get_max_height() - 1;
+        if top > 0 && self.head().next(top).is_none() {
+            self.inner.background.schedule(Job::ShrinkHeight);
+        }
+    }
+
+    /// Returns `true` if `node` starts a bloom segment: the keys after it are in
+    /// its filter, they would be lost if it went away.
+    fn in_segments(&self, node: &Node) -> bool {
+        self.inner
+            .blooms
+            .as_ref()
+            .is_some_and(|blooms| node.height() > blooms.segment_level())
+    }
+
+    /// Take `node` out of every level it is linked at. Its own links are kept, so
+    /// readers on it carry on with the next nodes.
+    fn unlink(&self, node: &Node) {
+        let _spans = self.spans();
+        let mut prev = [self.head(); K_MAX_HEIGHT];
+        self.seek(node.data.as_ref(), &mut prev);
+        for (level, &start) in prev.iter().enumerate().take(node.height()) {
+            // `start` is before the first node equal to `node`, which may come
+            // after other equal ones.
+            let mut x = start;
+            while let Some(next) = x.next(level).filter(|next| self.eq(next.key(), &node.data)) {
+                if next.is(node) {
+                    x.add_span(level, node.span(level) as isize);
+                    x.set_next(level, next.next(level));
+                    break;
+                }
+                x = next;
+            }
+        }
+        self.inner.nodes.retire(Nav::from(node), 1);
+        // Splices may point to the node, which doesn't lead to new ones anymore.
+        self.inner
+            .generation
+            .store(next_generation(), Ordering::SeqCst);
+        self.schedule_shrink();
+    }
+
+    /// Remove every entry in `range` at once: the towers are cut where the range
+    /// starts and relinked where it ends, instead of searching and tombstoning the
+    /// keys one by one. Returns the number of entries removed. Counting them and
+    /// their size walks the range on the bottom level.
+    ///
+    /// Like `pop`, the nodes keep their own links, so readers on them carry on
+    /// past the range. Their memory goes back with the arena, once no handle of
+    /// the list is left.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(0..10);
+    /// assert_eq!(sl.delete_range(&[2][..]..&[7][..]), 5);
+    /// assert_eq!(sl.delete_range(&[8][..]..), 2);
+    /// assert_eq!(format!("{}", sl), "[[0] [1] [7] ]");
+    /// ```
+    pub fn delete_range<'k>(&self, range: impl RangeBounds<&'k [u8]>) -> usize {
+        let _writer = self.writer();
+        let _spans = self.spans();
+        let start = self.last_before(|key| !self.is_after_start_bound(key, range.start_bound()));
+        let end = self.last_before(|key| self.is_before_end_bound(key, range.end_bound()));
+        if end[0] == start[0] || self.node_is_before(end[0], start[0]) {
+            return 0;
+        }
+        let segment_level = self.inner.blooms.as_ref().map(|b| b.segment_level());
+        let mut segments = vec![];
+        // The live entries of the range according to the spans, some may have been
+        // removed by another handle still waiting to update them.
+        let mut counted = 0;
+        let (mut unlinked, mut removed, mut data_size) = (0, 0, 0);
+        let mut x = start[0];
+        while x != end[0] {
+            counted += x.span(0);
+            // `end[0]` comes later on the level.
+            x = x.next(0).unwrap();
+            unlinked += 1;
+            if x.mark_deleted() {
+                removed += 1;
+            }
+            data_size += (x.data.len() + x.value().len()) as u64;
+            if segment_level.is_some_and(|level| x.height() > level) {
+                segments.push(x.as_ptr() as usize);
+            }
+        }
+        if let (Some(blooms), Some(level)) = (&self.inner.blooms, segment_level) {
+            // The segment before the range is about to take over the keys after
+            // it: without a filter, it may contain any key until the new one is
+            // built.
+            blooms.remove(start[level].as_ptr() as usize);
+        }
+        let max_height = self.get_max_height();
+        for (level, (&first, &last)) in start.iter().zip(end.iter()).enumerate() {
+            let mut span = first.span(level);
+            let mut x = first;
+            while x != last {
+                x = x.next(level).unwrap();
+                span += x.span(level);
+            }
+            first.set_span(level, span - counted);
+            if first != last && level < max_height {
+                if level == 0 {
+                    self.inner.nodes.retire(first.next(0).unwrap(), unlinked);
+                }
+                first.set_next(level, last.next(level));
+            }
+        }
+        self.inner.len.fetch_sub(removed as u64, Ordering::SeqCst);
+        self.inner.data_size.fetch_sub(data_size, Ordering::SeqCst);
+        if let (Some(blooms), Some(level)) = (&self.inner.blooms, segment_level) {
+            for segment in segments {
+                blooms.remove(segment);
+            }
+            // The segment before the range now runs up to the next one after it.
+            let segment = start[level].as_ptr() as usize;
+            blooms.split(segment, self.segment_keys(blooms, segment));
+        }
+        // Splices may point into the range, which doesn't lead to new nodes anymore.
+        self.inner
+            .generation
+            .store(next_generation(), Ordering::SeqCst);
+        self.schedule_shrink();
+        removed
+    }
+
+    /// Remove the entries in `range` for which `keep` returns `false`, e.g. for a
+    /// cleanup job over a slice of the keyspace. A single search finds the start
+    /// of the range, then the entries are walked in order up to its end, and the
+    /// rejected ones are unlinked like by `pop_first` once the walk is over.
+    /// `keep` runs without the writer lock, so it may write to the list. Returns
+    /// the number of entries removed.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(0..10);
+    /// assert_eq!(sl.retain_range(&[2][..]..&[8][..], |node| node.data[0] % 2 == 0), 3);
+    /// assert_eq!(format!("{}", sl), "[[0] [1] [2] [4] [6] [8] [9] ]");
+    /// ```
+    pub fn retain_range<'k, F>(&self, range: impl RangeBounds<&'k [u8]>, mut keep: F) -> usize
+    where
+        F: FnMut(&Node) -> bool,
+    {
+        let mut rejected = vec![];
+        let mut x = self.seek_start_bound(range.start_bound());
+        while let Some(node) = x.filter(|x| self.is_before_end_bound(x.key(), range.end_bound())) {
+            // An unlinked node keeps its own links, the walk goes on from it.
+            if !node.is_deleted() && !keep(node.node()) {
+                rejected.push(NonNull::from(node.node()));
+            }
+            x = node.next(0);
+        }
+        let _writer = self.writer();
+        // Nodes stay in the arena once removed, `pop` skips the ones removed
+        // meanwhile.
+        rejected
+            .into_iter()
+            .filter(|node| self.pop(unsafe { node.as_ref() }).is_some())
+            .count()
+    }
+
+    /// Returns, for every level, the last node before the first key for which
+    /// `before` is `false`. Keys must go from `true` to `false` once.
+    fn last_before(&self, before: impl Fn(&[u8]) -> bool) -> Path<'_> {
+        let mut path = [self.head(); K_MAX_HEIGHT];
+        let mut x = self.head();
+        for level in (0..self.get_max_height()).rev() {
+            while let Some(next) = x.next(level).filter(|next| before(next.key())) {
+                x = next;
+            }
+            path[level] = x;
+        }
+        path
+    }
+
+    /// Move the entries greater than or equal to `key` to a new list, e.g. to
+    /// partition a memtable by range. The towers are relinked where the list is
+    /// cut, no entry is copied or inserted again, but counting the moved entries
+    /// walks them.
+    ///
+    /// The new list gets clones of the comparator, the random generator and the
+    /// config, and shares the arena, which still holds the moved nodes: both
+    /// lists report the memory of the whole arena. Readers of this list may
+    /// still be on the moved nodes, so their keys and values are only dropped
+    /// once both lists are.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(0..10);
+    /// let high = sl.split_off(&[6]);
+    /// assert_eq!(format!("{}", sl), "[[0] [1] [2] [3] [4] [5] ]");
+    /// assert_eq!(format!("{}", high), "[[6] [7] [8] [9] ]");
+    /// ```
+    pub fn split_off(&mut self, key: &[u8]) -> Self
+    where
+        R: Clone,
+        C: Clone,
+        A: SharedArena + Send + 'static,
+    {
+        let mut other = Self::with_config_unchecked(
+            self.inner.rnd.clone(),
+            self.inner.cmp.clone(),
+            self.inner.arena.clone(),
+            self.inner.config.clone(),
+        );
+        if let Some(inner) = Arc::get_mut(&mut other.inner) {
+            inner.adopted = self.inner.adopted.clone();
+        }
+        // The nodes of the new list drop with the last of both lists, before the
+        // arena they are in.
+        self.inner.adopted.lock().unwrap().push(Box::new((
+            other.inner.nodes.clone(),
+            other.inner.arena.clone(),
+        )));
+        let _writer = self.writer();
+        let _spans = self.spans();
+        let mut prev = [self.head(); K_MAX_HEIGHT];
+        self.seek(key, &mut prev);
+        let max_height = self.get_max_height();
+        let before = self.settle_path(&mut prev);
+        let other_head = other.head();
+        for (level, &node) in prev.iter().enumerate() {
+            other_head.set_span(level, node.span(level) - before[level]);
+            node.set_span(level, before[level]);
+            if level < max_height {
+                other_head.set_next(level, node.next(level));
+                node.set_next(level, None);
+            }
+        }
+        other.raise_max_height(max_height);
+        // Readers of this list stop at the cut, the splices may lead past it.
+        self.inner
+            .generation
+            .store(next_generation(), Ordering::SeqCst);
+
+        let (mut len, mut data_size) = (0, 0);
+        for node in other.nodes() {
+            if !node.is_deleted() {
+                len += 1;
+            }
+            data_size += (node.data.len() + node.value().len()) as u64;
+        }
+        self.inner.len.fetch_sub(len, Ordering::SeqCst);
+        self.inner.data_size.fetch_sub(data_size, Ordering::SeqCst);
+        other.inner.len.store(len, Ordering::SeqCst);
+        other.inner.data_size.store(data_size, Ordering::SeqCst);
+        other.inner.seq.store(self.last_seq(), Ordering::SeqCst);
+
+        if let (Some(blooms), Some(other_blooms)) = (&self.inner.blooms, &other.inner.blooms) {
+            let level = blooms.segment_level();
+            self.rebuild_bloom(blooms, prev[level].as_ptr() as usize);
+            other.index_segments(other_blooms, other_head);
+        }
+        other
+    }
+
+    /// Build the filters of the segments from the one starting at `segment` to
+    /// the end of the list.
+    fn index_segments(&self, blooms: &SegmentBlooms, segment: Nav<'_>) {
+        let level = blooms.segment_level();
+        for segment in iter::successors(Some(segment), |x| x.next(level)) {
+            let segment = segment.as_ptr() as usize;
+            blooms.split(segment, self.segment_keys(blooms, segment));
+        }
+    }
+
+    /// Move the entries of `other` into this list. If all of them come after
+    /// the last entry of this list and no other handle shares `other`, its
+    /// towers are linked after the ones of this list as they are, without
+    /// copying any node, and this list keeps `other` alive. Otherwise they are
+    /// inserted like with `ingest`, which applies the duplicate policy. Fails if
+    /// `other` is a handle of this list.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let new_list = |keys: Vec<u8>| {
+    ///     let mut sl = SkipList::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+    ///     sl.extend(keys);
+    ///     sl
+    /// };
+    /// let mut sl = new_list(vec![1, 2]);
+    /// sl.merge(new_list(vec![5, 6])).unwrap();
+    /// sl.merge(new_list(vec![0, 3])).unwrap();
+    /// assert_eq!(format!("{}", sl), "[[0] [1] [2] [3] [5] [6] ]");
+    /// assert!(sl.merge(sl.clone()).is_err());
+    /// ```
+    pub fn merge(&mut self, mut other: Self) -> Result<(), SameListError>
+    where
+        Self: Send + 'static,
+    {
+        if Arc::ptr_eq(&self.inner, &other.inner) {
+            return Err(self.same_list_error());
+        }
+        let first = match other.head().next(0) {
+            Some(first) => first,
+            None => return Ok(()),
+        };
+        let _writer = self.writer();
+        let last = self.tail();
+        let disjoint = last == self.head() || self.lt(last.key(), first.key());
+        if !disjoint || Arc::get_mut(&mut other.inner).is_none() {
+            self.ingest_locked(&other);
+            return Ok(());
+        }
+
+        let other_height = other.get_max_height();
+        let other_head = other.head();
+        let segment_level = self.inner.blooms.as_ref().map(|b| b.segment_level());
+        let mut segment = None;
+        let spans = self.spans();
+        let mut tail = self.head();
+        for level in (0..K_MAX_HEIGHT).rev() {
+            while let Some(next) = tail.next(level) {
+                tail = next;
+            }
+            tail.add_span(level, other_head.span(level) as isize);
+            if level < other_height {
+                tail.set_next(level, other_head.next(level));
+                // The nodes are this list's to drop now.
+                other_head.set_next(level, None);
+            }
+            if segment_level == Some(level) {
+                segment = Some(tail);
+            }
+        }
+        drop(spans);
+        if let (Some(blooms), Some(segment)) = (&self.inner.blooms, segment) {
+            // The last segment of this list now holds the keys of the first one
+            // of `other`.
+            self.index_segments(blooms, segment);
+        }
+        self.raise_max_height(other_height);
+        self.inner.len.fetch_add(other.len(), Ordering::SeqCst);
+        self.inner.data_size.fetch_add(
+            other.inner.data_size.load(Ordering::SeqCst),
+            Ordering::SeqCst,
+        );
+        self.inner.adopted.lock().unwrap().push(Box::new(other));
+        Ok(())
+    }
+
+    /// Every node linked at level 0, removed ones included.
+    pub(crate) fn nodes(&self) -> impl Iterator<Item = &Node> {
+        iter::successors(self.head().next(0), |x| x.next(0)).map(Nav::node)
+    }
+
+    /// Rename the entry `old` to `new`, keeping its value and metadata byte.
+    /// Returns `false` if there is no entry `old`, or if the duplicate policy
+    /// keeps an entry equal to `new` (`Ignore`, `Error` and `Count`). With
+    /// `Replace`, the
+    /// entries equal to `new` are removed.
+    ///
+    /// The new entry is linked hidden, then appears at the very moment the old
+    /// one is removed: a lookup never finds both keys or neither. A scan isn't
+    /// a snapshot though, it can still meet the two entries before and after the
+    /// switch.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![1, 2]);
+    /// assert!(sl.rename(&[1], vec![3u8]));
+    /// assert!(!sl.rename(&[1], vec![4u8]));
+    /// assert_eq!(format!("{}", sl), "[[2] [3] ]");
+    /// ```
+    pub fn rename(&self, old: &[u8], new: impl Into<Bytes>) -> bool {
+        let mut splice = self.writer();
+        let source = match self.find_equal(old) {
+            Some(source) => NonNull::from(source),
+            None => return false,
+        };
+        let source = unsafe { source.as_ref() };
+        let new = new.into();
+        let policy = self.inner.config.duplicate_policy;
+        if matches!(
+            policy,
+            DuplicatePolicy::Ignore | DuplicatePolicy::Error | DuplicatePolicy::Count
+        ) && self.contains_key(&new)
+        {
+            return false;
+        }
+        let (n, _) =
+            self.insert_at_height(&mut splice, new, source.value().clone(), None, Some(source));
+        let n = unsafe { n.as_ref() };
+        n.set_meta(source.get_meta());
+        n.set_count(source.count());
+
+        // The switch: `n` becomes visible as `source` goes away.
+        let removed = source.mark_deleted();
+        n.clear_pending();
+        if removed {
+            self.count_live(source, -1);
+        }
+        if !n.is_deleted() {
+            self.count_live(n, 1);
+        }
+        match (removed, n.is_deleted()) {
+            (true, true) => {
+                self.inner.len.fetch_sub(1, Ordering::SeqCst);
+            }
+            (false, false) => {
+                self.inner.len.fetch_add(1, Ordering::SeqCst);
+            }
+            _ => {}
+        }
+        if policy == DuplicatePolicy::Replace {
+            self.remove_from(Nav::from(n).next(0), &n.data);
+        }
+        true
+    }
+
+    /// Write the entries of `batch` so that readers find either none of them or
+    /// all of them. Keys go through the duplicate policy as if inserted one by
+    /// one in the order of the batch, and the number of keys it didn't ignore is
+    /// returned. If `DuplicatePolicy::Error` rejects one, nothing is written.
+    ///
+    /// The entries are linked hidden, like the new entry of a `rename`, behind a
+    /// gate node that is never linked: removing the gate makes all of them show
+    /// up with a single store. Each entry points to the gate, so readers check it
+    /// without taking a lock, and batches on other lists don't get in the way.
+    /// A scan running meanwhile isn't a snapshot though,
+    /// it can meet the entries of the batch after skipping some of them. With
+    /// `DuplicatePolicy::Count`, the counts of the entries already in the list go
+    /// up right after the batch shows up, and with an evicting `OverflowPolicy`,
+    /// the entries past `max_entries` go away right after it. `Reject` drops the
+    /// entries of the batch past `max_entries`, in the order of the keys.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, DefaultComparator, DuplicatePolicy, SkipListConfig, WriteBatch};
+    ///
+    /// let config = SkipListConfig {
+    ///     duplicate_policy: DuplicatePolicy::Error,
+    ///     ..Default::default()
+    /// };
+    /// let sl = SkipList::from_config(Random::new(0xdead_beef), DefaultComparator::default (), config).unwrap();
+    /// sl.insert(vec![3u8]);
+    ///
+    /// let mut batch = WriteBatch::new();
+    /// batch.insert(vec![1u8]);
+    /// batch.insert(vec![3u8]);
+    /// assert_eq!(sl.write(batch.clone()).unwrap_err().key.as_ref(), &[3]);
+    /// assert!(!sl.contains_key(&[1]));
+    ///
+    /// sl.remove(&[3]);
+    /// assert_eq!(sl.write(batch), Ok(2));
+    /// assert_eq!(sl.len(), 2);
+    /// ```
+    pub fn write(&self, batch: WriteBatch) -> Result<usize, DuplicateKeyError> {
+        let mut splice = self.writer();
+        let policy = self.inner.config.duplicate_policy;
+        let mut entries = batch.entries;
+        let mut written = entries.len();
+        // Sorted keys are linked in a single pass. The sort is stable, equal keys
+        // keep the order of the batch.
+        let cmp = &self.inner.cmp;
+        entries.sort_by(|a, b| cmp.compare(&a.0, &b.0));
+
+        // The entries to link and their count, then the ones counted in an entry
+        // already in the list.
+        let mut staged: Vec<(Bytes, Bytes, usize)> = Vec::with_capacity(entries.len());
+        let mut counted = vec![];
+        for (key, value) in entries {
+            let repeated = staged
+                .last()
+                .is_some_and(|(last, _, _)| self.eq(last, &key));
+            match policy {
+                DuplicatePolicy::Ignore if repeated || self.contains_key(&key) => {
+                    written -= 1;
+                    continue;
+                }
+                DuplicatePolicy::Error if repeated || self.contains_key(&key) => {
+                    return Err(DuplicateKeyError {
+                        key,
+                        list: self.name().map(String::from),
+                    });
+                }
+                DuplicatePolicy::Replace if repeated => {
+                    staged.pop();
+                }
+                DuplicatePolicy::Count if repeated => {
+                    staged.last_mut().unwrap().2 += 1;
+                    continue;
+                }
+                DuplicatePolicy::Count => {
+                    if let Some(existing) = self.find_equal(&key) {
+                        counted.push(NonNull::from(existing));
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            staged.push((key, value, 1));
+        }
+        if let (Some(max), OverflowPolicy::Reject) = (
+            self.inner.config.max_entries,
+            self.inner.config.overflow_policy,
+        ) {
+            let mut room = max.saturating_sub(self.len());
+            staged.retain(|(key, _, _)| {
+                let grows = policy != DuplicatePolicy::Replace || !self.contains_key(key);
+                if grows && room == 0 {
+                    written -= 1;
+                    return false;
+                }
+                room -= u64::from(grows);
+                true
+            });
+        }
+        if staged.is_empty() && counted.is_empty() {
+            return Ok(written);
+        }
+
+        let gate = Node::new(Bytes::new(), 1, &self.inner.arena);
+        let nodes: Vec<NonNull<Node>> = staged
+            .into_iter()
+            .map(|(key, value, count)| {
+                let gate = unsafe { gate.as_ref() };
+                let (n, _) = self.insert_at_height(&mut splice, key, value, None, Some(gate));
+                unsafe { n.as_ref() }.set_count(count);
+                n
+            })
+            .collect();
+
+        // The switch: every entry of the batch shows up at once. The gate
+        // stays in the arena, the entries keep pointing to it.
+        unsafe { gate.as_ref() }.mark_deleted();
+        let mut live = 0;
+        for n in &nodes {
+            let n = unsafe { n.as_ref() };
+            n.clear_pending();
+            // Removed while hidden.
+            if !n.is_deleted() {
+                self.count_live(n, 1);
+                live += 1;
+            }
+        }
+        self.inner.len.fetch_add(live, Ordering::SeqCst);
+        if policy == DuplicatePolicy::Replace {
+            for n in &nodes {
+                let n = unsafe { n.as_ref() };
+                self.remove_from(Nav::from(n).next(0), &n.data);
+            }
+        }
+        for node in &counted {
+            unsafe { node.as_ref() }.add_count(1);
+        }
+        self.evict_overflow();
+        Ok(written)
+    }
+
+    /// Remove the entries past `max_entries` as an evicting `OverflowPolicy`
+    /// asks, once a batch showed up at once.
+    fn evict_overflow(&self) {
+        let max = match self.inner.config.max_entries {
+            Some(max) => max,
+            None => return,
+        };
+        while self.len() > max {
+            let victim = match self.inner.config.overflow_policy {
+                OverflowPolicy::Reject => return,
+                OverflowPolicy::EvictSmallest => self.head().next_live(),
+                OverflowPolicy::EvictLargest => self.back(),
+            };
+            match victim {
+                Some(victim) => self.pop(victim.node()),
+                None => return,
+            };
+        }
+    }
+
+    /// Insert a removed entry for `key`, hiding the entries of older lists.
+    #[cfg(feature = "memtable")]
+    pub(crate) fn insert_tombstone(&self, key: Bytes) {
+        let (n, outcome) = self.insert_node(key, Bytes::new());
+        let dropped = matches!(outcome, InsertOutcome::Violation | InsertOutcome::Full);
+        if !dropped && unsafe { n.as_ref() }.mark_deleted() {
+            self.count_live(unsafe { n.as_ref() }, -1);
+            self.inner.len.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    fn record_access(&self, node: &Node) {
+        if let Some(access) = &self.inner.access {
+            access.record(node);
+        }
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    #[inline(always)]
+    fn record_access(&self, _node: &Node) {}
+
+    /// Returns the `top_k` most looked up keys with an estimate of their number of
+    /// lookups, most looked up first. Only lookups that found their key count:
+    /// `get`, `get_bytes`, `get_ref`, `contains_key` and `Guard::get`. Empty unless `hot_key_sampling`
+    /// is configured.
+    ///
+    /// The counts live in the nodes: with the `metrics` feature every node takes
+    /// 8 more bytes of the arena, and entries no longer in the list are no longer
+    /// counted. Finding the hottest walks the whole list.
+    /// # Examples
+    /// ```
+    /// use dakv_skiplist::{SkipList, SkipListConfig, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let config = SkipListConfig {
+    ///     hot_key_sampling: Some(1),
+    ///     ..Default::default()
+    /// };
+    /// let mut sl = SkipList::with_config(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    ///     config,
+    /// ).unwrap();
+    /// sl.extend(0..10);
+    /// for _ in 0..3 {
+    ///     sl.contains_key(&[7]);
+    /// }
+    /// sl.contains_key(&[2]);
+    /// let hot = sl.hot_keys(1);
+    /// assert_eq!(hot[0].0.as_ref(), &[7]);
+    /// assert_eq!(hot[0].1, 3);
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn hot_keys(&self, top_k: usize) -> Vec<(Bytes, u64)> {
+        match &self.inner.access {
+            Some(access) => access
+                .top(self.nodes().filter(|node| !node.is_deleted()), top_k)
+                .into_iter()
+                .map(|(node, count)| (node.data.clone(), count))
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Returns the stored key equal to `key`, if any.
+    ///
+    /// The stored key may differ from `key` when the comparator considers
+    /// different bytes equal, e.g. a case insensitive one. The slice borrows from
+    /// the list: it stays valid even if the entry is removed or the list cleared
+    /// meanwhile, as the arena keeps the node.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.insert(&b"key"[..]);
+    /// assert_eq!(sl.get(b"key"), Some(&b"key"[..]));
+    /// assert_eq!(sl.get(b"other"), None);
+    /// ```
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.find_equal(key).map(|node| node.data.as_ref())
+    }
+
+    /// Returns the stored key equal to `key` as `Bytes`, sharing the buffer of the
+    /// node instead of copying it.
+    pub fn get_bytes(&self, key: &[u8]) -> Option<Bytes> {
+        self.find_equal(key).map(|node| node.data.clone())
+    }
+
+    /// Returns the entry for `key`, to look it up and insert it if missing with a
+    /// single search.
+    ///
+    /// A vacant entry holds the writer lock of the list until it is filled or
+    /// dropped, so no other write slips in between: writing to the list
+    /// meanwhile, through any handle, deadlocks.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// const SEEN_TWICE: u8 = 1;
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// for word in ["apple", "pear", "apple"] {
+    ///     sl.entry(word.as_bytes())
+    ///         .and_modify(|e| e.set_meta(SEEN_TWICE))
+    ///         .or_insert_with(|| word.to_string());
+    /// }
+    /// assert_eq!(sl.len(), 2);
+    /// let apple = sl.get_ref(b"apple").unwrap();
+    /// assert_eq!(sl.meta(apple), Some(SEEN_TWICE));
+    /// ```
+    pub fn entry<'k>(&self, key: &'k [u8]) -> Entry<'_, 'k, R, C, A> {
+        let mut writer = self.writer();
+        let generation = self.inner.generation.load(Ordering::SeqCst);
+        let mut path = [self.head(); K_MAX_HEIGHT];
+        let x = self.find_from(key, &mut path, self.get_max_height());
+        let x = self.live_equal(x, key).map(|x| NonNull::from(x.node()));
+        match x {
+            Some(x) => {
+                let node = unsafe { x.as_ref() };
+                self.record_access(node);
+                Entry::Occupied(OccupiedEntry::new(self, node))
+            }
+            None => {
+                *writer = Splice {
+                    generation,
+                    prev: path.map(Nav::as_ptr),
+                };
+                Entry::Vacant(VacantEntry::new(self, key, writer))
+            }
+        }
+    }
+
+    /// Insert `key` unless an equal key is already in the list, whatever the
+    /// `DuplicatePolicy`, and returns `true` if it was inserted, `false` as well
+    /// if the `ComparatorCheck` or the `OverflowPolicy` dropped it. Both the lookup
+    /// and the insert use a single search, so no other write can slip in between
+    /// them as with `contains_key` followed by `insert`.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// assert!(sl.insert_if_absent(vec![1u8]));
+    /// assert!(!sl.insert_if_absent(vec![1u8]));
+    /// assert_eq!(sl.len(), 1);
+    /// ```
+    pub fn insert_if_absent(&self, key: impl Into<Bytes>) -> bool {
+        let key = key.into();
+        match self.entry(&key) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => !matches!(
+                entry.insert_outcome(key.clone()).1,
+                InsertOutcome::Violation | InsertOutcome::Full
+            ),
+        }
+    }
+
+    /// Returns the stored key equal to `key`, inserting `key` first if there is
+    /// none, with a single search. See `insert_if_absent`.
+    /// # Panics
+    /// Like `VacantEntry::insert_key`, if the key is dropped.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// assert_eq!(sl.get_or_insert(vec![1u8]), &[1]);
+    /// assert_eq!(sl.get_or_insert(vec![1u8]), &[1]);
+    /// assert_eq!(sl.len(), 1);
+    /// ```
+    pub fn get_or_insert(&self, key: impl Into<Bytes>) -> &[u8] {
+        let key = key.into();
+        self.entry(&key).or_insert_with(|| key.clone()).key()
+    }
+
+    /// Returns a handle to the entry equal to `key`, if any.
+    pub fn get_ref(&self, key: &[u8]) -> Option<NodeRef> {
+        self.find_equal(key)
+            .map(|node| NodeRef::new(self.id(), NonNull::from(node)))
+    }
+
+    /// Returns a new sequence number, 1 for the first one, shared by all the
+    /// handles of the list. See `SkipMap::insert_seq`.
+    pub fn next_seq(&self) -> u64 {
+        self.inner.seq.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Returns the last sequence number handed out by `next_seq`, 0 if none was.
+    /// Reading at this number sees every stamped entry inserted so far.
+    pub fn last_seq(&self) -> u64 {
+        self.inner.seq.load(Ordering::SeqCst)
+    }
+
+    /// Lock the keys in `range` for the caller, waiting until no other lock of
+    /// an overlapping range is held, e.g. to keep conflicting transactions
+    /// apart. The lock is shared by all the handles of the list and released
+    /// when the guard is dropped.
+    ///
+    /// The list itself doesn't look at the locks: writers have to take them.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// let guard = sl.lock_range(&b"a"[..]..&b"m"[..]);
+    /// assert!(sl.try_lock_range(&b"k"[..]..).is_none());
+    /// assert!(sl.try_lock_range(&b"m"[..]..).is_some());
+    /// drop(guard);
+    /// assert!(sl.try_lock_range(..).is_some());
+    /// ```
+    pub fn lock_range<'k>(&self, range: impl RangeBounds<&'k [u8]>) -> RangeLockGuard<'_> {
+        let id = self.inner.range_locks.lock(&self.inner.cmp, range, true);
+        RangeLockGuard::new(&self.inner.range_locks, id.unwrap())
+    }
+
+    /// Lock the keys in `range` like `lock_range`, or returns `None` if an
+    /// overlapping range is locked.
+    pub fn try_lock_range<'k>(
+        &self,
+        range: impl RangeBounds<&'k [u8]>,
+    ) -> Option<RangeLockGuard<'_>> {
+        let id = self.inner.range_locks.lock(&self.inner.cmp, range, false)?;
+        Some(RangeLockGuard::new(&self.inner.range_locks, id))
+    }
+
+    /// Returns the number of levels the entry equal to `key` is linked at, if
+    /// any, e.g. for tools checking the shape of the list.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.insert(vec![1u8]);
+    /// let height = sl.height_of(&[1]).unwrap();
+    /// assert!(height >= 1 && height <= sl.get_max_height());
+    /// assert_eq!(sl.height_of(&[2]), None);
+    /// ```
+    pub fn height_of(&self, key: &[u8]) -> Option<usize> {
+        self.find_equal(key).map(|node| node.height())
+    }
+
+    /// Returns the key of the entry behind `handle` without searching, or `None`
+    /// if the handle was created by another list.
+    pub fn key_of(&self, handle: NodeRef) -> Option<&[u8]> {
+        self.deref_handle(handle).map(|n| n.data.as_ref())
+    }
+
+    /// Returns the metadata byte of the entry behind `handle`, or `None` if the
+    /// handle was created by another list.
+    ///
+    /// Every entry carries a byte, 0 when inserted, that the list never looks at.
+    /// Embedders can use it to tag entries (e.g. "came from replication") without
+    /// keeping a map keyed by the same keys.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, SkipList};
+    ///
+    /// const REPLICATED: u8 = 1;
+    /// const PENDING_INDEX: u8 = 2;
+    ///
+    /// let sl = SkipList::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+    /// let entry = sl.insert_ref(vec![1u8]);
+    /// assert_eq!(sl.meta(entry), Some(0));
+    /// sl.set_meta(entry, REPLICATED);
+    /// sl.update_meta(entry, |meta| meta | PENDING_INDEX);
+    /// assert_eq!(sl.meta(entry), Some(REPLICATED | PENDING_INDEX));
+    /// ```
+    pub fn meta(&self, handle: NodeRef) -> Option<u8> {
+        self.deref_handle(handle).map(|n| n.get_meta())
+    }
+
+    /// Overwrite the metadata byte of the entry behind `handle`, returns `false`
+    /// if the handle was created by another list.
+    pub fn set_meta(&self, handle: NodeRef, meta: u8) -> bool {
+        self.deref_handle(handle)
+            .map(|n| n.set_meta(meta))
+            .is_some()
+    }
+
+    /// Atomically replace the metadata byte of the entry behind `handle` by
+    /// `f(meta)`, e.g. to set or clear a single flag while other threads update
+    /// the others. Returns the previous byte, or `None` if the handle was created
+    /// by another list.
+    pub fn update_meta(&self, handle: NodeRef, f: impl Fn(u8) -> u8) -> Option<u8> {
+        let node = self.deref_handle(handle)?;
+        node.meta
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |meta| Some(f(meta)))
+            .ok()
+    }
+
+    /// Returns a handle to the entry following the one behind `handle`, or `None`
+    /// if it is the last entry or if the handle was created by another list.
+    pub fn next(&self, handle: NodeRef) -> Option<NodeRef> {
+        let next = Nav::from(self.deref_handle(handle)?).next_live()?;
+        Some(NodeRef::new(self.id(), NonNull::from(next.node())))
+    }
+
+    fn deref_handle(&self, handle: NodeRef) -> Option<&Node> {
+        if handle.list == self.id() {
+            // The node lives in our arena, which lives as long as `self`.
+            Some(unsafe { handle.node.as_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the id of the list, shared by all the clones of this handle and
+    /// never given to another list of the process. A frozen list keeps it.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, SkipListConfig, Random, DefaultComparator};
+    ///
+    /// let config = SkipListConfig {
+    ///     name: Some("memtable".into()),
+    ///     ..Default::default()
+    /// };
+    /// let sl = SkipList::from_config(Random::new(0xdead_beef), DefaultComparator::default (), config).unwrap();
+    /// let other = SkipList::from_config(Random::new(0xdead_beef), DefaultComparator::default (), Default::default()).unwrap();
+    /// assert_ne!(sl.id(), other.id());
+    /// assert_eq!(sl.clone().id(), sl.id());
+    /// assert_eq!(sl.name(), Some("memtable"));
+    /// assert_eq!(format!("{:?}", sl), format!("SkipList {{ id: {}, name: Some(\"memtable\"), len: 0 }}", sl.id()));
+    /// ```
+    pub fn id(&self) -> u64 {
+        self.inner.id
+    }
+
+    /// Returns the name of the list, see `SkipListConfig::name`.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.config.name.as_deref()
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.contains_key(key)
+    }
+
+    /// Returns `true` if the skiplist contains an entry equal to `key`.
+    ///
+    /// Unlike `contains` this only needs a shared reference, and the search
+    /// doesn't record the previous nodes so nothing is allocated.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.insert(vec![1u8]);
+    ///
+    /// let reader = &sl;
+    /// assert!(reader.contains_key(&[1u8]));
+    /// assert!(!reader.contains_key(&[2u8]));
+    /// ```
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        let x = match &self.inner.blooms {
+            Some(blooms) => {
+                let level = blooms.segment_level();
+                let segment = self.bloom_segment(level, key);
+                if let Some(next) = segment
+                    .next(level)
+                    .filter(|next| !next.is_deleted() && self.eq(key, next.key()))
+                {
+                    self.record_access(next.node());
+                    return true;
+                }
+                if !blooms.may_contain(segment.as_ptr() as usize, blooms.prefix(key)) {
+                    return false;
+                }
+                self.descend(segment, level, 0, key).next(0)
+            }
+            None => self.seek(key, &mut []),
+        };
+        match self.live_equal(x, key) {
+            Some(x) => {
+                self.record_access(x.node());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Starting from `x` at level `from`, where `x` must be before `key`, walk down
+    /// to level `to` and return the last node of that level before `key`.
+    fn descend<'a>(&self, mut x: Nav<'a>, from: usize, to: usize, key: &[u8]) -> Nav<'a> {
+        let mut height = from;
+        loop {
+            if let Some(next) = self.before_key(x.next(height), key) {
+                x = next;
+            } else if height == to {
+                return x;
+            } else {
+                height -= 1;
+            }
+        }
+    }
+
+    /// Returns the node starting the bloom segment where `key` would be.
+    fn bloom_segment(&self, level: usize, key: &[u8]) -> Nav<'_> {
+        let top = self.get_max_height() - 1;
+        if level > top {
+            // Nothing reached the segment level yet, everything is in the first one.
+            self.head()
+        } else {
+            self.descend(self.head(), top, level, key)
+        }
+    }
+
+    /// Call `f` with every key starting with `prefix`, in order, until it returns
+    /// `ControlFlow::Break`.
+    ///
+    /// With prefix blooms configured and a `prefix` at least `prefix_len` bytes
+    /// long, the scan gives up without touching level 0 when the segment where
+    /// the prefix would be can't hold it. Keys sharing a prefix must be ordered
+    /// next to each other by the comparator, which is the case of bytewise order.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    /// use std::ops::ControlFlow;
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// for key in ["apple", "apricot", "banana"] {
+    ///     sl.insert(key.as_bytes().to_vec());
+    /// }
+    /// let mut found = vec![];
+    /// let _ = sl.for_each_with_prefix(b"ap", |key| {
+    ///     found.push(key.to_vec());
+    ///     ControlFlow::<()>::Continue(())
+    /// });
+    /// assert_eq!(found, vec![b"apple".to_vec(), b"apricot".to_vec()]);
+    /// ```
+    pub fn for_each_with_prefix<B, F>(&self, prefix: &[u8], mut f: F) -> ControlFlow<B>
+    where
+        F: FnMut(&[u8]) -> ControlFlow<B>,
+    {
+        let nodes = iter::successors(self.seek_prefix(prefix), |x| x.next(0));
+        for x in nodes.take_while(|x| x.key().starts_with(prefix)) {
+            if !x.is_deleted() {
+                f(x.key())?;
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Returns the first node starting with `prefix` if any, else a node after
+    /// the keys starting with it, or `None`. With prefix blooms it may be `None`
+    /// even if there are keys after the prefix.
+    fn seek_prefix(&self, prefix: &[u8]) -> Option<Nav<'_>> {
+        match &self.inner.blooms {
+            Some(blooms) if prefix.len() >= blooms.prefix_len() => {
+                let level = blooms.segment_level();
+                let segment = self.bloom_segment(level, prefix);
+                let next_matches = segment
+                    .next(level)
+                    .is_some_and(|next| next.key().starts_with(prefix));
+                if !next_matches
+                    && !blooms.may_contain(segment.as_ptr() as usize, blooms.prefix(prefix))
+                {
+                    return None;
+                }
+                self.descend(segment, level, 0, prefix).next(0)
+            }
+            _ => self.seek(prefix, &mut []),
+        }
+    }
+
+    pub(crate) fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+        self.inner.counters.compared();
+        self.inner.cmp.compare(a, b)
+    }
+
+    fn eq(&self, a: &[u8], b: &[u8]) -> bool {
+        self.compare(a, b) == cmp::Ordering::Equal
+    }
+
+    fn lt(&self, a: &[u8], b: &[u8]) -> bool {
+        self.compare(a, b) == cmp::Ordering::Less
+    }
+
+    fn gte(&self, a: &[u8], b: &[u8]) -> bool {
+        let r = self.compare(a, b);
+        r == cmp::Ordering::Greater || r == cmp::Ordering::Equal
+    }
+
+    /// Returns the work done since the list was created or since the last call to
+    /// `reset_op_counters`. Unlike timings, these only depend on the data, the
+    /// comparator and the random generator, which makes them suitable for
+    /// performance tests.
+    /// # Examples
+    /// ```
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(0..100);
+    /// sl.reset_op_counters();
+    /// assert!(sl.contains_key(&[42]));
+    /// let counters = sl.op_counters();
+    /// assert!(counters.comparisons > 0);
+    /// assert!(counters.nodes_visited < 100);
+    /// ```
+    #[cfg(feature = "deterministic")]
+    pub fn op_counters(&self) -> OpCounters {
+        self.inner.counters.snapshot()
+    }
+
+    #[cfg(feature = "deterministic")]
+    pub fn reset_op_counters(&self) {
+        self.inner.counters.reset();
+    }
+
+    /// Register a callback that runs exactly once, after the last handle to the
+    /// list has been dropped. Callbacks carry over to the list returned by
+    /// `freeze`, which makes this the place to e.g. delete the WAL file of a
+    /// flushed memtable once no reader can see it anymore.
+    ///
+    /// Several callbacks may be registered, they run in registration order.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// let dropped = Arc::new(AtomicBool::new(false));
+    /// let flag = dropped.clone();
+    /// sl.on_drop(move || flag.store(true, Ordering::SeqCst));
+    ///
+    /// let reader = sl.clone();
+    /// drop(sl);
+    /// assert!(!dropped.load(Ordering::SeqCst));
+    /// drop(reader);
+    /// assert!(dropped.load(Ordering::SeqCst));
+    /// ```
+    pub fn on_drop(&self, f: impl FnOnce() + Send + 'static) {
+        self.inner.on_drop.push(Box::new(f));
+    }
+
+    /// Convert the list into an `ImmutableSkipList` that only supports reads,
+    /// dropping the random generator used by writers. This is the transition a
+    /// memtable goes through once it is full and waiting to be flushed.
+    ///
+    /// Freezing needs the last handle to the list: if other clones are still
+    /// alive they could keep writing, so the list is handed back unchanged.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.insert(vec![1u8]);
+    ///
+    /// let imm = sl.freeze().ok().unwrap();
+    /// assert!(imm.contains(&[1u8]));
+    /// ```
+    pub fn freeze(self) -> Result<ImmutableSkipList<C, A>, Self> {
+        match Arc::try_unwrap(self.inner) {
+            Ok(inner) => {
+                // The frozen list may still link nodes of merged lists.
+                let adopted = inner.adopted;
+                inner.on_drop.push(Box::new(move || drop(adopted)));
+                Ok(ImmutableSkipList::new(
+                    inner.id,
+                    inner.config.name,
+                    inner.nodes,
+                    inner.max_height.into_inner(),
+                    inner.len.into_inner(),
+                    inner.data_size.into_inner(),
+                    inner.cmp,
+                    inner.arena,
+                    inner.on_drop,
+                ))
+            }
+            Err(inner) => Err(SkipList::from_inner(inner)),
+        }
+    }
+
+    /// Look up several keys at once, returning the stored entry equal to each
+    /// key, in the order of `keys`.
+    ///
+    /// The probes are sorted with the comparator first, and every search resumes
+    /// from the towers where the previous one stopped instead of starting from the
+    /// head again, so close keys share most of their path.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![1, 3, 5]);
+    /// let found: Vec<_> = sl
+    ///     .get_many(&[&[5], &[2], &[1]])
+    ///     .into_iter()
+    ///     .map(|k| k.map(|k| k[0]))
+    ///     .collect();
+    /// assert_eq!(found, vec![Some(5), None, Some(1)]);
+    /// ```
+    pub fn get_many(&self, keys: &[&[u8]]) -> Vec<Option<Bytes>> {
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_by(|&a, &b| self.compare(keys[a], keys[b]));
+
+        let mut result = vec![None; keys.len()];
+        let mut prev = [self.head(); K_MAX_HEIGHT];
+        let max_height = self.get_max_height();
+        for i in order {
+            let x = self.find_from(keys[i], &mut prev, max_height);
+            result[i] = self.live_equal(x, keys[i]).map(|x| x.data.clone());
+        }
+        result
+    }
+
+    /// Like `find`, but the search at each level starts from `prev`, which must
+    /// hold nodes before `key` left by the search of a smaller or equal key.
+    fn find_from<'a>(
+        &self,
+        key: &[u8],
+        prev: &mut Path<'a>,
+        max_height: usize,
+    ) -> Option<Nav<'a>> {
+        let mut level = max_height - 1;
+        let mut x = prev[level];
+        loop {
+            let next = x.next(level);
+            if let Some(next) = self.before_key(next, key) {
+                x = next;
+            } else {
+                prev[level] = x;
+                if level == 0 {
+                    return next;
+                }
+                level -= 1;
+                // The finger left at the lower level may be ahead of us.
+                if self.node_is_before(x, prev[level]) {
+                    x = prev[level];
+                }
+            }
+        }
+    }
+
+    /// Move `prev[level]`, a node before `key`, forward to the last node before
+    /// `key` on `level`.
+    fn catch_up<'a>(&self, prev: &mut Path<'a>, level: usize, key: &[u8]) {
+        while let Some(next) = self.before_key(prev[level].next(level), key) {
+            prev[level] = next;
+        }
+    }
+
+    fn node_is_before_key(&self, node: Nav<'_>, key: &[u8]) -> bool {
+        node == self.head() || self.lt(node.key(), key)
+    }
+
+    fn node_is_before(&self, a: Nav<'_>, b: Nav<'_>) -> bool {
+        let head = self.head();
+        if b == head {
+            false
+        } else if a == head {
+            true
+        } else {
+            self.lt(a.key(), b.key())
+        }
+    }
+
+    /// Returns up to `n - 1` increasing keys splitting the list into `n` ranges
+    /// holding roughly the same number of entries, e.g. to plan parallel scans or
+    /// shard splits. The first range ends before the first key, the last one
+    /// starts at the last key.
+    ///
+    /// Nodes reaching a level are spread evenly among the entries, so instead of
+    /// counting every entry the keys are picked from the sparsest level holding at
+    /// least 8 nodes per range. Fewer keys are returned if the
+    /// list doesn't have enough distinct ones.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(0..100);
+    /// let points = sl.partition_points(4);
+    /// assert_eq!(points.len(), 3);
+    /// assert!(points.windows(2).all(|w| w[0] < w[1]));
+    /// ```
+    pub fn partition_points(&self, n: usize) -> Vec<Bytes> {
+        const PARTITION_SAMPLES: usize = 8;
+        if n < 2 {
+            return vec![];
+        }
+        let target = n.saturating_mul(PARTITION_SAMPLES);
+        let mut level = self.get_max_height() - 1;
+        let mut nodes = self.level_nodes(level);
+        while level > 0 && nodes.len() < target {
+            level -= 1;
+            nodes = self.level_nodes(level);
+        }
+
+        let mut points: Vec<Bytes> = Vec::with_capacity(n - 1);
+        for i in 1..n {
+            let node = match nodes.get(i * nodes.len() / n) {
+                Some(node) => node,
+                None => break,
+            };
+            match points.last() {
+                Some(last) if !self.lt(last.as_ref(), node.data.as_ref()) => {}
+                _ => points.push(node.data.clone()),
+            }
+        }
+        points
+    }
+
+    /// Returns the last key such that the entries up to it are charged at most
+    /// `budget` bytes, e.g. to flush part of a memtable. An entry is charged its
+    /// key, its value and its node, and equal keys are charged together so the
+    /// split never falls between them.
+    ///
+    /// Returns the last key if the whole list fits, and `None` if it is empty or
+    /// its first key alone is over the budget.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// for i in 0..10u8 {
+    ///     sl.insert(vec![i; 1000]);
+    /// }
+    /// let split = sl.last_key_before_memory_limit(3500).unwrap();
+    /// assert_eq!(split[0], 2);
+    /// assert_eq!(sl.last_key_before_memory_limit(100), None);
+    /// ```
+    pub fn last_key_before_memory_limit(&self, budget: u64) -> Option<Bytes> {
+        // The last node before a different key, where the list can be split.
+        let mut boundary: Option<&Node> = None;
+        let mut last: Option<&Node> = None;
+        let mut charged = 0u64;
+        for node in self {
+            if let Some(last) = last {
+                if !self.eq(last.data.as_ref(), node.data.as_ref()) {
+                    boundary = Some(last);
+                }
+            }
+            let size = (node.data.len() + node.value().len() + Node::size(node.height())) as u64;
+            charged = charged.saturating_add(size);
+            if charged > budget {
+                return boundary.map(|node| node.data.clone());
+            }
+            last = Some(node);
+        }
+        last.map(|node| node.data.clone())
+    }
+
+    fn level_nodes(&self, level: usize) -> Vec<&Node> {
+        iter::successors(self.head().next(level), |x| x.next(level))
+            .map(Nav::node)
+            .collect()
+    }
+
+    /// Iterate over the keys linked at `level`, level 0 holding every entry.
+    /// Levels at or above `K_MAX_HEIGHT` yield nothing. Removed entries are still
+    /// linked, and included.
+    ///
+    /// This is meant for diagnostics: level density, chain lengths or checking
+    /// that every level is sorted.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(0..100);
+    /// assert_eq!(sl.iter_level(0).count(), 100);
+    /// assert!(sl.iter_level(1).count() < 100);
+    /// assert_eq!(sl.iter_level(sl.get_max_height()).count(), 0);
+    /// ```
+    pub fn iter_level(&self, level: usize) -> LevelIter<'_> {
+        LevelIter {
+            node: self.head(),
+            level,
+        }
+    }
+
+    /// Check that every key on the bottom level compares greater than or equal to
+    /// the one before it, returning the first pair that doesn't.
+    ///
+    /// This is a single walk over the list, cheap enough to run in production as a
+    /// paranoid check after a bulk load or a snapshot restore, or to catch a
+    /// comparator that isn't consistent with the one the data was written with.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![5, 1, 3, 3]);
+    /// assert_eq!(sl.is_sorted(), Ok(()));
+    /// ```
+    pub fn is_sorted(&self) -> Result<(), OrderError> {
+        let mut prev = self.head().next(0);
+        while let Some((x, next)) = prev.and_then(|x| Some((x, x.next(0)?))) {
+            if self.lt(next.key(), x.key()) {
+                return Err(OrderError {
+                    prev: x.data.clone(),
+                    next: next.data.clone(),
+                    list: self.name().map(String::from),
+                });
+            }
+            prev = Some(next);
+        }
+        Ok(())
+    }
+
+    /// Run a quick sanity pass over the comparator and the random generator of
+    /// the list, e.g. at startup in a paranoid mode, so that a broken one is
+    /// reported instead of silently misplacing entries later.
+    ///
+    /// Sample keys are compared with each other, inserted into a scratch list
+    /// built with clones of both, looked up and iterated over, and towers are
+    /// drawn from the generator. The list itself isn't modified, but a generator
+    /// shared by its clones, like `Random`, moves on.
+    ///
+    /// The samples are the first keys of the list, or 8-byte keys while it holds
+    /// fewer than two. A comparator that can only read keys of its own format
+    /// should be checked once the list holds some.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// assert_eq!(sl.self_check(), Ok(()));
+    /// sl.extend(0..100);
+    /// assert_eq!(sl.self_check(), Ok(()));
+    /// ```
+    pub fn self_check(&self) -> Result<(), SelfCheckError>
+    where
+        R: Clone,
+        C: Clone,
+        A: Default,
+    {
+        const SAMPLES: usize = 64;
+        const DRAWS: usize = 1024;
+
+        let mut samples: Vec<Bytes> = self
+            .into_iter()
+            .take(SAMPLES)
+            .map(|node| node.data.clone())
+            .collect();
+        if samples.len() < 2 {
+            samples = (0..SAMPLES as u64)
+                .map(|i| {
+                    Bytes::copy_from_slice(&i.wrapping_mul(0x9e37_79b9_7f4a_7c15).to_be_bytes())
+                })
+                .collect();
+        }
+        let cmp = &self.inner.cmp;
+        for (i, a) in samples.iter().enumerate() {
+            if cmp.compare(a, a) != cmp::Ordering::Equal {
+                return Err(SelfCheckError::NotReflexive { key: a.clone() });
+            }
+            for b in &samples[i + 1..] {
+                if cmp.compare(a, b) != cmp.compare(b, a).reverse() {
+                    return Err(SelfCheckError::NotAntisymmetric {
+                        a: a.clone(),
+                        b: b.clone(),
+                    });
+                }
+            }
+        }
+
+        let config = SkipListConfig {
+            max_height: self.inner.config.max_height,
+            branching_factor: self.inner.config.branching_factor,
+            ..Default::default()
+        };
+        let scratch =
+            Self::with_config_unchecked(self.inner.rnd.clone(), cmp.clone(), A::default(), config);
+        // Out of order, so that the inserts don't all append.
+        for key in samples
+            .iter()
+            .step_by(2)
+            .chain(samples.iter().skip(1).step_by(2).rev())
+        {
+            scratch.insert(key.clone());
+        }
+        if let Some(key) = samples.iter().find(|key| !scratch.contains_key(key)) {
+            return Err(SelfCheckError::LostKey { key: key.clone() });
+        }
+        let sorted: Vec<&Bytes> = (&scratch).into_iter().map(|node| &node.data).collect();
+        if sorted.len() != samples.len() {
+            return Err(SelfCheckError::LostEntries {
+                inserted: samples.len(),
+                iterated: sorted.len(),
+            });
+        }
+        // No order can satisfy every pair if the comparator isn't transitive.
+        for (i, &a) in sorted.iter().enumerate() {
+            if let Some(&b) = sorted[i + 1..].iter().find(|&&b| self.lt(b, a)) {
+                return Err(SelfCheckError::NotTransitive {
+                    a: a.clone(),
+                    b: b.clone(),
+                });
+            }
+        }
+
+        if self.inner.config.max_height > 1 {
+            let tall = (0..DRAWS).filter(|_| self.random_height() > 1).count();
+            let expected = DRAWS / self.inner.config.branching_factor as usize;
+            if tall < expected / 4 || tall > cmp::min(expected * 4, DRAWS - 1) {
+                return Err(SelfCheckError::SkewedHeights { draws: DRAWS, tall });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the smallest key strictly greater than `key`.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![1, 3, 5]);
+    /// assert_eq!(sl.successor(&[3]).unwrap().as_ref(), &[5]);
+    /// assert_eq!(sl.successor(&[4]).unwrap().as_ref(), &[5]);
+    /// assert_eq!(sl.successor(&[5]), None);
+    /// ```
+    pub fn successor(&self, key: &[u8]) -> Option<Bytes> {
+        let x = self.seek_start_bound(Bound::Excluded(&key))?;
+        Some(x.data.clone())
+    }
+
+    /// Returns the largest key strictly less than `key`.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![1, 3, 5]);
+    /// assert_eq!(sl.predecessor(&[3]).unwrap().as_ref(), &[1]);
+    /// assert_eq!(sl.predecessor(&[2]).unwrap().as_ref(), &[1]);
+    /// assert_eq!(sl.predecessor(&[1]), None);
+    /// ```
+    pub fn predecessor(&self, key: &[u8]) -> Option<Bytes> {
+        let x = self.last_live_up_to(self.less_than(key));
+        if x == self.head() {
+            None
+        } else {
+            Some(x.data.clone())
+        }
+    }
+
+    /// Returns the smallest key, `None` if the list is empty.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// assert_eq!(sl.first(), None);
+    /// sl.extend(vec![3, 1, 2]);
+    /// assert_eq!(sl.first(), Some(&[1u8][..]));
+    /// assert_eq!(sl.last(), Some(&[3u8][..]));
+    /// ```
+    pub fn first(&self) -> Option<&[u8]> {
+        self.head().next_live().map(Nav::key)
+    }
+
+    /// Returns the `index`-th smallest key, counting from 0, `None` if the list
+    /// holds fewer entries. Every link keeps the number of entries it leads over,
+    /// so this takes O(log n) like a lookup, e.g. to rank or page through keys.
+    ///
+    /// The result is only exact when no other handle modifies the list
+    /// meanwhile.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![7, 1, 5, 3]);
+    /// sl.remove(&[3]);
+    /// assert_eq!(sl.select(0), Some(&[1u8][..]));
+    /// assert_eq!(sl.select(1), Some(&[5u8][..]));
+    /// assert_eq!(sl.select(2), Some(&[7u8][..]));
+    /// assert_eq!(sl.select(3), None);
+    /// ```
+    pub fn select(&self, index: u64) -> Option<&[u8]> {
+        if index >= self.len() {
+            return None;
+        }
+        // Entries up to `x` included.
+        let mut rank = 0;
+        let mut x = self.head();
+        for level in (0..self.get_max_height()).rev() {
+            while let Some(next) = x.next(level) {
+                let span = x.span(level) as u64;
+                if rank + span > index {
+                    break;
+                }
+                rank += span;
+                x = next;
+            }
+        }
+        x.next_live().map(Nav::key)
+    }
+
+    /// Returns the number of entries less than `key`, which is also the index
+    /// `select` returns it at if the list holds it, in O(log n) like `select`.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend((0..100).map(|i| i * 2));
+    /// assert_eq!(sl.rank(&[0]), 0);
+    /// assert_eq!(sl.rank(&[20]), 10);
+    /// assert_eq!(sl.rank(&[21]), 11);
+    /// assert_eq!(sl.rank(&[255]), 100);
+    ///
+    /// // The 90th percentile.
+    /// assert_eq!(sl.select(sl.len() * 9 / 10), Some(&[180u8][..]));
+    /// ```
+    pub fn rank(&self, key: &[u8]) -> u64 {
+        self.count_below(key, false) as u64
+    }
+
+    /// Returns the number of entries less than `key`, or equal to it if
+    /// `inclusive`, summing the spans of the links of the search.
+    fn count_below(&self, key: &[u8], inclusive: bool) -> usize {
+        let mut count = 0;
+        let mut x = self.head();
+        for level in (0..self.get_max_height()).rev() {
+            while let Some(next) = x.next(level) {
+                let order = self.compare(next.key(), key);
+                if order == cmp::Ordering::Greater || !inclusive && order == cmp::Ordering::Equal {
+                    break;
+                }
+                count += x.span(level);
+                x = next;
+            }
+        }
+        count
+    }
+
+    /// Returns the largest key, `None` if the list is empty. See `first`.
+    pub fn last(&self) -> Option<&[u8]> {
+        self.back().map(Nav::key)
+    }
+
+    /// Returns the entry of the smallest key, `None` if the list is empty. Unlike
+    /// `first`, the entry also gives access to the metadata byte and a handle.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// assert!(sl.front_entry().is_none());
+    /// sl.extend(vec![3, 1, 2]);
+    /// sl.remove(&[1]);
+    /// let mut front = sl.front_entry().unwrap();
+    /// assert_eq!(front.key(), &[2]);
+    /// front.set_meta(7);
+    /// assert_eq!(sl.meta(front.handle()), Some(7));
+    /// assert_eq!(sl.back_entry().unwrap().key(), &[3]);
+    /// ```
+    pub fn front_entry(&self) -> Option<OccupiedEntry<'_, R, C, A>> {
+        let x = self.head().next_live()?;
+        Some(OccupiedEntry::new(self, x.node()))
+    }
+
+    /// Returns the entry of the largest key, see `front_entry`.
+    pub fn back_entry(&self) -> Option<OccupiedEntry<'_, R, C, A>> {
+        let x = self.back()?;
+        Some(OccupiedEntry::new(self, x.node()))
+    }
+
+    /// Returns a cursor on the first entry, or after the last one if the list is
+    /// empty.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![1, 2, 3]);
+    /// let mut cursor = sl.cursor_front();
+    /// assert_eq!(cursor.key(), Some(&[1u8][..]));
+    /// assert_eq!(cursor.prev(), None);
+    ///
+    /// let mut cursor = sl.cursor_back();
+    /// assert_eq!(cursor.key(), Some(&[3u8][..]));
+    /// assert_eq!(cursor.next(), None);
+    /// ```
+    pub fn cursor_front(&self) -> Cursor<'_, R, C, A> {
+        Cursor::new(self, self.head().next_live())
+    }
+
+    /// Returns a cursor on the last entry, or before the first one if the list is
+    /// empty.
+    pub fn cursor_back(&self) -> Cursor<'_, R, C, A> {
+        Cursor::new(self, Some(self.last_live_up_to(self.tail())))
+    }
+
+    /// Returns a cursor on the last entry less than `key`, or before the first
+    /// one if there is none.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![1, 3, 5]);
+    /// assert_eq!(sl.find_less_than(&[4]).key(), Some(&[3u8][..]));
+    /// assert_eq!(sl.find_less_than(&[3]).key(), Some(&[1u8][..]));
+    /// assert_eq!(sl.find_less_than(&[1]).key(), None);
+    /// ```
+    pub fn find_less_than(&self, key: &[u8]) -> Cursor<'_, R, C, A> {
+        Cursor::new(self, Some(self.last_live_up_to(self.less_than(key))))
+    }
+
+    /// Returns the last node before `key`, the head if there is none.
+    pub(crate) fn less_than(&self, key: &[u8]) -> Nav<'_> {
+        let mut x = self.head();
+        let mut level = self.get_max_height() - 1;
+        loop {
+            match x.next(level) {
+                Some(next) if self.lt(next.key(), key) => x = next,
+                _ if level == 0 => return x,
+                _ => level -= 1,
+            }
+        }
+    }
+
+    /// Returns `x` if it is the head or a live node, or else the last live node
+    /// before it.
+    pub(crate) fn last_live_up_to<'a>(&'a self, mut x: Nav<'a>) -> Nav<'a> {
+        let head = self.head();
+        while x != head && x.is_deleted() {
+            let before = self.less_than(x.key());
+            // Entries equal to `x` and older than it sit between `before` and `x`.
+            x = iter::successors(before.next(0), |y| y.next(0))
+                .take_while(|&y| y != x)
+                .filter(|y| !y.is_deleted())
+                .last()
+                .unwrap_or(before);
+        }
+        x
+    }
+
+    /// Returns the last live node, `None` if the list is empty.
+    fn back(&self) -> Option<Nav<'_>> {
+        Some(self.last_live_up_to(self.tail())).filter(|&x| x != self.head())
+    }
+
+    /// Returns the key of the last entry, `None` if the list is empty.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// assert_eq!(sl.find_last(), None);
+    /// sl.extend(vec![1, 3, 5]);
+    /// sl.remove(&[5]);
+    /// assert_eq!(sl.find_last(), Some(&[3u8][..]));
+    /// ```
+    pub fn find_last(&self) -> Option<&[u8]> {
+        self.back().map(Nav::key)
+    }
+
+    /// Returns the last node of the list, removed or not, the head if it is
+    /// empty.
+    pub(crate) fn tail(&self) -> Nav<'_> {
+        let mut x = self.head();
+        for level in (0..self.get_max_height()).rev() {
+            while let Some(next) = x.next(level) {
+                x = next;
+            }
+        }
+        x
+    }
+
+    /// Returns the last node linked at `level` before `end`, or before the end of
+    /// the list if `end` is `None`. May be the head, or a deleted node.
+    fn find_before_at<'a>(&'a self, end: Option<Nav<'a>>, level: usize) -> Nav<'a> {
+        let mut x = self.head();
+        let max_height = self.get_max_height();
+        for l in (cmp::min(level, max_height - 1)..max_height).rev() {
+            while let Some(next) = x.next(l) {
+                if end.is_some_and(|end| self.gte(next.key(), end.key())) {
+                    break;
+                }
+                x = next;
+            }
+        }
+        x
+    }
+
+    /// Returns an iterator over the entries in descending order.
+    ///
+    /// Nodes only link forward, so the iterator buffers a block of entries at a
+    /// time, found with one search that stops at a level high enough for the
+    /// block to be large. This costs a search every few hundred entries instead
+    /// of one per entry as with `SkipListIter::prev`.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(0..5);
+    ///
+    /// let keys: Vec<u8> = sl.rev_iter().map(|node| node.data[0]).collect();
+    /// assert_eq!(keys, vec![4, 3, 2, 1, 0]);
+    /// ```
+    pub fn rev_iter(&self) -> RevIter<'_, R, C, A> {
+        RevIter {
+            list: self,
+            end: None,
+            block: vec![],
+            level: 0,
+        }
+    }
+
+    /// Returns a cursor on the first entry greater than or equal to `key`, or
+    /// after the last entry if there is none.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![1, 3, 5]);
+    ///
+    /// let mut cursor = sl.lower_bound(&[3]);
+    /// assert_eq!(cursor.key(), Some(&[3u8][..]));
+    /// assert_eq!(cursor.prev(), Some(&[1u8][..]));
+    /// assert_eq!(cursor.prev(), None);
+    /// assert_eq!(cursor.next(), Some(&[1u8][..]));
+    ///
+    /// // The greatest key below 6.
+    /// let mut cursor = sl.lower_bound(&[6]);
+    /// assert_eq!(cursor.key(), None);
+    /// assert_eq!(cursor.prev(), Some(&[5u8][..]));
+    /// ```
+    pub fn lower_bound(&self, key: &[u8]) -> Cursor<'_, R, C, A> {
+        Cursor::new(self, self.seek_start_bound(Bound::Included(&key)))
+    }
+
+    /// Returns a cursor on the first entry greater than `key`, or after the last
+    /// entry if there is none.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![1, 3, 5]);
+    ///
+    /// let mut cursor = sl.upper_bound(&[3]);
+    /// assert_eq!(cursor.key(), Some(&[5u8][..]));
+    /// assert_eq!(cursor.next(), None);
+    /// ```
+    pub fn upper_bound(&self, key: &[u8]) -> Cursor<'_, R, C, A> {
+        Cursor::new(self, self.seek_start_bound(Bound::Excluded(&key)))
+    }
+
+    /// Returns a cursor answering `contains` probes made in increasing key
+    /// order, each one resuming from the previous instead of the head.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend((0..100).map(|i| i * 2));
+    ///
+    /// // Join a sorted stream against the list.
+    /// let mut cursor = sl.membership_cursor();
+    /// let found: Vec<u8> = (10..20).filter(|i| cursor.contains(&[*i])).collect();
+    /// assert_eq!(found, vec![10, 12, 14, 16, 18]);
+    /// ```
+    pub fn membership_cursor(&self) -> MembershipCursor<'_, R, C, A> {
+        MembershipCursor::new(self)
+    }
+
+    /// Iterate over the keys in order. Iterating over `&SkipList` yields the
+    /// nodes instead, along with their values and metadata.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![3, 1, 2]);
+    /// sl.remove(&[2]);
+    ///
+    /// let keys: Vec<&[u8]> = sl.keys().collect();
+    /// assert_eq!(keys, vec![&[1], &[3]]);
+    /// ```
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.into_iter().map(|node| node.data.as_ref())
+    }
+
+    /// Returns an iterator over the entries from the first one greater than or
+    /// equal to `key`, like `SkipListIter::seek` followed by plain iteration.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend((0..10).map(|i| i * 2));
+    ///
+    /// let keys: Vec<u8> = sl.iter_from(&[13]).map(|node| node.data[0]).collect();
+    /// assert_eq!(keys, vec![14, 16, 18]);
+    /// assert!(sl.iter_from(&[20]).next().is_none());
+    /// ```
+    pub fn iter_from(&self, key: &[u8]) -> Iter<'_> {
+        Iter::after(self.less_than(key), self.len_relaxed())
+    }
+
+    /// Returns an iterator positioned at the entry saved in `token`, or at the
+    /// first one after it if it was removed since, see
+    /// `SkipListIter::save_position`. Of several entries equal to the key, it
+    /// goes back to the first one.
+    #[cfg(feature = "iter")]
+    pub fn resume(&self, token: &ResumeToken) -> SkipListIter<R, C, A> {
+        let mut iter = SkipListIter::new(self.clone());
+        iter.seek(&token.key);
+        iter
+    }
+
+    /// Iterate over the keys as `Bytes` sharing the buffers of the nodes, e.g.
+    /// to hand them over to the next stage of a pipeline without copying them.
+    ///
+    /// The iterator holds a handle to the list instead of borrowing it: it can
+    /// be sent to another thread and outlive this handle, and the keys it
+    /// returns outlive the list.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(vec![2, 1]);
+    /// let keys = sl.iter_bytes();
+    /// drop(sl);
+    /// let keys: Vec<_> = std::thread::spawn(move || keys.collect()).join().unwrap();
+    /// assert_eq!(keys, vec![vec![1u8], vec![2u8]]);
+    /// ```
+    pub fn iter_bytes(&self) -> BytesIter<R, C, A> {
+        BytesIter {
+            _list: self.clone(),
+            node: self.inner.nodes.head(),
+            drain: false,
+        }
+    }
+
+    /// Call `f` with every key in `range`, in order, until it returns
+    /// `ControlFlow::Break`. The keys are borrowed straight from the nodes, so
+    /// tight aggregation loops don't pay for an iterator or any copy.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    /// use std::ops::ControlFlow;
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(0..10);
+    ///
+    /// let mut sum = 0;
+    /// let _ = sl.for_each_in_range(&[2u8][..]..&[5u8][..], |key| {
+    ///     sum += key[0];
+    ///     ControlFlow::<()>::Continue(())
+    /// });
+    /// assert_eq!(sum, 2 + 3 + 4);
+    ///
+    /// let first_odd = sl.for_each_in_range(.., |key| {
+    ///     if key[0] % 2 == 1 {
+    ///         ControlFlow::Break(key[0])
+    ///     } else {
+    ///         ControlFlow::Continue(())
+    ///     }
+    /// });
+    /// assert_eq!(first_odd, ControlFlow::Break(1));
+    /// ```
+    pub fn for_each_in_range<'k, B, F>(
+        &self,
+        range: impl RangeBounds<&'k [u8]>,
+        mut f: F,
+    ) -> ControlFlow<B>
+    where
+        F: FnMut(&[u8]) -> ControlFlow<B>,
+    {
+        let nodes = iter::successors(self.seek_start_bound(range.start_bound()), |x| x.next(0));
+        for x in nodes.take_while(|x| self.is_before_end_bound(x.key(), range.end_bound())) {
+            if !x.is_deleted() {
+                f(x.key())?;
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Iterate over the entries in `range`, in order. Bounds are compared with the
+    /// comparator of the list.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(0..10);
+    /// let keys: Vec<u8> = sl.range(&[2u8][..]..=&[4u8][..]).map(|n| n.data[0]).collect();
+    /// assert_eq!(keys, vec![2, 3, 4]);
+    /// assert_eq!(sl.range(&[8u8][..]..).count(), 2);
+    /// ```
+    pub fn range<'k>(&self, range: impl RangeBounds<&'k [u8]>) -> RangeIter<'_, 'k, R, C, A> {
+        RangeIter {
+            list: self,
+            node: self.seek_start_bound(range.start_bound()),
+            end: range.end_bound().cloned(),
+            prefix: None,
+        }
+    }
+
+    /// Returns the number of entries in `range` without walking them: two
+    /// searches count the entries below each bound, see `rank`.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let mut sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// sl.extend(0..100);
+    /// assert_eq!(sl.count_range(&[10u8][..]..&[20u8][..]), 10);
+    /// assert_eq!(sl.count_range(&[10u8][..]..=&[20u8][..]), 11);
+    /// assert_eq!(sl.count_range(&[90u8][..]..), 10);
+    /// assert_eq!(sl.count_range(..), 100);
+    /// ```
+    pub fn count_range<'k>(&self, range: impl RangeBounds<&'k [u8]>) -> u64 {
+        let start = match range.start_bound() {
+            Bound::Included(key) => self.count_below(key, false) as u64,
+            Bound::Excluded(key) => self.count_below(key, true) as u64,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => self.count_below(key, true) as u64,
+            Bound::Excluded(key) => self.count_below(key, false) as u64,
+            Bound::Unbounded => self.len(),
+        };
+        end.saturating_sub(start)
+    }
+
+    /// Returns an iterator over the entries whose key passes `filter`. The filter
+    /// is pushed down to the walk over the list: the iterator starts with a
+    /// search for the first matching key, and stops at the first key past the
+    /// matching ones, without looking at the others. Keys sharing a prefix must
+    /// be ordered next to each other by the comparator, see
+    /// `for_each_with_prefix`.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator, KeyFilter};
+    /// use std::ops::Bound;
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// for key in ["apple", "apricot", "banana", "blueberry", "cherry"] {
+    ///     sl.insert(key);
+    /// }
+    /// let keys = |filter| sl.iter_filtered(filter).map(|n| n.data.clone()).collect::<Vec<_>>();
+    /// assert_eq!(keys(KeyFilter::Prefix(b"ap")), vec!["apple", "apricot"]);
+    /// assert_eq!(
+    ///     keys(KeyFilter::Range(Bound::Excluded(b"apricot"), Bound::Included(b"blueberry"))),
+    ///     vec!["banana", "blueberry"],
+    /// );
+    /// ```
+    pub fn iter_filtered<'k>(&self, filter: KeyFilter<'k>) -> RangeIter<'_, 'k, R, C, A> {
+        match filter {
+            KeyFilter::Prefix(prefix) => RangeIter {
+                list: self,
+                node: live_from(self.seek_prefix(prefix)),
+                end: Bound::Unbounded,
+                prefix: Some(prefix),
+            },
+            KeyFilter::Range(start, end) => self.range((start, end)),
+        }
+    }
+
+    /// Returns the keys in `range` until their size reaches `max_bytes`, along
+    /// with the key to resume from, e.g. to paginate over a memtable without
+    /// copying all of it. The next page is `resume_key..` up to the same end.
+    ///
+    /// Equal keys are never split across pages, and a page holds at least one
+    /// key so that pagination always moves on.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+    ///
+    /// let sl = SkipList::new(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    /// );
+    /// for i in 0..10u8 {
+    ///     sl.insert(vec![i; 100]);
+    /// }
+    /// let page = sl.collect_range_bounded(.., 250);
+    /// assert_eq!(page.entries.len(), 2);
+    /// let resume = page.resume_key.unwrap();
+    /// assert_eq!(resume[0], 2);
+    /// let page = sl.collect_range_bounded(&resume[..].., 1000);
+    /// assert_eq!(page.entries.len(), 8);
+    /// assert_eq!(page.resume_key, None);
+    /// ```
+    pub fn collect_range_bounded<'k>(
+        &self,
+        range: impl RangeBounds<&'k [u8]>,
+        max_bytes: usize,
+    ) -> Page<Bytes> {
+        self.collect_page(range, max_bytes, |node| node.data.clone())
+    }
+
+    pub(crate) fn collect_page<'k, T>(
+        &self,
+        range: impl RangeBounds<&'k [u8]>,
+        max_bytes: usize,
+        mut f: impl FnMut(&Node) -> T,
+    ) -> Page<T> {
+        let mut entries = vec![];
+        let mut bytes = 0usize;
+        let mut last: Option<&Node> = None;
+        for node in self.range(range) {
+            let size = node.data.len() + node.value().len();
+            let same_key = last.is_some_and(|last| self.eq(&last.data, &node.data));
+            if last.is_some() && !same_key && bytes.saturating_add(size) > max_bytes {
+                return Page {
+                    entries,
+                    resume_key: Some(node.data.clone()),
+                };
+            }
+            bytes = bytes.saturating_add(size);
+            entries.push(f(node));
+            last = Some(node);
+        }
+        Page {
+            entries,
+            resume_key: None,
+        }
+    }
+
+    /// Returns the first live node within the start bound of a range.
+    fn seek_start_bound(&self, start: Bound<&&[u8]>) -> Option<Nav<'_>> {
+        let x = match start {
+            Bound::Included(key) => self.seek(key, &mut []),
+            Bound::Excluded(key) => {
+                let mut x = self.seek(key, &mut []);
+                while let Some(node) = x.filter(|x| self.eq(x.key(), key)) {
+                    x = node.next(0);
+                }
+                x
+            }
+            Bound::Unbounded => self.head().next(0),
+        };
+        live_from(x)
+    }
+
+    fn is_after_start_bound(&self, key: &[u8], start: Bound<&&[u8]>) -> bool {
+        match start {
+            Bound::Included(start) => self.inner.cmp.le(start, key),
+            Bound::Excluded(start) => self.lt(start, key),
+            Bound::Unbounded => true,
+        }
+    }
+
+    fn is_before_end_bound(&self, key: &[u8], end: Bound<&&[u8]>) -> bool {
+        match end {
+            Bound::Included(end) => self.inner.cmp.le(key, end),
+            Bound::Excluded(end) => self.lt(key, end),
+            Bound::Unbounded => true,
+        }
+    }
+}
+
+#[cfg(feature = "arena")]
+impl<R, C> SkipList<R, C, ArenaImpl>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+{
+    /// Create a skiplist tuned by `config`, backed by an `ArenaImpl` allocating
+    /// blocks of `arena_block_size` bytes.
+    pub fn from_config(rnd: R, cmp: C, config: SkipListConfig) -> Result<Self, ConfigError> {
+        config.validate()?;
+        let arena = ArenaImpl::with_block_size(config.arena_block_size);
+        Ok(Self::with_config_unchecked(rnd, cmp, arena, config))
+    }
+}
+
+impl<R, C, A> fmt::Display for SkipList<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    /// Writes the keys of the list, walking it under a `Guard`. At most
+    /// `DISPLAY_LIMIT` keys are written, followed by `...` if there are more; the
+    /// precision sets another limit, e.g. `format!("{:.1000}", sl)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let guard = self.pin();
+        fmt_keys(f, guard.iter())
+    }
+}
+
+impl<R, C, A> fmt::Debug for SkipList<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SkipList")
+            .field("id", &self.id())
+            .field("name", &self.name())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Lists are equal if their comparators have the same `BaseComparator::name` and
+/// they hold the same entries in the same order: keys equal under the
+/// comparator, equal values, and equal counts under `DuplicatePolicy::Count`.
+/// Removed entries and the configs of the lists don't matter, e.g. to check
+/// that two replicas didn't diverge.
+///
+/// Writes running meanwhile can make lists holding the same entries compare
+/// unequal, or the other way around.
+impl<R, C, A, R2, A2> PartialEq<SkipList<R2, C, A2>> for SkipList<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+    R2: RandomGenerator,
+    A2: Arena,
+{
+    fn eq(&self, other: &SkipList<R2, C, A2>) -> bool {
+        let cmp = self.comparator();
+        if cmp.name() != other.comparator().name() {
+            return false;
+        }
+        let mut theirs = other.into_iter();
+        for ours in self {
+            match theirs.next() {
+                Some(node)
+                    if cmp.eq(&ours.data, &node.data)
+                        && ours.value() == node.value()
+                        && ours.count() == node.count() => {}
+                _ => return false,
+            }
+        }
+        theirs.next().is_none()
+    }
+}
+
+impl<R, C, A> Eq for SkipList<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+}
+
+/// Number of keys written by the `Display` implementations of the lists, unless
+/// the precision says otherwise.
+pub const DISPLAY_LIMIT: usize = 64;
+
+pub(crate) fn fmt_keys<K: AsRef<[u8]>>(
+    f: &mut fmt::Formatter,
+    keys: impl Iterator<Item = K>,
+) -> fmt::Result {
+    let limit = f.precision().unwrap_or(DISPLAY_LIMIT);
+    write!(f, "[")?;
+    for (i, key) in keys.enumerate() {
+        if i == limit {
+            write!(f, "... ")?;
+            break;
+        }
+        write!(f, "{:?} ", key.as_ref())?;
+    }
+    write!(f, "]")
+}
+
+impl<R, C, A, T> Extend<T> for SkipList<R, C, A>
+where
+    T: Into<u8>,
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    #[inline]
+    fn extend<I: iter::IntoIterator<Item = T>>(&mut self, iterable: I) {
+        let iterator = iterable.into_iter();
+        for element in iterator {
+            self.insert(Bytes::from(vec![element.into()]));
+        }
+    }
+}
+
+/// What `SkipList::insert` did with a key, depending on whether an equal one was
+/// already in the list and on the `DuplicatePolicy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertOutcome {
+    /// There was no equal key, the key was inserted.
+    Inserted,
+    /// An equal key was already there, the key was inserted next to it.
+    Duplicate,
+    /// An equal key was already there, the key was dropped.
+    Ignored,
+    /// An equal key was already there, it was removed and the key inserted.
+    Replaced,
+    /// An equal key was already there and the policy treats it as an error, the
+    /// key was dropped. See `SkipList::try_insert`.
+    Rejected,
+    /// An equal key was already there, the key was counted in it. See
+    /// `DuplicatePolicy::Count`.
+    Counted,
+    /// The comparator orders the key inconsistently with the entries around its
+    /// place, the key was dropped. See `ComparatorCheck`.
+    Violation,
+    /// The list already held `max_entries` entries and the `OverflowPolicy`
+    /// kept them over the key, which was dropped.
+    Full,
+}
+
+impl InsertOutcome {
+    /// Returns `true` if an equal key was already in the list.
+    pub fn existed(self) -> bool {
+        !matches!(
+            self,
+            InsertOutcome::Inserted | InsertOutcome::Violation | InsertOutcome::Full
+        )
+    }
+}
+
+/// Iterator over the keys of a list as `Bytes`, see `SkipList::iter_bytes`.
+pub struct BytesIter<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    /// Keeps the arena of the nodes alive.
+    _list: SkipList<R, C, A>,
+    /// The last returned node, the head at first.
+    node: NodePtr,
+    /// Whether the keys are moved out of the nodes, see `IntoIterator`.
+    drain: bool,
+}
+
+impl<R, C, A> BytesIter<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    /// Move to the next entry and returns its key and stored value.
+    pub(crate) fn next_entry(&mut self) -> Option<(Bytes, Bytes)> {
+        // `_list` keeps the arena of the node alive.
+        let node = unsafe { self.node.nav() }.next_live()?;
+        self.node = NodePtr::new(NonNull::from(node.node()));
+        Some((node.data.clone(), node.value().clone()))
+    }
+}
+
+impl<R, C, A> Iterator for BytesIter<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    type Item = Bytes;
+
+    fn next(&mut self) -> Option<Bytes> {
+        if !self.drain {
+            return self.next_entry().map(|(key, _)| key);
+        }
+        let node = unsafe { self.node.nav() }.next_live()?;
+        self.node = NodePtr::new(NonNull::from(node.node()));
+        // The iterator holds the last handle: nothing else reads the key.
+        Some(mem::take(unsafe { &mut (*node.as_ptr()).data }))
+    }
+}
+
+pub struct Iter<'a> {
+    head: Nav<'a>,
+    size: u64,
+    /// Whether `size` is the exact number of entries left, or only a bound.
+    exact: bool,
+}
+
+impl<'a> Iter<'a> {
+    pub(crate) fn new(head: Nav<'a>, size: u64) -> Self {
+        Iter {
+            head,
+            size,
+            exact: true,
+        }
+    }
+
+    /// Iterates over the entries after `node`, at most `size` of them.
+    pub(crate) fn after(node: Nav<'a>, size: u64) -> Self {
+        Iter {
+            exact: false,
+            ..Iter::new(node, size)
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.head = self.head.next_live()?;
+        self.size = self.size.saturating_sub(1);
+        Some(self.head.node())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // Past `usize::MAX` the hint is as close as it gets.
+        let size = usize::try_from(self.size).unwrap_or(usize::MAX);
+        let lower = if self.exact { size } else { 0 };
+        (lower, Some(size))
+    }
+}
+
+/// Entries of a range collected up to a size budget, see
+/// `SkipList::collect_range_bounded`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<T> {
+    pub entries: Vec<T>,
+    /// The first key left out, `None` if the range was exhausted.
+    pub resume_key: Option<Bytes>,
+}
+
+/// Iterator over the entries of a range, see `SkipList::range`.
+pub struct RangeIter<'a, 'k, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    list: &'a SkipList<R, C, A>,
+    /// The next live node, `None` once done.
+    node: Option<Nav<'a>>,
+    end: Bound<&'k [u8]>,
+    /// The prefix of every key, see `SkipList::iter_filtered`.
+    prefix: Option<&'k [u8]>,
+}
+
+impl<'a, 'k, R, C, A> Iterator for RangeIter<'a, 'k, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.node?;
+        let key = node.key();
+        if !self.list.is_before_end_bound(key, self.end.as_ref())
+            || self.prefix.is_some_and(|prefix| !key.starts_with(prefix))
+        {
+            self.node = None;
+            return None;
+        }
+        self.node = node.next_live();
+        Some(node.node())
+    }
+}
+
+/// A condition on keys, see `SkipList::iter_filtered`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFilter<'k> {
+    /// Keys starting with the bytes.
+    Prefix(&'k [u8]),
+    /// Keys between the bounds.
+    Range(Bound<&'k [u8]>, Bound<&'k [u8]>),
+}
+
+/// Entries buffered by `RevIter` past which the block level stops growing.
+const REV_BLOCK: usize = 256;
+
+/// Iterator over the entries in descending order, see `SkipList::rev_iter`.
+pub struct RevIter<'a, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    list: &'a SkipList<R, C, A>,
+    /// The first node of the last block, `None` before the first one. Reaches
+    /// the head once every block is read.
+    end: Option<Nav<'a>>,
+    /// Live nodes of the current block, in ascending order.
+    block: Vec<&'a Node>,
+    /// Level of the search starting the next block.
+    level: usize,
+}
+
+impl<'a, R, C, A> RevIter<'a, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    /// Buffer the live nodes from the last node at `level` before `end` up to
+    /// `end`, which also covers the entries equal to `end` but older than it.
+    fn fill(&mut self) {
+        let head = self.list.head();
+        while self.block.is_empty() && self.end != Some(head) {
+            let start = self.list.find_before_at(self.end, self.level);
+            let first = if start == head {
+                head.next(0)
+            } else {
+                Some(start)
+            };
+            let end = self.end;
+            let nodes = iter::successors(first, |x| x.next(0)).take_while(|&x| Some(x) != end);
+            self.block
+                .extend(nodes.filter(|x| !x.is_deleted()).map(Nav::node));
+            self.end = Some(start);
+            if self.block.len() < REV_BLOCK && self.level + 1 < self.list.get_max_height() {
+                self.level += 1;
+            }
+        }
+    }
+}
+
+impl<'a, R, C, A> Iterator for RevIter<'a, R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.block.is_empty() {
+            self.fill();
+        }
+        self.block.pop()
+    }
+}
+
+/// Iterator over the keys linked at one level, see `SkipList::iter_level`.
+pub struct LevelIter<'a> {
+    node: Nav<'a>,
+    level: usize,
+}
+
+impl<'a> Iterator for LevelIter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.level >= K_MAX_HEIGHT {
+            return None;
+        }
+        self.node = self.node.next(self.level)?;
+        Some(self.node.key())
+    }
+}
+
+impl<'a, R, C, A> iter::IntoIterator for &'a SkipList<R, C, A>
 where
     R: RandomGenerator,
     C: BaseComparator,
@@ -369,129 +4587,3077 @@ where
     type Item = &'a Node;
     type IntoIter = Iter<'a>;
 
-    fn into_iter(self) -> Iter<'a> {
-        Iter {
-            head: unsafe { mem::transmute_copy(&self.inner.head) },
-            size: self.len(),
-            _lifetime: PhantomData,
+    fn into_iter(self) -> Iter<'a> {
+        Iter::new(self.head(), self.len_relaxed())
+    }
+}
+
+/// Consumes the handle, yielding the keys in order like `iter_bytes`. If it is
+/// the last handle of the list, weak ones held by background tasks included,
+/// the keys are moved out of the nodes instead of shared with them, so their
+/// buffers go back as soon as the caller drops them.
+/// # Examples
+#[cfg_attr(feature = "arena", doc = "```")]
+#[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+/// use dakv_skiplist::{SkipList, Random, ArenaImpl, DefaultComparator};
+///
+/// let mut sl = SkipList::new(
+///     Random::new(0xdead_beef),
+///     DefaultComparator::default (),
+///     ArenaImpl::new(),
+/// );
+/// sl.extend(vec![2, 1]);
+/// let keys: Vec<_> = sl.into_iter().collect();
+/// assert_eq!(keys, vec![vec![1u8], vec![2u8]]);
+/// ```
+impl<R, C, A> iter::IntoIterator for SkipList<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    type Item = Bytes;
+    type IntoIter = BytesIter<R, C, A>;
+
+    fn into_iter(mut self) -> BytesIter<R, C, A> {
+        BytesIter {
+            node: self.inner.nodes.head(),
+            // Weak handles, e.g. of background jobs, could upgrade and read
+            // the keys.
+            drain: Arc::get_mut(&mut self.inner).is_some(),
+            _list: self,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use crate::arena::K_BLOCK_SIZE;
+    use crate::skipnode::Node;
+    #[cfg(feature = "iter")]
+    use crate::SkipListIter;
+    use crate::{
+        ArenaImpl, ComparatorCheck, ConfigError, DefaultComparator, DuplicatePolicy,
+        ImmutableSkipList, OverflowPolicy, PrefixBloomConfig, Random, SkipList, SkipListConfig,
+    };
+    use crate::{
+        BaseComparator, InsertOutcome, KeyFilter, LevelIter, NodeRef, RandomGenerator,
+        SelfCheckError, Splice, WriteBatch, K_MAX_HEIGHT,
+    };
+    use bytes::{Bytes, BytesMut};
+    use std::cmp;
+    use std::mem;
+    use std::ops::Bound::{self, Excluded, Included, Unbounded};
+    use std::ops::ControlFlow;
+    use std::panic::{self, RefUnwindSafe, UnwindSafe};
+    use std::ptr::NonNull;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_basic() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..100u8 {
+            sl.insert(vec![i]);
+        }
+        assert_eq!(sl.len(), 100);
+        for i in 0..100 {
+            assert!(sl.contains(&[i]));
+        }
+        for i in 100..120 {
+            assert!(!sl.contains(&[i]));
+        }
+    }
+
+    #[test]
+    fn test_insert_outcome() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        assert_eq!(sl.insert(vec![2u8]), InsertOutcome::Inserted);
+        assert_eq!(sl.insert(vec![1u8]), InsertOutcome::Inserted);
+        assert_eq!(sl.insert(vec![2u8]), InsertOutcome::Duplicate);
+        assert!(sl.insert(vec![1u8]).existed());
+        assert_eq!(sl.len(), 4);
+        sl.remove(&[1]);
+        assert_eq!(sl.insert(vec![1u8]), InsertOutcome::Inserted);
+        let mut splice = Splice::new();
+        assert_eq!(
+            sl.insert_with_splice(&mut splice, vec![3u8]),
+            InsertOutcome::Inserted
+        );
+        assert!(sl.insert_with_splice(&mut splice, vec![3u8]).existed());
+
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::Ignore,
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        assert_eq!(sl.insert(vec![1u8]), InsertOutcome::Inserted);
+        assert_eq!(sl.insert(vec![1u8]), InsertOutcome::Ignored);
+        assert_eq!(sl.len(), 1);
+    }
+
+    #[test]
+    fn test_name() {
+        let config = SkipListConfig {
+            name: Some("cf1".into()),
+            duplicate_policy: DuplicatePolicy::Error,
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        sl.insert(vec![1u8]);
+        let err = sl.try_insert(vec![1u8]).unwrap_err();
+        assert_eq!(err.list.as_deref(), Some("cf1"));
+        assert_eq!(err.to_string(), "duplicate key in cf1: [1]");
+        let err = sl.append_sorted(vec![vec![0u8]]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "keys out of order in cf1: [1] is before [0]"
+        );
+
+        let id = sl.id();
+        let imm = sl.freeze().ok().unwrap();
+        assert_eq!(imm.id(), id);
+        assert_eq!(imm.name(), Some("cf1"));
+        assert_eq!(
+            format!("{:?}", imm),
+            format!(
+                "ImmutableSkipList {{ id: {}, name: Some(\"cf1\"), len: 1 }}",
+                id
+            )
+        );
+        assert_eq!(imm.compact().id(), id);
+
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        assert!(sl.id() > id);
+        assert_eq!(sl.name(), None);
+    }
+
+    #[test]
+    fn test_comparator_check() {
+        // Natural order, except that [5] claims to be greater than every other key.
+        struct Sticky;
+        impl BaseComparator for Sticky {
+            fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+                match (a, b) {
+                    ([5], [5]) => cmp::Ordering::Equal,
+                    ([5], _) => cmp::Ordering::Greater,
+                    _ => a.cmp(b),
+                }
+            }
+        }
+        let new = |comparator_check| {
+            let config = SkipListConfig {
+                comparator_check,
+                ..Default::default()
+            };
+            let sl = SkipList::from_config(Random::new(0xdead_beef), Sticky, config).unwrap();
+            for key in [1u8, 3, 7] {
+                assert_eq!(sl.insert(vec![key]), InsertOutcome::Inserted);
+            }
+            sl
+        };
+
+        let sl = new(ComparatorCheck::Off);
+        assert_eq!(sl.insert(vec![5u8]), InsertOutcome::Inserted);
+
+        let sl = new(ComparatorCheck::Error);
+        assert_eq!(sl.insert(vec![5u8]), InsertOutcome::Violation);
+        assert!(!InsertOutcome::Violation.existed());
+        let err = sl.checked_insert(vec![5u8]).unwrap_err();
+        assert_eq!(err.key.as_ref(), &[5]);
+        assert_eq!(err.neighbour.as_ref(), &[7]);
+        assert_eq!(
+            err.to_string(),
+            "comparator violation: [5] is ordered inconsistently with [7]"
+        );
+        assert_eq!(sl.checked_insert(vec![8u8]), Ok(InsertOutcome::Inserted));
+        assert_eq!(sl.try_insert_batch(vec![vec![0u8], vec![5]]), Ok(1));
+        assert_eq!(format!("{}", sl), "[[0] [1] [3] [7] [8] ]");
+        assert_eq!(sl.is_sorted(), Ok(()));
+
+        let panics = |check| {
+            let sl = new(check);
+            panic::catch_unwind(panic::AssertUnwindSafe(|| sl.insert(vec![5u8]))).is_err()
+        };
+        assert!(panics(ComparatorCheck::Panic));
+        assert_eq!(panics(ComparatorCheck::DebugPanic), cfg!(debug_assertions));
+
+        // A consistent comparator passes.
+        let config = SkipListConfig {
+            comparator_check: ComparatorCheck::Panic,
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        let rnd = Random::new(7);
+        for _ in 0..1000 {
+            sl.insert((rnd.next() % 500).to_be_bytes().to_vec());
+        }
+        assert_eq!(sl.len(), 1000);
+    }
+
+    #[test]
+    fn test_eq() {
+        let new = || {
+            SkipList::new(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                ArenaImpl::new(),
+            )
+        };
+        let (a, b) = (new(), new());
+        assert_eq!(a, b);
+        for i in 0..100u8 {
+            a.insert(vec![i]);
+            b.insert(vec![99 - i]);
+        }
+        // Other heights and removed entries don't matter.
+        b.insert(vec![200u8]);
+        b.remove(&[200]);
+        assert_eq!(a, b);
+        a.insert(vec![7u8]);
+        assert_ne!(a, b);
+        b.insert(vec![7u8]);
+        assert_eq!(a, b);
+        a.pop_last();
+        assert_ne!(a, b);
+        assert_ne!(b, a);
+
+        // Same keys, ordered by comparators of the same type but not the same
+        // order.
+        struct Named(&'static str);
+        impl BaseComparator for Named {
+            fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+                a.cmp(b)
+            }
+
+            fn name(&self) -> &str {
+                self.0
+            }
+        }
+        let named = |name| SkipList::new(Random::new(0xdead_beef), Named(name), ArenaImpl::new());
+        let (a, b) = (named("asc"), named("other"));
+        a.insert(vec![1u8]);
+        b.insert(vec![1u8]);
+        assert_ne!(a, b);
+        let c = named("asc");
+        c.insert(vec![1u8]);
+        assert_eq!(a, c);
+    }
+
+    #[test]
+    fn test_max_entries() {
+        let new = |policy| {
+            let config = SkipListConfig {
+                max_entries: Some(3),
+                overflow_policy: policy,
+                ..Default::default()
+            };
+            SkipList::from_config(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                config,
+            )
+            .unwrap()
+        };
+        let keys = |sl: &SkipList<_, _, _>| sl.keys().map(|k| k[0]).collect::<Vec<_>>();
+
+        let sl = new(OverflowPolicy::Reject);
+        for i in [5, 1, 3] {
+            assert_eq!(sl.insert(vec![i]), InsertOutcome::Inserted);
+        }
+        assert_eq!(sl.insert(vec![4u8]), InsertOutcome::Full);
+        assert!(!InsertOutcome::Full.existed());
+        assert!(!sl.insert_if_absent(vec![2u8]));
+        assert_eq!(keys(&sl), vec![1, 3, 5]);
+        sl.remove(&[1]);
+        assert_eq!(sl.insert(vec![4u8]), InsertOutcome::Inserted);
+        assert_eq!(keys(&sl), vec![3, 4, 5]);
+
+        // Top 3.
+        let sl = new(OverflowPolicy::EvictSmallest);
+        for i in [5u8, 1, 3, 4, 0, 9, 3] {
+            sl.insert(vec![i]);
+        }
+        assert_eq!(keys(&sl), vec![4, 5, 9]);
+        assert_eq!(sl.insert(vec![4u8]), InsertOutcome::Full);
+        assert_eq!(sl.append_sorted(vec![vec![9u8], vec![10], vec![11]]), Ok(3));
+        assert_eq!(keys(&sl), vec![9, 10, 11]);
+        assert_eq!(sl.is_sorted(), Ok(()));
+
+        // Bottom 3.
+        let sl = new(OverflowPolicy::EvictLargest);
+        for i in [5u8, 1, 3, 4, 0, 9, 3] {
+            sl.insert(vec![i]);
+        }
+        assert_eq!(keys(&sl), vec![0, 1, 3]);
+        assert_eq!(sl.append_sorted(vec![vec![7u8]]), Ok(0));
+        let mut batch = WriteBatch::new();
+        for i in [2u8, 0, 8] {
+            batch.insert(vec![i]);
+        }
+        assert_eq!(sl.write(batch), Ok(3));
+        assert_eq!(keys(&sl), vec![0, 0, 1]);
+        assert_eq!(sl.len(), 3);
+
+        let sl = new(OverflowPolicy::Reject);
+        let mut batch = WriteBatch::new();
+        for i in [4u8, 2, 3, 1] {
+            batch.insert(vec![i]);
+        }
+        assert_eq!(sl.write(batch), Ok(3));
+        assert_eq!(keys(&sl), vec![1, 2, 3]);
+        assert_eq!(sl.self_check(), Ok(()));
+    }
+
+    #[test]
+    fn test_duplicate_policy() {
+        let new = |duplicate_policy| {
+            let config = SkipListConfig {
+                duplicate_policy,
+                ..Default::default()
+            };
+            SkipList::from_config(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                config,
+            )
+            .unwrap()
+        };
+
+        let sl = new(DuplicatePolicy::Replace);
+        let first = sl.insert_ref(vec![1u8]);
+        sl.set_meta(first, 7);
+        assert_eq!(sl.insert(vec![1u8]), InsertOutcome::Replaced);
+        assert_eq!(sl.len(), 1);
+        let second = sl.get_ref(&[1]).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(sl.meta(second), Some(0));
+        assert_eq!((&sl).into_iter().count(), 1);
+        assert_eq!(sl.try_insert(vec![1u8]), Ok(InsertOutcome::Replaced));
+
+        let sl = new(DuplicatePolicy::Error);
+        assert_eq!(sl.try_insert(vec![1u8]), Ok(InsertOutcome::Inserted));
+        let handle = sl.get_ref(&[1]).unwrap();
+        let err = sl.try_insert(vec![1u8]).unwrap_err();
+        assert_eq!(err.key.as_ref(), &[1]);
+        assert_eq!(err.to_string(), "duplicate key: [1]");
+        assert_eq!(sl.insert(vec![1u8]), InsertOutcome::Rejected);
+        assert_eq!(sl.get_ref(&[1]), Some(handle));
+        assert_eq!(sl.len(), 1);
+        sl.remove(&[1]);
+        assert_eq!(sl.try_insert(vec![1u8]), Ok(InsertOutcome::Inserted));
+
+        let sl = new(DuplicatePolicy::AllowDuplicates);
+        sl.insert(vec![1u8]);
+        assert_eq!(sl.try_insert(vec![1u8]), Ok(InsertOutcome::Duplicate));
+        assert_eq!(sl.len(), 2);
+        assert_eq!(sl.count(&[1]), 2);
+        assert!(sl.remove_one(&[1]));
+        assert_eq!(sl.len(), 1);
+        assert_eq!(sl.count(&[1]), 1);
+    }
+
+    #[test]
+    fn test_multiset() {
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::Count,
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        for i in 0..100u32 {
+            for _ in 0..=i % 4 {
+                sl.insert(i.to_be_bytes().to_vec());
+            }
+        }
+        assert_eq!(sl.len(), 100);
+        assert_eq!((&sl).into_iter().count(), 100);
+        assert_eq!(sl.count(&7u32.to_be_bytes()), 4);
+        assert_eq!(sl.count(&100u32.to_be_bytes()), 0);
+        let handle = sl.get_ref(&3u32.to_be_bytes()).unwrap();
+        assert_eq!(
+            sl.insert(3u32.to_be_bytes().to_vec()),
+            InsertOutcome::Counted
+        );
+        assert_eq!(sl.get_ref(&3u32.to_be_bytes()), Some(handle));
+
+        // The entry goes away with its last count.
+        let key = 3u32.to_be_bytes();
+        for count in (0..5).rev() {
+            assert!(sl.remove_one(&key));
+            assert_eq!(sl.count(&key), count);
+        }
+        assert!(!sl.contains_key(&key));
+        assert!(!sl.remove_one(&key));
+        assert_eq!(sl.len(), 99);
+        assert_eq!(sl.rank(&4u32.to_be_bytes()), 3);
+        assert_eq!(sl.insert(key.to_vec()), InsertOutcome::Inserted);
+        assert_eq!(sl.count(&key), 1);
+
+        // Counts follow renamed and ingested entries.
+        assert!(sl.rename(&7u32.to_be_bytes(), 1000u32.to_be_bytes().to_vec()));
+        assert_eq!(sl.count(&1000u32.to_be_bytes()), 4);
+        let other = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            sl.config().clone(),
+        )
+        .unwrap();
+        other.insert(1000u32.to_be_bytes().to_vec());
+        other.ingest(&sl).unwrap();
+        assert_eq!(other.count(&1000u32.to_be_bytes()), 5);
+        assert_eq!(other.count(&11u32.to_be_bytes()), 4);
+        assert_eq!(other.len(), 100);
+    }
+
+    #[test]
+    fn test_insert_if_absent() {
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::AllowDuplicates,
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        for i in (0..100u8).chain(0..100) {
+            let absent = sl.len() == u64::from(i);
+            assert_eq!(sl.insert_if_absent(vec![i]), absent);
+        }
+        assert_eq!(sl.len(), 100);
+
+        // The stored key is returned, not the one passed in.
+        let stored = sl.get_or_insert(vec![1u8]).as_ptr();
+        assert_eq!(stored, sl.get(&[1]).unwrap().as_ptr());
+        assert_eq!(sl.get_or_insert(vec![100u8]), &[100]);
+        assert_eq!(sl.len(), 101);
+        sl.remove(&[100]);
+        assert!(sl.insert_if_absent(vec![100u8]));
+        assert_eq!(sl.len(), 101);
+    }
+
+    #[test]
+    fn test_contains_key() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        assert!(!sl.contains_key(&[]));
+        for i in (0..100u8).step_by(2) {
+            sl.insert(vec![i]);
+        }
+        let reader = &sl;
+        for i in 0..100u8 {
+            assert_eq!(reader.contains_key(&[i]), i % 2 == 0);
+        }
+    }
+
+    #[test]
+    fn test_config() {
+        let config = SkipListConfig {
+            max_height: 3,
+            branching_factor: 2,
+            duplicate_policy: DuplicatePolicy::Ignore,
+            memory_budget: Some(3072),
+            arena_block_size: 2048,
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        assert_eq!(sl.memory_size(), 2048 + mem::size_of::<usize>() as u64);
+        for i in 0..100u8 {
+            sl.insert(vec![i % 10]);
+        }
+        assert_eq!(sl.len(), 10);
+        assert!(sl.get_max_height() <= 3);
+        assert!(!sl.exceeds_memory_budget());
+        sl.insert(vec![100; 2000]);
+        assert!(sl.exceeds_memory_budget());
+
+        let config = SkipListConfig {
+            max_height: 0,
+            ..Default::default()
+        };
+        assert_eq!(
+            SkipList::from_config(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                config
+            )
+            .err(),
+            Some(ConfigError::InvalidMaxHeight(0))
+        );
+    }
+
+    #[test]
+    fn test_for_each_in_range() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in (0..20u8).step_by(2) {
+            sl.insert(vec![i]);
+        }
+        let collect = |range: (Bound<&[u8]>, Bound<&[u8]>)| {
+            let mut keys = vec![];
+            let _ = sl.for_each_in_range(range, |key| {
+                keys.push(key[0]);
+                ControlFlow::<()>::Continue(())
+            });
+            keys
+        };
+        let (a, b): (&[u8], &[u8]) = (&[4], &[10]);
+        assert_eq!(collect((Included(a), Excluded(b))), vec![4, 6, 8]);
+        assert_eq!(collect((Excluded(a), Included(b))), vec![6, 8, 10]);
+        let (a, b): (&[u8], &[u8]) = (&[3], &[9]);
+        assert_eq!(collect((Included(a), Included(b))), vec![4, 6, 8]);
+        assert_eq!(collect((Excluded(b), Unbounded)), vec![10, 12, 14, 16, 18]);
+        assert_eq!(collect((Unbounded, Excluded(a))), vec![0, 2]);
+        assert!(collect((Included(b), Excluded(a))).is_empty());
+
+        let mut visited = 0;
+        let r = sl.for_each_in_range(.., |key| {
+            visited += 1;
+            if key[0] >= 6 {
+                ControlFlow::Break(key[0])
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(r, ControlFlow::Break(6));
+        assert_eq!(visited, 4);
+    }
+
+    #[test]
+    fn test_range() {
+        struct Reverse;
+
+        impl BaseComparator for Reverse {
+            fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+                b.cmp(a)
+            }
+        }
+
+        let mut sl = SkipList::new(Random::new(0xdead_beef), Reverse, ArenaImpl::new());
+        sl.extend(0..20);
+        sl.remove(&[15]);
+        let keys = |range: (Bound<&[u8]>, Bound<&[u8]>)| -> Vec<u8> {
+            sl.range(range).map(|n| n.data[0]).collect()
+        };
+        // The comparator sorts in reverse.
+        assert_eq!(
+            keys((Included(&[17][..]), Excluded(&[13][..]))),
+            vec![17, 16, 14]
+        );
+        assert_eq!(keys((Excluded(&[2][..]), Unbounded)), vec![1, 0]);
+        assert_eq!(keys((Unbounded, Included(&[18][..]))), vec![19, 18]);
+        assert_eq!(
+            keys((Included(&[3][..]), Excluded(&[3][..]))),
+            Vec::<u8>::new()
+        );
+        assert_eq!(keys((Unbounded, Unbounded)).len(), 19);
+        let mut iter = sl.range(&[1u8][..]..);
+        assert!(iter.next().is_some());
+        assert!(iter.next().is_some());
+        assert!(iter.next().is_none());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_try_insert_batch() {
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::Error,
+            ..Default::default()
+        };
+        let mut sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        sl.extend((0..100u8).map(|i| i * 2));
+        let (memory, size) = (sl.memory_usage(), sl.memory_size());
+        let batch = |keys: &[u8]| keys.iter().map(|&k| vec![k]).collect::<Vec<_>>();
+
+        // Rejected by the list, then within the batch.
+        let err = sl.try_insert_batch(batch(&[1, 3, 5, 8, 9])).unwrap_err();
+        assert_eq!(err.key.as_ref(), &[8]);
+        let err = sl.try_insert_batch(batch(&[1, 3, 1])).unwrap_err();
+        assert_eq!(err.key.as_ref(), &[1]);
+        assert_eq!(sl.len(), 100);
+        assert_eq!((sl.memory_usage(), sl.memory_size()), (memory, size));
+        assert!((&sl).into_iter().all(|n| n.data[0] % 2 == 0));
+        assert!(sl.is_sorted().is_ok());
+
+        // Another handle may be reading the unlinked nodes, they are kept.
+        let reader = sl.clone();
+        assert!(sl.try_insert_batch(batch(&[1, 3, 8])).is_err());
+        assert!(sl.memory_usage() > memory);
+        assert_eq!(reader.len(), 100);
+        drop(reader);
+
+        assert_eq!(sl.try_insert_batch(batch(&[1, 3, 5])), Ok(3));
+        assert_eq!(sl.len(), 103);
+        assert!(sl.contains_key(&[3]));
+
+        // The keys are taken before any is linked: what the iterator reads of
+        // the list outlives a failed batch.
+        let mut read = vec![];
+        let keys = [7u8, 9, 7].iter().map(|&k| {
+            read.push((sl.contains_key(&[k]), sl.get(&[k - 1]).unwrap()));
+            vec![k]
+        });
+        assert!(sl.try_insert_batch(keys).is_err());
+        assert_eq!(
+            read,
+            vec![(false, &[6u8][..]), (false, &[8]), (false, &[6])]
+        );
+        assert_eq!(sl.len(), 103);
+    }
+
+    #[test]
+    fn test_try_insert_batch_policies() {
+        let stored = |duplicate_policy| {
+            let config = SkipListConfig {
+                duplicate_policy,
+                ..Default::default()
+            };
+            let sl = SkipList::from_config(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                config,
+            )
+            .unwrap();
+            sl.insert(vec![1u8]);
+            let stored = sl.try_insert_batch(vec![vec![1u8], vec![2], vec![2]]);
+            (stored, sl.len())
+        };
+        assert_eq!(stored(DuplicatePolicy::AllowDuplicates), (Ok(3), 4));
+        assert_eq!(stored(DuplicatePolicy::Replace), (Ok(3), 2));
+        assert_eq!(stored(DuplicatePolicy::Ignore), (Ok(1), 2));
+        assert_eq!(stored(DuplicatePolicy::Count), (Ok(1), 2));
+        assert!(stored(DuplicatePolicy::Error).0.is_err());
+    }
+
+    #[test]
+    fn test_try_insert_batch_overflow() {
+        let with_policy = |overflow_policy| {
+            let config = SkipListConfig {
+                duplicate_policy: DuplicatePolicy::Error,
+                max_entries: Some(4),
+                overflow_policy,
+                ..Default::default()
+            };
+            let sl = SkipList::from_config(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                config,
+            )
+            .unwrap();
+            for key in [2u8, 4, 6] {
+                sl.insert(vec![key]);
+            }
+            sl
+        };
+
+        // Keys dropped by the policy aren't counted.
+        let sl = with_policy(OverflowPolicy::Reject);
+        assert_eq!(
+            sl.try_insert_batch(vec![vec![1u8], vec![3], vec![5]]),
+            Ok(1)
+        );
+        assert_eq!(format!("{}", sl), "[[1] [2] [4] [6] ]");
+
+        // A failed batch brings back the entries it evicted.
+        let sl = with_policy(OverflowPolicy::EvictSmallest);
+        sl.insert(vec![8u8]);
+        assert!(sl.set_meta(sl.get_ref(&[2]).unwrap(), 7));
+        let memory = sl.memory_usage();
+        let err = sl
+            .try_insert_batch(vec![vec![5u8], vec![7], vec![6]])
+            .unwrap_err();
+        assert_eq!(err.key.as_ref(), &[6]);
+        assert_eq!(format!("{}", sl), "[[2] [4] [6] [8] ]");
+        assert_eq!(sl.meta(sl.get_ref(&[2]).unwrap()), Some(7));
+        assert_eq!(sl.memory_usage(), memory);
+        assert_spans(&sl);
+        assert_eq!(sl.try_insert_batch(vec![vec![5u8], vec![7]]), Ok(2));
+        assert_eq!(format!("{}", sl), "[[5] [6] [7] [8] ]");
+
+        let sl = with_policy(OverflowPolicy::EvictLargest);
+        sl.insert(vec![8u8]);
+        assert!(sl
+            .try_insert_batch(vec![vec![1u8], vec![3], vec![1]])
+            .is_err());
+        assert_eq!(format!("{}", sl), "[[2] [4] [6] [8] ]");
+        assert_spans(&sl);
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn test_build_par() {
+        let rnd = Random::new(301);
+        let keys: Vec<Vec<u8>> = (0..100_000)
+            .map(|_| (rnd.next() % 50_000).to_be_bytes().to_vec())
+            .collect();
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        sl.insert(vec![0u8; 4]);
+        sl.build_par(keys.clone());
+        assert_eq!(sl.len(), keys.len() as u64 + 1);
+        assert!(sl.is_sorted().is_ok());
+
+        let mut sorted = keys;
+        sorted.push(vec![0u8; 4]);
+        sorted.sort();
+        let stored: Vec<&[u8]> = (&sl).into_iter().map(|n| n.data.as_ref()).collect();
+        assert_eq!(stored, sorted);
+    }
+
+    #[test]
+    fn test_iter_filtered() {
+        let config = SkipListConfig {
+            prefix_bloom: Some(PrefixBloomConfig {
+                prefix_len: 2,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        for a in 0..20u8 {
+            for b in 0..50u8 {
+                sl.insert(vec![a * 2, b, b]);
+            }
+        }
+        sl.remove(&[4, 0, 0]);
+
+        let keys = |filter| {
+            sl.iter_filtered(filter)
+                .map(|n| n.data.to_vec())
+                .collect::<Vec<_>>()
+        };
+        let found = keys(KeyFilter::Prefix(&[4]));
+        assert_eq!(found.len(), 49);
+        assert!(found.iter().all(|k| k[0] == 4));
+        assert_eq!(keys(KeyFilter::Prefix(&[4, 7])), vec![vec![4, 7, 7]]);
+        assert!(keys(KeyFilter::Prefix(&[5])).is_empty());
+        assert!(keys(KeyFilter::Prefix(&[4, 7, 8])).is_empty());
+
+        // A selective scan only compares keys around the matching ones.
+        #[cfg(feature = "deterministic")]
+        {
+            let before = sl.op_counters().comparisons;
+            assert_eq!(keys(KeyFilter::Prefix(&[20, 1])).len(), 1);
+            assert!(sl.op_counters().comparisons - before < 100);
+        }
+
+        let found = keys(KeyFilter::Range(
+            Bound::Included(&[10, 48]),
+            Bound::Excluded(&[12, 1]),
+        ));
+        assert_eq!(
+            found,
+            vec![vec![10, 48, 48], vec![10, 49, 49], vec![12, 0, 0]]
+        );
+    }
+
+    #[test]
+    fn test_split_off() {
+        let config = SkipListConfig {
+            prefix_bloom: Some(PrefixBloomConfig {
+                prefix_len: 2,
+                segment_level: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        for i in 0..1000u16 {
+            sl.insert(i.to_be_bytes().to_vec());
+        }
+        sl.remove(&100u16.to_be_bytes());
+        sl.remove(&900u16.to_be_bytes());
+        let size = sl.memory_usage();
+
+        let mut high = sl.split_off(&500u16.to_be_bytes());
+        assert_eq!((sl.len(), high.len()), (499, 499));
+        assert!(sl.is_sorted().is_ok() && high.is_sorted().is_ok());
+        assert_eq!(sl.last(), Some(&499u16.to_be_bytes()[..]));
+        assert_eq!(high.first(), Some(&500u16.to_be_bytes()[..]));
+        for level in 0..sl.get_max_height() {
+            assert!(sl.iter_level(level).all(|k| k < &500u16.to_be_bytes()[..]));
+            assert!(high
+                .iter_level(level)
+                .all(|k| k >= &500u16.to_be_bytes()[..]));
+        }
+        // The filters of both lists know their keys.
+        for i in (0..1000u16).filter(|&i| i != 100 && i != 900) {
+            let key = i.to_be_bytes();
+            assert_eq!(sl.contains_key(&key), i < 500);
+            assert_eq!(high.contains_key(&key), i >= 500);
+        }
+        assert!(high.memory_usage() < size);
+
+        // Both lists keep working on their own.
+        sl.insert(600u16.to_be_bytes().to_vec());
+        high.insert(100u16.to_be_bytes().to_vec());
+        assert_eq!(sl.last(), Some(&600u16.to_be_bytes()[..]));
+        assert_eq!(high.first(), Some(&100u16.to_be_bytes()[..]));
+
+        let rest = high.split_off(&[0xff]);
+        assert!(rest.is_empty());
+        let all = sl.split_off(&[]);
+        assert!(sl.is_empty());
+        assert_eq!(all.len(), 500);
+    }
+
+    /// Check that every link spans the live entries it leads over.
+    fn assert_spans(sl: &SkipList<Random, DefaultComparator, ArenaImpl>) {
+        let nodes: Vec<&Node> = sl.nodes().collect();
+        let live_after = |node: *const Node| {
+            let position = nodes
+                .iter()
+                .position(|&n| std::ptr::eq(n, node))
+                .map_or(0, |i| i + 1);
+            nodes[position..].iter().filter(|n| !n.is_deleted()).count()
+        };
+        for level in 0..K_MAX_HEIGHT {
+            let mut x = Some(sl.head());
+            while let Some(node) = x {
+                x = node.next(level);
+                let expected = live_after(node.as_ptr()) - x.map_or(0, |x| live_after(x.as_ptr()));
+                assert_eq!(node.span(level), expected, "level {}", level);
+            }
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::AllowDuplicates,
+            ..Default::default()
+        };
+        let mut sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config.clone(),
+        )
+        .unwrap();
+        let rnd = Random::new(7);
+        for _ in 0..500 {
+            sl.insert(vec![(rnd.next() % 200) as u8]);
+        }
+        for i in 0..50u8 {
+            sl.remove(&[i * 4]);
+        }
+        sl.pop_first();
+        sl.pop_last();
+        sl.rename(&[1], vec![201u8]);
+        assert_spans(&sl);
+
+        let high = sl.split_off(&[120]);
+        assert_spans(&sl);
+        assert_spans(&high);
+        high.insert(vec![250u8]);
+        sl.merge(high).unwrap();
+        assert_spans(&sl);
+        sl.merge(
+            SkipList::from_config(Random::new(1), DefaultComparator::default(), config).unwrap(),
+        )
+        .unwrap();
+
+        let keys: Vec<Vec<u8>> = (&sl).into_iter().map(|n| n.data.to_vec()).collect();
+        assert_eq!(keys.len() as u64, sl.len());
+        for (i, key) in (0..).zip(&keys) {
+            assert_eq!(sl.select(i), Some(&key[..]), "{}", i);
+        }
+        for k in 0..=255u8 {
+            let less = keys.iter().filter(|key| key[0] < k).count() as u64;
+            assert_eq!(sl.rank(&[k]), less, "{}", k);
+            let hi = k.saturating_add(30);
+            assert_eq!(
+                sl.count_range(&[k][..]..=&[hi][..]),
+                sl.range(&[k][..]..=&[hi][..]).count() as u64,
+                "{}",
+                k
+            );
+            assert_eq!(
+                sl.count_range((Bound::Excluded(&[k][..]), Bound::Excluded(&[hi][..]))),
+                sl.range((Bound::Excluded(&[k][..]), Bound::Excluded(&[hi][..])))
+                    .count() as u64,
+                "{}",
+                k
+            );
+        }
+        assert_eq!(sl.count_range(&[200u8][..]..&[100u8][..]), 0);
+        assert_eq!(sl.select(keys.len() as u64), None);
+
+        sl.clear();
+        assert_spans(&sl);
+        assert_eq!(sl.select(0), None);
+        sl.insert(vec![1u8]);
+        assert_eq!(sl.select(0), Some(&[1u8][..]));
+
+        let with_policy = |duplicate_policy| {
+            let config = SkipListConfig {
+                duplicate_policy,
+                ..Default::default()
+            };
+            SkipList::from_config(Random::new(3), DefaultComparator::default(), config).unwrap()
+        };
+        let sl = with_policy(DuplicatePolicy::Replace);
+        sl.extend_sorted((0..100u8).map(|i| vec![i]));
+        sl.insert(vec![50u8]);
+        assert_spans(&sl);
+        assert_eq!(sl.select(50), Some(&[50u8][..]));
+        let sl = with_policy(DuplicatePolicy::Error);
+        sl.insert(vec![3u8]);
+        assert!(sl
+            .try_insert_batch(vec![vec![1u8], vec![2], vec![3]])
+            .is_err());
+        assert_spans(&sl);
+        assert_eq!(sl.select(0), Some(&[3u8][..]));
+    }
+
+    #[test]
+    fn test_merge() {
+        let config = SkipListConfig {
+            prefix_bloom: Some(PrefixBloomConfig {
+                prefix_len: 2,
+                segment_level: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let new_list = |keys: std::ops::Range<u16>| {
+            let sl = SkipList::from_config(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                config.clone(),
+            )
+            .unwrap();
+            for i in keys {
+                sl.insert(i.to_be_bytes().to_vec());
+            }
+            sl
+        };
+        let mut sl = new_list(0..500);
+        let high = new_list(500..1000);
+        high.remove(&700u16.to_be_bytes());
+        let tail = high.last().unwrap().as_ptr();
+
+        // Disjoint, the nodes of `high` are linked as they are.
+        sl.merge(high).unwrap();
+        assert_eq!(sl.len(), 999);
+        assert!(sl.is_sorted().is_ok());
+        assert_eq!(sl.last().unwrap().as_ptr(), tail);
+        for i in 0..1000u16 {
+            assert_eq!(sl.contains_key(&i.to_be_bytes()), i != 700);
+        }
+
+        // Overlapping, or shared, the entries are inserted.
+        sl.merge(new_list(990..1010)).unwrap();
+        let shared = new_list(2000..2010);
+        let reader = shared.clone();
+        sl.merge(shared).unwrap();
+        assert_eq!(sl.len(), 999 + 20 + 10);
+        assert!(sl.is_sorted().is_ok());
+        assert_eq!(reader.len(), 10);
+
+        // Nodes of merged lists outlive them, frozen or split off.
+        let sl = sl.freeze().ok().unwrap();
+        assert!(sl.contains(&999u16.to_be_bytes()));
+        drop(reader);
+        let mut sl = new_list(0..10);
+        sl.merge(new_list(10..20)).unwrap();
+        let high = sl.split_off(&15u16.to_be_bytes());
+        drop(sl);
+        assert_eq!(high.len(), 5);
+        assert!(high.contains_key(&19u16.to_be_bytes()));
+    }
+
+    #[test]
+    fn test_delete_range() {
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::AllowDuplicates,
+            prefix_bloom: Some(PrefixBloomConfig {
+                prefix_len: 2,
+                segment_level: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        for i in 0..1000u16 {
+            sl.insert(i.to_be_bytes().to_vec());
+        }
+        sl.insert(300u16.to_be_bytes().to_vec());
+        sl.remove(&400u16.to_be_bytes());
+        let key = |i: u16| i.to_be_bytes();
+        let (lo, hi) = (key(300), key(500));
+        let reader = sl.clone();
+        let mut iter = reader.iter_from(&key(450));
+        let data_size = sl.inner.data_size.load(AtomicOrdering::SeqCst);
+
+        assert_eq!(sl.delete_range(&lo[..]..&hi[..]), 200);
+        assert_eq!(sl.len(), 800);
+        assert!(sl.inner.data_size.load(AtomicOrdering::SeqCst) < data_size);
+        assert_eq!(sl.count_range(&lo[..]..&hi[..]), 0);
+        assert!(!sl.contains_key(&key(300)));
+        assert!(sl.contains_key(&key(299)) && sl.contains_key(&key(500)));
+        assert_eq!(sl.select(300), Some(&key(500)[..]));
+        assert_spans(&sl);
+        // A reader inside the range carries on past it.
+        assert_eq!(iter.next().unwrap().data.as_ref(), &key(500));
+
+        // Bounds, empty and reversed ranges.
+        let (lo, hi) = (key(100), key(200));
+        assert_eq!(
+            sl.delete_range((Bound::Excluded(&lo[..]), Bound::Included(&hi[..]))),
+            100
+        );
+        assert!(sl.contains_key(&key(100)) && !sl.contains_key(&key(200)));
+        assert_eq!(sl.delete_range(&key(300)[..]..&key(400)[..]), 0);
+        assert_eq!(sl.delete_range(&hi[..]..&lo[..]), 0);
+        assert_eq!(sl.delete_range(..&key(50)[..]), 50);
+        assert_spans(&sl);
+        for i in 0..1000u16 {
+            let kept = (50..=100).contains(&i) || (201..300).contains(&i) || i >= 500;
+            assert_eq!(sl.contains_key(&key(i)), kept, "{}", i);
+        }
+        // The filter of the segment before the first range now covers the keys after it.
+        let mut found = vec![];
+        let _ = sl.for_each_with_prefix(&key(500), |key| {
+            found.push(key.to_vec());
+            ControlFlow::<()>::Continue(())
+        });
+        assert_eq!(found, vec![key(500).to_vec()]);
+
+        assert_eq!(sl.delete_range(..), 650);
+        assert!(sl.is_empty());
+        assert_spans(&sl);
+        sl.insert(key(7).to_vec());
+        assert_eq!(sl.first(), Some(&key(7)[..]));
+        assert_spans(&sl);
+    }
+
+    #[test]
+    fn test_retain_range() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let key = |i: u16| i.to_be_bytes();
+        for i in 0..1000u16 {
+            sl.insert(key(i).to_vec());
+        }
+        sl.insert(key(300).to_vec());
+        sl.remove(&key(400));
+        let reader = sl.clone();
+        let mut iter = reader.iter_from(&key(451));
+
+        // Keep the multiples of 10 from 300 to 500.
+        let mut seen = 0;
+        let (lo, hi) = (key(300), key(500));
+        let removed = sl.retain_range(&lo[..]..&hi[..], |node| {
+            seen += 1;
+            u16::from_be_bytes([node.data[0], node.data[1]]) % 10 == 0
+        });
+        // Both entries of 300 are kept, 400 was already removed.
+        assert_eq!(seen, 200);
+        assert_eq!(removed, 180);
+        assert_eq!(sl.len(), 1000 - 180);
+        assert_eq!(sl.count(&key(300)), 2);
+        assert!(sl.contains_key(&key(310)) && !sl.contains_key(&key(311)));
+        assert!(sl.contains_key(&key(299)) && sl.contains_key(&key(500)));
+        assert_eq!(sl.select(302), Some(&key(310)[..]));
+        assert_eq!(sl.select(320), Some(&key(500)[..]));
+        assert_spans(&sl);
+        // A reader inside the range carries on to the entries kept.
+        assert_eq!(iter.next().unwrap().data.as_ref(), &key(460));
+
+        let (lo, hi) = (key(10), key(20));
+        assert_eq!(
+            sl.retain_range((Bound::Excluded(&lo[..]), Bound::Included(&hi[..])), |_| {
+                false
+            }),
+            10
+        );
+        assert!(sl.contains_key(&key(10)) && !sl.contains_key(&key(20)));
+        assert_eq!(sl.retain_range(&hi[..]..&lo[..], |_| false), 0);
+        assert_eq!(sl.retain_range(.., |_| true), 0);
+        assert_eq!(sl.retain_range(.., |_| false), 1000 - 190);
+        assert!(sl.is_empty());
+        assert_spans(&sl);
+
+        // `keep` may write to the list, here moving the even keys past the range
+        // and removing an odd key it rejects ahead of the walk.
+        for i in 0..10u16 {
+            sl.insert(key(i).to_vec());
+        }
+        let (lo, hi) = (key(0), key(10));
+        let removed = sl.retain_range(&lo[..]..&hi[..], |node| {
+            let i = u16::from_be_bytes([node.data[0], node.data[1]]);
+            if i % 2 == 0 {
+                sl.insert(key(i + 100).to_vec());
+            } else if i == 5 {
+                sl.remove(&key(7));
+            }
+            i % 2 == 1
+        });
+        assert_eq!(removed, 5);
+        assert_eq!(sl.len(), 9);
+        assert!(sl.contains_key(&key(104)) && !sl.contains_key(&key(4)));
+        assert!(sl.contains_key(&key(5)) && !sl.contains_key(&key(7)));
+        assert_spans(&sl);
+    }
+
+    #[test]
+    fn test_height_of() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..1000u32 {
+            sl.insert(i.to_be_bytes().to_vec());
+        }
+        for i in 0..1000u32 {
+            let key = i.to_be_bytes();
+            let height = sl.height_of(&key).unwrap();
+            // Linked at every level below its height, and none above.
+            assert!(sl.iter_level(height - 1).any(|k| k == key));
+            assert!(!sl.iter_level(height).any(|k| k == key));
+        }
+        assert_eq!(sl.height_of(&1000u32.to_be_bytes()), None);
+        sl.remove(&7u32.to_be_bytes());
+        assert_eq!(sl.height_of(&7u32.to_be_bytes()), None);
+    }
+
+    #[test]
+    fn test_rev_iter() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        assert!(sl.rev_iter().next().is_none());
+        for i in 0..5000u32 {
+            sl.insert(i.to_be_bytes().to_vec());
+        }
+        // Duplicates come out in the reverse of the forward order.
+        let dup = Bytes::from(2500u32.to_be_bytes().to_vec());
+        sl.insert(dup.clone());
+        for i in (0..5000u32).step_by(3) {
+            sl.remove(&i.to_be_bytes());
+        }
+        let forward: Vec<*const Node> = (&sl).into_iter().map(|n| n as *const Node).collect();
+        let mut backward: Vec<*const Node> = sl.rev_iter().map(|n| n as *const Node).collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+        assert_eq!(forward.len() as u64, sl.len());
+        assert!(sl.rev_iter().any(|n| n.data == dup));
+    }
+
+    #[test]
+    fn test_iter_from() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..100u32 {
+            sl.insert((i * 2).to_be_bytes().to_vec());
+        }
+        sl.insert(50u32.to_be_bytes().to_vec());
+        sl.remove(&52u32.to_be_bytes());
+
+        let mut iter = sl.iter_from(&50u32.to_be_bytes());
+        assert_eq!(iter.size_hint(), (0, Some(100)));
+        for expected in [50u32, 50, 54, 56] {
+            assert_eq!(iter.next().unwrap().data.as_ref(), &expected.to_be_bytes());
+        }
+        assert_eq!(sl.iter_from(&51u32.to_be_bytes()).count(), 73);
+        assert_eq!(sl.iter_from(&[]).count(), 100);
+        assert_eq!(sl.iter_from(&[0xff]).count(), 0);
+    }
+
+    #[test]
+    fn test_collect_range_bounded() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..100u8 {
+            sl.insert(vec![i; 10]);
+        }
+        sl.insert(vec![59u8; 10]);
+
+        // Page through 30..=80 by 100 bytes.
+        let end = [80u8; 10];
+        let mut start = Bytes::from(vec![30u8; 10]);
+        let mut pages = vec![];
+        loop {
+            let page = sl.collect_range_bounded(&start[..]..=&end[..], 100);
+            pages.push(page.entries.len());
+            match page.resume_key {
+                Some(resume) => start = resume,
+                None => break,
+            }
+        }
+        // The second [59; 10] is over the budget but stays with the first.
+        assert_eq!(pages, vec![10, 10, 11, 10, 10, 1]);
+
+        // A key over the budget still makes a page on its own.
+        sl.insert(vec![200u8; 1000]);
+        let page = sl.collect_range_bounded(&[150u8][..].., 10);
+        assert_eq!(page.entries, vec![Bytes::from(vec![200u8; 1000])]);
+        assert_eq!(page.resume_key, None);
+        let page = sl.collect_range_bounded(&[250u8][..].., 10);
+        assert!(page.entries.is_empty());
+    }
+
+    #[test]
+    fn test_get() {
+        let mut sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        sl.extend(0..10);
+        for i in 0..10u8 {
+            assert_eq!(sl.get(&[i]), Some(&[i][..]));
+            assert_eq!(sl.get_bytes(&[i]).unwrap().as_ref(), &[i]);
+        }
+        assert_eq!(sl.get(&[10]), None);
+        assert_eq!(sl.get_bytes(&[10]), None);
+
+        let stored = sl.get_bytes(&[3]).unwrap();
+        assert_eq!(stored.as_ptr(), sl.get(&[3]).unwrap().as_ptr());
+        sl.remove(&[3]);
+        assert_eq!(sl.get(&[3]), None);
+        assert_eq!(stored.as_ref(), &[3]);
+    }
+
+    #[test]
+    fn test_node_ref() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let refs: Vec<_> = (0..10u8).rev().map(|i| sl.insert_ref(vec![i])).collect();
+        for (i, r) in refs.iter().rev().enumerate() {
+            assert_eq!(sl.key_of(*r), Some(&[i as u8][..]));
+            assert_eq!(sl.get_ref(&[i as u8]), Some(*r));
+        }
+        let mut handle = sl.get_ref(&[0]);
+        let mut count = 0;
+        while let Some(h) = handle {
+            assert_eq!(sl.key_of(h), Some(&[count][..]));
+            count += 1;
+            handle = sl.next(h);
+        }
+        assert_eq!(count, 10);
+        assert_eq!(sl.get_ref(&[10]), None);
+
+        let other = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        assert_eq!(other.key_of(refs[0]), None);
+        assert_eq!(other.next(refs[0]), None);
+        assert_eq!(sl.clone().key_of(refs[0]), Some(&[9][..]));
+    }
+
+    #[test]
+    fn test_meta() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let handles: Vec<_> = (0..100u8).map(|i| sl.insert_ref(vec![i])).collect();
+        for (i, h) in handles.iter().enumerate() {
+            assert_eq!(sl.meta(*h), Some(0));
+            assert!(sl.set_meta(*h, i as u8));
+        }
+        assert_eq!(sl.update_meta(handles[3], |meta| meta | 0x80), Some(3));
+        assert_eq!(sl.meta(handles[3]), Some(0x83));
+
+        // Concurrent flag updates don't lose each other.
+        let h = handles[0];
+        thread::scope(|s| {
+            for bit in 0..8 {
+                let sl = sl.clone();
+                s.spawn(move || {
+                    sl.update_meta(h, |meta| meta | (1 << bit));
+                });
+            }
+        });
+        assert_eq!(sl.meta(h), Some(0xff));
+
+        // Tags survive inserts around them and are visible through guards.
+        for i in 100..200u8 {
+            sl.insert(vec![i]);
+        }
+        let guard = sl.pin();
+        assert_eq!(guard.get(&[42]).unwrap().meta(), 42);
+        drop(guard);
+
+        let other = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        assert_eq!(other.meta(h), None);
+        assert!(!other.set_meta(h, 1));
+        assert_eq!(other.update_meta(h, |meta| meta), None);
+
+        let imm = sl.freeze().ok().unwrap().compact();
+        assert_eq!((&imm).into_iter().nth(42).unwrap().get_meta(), 42);
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        assert_eq!(sl.successor(&[0]), None);
+        assert_eq!(sl.predecessor(&[0]), None);
+        for i in (10..100u8).step_by(10) {
+            sl.insert(vec![i]);
+            sl.insert(vec![i]);
+        }
+        for i in 0..100u8 {
+            let succ = sl.successor(&[i]).map(|k| k[0]);
+            let pred = sl.predecessor(&[i]).map(|k| k[0]);
+            assert_eq!(succ, if i < 90 { Some(i / 10 * 10 + 10) } else { None });
+            assert_eq!(
+                pred,
+                if i > 10 {
+                    Some((i - 1) / 10 * 10)
+                } else {
+                    None
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_first_last() {
+        let mut sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        assert_eq!(sl.first(), None);
+        assert_eq!(sl.last(), None);
+        sl.extend(vec![5, 1, 9, 9, 3]);
+        assert_eq!(sl.first(), Some(&[1u8][..]));
+        assert_eq!(sl.last(), Some(&[9u8][..]));
+        sl.remove(&[1]);
+        sl.remove(&[9]);
+        assert_eq!(sl.first(), Some(&[3u8][..]));
+        assert_eq!(sl.last(), Some(&[5u8][..]));
+        sl.remove(&[3]);
+        sl.remove(&[5]);
+        assert_eq!(sl.first(), None);
+        assert_eq!(sl.last(), None);
+    }
+
+    #[test]
+    fn test_get_many() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in (0..200u8).step_by(3) {
+            sl.insert(vec![i]);
+        }
+        let probes: Vec<[u8; 1]> = (0..=255u8).rev().chain(0..100).map(|i| [i]).collect();
+        let keys: Vec<&[u8]> = probes.iter().map(|p| &p[..]).collect();
+        let found = sl.get_many(&keys);
+        assert_eq!(found.len(), keys.len());
+        for (key, found) in keys.iter().zip(found) {
+            let expected = key[0] < 200 && key[0] % 3 == 0;
+            assert_eq!(found.is_some(), expected);
+            if let Some(found) = found {
+                assert_eq!(&found[..], *key);
+            }
+        }
+        assert!(sl.get_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_last_key_before_memory_limit() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        assert_eq!(sl.last_key_before_memory_limit(u64::MAX), None);
+        for i in 0..10u8 {
+            sl.insert(vec![i; 1000]);
+        }
+        // Two copies of [5; 1000] are charged together.
+        sl.insert(vec![5u8; 1000]);
+        let charged = |keys: &[u8]| -> u64 {
+            keys.iter()
+                .map(|&k| {
+                    (&sl)
+                        .into_iter()
+                        .filter(|n| n.data[0] == k)
+                        .map(|n| 1000 + Node::size(n.height()) as u64)
+                        .sum::<u64>()
+                })
+                .sum()
+        };
+        let up_to_4 = charged(&[0, 1, 2, 3, 4]);
+        let up_to_5 = up_to_4 + charged(&[5]);
+        let split = |budget| sl.last_key_before_memory_limit(budget).map(|k| k[0]);
+        assert_eq!(split(up_to_4), Some(4));
+        assert_eq!(split(up_to_5 - 1), Some(4));
+        assert_eq!(split(up_to_5), Some(5));
+        assert_eq!(split(up_to_4 - 1), Some(3));
+        assert_eq!(split(999), None);
+        assert_eq!(split(u64::MAX), Some(9));
+    }
+
+    #[test]
+    fn test_iter_level() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in (0..=255u8).rev() {
+            sl.insert(vec![i]);
+        }
+        let mut below: Vec<&[u8]> = sl.iter_level(0).collect();
+        assert_eq!(below.len(), 256);
+        for level in 1..K_MAX_HEIGHT + 1 {
+            let keys: Vec<&[u8]> = sl.iter_level(level).collect();
+            assert!(keys.windows(2).all(|w| w[0] < w[1]));
+            // Every node of a level is also linked in the level below.
+            assert!(keys.iter().all(|k| below.contains(k)));
+            if level >= sl.get_max_height() {
+                assert!(keys.is_empty());
+            }
+            below = keys;
+        }
+    }
+
+    #[test]
+    fn test_is_sorted() {
+        #[derive(Clone, Default)]
+        struct FlippableComparator {
+            reversed: Arc<AtomicBool>,
+        }
+        impl BaseComparator for FlippableComparator {
+            fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+                if self.reversed.load(AtomicOrdering::SeqCst) {
+                    b.cmp(a)
+                } else {
+                    a.cmp(b)
+                }
+            }
+        }
+
+        let cmp = FlippableComparator::default();
+        let sl = SkipList::new(Random::new(0xdead_beef), cmp.clone(), ArenaImpl::new());
+        assert_eq!(sl.is_sorted(), Ok(()));
+        sl.insert(vec![2]);
+        assert_eq!(sl.is_sorted(), Ok(()));
+        for i in [3, 1, 3, 0] {
+            sl.insert(vec![i]);
+        }
+        assert_eq!(sl.is_sorted(), Ok(()));
+
+        // The data was written with another order than the one used to check it.
+        cmp.reversed.store(true, AtomicOrdering::SeqCst);
+        let err = sl.is_sorted().unwrap_err();
+        assert_eq!(err.prev.as_ref(), &[0]);
+        assert_eq!(err.next.as_ref(), &[1]);
+    }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SkipList<Random, DefaultComparator, ArenaImpl>>();
+        assert_send_sync::<ImmutableSkipList<DefaultComparator, ArenaImpl>>();
+        assert_send_sync::<NodeRef>();
+        assert_send_sync::<Splice>();
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let writers: Vec<_> = (0..4u32)
+            .map(|t| {
+                let sl = sl.clone();
+                thread::spawn(move || {
+                    for i in 0..500u32 {
+                        // Interleaved keys, so the writers keep moving each
+                        // other's path.
+                        sl.insert((i * 4 + t).to_be_bytes().to_vec());
+                        sl.insert_if_absent((10_000 + i).to_be_bytes().to_vec());
+                        assert!(sl.contains(&(i * 4 + t).to_be_bytes()));
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        // Each of the 500 keys given to `insert_if_absent` was added once.
+        assert_eq!(sl.len(), 2500);
+        assert_eq!(sl.is_sorted(), Ok(()));
+        assert_eq!(sl.self_check(), Ok(()));
+    }
+
+    #[test]
+    fn test_into_iter_drain() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in (0..100u8).rev() {
+            sl.insert(vec![i]);
+        }
+        sl.remove(&[50]);
+        let expected: Vec<_> = (0..100u8).filter(|&i| i != 50).map(|i| vec![i]).collect();
+
+        // Another handle still reads the keys.
+        let other = sl.clone();
+        let keys: Vec<_> = sl.into_iter().collect();
+        assert_eq!(keys, expected);
+        assert_eq!(other.len(), 99);
+        assert!(other.contains_key(&[0]));
+
+        // So does a weak handle, e.g. of a background job, once upgraded.
+        let weak = Arc::downgrade(&other.inner);
+        let mut keys = other.into_iter();
+        assert_eq!(keys.next().unwrap().as_ref(), &[0]);
+        let list = SkipList::from_inner(weak.upgrade().unwrap());
+        assert!(list.contains_key(&[0]));
+        drop((keys, weak));
+
+        // The last handle moves them out.
+        let keys: Vec<_> = list.into_iter().collect();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn test_unwind_safe() {
+        fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe>() {}
+        assert_unwind_safe::<LevelIter>();
+        assert_unwind_safe::<NodeRef>();
+
+        #[derive(Clone)]
+        struct PanickingComparator;
+
+        impl BaseComparator for PanickingComparator {
+            fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+                if a == [13] || b == [13] {
+                    panic!("unlucky key");
+                }
+                a.cmp(b)
+            }
+        }
+
+        let mut sl = SkipList::new(
+            Random::new(0xdead_beef),
+            PanickingComparator,
+            ArenaImpl::new(),
+        );
+        sl.extend(0..10);
+        let writer = sl.clone();
+        let r = panic::catch_unwind(panic::AssertUnwindSafe(|| writer.insert(vec![13])));
+        assert!(r.is_err());
+        assert_eq!(sl.len(), 10);
+        assert_eq!(sl.iter_level(0).count(), 10);
+        for i in 0..10 {
+            assert!(sl.contains_key(&[i]));
+        }
+        // The locks of a panicking writer don't stop the next ones.
+        assert_eq!(writer.insert(vec![10]), InsertOutcome::Inserted);
+        assert!(writer.remove(&[3]));
+        assert_eq!(sl.pop_first().unwrap().as_ref(), &[0]);
+        assert_eq!(sl.len(), 9);
+        assert_eq!(sl.self_check(), Ok(()));
+    }
+
+    #[test]
+    fn test_prefix_bloom() {
+        let config = SkipListConfig {
+            prefix_bloom: Some(PrefixBloomConfig {
+                prefix_len: 2,
+                segment_level: 1,
+                bits_per_segment: 128,
+            }),
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        let empty = sl.memory_usage();
+        for a in (0..=255u8).step_by(2) {
+            for b in 0..4u8 {
+                sl.insert(vec![a, b, 0]);
+            }
+        }
+        assert!(sl.memory_usage() > empty + sl.len() * 3);
+        for a in 0..=255u8 {
+            for b in 0..4u8 {
+                assert_eq!(sl.contains_key(&[a, b, 0]), a % 2 == 0);
+                assert!(!sl.contains_key(&[a, b, 1]));
+            }
+            let mut keys = vec![];
+            let _ = sl.for_each_with_prefix(&[a], |key| {
+                keys.push(key.to_vec());
+                ControlFlow::<()>::Continue(())
+            });
+            let expected: Vec<_> = if a % 2 == 0 {
+                (0..4u8).map(|b| vec![a, b, 0]).collect()
+            } else {
+                vec![]
+            };
+            assert_eq!(keys, expected);
+            for b in 0..8u8 {
+                let mut keys = vec![];
+                let _ = sl.for_each_with_prefix(&[a, b], |key| {
+                    keys.push(key.to_vec());
+                    ControlFlow::<()>::Continue(())
+                });
+                let expected: Vec<_> = if a % 2 == 0 && b < 4 {
+                    vec![vec![a, b, 0]]
+                } else {
+                    vec![]
+                };
+                assert_eq!(keys, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_prefix_bloom_concurrent() {
+        let config = SkipListConfig {
+            prefix_bloom: Some(PrefixBloomConfig {
+                prefix_len: 4,
+                segment_level: 1,
+                bits_per_segment: 128,
+            }),
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        let key = |i: u32| i.to_be_bytes();
+        sl.extend_sorted((0..2000).map(|i| key(i * 2).to_vec()));
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let sl = sl.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                while !done.load(AtomicOrdering::SeqCst) {
+                    // New segments and the ranges deleted never hide the keys
+                    // around them.
+                    for i in (0..2000).filter(|i| i % 100 != 50) {
+                        assert!(sl.contains_key(&key(i * 2)), "{}", i * 2);
+                    }
+                }
+            })
+        };
+        for round in 0..20 {
+            for i in 0..1000 {
+                sl.insert(key(i * 4 + 1).to_vec());
+            }
+            let lo = key(round * 200 + 100);
+            let hi = key(round * 200 + 101);
+            sl.delete_range(&lo[..]..=&hi[..]);
+        }
+        done.store(true, AtomicOrdering::SeqCst);
+        reader.join().unwrap();
+        assert!(sl.is_sorted().is_ok());
+    }
+
+    #[test]
+    #[cfg(not(feature = "deterministic"))]
+    fn test_spawn_fn() {
+        use crate::BackgroundTask;
+        use std::sync::Mutex;
+
+        let config = SkipListConfig {
+            prefix_bloom: Some(PrefixBloomConfig {
+                prefix_len: 4,
+                segment_level: 1,
+                bits_per_segment: 128,
+            }),
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        let tasks = Arc::new(Mutex::new(Vec::<BackgroundTask>::new()));
+        let queue = tasks.clone();
+        sl.set_spawn_fn(move |task| queue.lock().unwrap().push(task));
+
+        let rnd = Random::new(301);
+        let keys: Vec<_> = (0..500).map(|_| rnd.next().to_be_bytes()).collect();
+        for key in &keys {
+            sl.insert(key.to_vec());
+        }
+        // Filters are only approximated until the tasks run, but never miss a key.
+        assert!(!tasks.lock().unwrap().is_empty());
+        for key in &keys {
+            assert!(sl.contains_key(key));
+        }
+        for task in tasks.lock().unwrap().drain(..) {
+            task();
+        }
+        for key in &keys {
+            assert!(sl.contains_key(key));
+        }
+        // Tasks left behind by a dropped list do nothing.
+        for i in 0..100u32 {
+            sl.insert(i.to_le_bytes().to_vec());
+        }
+        let left = mem::take(&mut *tasks.lock().unwrap());
+        drop(sl);
+        for task in left {
+            task();
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "deterministic"))]
+    fn test_spawn_fn_purge() {
+        use crate::BackgroundTask;
+        use std::sync::Mutex;
+
+        let owner = Arc::new(());
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let tasks = Arc::new(Mutex::new(Vec::<BackgroundTask>::new()));
+        let queue = tasks.clone();
+        sl.set_spawn_fn(move |task| queue.lock().unwrap().push(task));
+        let run = || loop {
+            let left = mem::take(&mut *tasks.lock().unwrap());
+            if left.is_empty() {
+                break;
+            }
+            left.into_iter().for_each(|task| task());
+        };
+
+        for i in 0..100 {
+            sl.insert(tracked(&owner, i));
+        }
+        for i in (0..100).step_by(2) {
+            assert!(sl.remove(&[i]));
+            assert!(!sl.remove(&[i]));
+        }
+        assert_eq!(tasks.lock().unwrap().len(), 50);
+        assert_eq!(sl.iter_level(0).count(), 100);
+        // Some of the removed nodes are unlinked before their task runs.
+        assert_eq!(sl.pop_first().unwrap().as_ref(), &[1]);
+        assert_eq!(sl.delete_range(&[10][..]..&[20][..]), 5);
+        run();
+        assert_eq!(sl.len(), 44);
+        assert_eq!(sl.iter_level(0).count(), 44);
+        assert_eq!(sl.self_check(), Ok(()));
+        for i in (21..100).step_by(2) {
+            assert!(sl.contains_key(&[i]));
+        }
+
+        assert_eq!(sl.delete_range(..), 44);
+        assert!(sl.get_max_height() > 1);
+        run();
+        assert_eq!(sl.get_max_height(), 1);
+        drop(sl);
+        assert_eq!(Arc::strong_count(&owner), 1);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..12 {
+            sl.insert(vec![i]);
+        }
+        sl.clear();
+        assert!(sl.is_empty());
+        assert_eq!(sl.get_max_height(), 1);
+        assert_eq!(format!("{}", sl), "[]");
+        for i in 0..12 {
+            assert!(!sl.contains_key(&[i]));
+        }
+        sl.insert(vec![3]);
+        assert_eq!(format!("{}", sl), "[[3] ]");
+    }
+
+    /// Returns the key `[n]`, which holds a reference to `owner` until it drops.
+    fn tracked(owner: &Arc<()>, n: u8) -> Bytes {
+        struct Tracked {
+            _owner: Arc<()>,
+            key: [u8; 1],
+        }
+
+        impl AsRef<[u8]> for Tracked {
+            fn as_ref(&self) -> &[u8] {
+                &self.key
+            }
+        }
+
+        Bytes::from_owner(Tracked {
+            _owner: owner.clone(),
+            key: [n],
+        })
+    }
+
+    #[test]
+    fn test_drop_keys() {
+        let owner = Arc::new(());
+        let new_list = |keys: std::ops::Range<u8>| {
+            let sl = SkipList::new(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                ArenaImpl::new(),
+            );
+            for i in keys {
+                sl.insert(tracked(&owner, i));
+            }
+            sl
+        };
+        let mut sl = new_list(0..20);
+        sl.pop_first();
+        sl.delete_range(&[5][..]..&[10][..]);
+        sl.merge(new_list(30..40)).unwrap();
+        drop(sl.split_off(&[15]));
+        sl.clear();
+        for i in 0..5 {
+            sl.insert(tracked(&owner, i));
+        }
+        let imm = sl.freeze().ok().unwrap().compact();
+        assert_eq!(imm.len(), 5);
+        // The 15 keys split off go with the drop callbacks of the list they were
+        // split from.
+        assert_eq!(Arc::strong_count(&owner), 1 + 5 + 15);
+        drop(imm);
+        assert_eq!(Arc::strong_count(&owner), 1);
+    }
+
+    #[test]
+    fn test_split_off_readers() {
+        let owner = Arc::new(());
+        let mut sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..20 {
+            sl.insert(tracked(&owner, i));
+        }
+        let reader = sl.clone();
+        let mut iter = reader.iter_from(&[14]);
+        assert_eq!(iter.next().unwrap().data.as_ref(), &[14]);
+
+        // The reader is past the cut: the moved keys outlive the new list.
+        drop(sl.split_off(&[10]));
+        assert_eq!(Arc::strong_count(&owner), 21);
+        let rest: Vec<u8> = iter.map(|node| node.data[0]).collect();
+        assert_eq!(rest, (15..20).collect::<Vec<_>>());
+        drop(reader);
+        drop(sl);
+        assert_eq!(Arc::strong_count(&owner), 1);
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        sl.extend(0..10);
+        assert_eq!(sl.len(), 10);
+        for i in 0..10 {
+            assert!(sl.contains(&[i]));
+        }
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let mut sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        sl.extend(0..10);
+        for (count, i) in (&sl).into_iter().enumerate() {
+            assert_eq!(i.data.as_ref()[0], count as u8);
+        }
+
+        let mut sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        sl.extend(vec![3, 4, 6, 7, 1, 2, 5]);
+        for i in [3, 4, 6, 7, 1, 2, 5] {
+            assert!(sl.contains(&[i]));
+        }
+    }
+
+    #[test]
+    fn test_display_limit() {
+        let mut sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        sl.extend(0..3);
+        assert_eq!(format!("{}", sl), "[[0] [1] [2] ]");
+        assert_eq!(format!("{:.2}", sl), "[[0] [1] ... ]");
+        assert_eq!(format!("{:.3}", sl), "[[0] [1] [2] ]");
+        sl.extend(3..=255);
+        let dump = format!("{}", sl);
+        assert!(dump.ends_with("[63] ... ]"), "{}", dump);
+        assert_eq!(format!("{:.1000}", sl).matches('[').count(), 257);
+        assert_eq!(sl.active_guards(), 0);
+    }
+
+    #[test]
+    fn test_basic_desc() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in (0..12).rev() {
+            sl.insert(vec![i]);
+        }
+        assert_eq!(
+            "[[0] [1] [2] [3] [4] [5] [6] [7] [8] [9] [10] [11] ]",
+            format!("{}", sl)
+        );
+
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in [3, 4, 6, 7, 1, 2, 5] {
+            sl.insert(vec![i]);
+        }
+        assert_eq!("[[1] [2] [3] [4] [5] [6] [7] ]", format!("{}", sl));
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        assert_eq!(
+            sl.memory_size(),
+            (K_BLOCK_SIZE + mem::size_of::<usize>()) as u64
+        );
+        // Every node holds a pointer to its value, null in a list, one to the
+        // source of a pending entry, the metadata byte which takes 8 bytes with
+        // the padding, and a span per level. The insert time and the lookup
+        // count take 8 more each.
+        let stamp = if cfg!(feature = "timestamps") { 8 } else { 0 }
+            + if cfg!(feature = "metrics") { 8 } else { 0 };
+        assert_eq!(sl.remain_bytes(), 3848 - stamp); // 3992 - 3848 = 144 = 24 + 8 + 8 + 8 + 8 * 12
+        sl.insert(vec![0; 1000]);
+        assert_eq!(
+            sl.memory_size(),
+            (K_BLOCK_SIZE + mem::size_of::<usize>()) as u64
+        );
+        assert_eq!(sl.remain_bytes(), 3760 - 2 * stamp); // 88 = 32 + 8 + 8 + 8 + 16 * height(2)
+        assert_eq!(sl.memory_usage(), sl.memory_size() + 1000);
+        sl.insert(vec![1; 5000]);
+        assert_eq!(sl.memory_usage(), sl.memory_size() + 6000);
+    }
+
+    #[test]
+    #[cfg(feature = "iter")]
+    fn test_remove() {
+        let config = SkipListConfig {
+            prefix_bloom: Some(PrefixBloomConfig {
+                prefix_len: 1,
+                segment_level: 1,
+                bits_per_segment: 64,
+            }),
+            ..Default::default()
+        };
+        let mut sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        sl.extend(0..100);
+        sl.insert(vec![50u8]);
+        let handle = sl.get_ref(&[9]).unwrap();
+        let mut iter = SkipListIter::new(sl.clone());
+        iter.seek(&[10]);
+        let guard = sl.pin();
+        let pinned = guard.get(&[10]).unwrap();
+
+        for i in (10..100).step_by(10) {
+            assert!(sl.remove(&[i]));
+        }
+        assert!(!sl.remove(&[10]));
+        assert!(!sl.remove(&[100]));
+        assert_eq!(sl.len(), 91);
+        for i in 0..100u8 {
+            assert_eq!(sl.contains_key(&[i]), i < 10 || i % 10 != 0);
+            assert_eq!(sl.get_ref(&[i]).is_some(), i < 10 || i % 10 != 0);
         }
+        assert_eq!((&sl).into_iter().count(), 91);
+        assert!((&sl)
+            .into_iter()
+            .all(|n| n.data[0] < 10 || n.data[0] % 10 != 0));
+        assert_eq!(sl.successor(&[19]).unwrap().as_ref(), &[21]);
+        assert_eq!(sl.predecessor(&[21]).unwrap().as_ref(), &[19]);
+        assert_eq!(sl.predecessor(&[51]).unwrap().as_ref(), &[49]);
+        assert_eq!(
+            sl.get_many(&[&[20], &[21]]),
+            vec![None, Some(vec![21u8].into())]
+        );
+        let mut in_range = vec![];
+        let _ = sl.for_each_in_range(&[18u8][..]..=&[20u8][..], |key| {
+            in_range.push(key[0]);
+            ControlFlow::<()>::Continue(())
+        });
+        assert_eq!(in_range, vec![18, 19]);
+        assert_eq!(
+            sl.for_each_with_prefix(&[30], |_| ControlFlow::Break(())),
+            ControlFlow::Continue(())
+        );
+
+        // Whatever was positioned on a removed entry stays usable.
+        assert_eq!(sl.next(handle).map(|h| sl.key_of(h).unwrap()[0]), Some(11));
+        assert_eq!(iter.key().unwrap(), &[10]);
+        iter.next().unwrap();
+        assert_eq!(iter.key().unwrap(), &[11]);
+        iter.prev().unwrap();
+        assert_eq!(iter.key().unwrap(), &[9]);
+        assert_eq!(&*pinned, &[10]);
+        assert_eq!(guard.lower_bound(&[10]).unwrap(), guard.get(&[11]).unwrap());
+        assert_eq!(guard.iter().count(), 91);
+        drop(guard);
+
+        assert!(sl.remove(&[99]));
+        iter.seek_to_last();
+        assert_eq!(iter.key().unwrap(), &[98]);
+        drop(iter);
+
+        // Removed keys can come back.
+        sl.insert(vec![20u8]);
+        assert!(sl.contains_key(&[20]));
+        assert_eq!(sl.len(), 91);
+        assert!(sl.is_sorted().is_ok());
+
+        let imm = sl.freeze().ok().unwrap();
+        assert!(!imm.contains(&[30]));
+        assert!(imm.contains(&[20]));
+        assert_eq!(imm.compact().len(), 91);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::arena::K_BLOCK_SIZE;
-    use crate::{ArenaImpl, DefaultComparator, Random, SkipList};
-    use std::mem;
+    #[test]
+    fn test_pop() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in (0..1000u32).rev() {
+            sl.insert(i.to_be_bytes().to_vec());
+        }
+        sl.insert(999u32.to_be_bytes().to_vec());
+        let reader = sl.clone();
+        let mut iter = (&reader).into_iter();
+        assert_eq!(iter.next().unwrap().data.as_ref(), &0u32.to_be_bytes());
+
+        let mut popped = vec![];
+        for _ in 0..500 {
+            popped.push(sl.pop_first().unwrap());
+        }
+        assert_eq!(sl.pop_last().unwrap().as_ref(), &999u32.to_be_bytes());
+        assert_eq!(sl.pop_last().unwrap().as_ref(), &999u32.to_be_bytes());
+        assert_eq!(sl.len(), 499);
+        assert!(popped
+            .iter()
+            .enumerate()
+            .all(|(i, key)| key.as_ref() == (i as u32).to_be_bytes()));
+        // The popped nodes are unlinked from every level.
+        for level in 0..sl.get_max_height() {
+            let mut keys = sl.iter_level(level);
+            assert!(keys.all(|key| key >= &500u32.to_be_bytes()[..]));
+        }
+        assert_eq!(sl.is_sorted(), Ok(()));
+        // An iterator on a popped node carries on with the live ones.
+        assert_eq!(iter.next().unwrap().data.as_ref(), &500u32.to_be_bytes());
+
+        // Inserts after popping don't go through stale splices.
+        sl.insert(0u32.to_be_bytes().to_vec());
+        sl.insert(2000u32.to_be_bytes().to_vec());
+        assert_eq!(sl.first(), Some(&0u32.to_be_bytes()[..]));
+        assert_eq!(sl.last(), Some(&2000u32.to_be_bytes()[..]));
+        while sl.pop_first().is_some() {}
+        assert!(sl.is_empty());
+        assert_eq!(sl.pop_last(), None);
+    }
 
     #[test]
-    fn test_basic() {
+    fn test_rename() {
         let mut sl = SkipList::new(
             Random::new(0xdead_beef),
             DefaultComparator::default(),
             ArenaImpl::new(),
         );
-        for i in 0..100u8 {
-            sl.insert(vec![i]);
+        sl.extend(vec![1, 5]);
+        let one = sl.get_ref(&[1]).unwrap();
+        sl.set_meta(one, 7);
+        assert!(sl.rename(&[1], vec![9u8]));
+        assert_eq!(sl.len(), 2);
+        assert_eq!(format!("{}", sl), "[[5] [9] ]");
+        assert_eq!(sl.meta(sl.get_ref(&[9]).unwrap()), Some(7));
+        assert!(!sl.rename(&[1], vec![2u8]));
+        // Renaming onto an existing key follows the duplicate policy.
+        assert!(sl.rename(&[9], vec![5u8]));
+        assert_eq!(format!("{}", sl), "[[5] [5] ]");
+        assert_eq!(sl.len(), 2);
+
+        // Until the source is removed the new entry stays hidden.
+        let source = NonNull::from(sl.find_equal(&[5]).unwrap());
+        let source = unsafe { source.as_ref() };
+        let (n, _) = sl.insert_at_height(
+            &mut Splice::new(),
+            Bytes::from(vec![3u8]),
+            Bytes::new(),
+            None,
+            Some(source),
+        );
+        assert!(!sl.contains_key(&[3]));
+        assert_eq!(sl.first(), Some(&[5u8][..]));
+        assert_eq!(sl.len(), 2);
+        source.mark_deleted();
+        assert!(sl.contains_key(&[3]));
+        assert_eq!(format!("{}", sl), "[[3] [5] ]");
+        unsafe { n.as_ref() }.clear_pending();
+        assert!(sl.contains_key(&[3]));
+
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::Replace,
+            ..Default::default()
+        };
+        let mut sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        sl.extend(vec![1, 2]);
+        assert!(sl.rename(&[1], vec![2u8]));
+        assert_eq!(format!("{}", sl), "[[2] ]");
+        assert_eq!(sl.len(), 1);
+    }
+
+    #[test]
+    fn test_rename_concurrent() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let old = |i: u32| i.to_be_bytes().to_vec();
+        let new = |i: u32| (i + 1000).to_be_bytes().to_vec();
+        for i in 0..1000 {
+            sl.insert(old(i));
         }
-        assert_eq!(sl.len(), 100);
-        for i in 0..100 {
-            assert!(sl.contains(&[i]));
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let sl = sl.clone();
+                let done = done.clone();
+                thread::spawn(move || {
+                    while !done.load(AtomicOrdering::SeqCst) {
+                        for i in 0..1000 {
+                            // Once the old key is gone the new one is there...
+                            if !sl.contains_key(&old(i)) {
+                                assert!(sl.contains_key(&new(i)));
+                            }
+                            // ...and once the new one is there the old one is gone.
+                            if sl.contains_key(&new(i)) {
+                                assert!(!sl.contains_key(&old(i)));
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        for i in 0..1000 {
+            assert!(sl.rename(&old(i), new(i)));
         }
-        for i in 100..120 {
-            assert_eq!(sl.contains(&[i]), false);
+        done.store(true, AtomicOrdering::SeqCst);
+        for reader in readers {
+            reader.join().unwrap();
         }
+        assert_eq!(sl.len(), 1000);
+        assert_eq!(sl.first(), Some(&new(0)[..]));
     }
 
     #[test]
-    fn test_clear() {
+    fn test_self_check() {
         let mut sl = SkipList::new(
             Random::new(0xdead_beef),
             DefaultComparator::default(),
             ArenaImpl::new(),
         );
-        for i in 0..12 {
-            sl.insert(vec![i]);
+        assert_eq!(sl.self_check(), Ok(()));
+        sl.extend(vec![3, 3, 1]);
+        assert_eq!(sl.self_check(), Ok(()));
+        assert_eq!(sl.len(), 3);
+
+        #[derive(Clone)]
+        struct AlwaysLess;
+        impl BaseComparator for AlwaysLess {
+            fn compare(&self, _: &[u8], _: &[u8]) -> cmp::Ordering {
+                cmp::Ordering::Less
+            }
         }
-        sl.clear();
-        assert!(sl.is_empty());
+        let sl = SkipList::new(Random::new(0xdead_beef), AlwaysLess, ArenaImpl::new());
+        assert!(matches!(
+            sl.self_check(),
+            Err(SelfCheckError::NotReflexive { .. })
+        ));
+
+        /// Rock, paper, scissors on the last byte.
+        #[derive(Clone)]
+        struct Cyclic;
+        impl BaseComparator for Cyclic {
+            fn compare(&self, a: &[u8], b: &[u8]) -> cmp::Ordering {
+                let (a, b) = (a[a.len() - 1] % 3, b[b.len() - 1] % 3);
+                if a == b {
+                    cmp::Ordering::Equal
+                } else if (a + 1) % 3 == b {
+                    cmp::Ordering::Less
+                } else {
+                    cmp::Ordering::Greater
+                }
+            }
+        }
+        let sl = SkipList::new(Random::new(0xdead_beef), Cyclic, ArenaImpl::new());
+        // Lookups lose their way before the order is checked.
+        assert!(matches!(
+            sl.self_check(),
+            Err(SelfCheckError::LostKey { .. })
+        ));
+
+        #[derive(Clone)]
+        struct Stuck;
+        impl RandomGenerator for Stuck {
+            fn next(&self) -> u32 {
+                0
+            }
+        }
+        let sl = SkipList::new(Stuck, DefaultComparator::default(), ArenaImpl::new());
+        assert_eq!(
+            sl.self_check(),
+            Err(SelfCheckError::SkewedHeights {
+                draws: 1024,
+                tall: 1024
+            })
+        );
+        assert_eq!(
+            sl.self_check().unwrap_err().to_string(),
+            "1024 of 1024 random towers are taller than one level"
+        );
     }
 
     #[test]
-    fn test_extend() {
+    fn test_write_batch() {
+        let new = |duplicate_policy| {
+            let config = SkipListConfig {
+                duplicate_policy,
+                ..Default::default()
+            };
+            let mut sl = SkipList::from_config(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                config,
+            )
+            .unwrap();
+            sl.extend(vec![1, 3]);
+            sl
+        };
+        let batch = |keys: &[u8]| {
+            let mut batch = WriteBatch::with_capacity(keys.len());
+            for (i, &key) in keys.iter().enumerate() {
+                batch.insert_value(vec![key], vec![i as u8]);
+            }
+            batch
+        };
+        let values = |sl: &SkipList<_, _, _>| {
+            sl.into_iter()
+                .map(|node| (node.data[0], node.value().first().copied()))
+                .collect::<Vec<_>>()
+        };
+
+        let sl = new(DuplicatePolicy::AllowDuplicates);
+        assert_eq!(sl.write(batch(&[2, 3, 0, 2])), Ok(4));
+        assert_eq!(sl.len(), 6);
+        assert_eq!(
+            values(&sl),
+            vec![
+                (0, Some(2)),
+                (1, None),
+                (2, Some(3)),
+                (2, Some(0)),
+                (3, Some(1)),
+                (3, None)
+            ]
+        );
+        assert_eq!(sl.rank(&[3]), 4);
+        assert_eq!(sl.write(WriteBatch::new()), Ok(0));
+
+        let sl = new(DuplicatePolicy::Ignore);
+        assert_eq!(sl.write(batch(&[2, 3, 2])), Ok(1));
+        assert_eq!(values(&sl), vec![(1, None), (2, Some(0)), (3, None)]);
+
+        let sl = new(DuplicatePolicy::Replace);
+        assert_eq!(sl.write(batch(&[2, 3, 2])), Ok(3));
+        assert_eq!(values(&sl), vec![(1, None), (2, Some(2)), (3, Some(1))]);
+        assert_eq!(sl.len(), 3);
+
+        let sl = new(DuplicatePolicy::Error);
+        let err = sl.write(batch(&[0, 2, 2])).unwrap_err();
+        assert_eq!(err.key.as_ref(), &[2]);
+        assert_eq!(sl.len(), 2);
+        assert!(!sl.contains_key(&[0]));
+
+        let sl = new(DuplicatePolicy::Count);
+        assert_eq!(sl.write(batch(&[2, 3, 2, 2])), Ok(4));
+        assert_eq!(sl.count(&[2]), 3);
+        assert_eq!(sl.count(&[3]), 2);
+        assert_eq!(sl.len(), 3);
+    }
+
+    #[test]
+    fn test_write_batch_concurrent() {
         let mut sl = SkipList::new(
             Random::new(0xdead_beef),
             DefaultComparator::default(),
             ArenaImpl::new(),
         );
-        sl.extend(0..10);
-        assert_eq!(sl.len(), 10);
-        for i in 0..10 {
-            assert!(sl.contains(&[i]));
+        sl.extend((0..100).map(|i| i * 2 + 1));
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let sl = sl.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                while !done.load(AtomicOrdering::SeqCst) {
+                    for round in 0..10u8 {
+                        // The last key of a batch shows up with the first one.
+                        if sl.contains_key(&[round * 20 + 18]) {
+                            assert!(sl.contains_key(&[round * 20]));
+                        }
+                    }
+                }
+            })
+        };
+        for round in 0..10u8 {
+            let mut batch = WriteBatch::new();
+            for i in 0..10 {
+                batch.insert(vec![round * 20 + i * 2]);
+            }
+            assert_eq!(sl.write(batch), Ok(10));
         }
+        done.store(true, AtomicOrdering::SeqCst);
+        reader.join().unwrap();
+        assert_eq!(sl.len(), 200);
+        assert_eq!(sl.select(100), Some(&[100u8][..]));
+        assert_eq!(sl.is_sorted(), Ok(()));
     }
 
     #[test]
-    fn test_into_iter() {
+    fn test_write_batch_lists() {
+        let lists: Vec<_> = (0..4)
+            .map(|_| {
+                SkipList::new(
+                    Random::new(0xdead_beef),
+                    DefaultComparator::default(),
+                    ArenaImpl::new(),
+                )
+            })
+            .collect();
+        let writers: Vec<_> = lists
+            .iter()
+            .map(|sl| {
+                let sl = sl.clone();
+                thread::spawn(move || {
+                    for round in 0..50u32 {
+                        let mut batch = WriteBatch::new();
+                        for i in 0..10u32 {
+                            batch.insert((round * 10 + i).to_be_bytes().to_vec());
+                        }
+                        assert_eq!(sl.write(batch), Ok(10));
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = lists
+            .iter()
+            .map(|sl| {
+                let sl = sl.clone();
+                thread::spawn(move || {
+                    for round in 0..50u32 {
+                        let last = (round * 10 + 9).to_be_bytes();
+                        while !sl.contains_key(&last) {
+                            thread::yield_now();
+                        }
+                        // A batch of one list shows up whole, whatever the
+                        // other lists are writing.
+                        assert!(sl.contains_key(&(round * 10).to_be_bytes()));
+                    }
+                })
+            })
+            .collect();
+        for handle in writers.into_iter().chain(readers) {
+            handle.join().unwrap();
+        }
+        for sl in &lists {
+            assert_eq!(sl.len(), 500);
+            assert_eq!(sl.is_sorted(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_remove_ignore_duplicates() {
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::Ignore,
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        let first = sl.insert_ref(vec![1u8]);
+        sl.remove(&[1]);
+        let second = sl.insert_ref(vec![1u8]);
+        assert_ne!(first, second);
+        assert_eq!(sl.insert_ref(vec![1u8]), second);
+        assert_eq!(sl.len(), 1);
+    }
+
+    #[test]
+    fn test_remove_concurrent() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        sl.extend_sorted((0..1000u32).map(|i| i.to_be_bytes().to_vec()));
+        let remover = sl.clone();
+        let handle = thread::spawn(move || {
+            for i in (0..1000u32).step_by(2) {
+                assert!(remover.remove(&i.to_be_bytes()));
+            }
+        });
+        for i in 1000..2000u32 {
+            sl.insert(i.to_be_bytes().to_vec());
+        }
+        handle.join().unwrap();
+        assert_eq!(sl.len(), 1500);
+        assert_eq!((&sl).into_iter().count(), 1500);
+    }
+
+    #[test]
+    fn test_shrink_height() {
         let mut sl = SkipList::new(
             Random::new(0xdead_beef),
             DefaultComparator::default(),
             ArenaImpl::new(),
         );
-        sl.extend(0..10);
-        for (count, i) in (&sl).into_iter().enumerate() {
-            assert_eq!(i.data.as_ref()[0], count as u8);
+        assert_eq!(sl.shrink_height(), 1);
+        for i in 0..1000u32 {
+            sl.insert(i.to_be_bytes().to_vec());
+        }
+        let height = sl.get_max_height();
+        assert!(height > 1);
+        assert_eq!(sl.shrink_height(), height);
+
+        sl.set_max_height(K_MAX_HEIGHT);
+        assert_eq!(sl.shrink_height(), height);
+        assert_eq!(sl.get_max_height(), height);
+        for i in 0..1000u32 {
+            assert!(sl.contains_key(&i.to_be_bytes()));
         }
+        sl.insert(1000u32.to_be_bytes().to_vec());
+        assert!(sl.is_sorted().is_ok());
+        assert_eq!(sl.len(), 1001);
+    }
 
+    #[test]
+    fn test_shrink_height_concurrent() {
         let mut sl = SkipList::new(
             Random::new(0xdead_beef),
             DefaultComparator::default(),
             ArenaImpl::new(),
         );
-        sl.extend(vec![3, 4, 6, 7, 1, 2, 5]);
-        for i in [3, 4, 6, 7, 1, 2, 5] {
-            assert!(sl.contains(&[i]));
+        let writer = {
+            let sl = sl.clone();
+            thread::spawn(move || {
+                for i in 0..20_000u32 {
+                    sl.insert(i.to_be_bytes().to_vec());
+                }
+            })
+        };
+        while !writer.is_finished() {
+            sl.shrink_height();
         }
+        writer.join().unwrap();
+        for i in 0..20_000u32 {
+            assert!(sl.contains_key(&i.to_be_bytes()));
+        }
+        assert_eq!(sl.shrink_height(), sl.get_max_height());
     }
 
     #[test]
-    fn test_basic_desc() {
+    fn test_insert_with_splice() {
         let mut sl = SkipList::new(
             Random::new(0xdead_beef),
             DefaultComparator::default(),
             ArenaImpl::new(),
         );
-        for i in (0..12).rev() {
-            sl.insert(vec![i]);
+        let mut splice = Splice::new();
+        // Sorted, reversed and shuffled batches through the same splice.
+        for i in (0..1000u32).step_by(2) {
+            sl.insert_with_splice(&mut splice, i.to_be_bytes().to_vec());
         }
-        assert_eq!(
-            "[[0] [1] [2] [3] [4] [5] [6] [7] [8] [9] [10] [11] ]",
-            format!("{}", sl)
+        for i in (1..1000u32).step_by(2).rev() {
+            sl.insert_with_splice(&mut splice, i.to_be_bytes().to_vec());
+        }
+        let rnd = Random::new(301);
+        for _ in 0..1000 {
+            let i = rnd.next() % 1000;
+            sl.insert_with_splice(&mut splice, i.to_be_bytes().to_vec());
+        }
+        assert_eq!(sl.len(), 2000);
+        assert_eq!(sl.is_sorted(), Ok(()));
+        for level in 1..sl.get_max_height() {
+            let keys: Vec<&[u8]> = sl.iter_level(level).collect();
+            assert!(keys.windows(2).all(|w| w[0] <= w[1]));
+        }
+
+        // The path is stale once the list is cleared, or for another list.
+        sl.clear();
+        sl.insert_with_splice(&mut splice, vec![1]);
+        let other = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        other.insert_with_splice(&mut splice, vec![2]);
+        sl.insert(vec![0]);
+        assert_eq!(format!("{}", sl), "[[0] [1] ]");
+        assert_eq!(format!("{}", other), "[[2] ]");
+    }
+
+    #[test]
+    fn test_insert_without_copy() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
         );
+        let vec = vec![1u8; 16];
+        let mut buf = BytesMut::with_capacity(64);
+        buf.extend_from_slice(&[2u8; 16]);
+        let boxed: Box<[u8]> = vec![3u8; 16].into_boxed_slice();
+        let expected = [vec.as_ptr(), buf.as_ptr(), boxed.as_ptr()];
+        sl.insert(vec);
+        sl.insert(buf);
+        sl.insert(boxed);
+        let stored: Vec<_> = (&sl).into_iter().map(|n| n.data.as_ptr()).collect();
+        assert_eq!(stored, expected);
+        assert_eq!(sl.memory_usage(), sl.memory_size() + 48);
+    }
 
+    #[test]
+    fn test_partition_points() {
         let mut sl = SkipList::new(
             Random::new(0xdead_beef),
             DefaultComparator::default(),
             ArenaImpl::new(),
         );
-        for i in [3, 4, 6, 7, 1, 2, 5] {
-            sl.insert(vec![i]);
+        assert!(sl.partition_points(4).is_empty());
+        sl.insert(vec![1]);
+        sl.insert(vec![1]);
+        // Not enough distinct keys for every range.
+        assert_eq!(sl.partition_points(4), vec![vec![1]]);
+        sl.clear();
+
+        for i in 0..10_000u32 {
+            sl.insert(i.to_be_bytes().to_vec());
+        }
+        assert!(sl.partition_points(1).is_empty());
+        let points = sl.partition_points(8);
+        assert_eq!(points.len(), 7);
+        let mut bounds = vec![0];
+        bounds.extend(
+            points
+                .iter()
+                .map(|p| u32::from_be_bytes([p[0], p[1], p[2], p[3]])),
+        );
+        bounds.push(10_000);
+        for w in bounds.windows(2) {
+            let count = (w[1] - w[0]) as usize;
+            assert!(count > 10_000 / 16 && count < 10_000 / 4, "{:?}", bounds);
         }
-        assert_eq!("[[1] [2] [3] [4] [5] [6] [7] ]", format!("{}", sl));
     }
 
     #[test]
-    fn test_memory_usage() {
+    #[cfg(feature = "metrics")]
+    fn test_hot_keys() {
         let mut sl = SkipList::new(
             Random::new(0xdead_beef),
             DefaultComparator::default(),
             ArenaImpl::new(),
         );
-        assert_eq!(sl.memory_size(), K_BLOCK_SIZE + mem::size_of::<usize>());
-        assert_eq!(sl.remain_bytes(), 3968); // 3992 - 3968 = 24 = (32 - 16)
-        sl.insert(vec![0; 1000]);
-        assert_eq!(sl.memory_size(), K_BLOCK_SIZE + mem::size_of::<usize>());
-        assert_eq!(sl.remain_bytes(), 3920); // 48 = 32 + 8 * height(2)
+        sl.extend(0..100);
+        sl.contains_key(&[1]);
+        assert!(sl.hot_keys(10).is_empty());
+
+        let config = SkipListConfig {
+            hot_key_sampling: Some(4),
+            ..Default::default()
+        };
+        let mut sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        sl.extend(0..100);
+        for i in 0..100u8 {
+            for _ in 0..(i % 10) as u32 * 100 {
+                sl.contains_key(&[i]);
+            }
+        }
+        let guard = sl.pin();
+        for _ in 0..2000 {
+            guard.get(&[42]);
+        }
+        drop(guard);
+        // Misses aren't counted.
+        for _ in 0..10_000 {
+            sl.contains_key(&[200]);
+        }
+        let hot = sl.hot_keys(3);
+        assert_eq!(hot.len(), 3);
+        assert_eq!(hot[0].0.as_ref(), &[42]);
+        assert!(hot[0].1 > 2000 && hot[0].1 < 3000, "{:?}", hot);
+        assert!(hot.iter().skip(1).all(|(key, _)| key[0] % 10 == 9));
+        // Unlinked entries are left out.
+        assert_eq!(sl.delete_range(&[42][..]..=&[42][..]), 1);
+        let hot = sl.hot_keys(3);
+        assert_eq!(hot.len(), 3);
+        assert!(hot.iter().all(|(key, _)| key[0] % 10 == 9));
+
+        sl.clear();
+        assert!(sl.hot_keys(3).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "deterministic")]
+    fn test_op_counters() {
+        fn run() -> crate::OpCounters {
+            let config = SkipListConfig {
+                prefix_bloom: Some(PrefixBloomConfig::default()),
+                ..Default::default()
+            };
+            let sl = SkipList::from_config(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                config,
+            )
+            .unwrap();
+            // Background work is done inline.
+            sl.set_spawn_fn(|task| {
+                thread::spawn(task);
+            });
+            let rnd = Random::new(301);
+            for _ in 0..1000 {
+                sl.insert(rnd.next().to_be_bytes().to_vec());
+            }
+            for _ in 0..1000 {
+                sl.contains_key(&rnd.next().to_be_bytes());
+            }
+            sl.op_counters()
+        }
+
+        let counters = run();
+        assert!(counters.comparisons > 0);
+        assert!(counters.nodes_visited > 0);
+        assert_eq!(run(), counters);
+
+        let mut sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        sl.extend(0..100);
+        sl.reset_op_counters();
+        assert_eq!(sl.op_counters(), crate::OpCounters::default());
+        assert!(!sl.contains_key(&[200]));
+        assert!(sl.op_counters().nodes_visited < 40);
+    }
+
+    #[test]
+    #[cfg(feature = "deterministic")]
+    fn test_insert_pattern_work() {
+        let comparisons = |keys: &mut dyn Iterator<Item = u32>| {
+            let sl = SkipList::new(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                ArenaImpl::new(),
+            );
+            for key in keys {
+                sl.insert(key.to_be_bytes().to_vec());
+            }
+            sl.op_counters().comparisons
+        };
+        let rnd = Random::new(301);
+        let random = comparisons(&mut (0..10_000).map(|_| rnd.next()));
+        let ascending = comparisons(&mut (0..10_000));
+        // Each ascending insert resumes right after the previous one.
+        assert!(ascending * 4 < random, "{} vs {}", ascending, random);
+
+        // Ascending runs interleaved with each other, and nearly ascending keys,
+        // still resume from the path.
+        let runs = comparisons(&mut (0..10_000).map(|i| (i % 100) * 1000 + i / 100));
+        let nearly = comparisons(&mut (0..10_000).map(|i| i ^ 3));
+        assert!(runs * 4 < random * 3, "{} vs {}", runs, random);
+        assert!(nearly * 3 < random, "{} vs {}", nearly, random);
+    }
+
+    #[test]
+    fn test_extend_sorted() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        sl.extend_sorted((0..1000u32).step_by(2).map(|i| i.to_be_bytes().to_vec()));
+        sl.extend_sorted((1..1000u32).step_by(2).map(|i| i.to_be_bytes().to_vec()));
+        // Out of order batches are still merged correctly.
+        sl.extend_sorted((0..10u32).rev().map(|i| i.to_be_bytes().to_vec()));
+        assert_eq!(sl.len(), 1010);
+        assert_eq!(sl.is_sorted(), Ok(()));
+        for level in 1..sl.get_max_height() {
+            let keys: Vec<&[u8]> = sl.iter_level(level).collect();
+            assert!(keys.windows(2).all(|w| w[0] <= w[1]));
+        }
+    }
+
+    #[test]
+    fn test_append_sorted() {
+        let key = |i: u32| i.to_be_bytes().to_vec();
+        let sl = SkipList::from_sorted_iter(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+            (0..1000).map(key),
+        )
+        .unwrap();
+        assert_eq!(sl.len(), 1000);
+        assert_eq!(sl.is_sorted(), Ok(()));
+        // One node out of four climbs every level.
+        assert_eq!(sl.get_max_height(), 5);
+        assert_eq!(sl.iter_level(1).count(), 250);
+        assert_eq!(sl.iter_level(4).count(), 3);
+        for i in (0..1000).step_by(97) {
+            assert_eq!(sl.select(i as u64), Some(&key(i)[..]));
+            assert_eq!(sl.rank(&key(i)), i as u64);
+        }
+
+        // Appending to a list built by inserts, equal keys go through insert.
+        sl.insert(key(1000));
+        assert_eq!(
+            sl.append_sorted(vec![key(1000), key(1001), key(1002)]),
+            Ok(3)
+        );
+        assert_eq!(sl.len(), 1004);
+        let err = sl
+            .append_sorted(vec![key(1003), key(1004), key(5), key(1005)])
+            .unwrap_err();
+        assert_eq!(err.prev.as_ref(), &key(1004)[..]);
+        assert_eq!(err.next.as_ref(), &key(5)[..]);
+        assert_eq!(sl.len(), 1006);
+        assert_eq!(sl.is_sorted(), Ok(()));
+        assert_eq!(sl.rank(&key(1004)), 1005);
+        assert_eq!(sl.select(1005), Some(&key(1004)[..]));
+
+        // Removed keys are still the last ones.
+        sl.remove(&key(1004));
+        assert!(sl.append_sorted(vec![key(1003)]).is_err());
+        assert_eq!(sl.append_sorted(vec![key(1004)]), Ok(1));
+        assert!(sl.contains(&key(1004)));
+
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::Ignore,
+            ..Default::default()
+        };
+        let sl = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        assert_eq!(sl.append_sorted(vec![vec![1u8], vec![1], vec![2]]), Ok(2));
+        assert_eq!(format!("{}", sl), "[[1] [2] ]");
+    }
+
+    #[test]
+    fn test_ingest() {
+        let src = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        src.extend_sorted((0..1000u32).map(|i| i.to_be_bytes().to_vec()));
+        src.remove(&7u32.to_be_bytes());
+        let levels = |sl: &SkipList<_, _, _>| -> Vec<Vec<Vec<u8>>> {
+            (0..K_MAX_HEIGHT)
+                .map(|level| sl.iter_level(level).map(|k| k.to_vec()).collect())
+                .collect()
+        };
+
+        // Two merges with different generators give the same structure.
+        let merged: Vec<_> = [1, 2]
+            .iter()
+            .map(|&seed| {
+                let dst = SkipList::new(
+                    Random::new(seed),
+                    DefaultComparator::default(),
+                    ArenaImpl::new(),
+                );
+                dst.ingest(&src).unwrap();
+                dst
+            })
+            .collect();
+        assert_eq!(merged[0].len(), 999);
+        assert_eq!(levels(&merged[0]), levels(&merged[1]));
+        assert_eq!(merged[0].get_max_height(), src.get_max_height());
+        let dst_heights: Vec<_> = (&merged[0]).into_iter().map(|n| n.height()).collect();
+        let src_heights: Vec<_> = (&src).into_iter().map(|n| n.height()).collect();
+        assert_eq!(dst_heights, src_heights);
+
+        // Heights are capped to the destination.
+        let config = SkipListConfig {
+            max_height: 2,
+            ..Default::default()
+        };
+        let low =
+            SkipList::from_config(Random::new(1), DefaultComparator::default(), config).unwrap();
+        low.ingest(&src).unwrap();
+        assert_eq!(low.get_max_height(), 2);
+        assert!((&low).into_iter().all(|n| n.height() <= 2));
+        assert_eq!(low.is_sorted(), Ok(()));
+
+        // A list isn't ingested or merged into itself, whatever the handle.
+        let config = SkipListConfig {
+            name: Some("shard".into()),
+            ..Default::default()
+        };
+        let mut sl =
+            SkipList::from_config(Random::new(1), DefaultComparator::default(), config).unwrap();
+        sl.extend(0..10);
+        let err = sl.ingest(&sl.clone()).unwrap_err();
+        assert_eq!(err.to_string(), "list merged into itself in shard");
+        assert_eq!(sl.merge(sl.clone()), Err(err));
+        assert_eq!(sl.len(), 10);
+        assert_eq!(sl.is_sorted(), Ok(()));
+    }
+
+    #[test]
+    #[cfg(feature = "deterministic")]
+    fn test_extend_sorted_work() {
+        let new = || {
+            let sl = SkipList::new(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                ArenaImpl::new(),
+            );
+            sl.extend_sorted((0..10_000u32).map(|i| (i * 2).to_be_bytes().to_vec()));
+            sl.reset_op_counters();
+            sl
+        };
+        let batch = || (0..1000u32).map(|i| (i * 2 + 1).to_be_bytes().to_vec());
+
+        // Every search starts from the head.
+        let one_by_one = new();
+        for key in batch() {
+            one_by_one.insert_with_splice(&mut Splice::new(), key);
+        }
+        let merged = new();
+        merged.extend_sorted(batch());
+        assert_eq!(
+            format!("{:.20000}", merged),
+            format!("{:.20000}", one_by_one)
+        );
+        assert!(
+            merged.op_counters().comparisons * 2 < one_by_one.op_counters().comparisons,
+            "{:?} {:?}",
+            merged.op_counters(),
+            one_by_one.op_counters()
+        );
+    }
+
+    #[test]
+    fn test_raise_max_height() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let handles: Vec<_> = (1..=K_MAX_HEIGHT)
+            .map(|height| {
+                let sl = sl.clone();
+                thread::spawn(move || sl.raise_max_height(height))
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(sl.get_max_height(), K_MAX_HEIGHT);
+        sl.raise_max_height(1);
+        assert_eq!(sl.get_max_height(), K_MAX_HEIGHT);
+    }
+
+    #[test]
+    fn test_read_while_growing() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        let inserted = Arc::new(AtomicUsize::new(0));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let sl = sl.clone();
+                let inserted = inserted.clone();
+                thread::spawn(move || loop {
+                    let n = inserted.load(AtomicOrdering::SeqCst);
+                    for i in (0..n as u32).step_by(97) {
+                        assert!(sl.contains_key(&i.to_be_bytes()));
+                    }
+                    if n == 10_000 {
+                        break;
+                    }
+                })
+            })
+            .collect();
+        for i in 0..10_000u32 {
+            sl.insert(i.to_be_bytes().to_vec());
+            inserted.store(i as usize + 1, AtomicOrdering::SeqCst);
+        }
+        for r in readers {
+            r.join().unwrap();
+        }
+        assert_eq!(sl.is_sorted(), Ok(()));
     }
 
     #[test]
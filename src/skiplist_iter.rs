@@ -1,7 +1,13 @@
-use crate::skipnode::Node;
-use crate::{Arena, BaseComparator, RandomGenerator, SkipList, K_MAX_HEIGHT};
-use std::iter;
-use std::ptr::{null, null_mut};
+use crate::encoding::{
+    get_length_prefixed_slice, get_varint64, put_length_prefixed_slice, put_varint64,
+};
+use crate::skipnode::{live_from, raw, Nav, Node};
+use crate::{Arena, BaseComparator, IterError, RandomGenerator, SkipList};
+use bytes::Bytes;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::ptr::null;
+#[cfg(feature = "timestamps")]
+use std::time::Instant;
 
 pub struct SkipListIter<R, C, A>
 where
@@ -13,6 +19,24 @@ where
     node: *const Node,
 }
 
+// Moving the iterator only overwrites `node` once the new position is known, so a
+// panicking comparator leaves it where it was.
+impl<R, C, A> UnwindSafe for SkipListIter<R, C, A>
+where
+    R: RandomGenerator + RefUnwindSafe,
+    C: BaseComparator + RefUnwindSafe,
+    A: Arena + RefUnwindSafe,
+{
+}
+
+impl<R, C, A> RefUnwindSafe for SkipListIter<R, C, A>
+where
+    R: RandomGenerator + RefUnwindSafe,
+    C: BaseComparator + RefUnwindSafe,
+    A: Arena + RefUnwindSafe,
+{
+}
+
 impl<R, C, A> SkipListIter<R, C, A>
 where
     R: RandomGenerator,
@@ -28,53 +52,260 @@ where
     }
 
     pub fn seek_to_first(&mut self) {
-        let n = self.list.get_head();
-        self.node = n.get_next(0);
+        self.node = raw(self.list.head().next_live());
     }
 
     pub fn seek_to_last(&mut self) {
-        self.node = self.list.find_last();
-        if self.node == self.list.get_head() {
-            self.node = null();
-        }
+        self.node = self.before_or_null(self.list.tail());
     }
 
     /// For mem table to seek entry.
     pub fn seek(&mut self, target: &[u8]) {
-        let mut prev = iter::repeat(null_mut()).take(K_MAX_HEIGHT).collect();
-        self.node = self.list.find(target, &mut prev);
+        self.node = raw(live_from(self.list.seek(target, &mut [])));
     }
 
-    pub fn next(&mut self) {
-        assert!(self.valid());
-        self.node = unsafe { (*self.node).get_next(0) };
+    /// Move to the next entry.
+    ///
+    /// Returns `IterError::Invalid` without moving if the iterator isn't positioned.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<(), IterError> {
+        self.node = raw(self.current()?.next_live());
+        Ok(())
     }
 
-    pub fn prev(&mut self) {
-        assert!(self.valid());
-        let key = unsafe { (*self.node).data.as_ref() };
-        self.node = self.list.find_less_than(key);
+    /// Move to the previous entry.
+    ///
+    /// Returns `IterError::Invalid` without moving if the iterator isn't positioned.
+    pub fn prev(&mut self) -> Result<(), IterError> {
+        let key = self.current()?.key();
+        self.node = self.before_or_null(self.list.less_than(key));
+        Ok(())
+    }
+
+    /// Returns the key of the current entry, or `IterError::Invalid` if the
+    /// iterator isn't positioned.
+    pub fn key(&self) -> Result<&[u8], IterError> {
+        Ok(self.current()?.key())
+    }
+
+    /// Returns when the current entry was inserted, see `Node::inserted_at`, or
+    /// `IterError::Invalid` if the iterator isn't positioned.
+    #[cfg(feature = "timestamps")]
+    pub fn inserted_at(&self) -> Result<Instant, IterError> {
+        Ok(self.current()?.node().inserted_at())
+    }
+
+    /// Returns the value of the current entry as stored, empty in a `SkipList`,
+    /// or `IterError::Invalid` if the iterator isn't positioned. Values of a
+    /// `SkipMap` with a value codec are compressed.
+    /// # Examples
+    /// ```
+    /// use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, SkipListIter, SkipMap};
+    ///
+    /// let map = SkipMap::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+    /// map.insert(&b"key"[..], &b"value"[..]);
+    /// let mut iter = SkipListIter::new(map.as_list().clone());
+    /// iter.seek_to_first();
+    /// assert_eq!(iter.value(), Ok(&b"value"[..]));
+    /// ```
+    pub fn value(&self) -> Result<&[u8], IterError> {
+        Ok(self.current()?.node().value())
+    }
+
+    /// Returns a token recording the current position, from which
+    /// `SkipList::resume` positions a new iterator once this one is gone, or
+    /// `IterError::Invalid` if the iterator isn't positioned.
+    /// # Examples
+    /// ```
+    /// use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, ResumeToken, SkipList, SkipListIter};
+    ///
+    /// let mut sl = SkipList::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+    /// sl.extend(vec![1, 3, 5]);
+    ///
+    /// let mut iter = SkipListIter::new(sl.clone());
+    /// iter.seek(&[2]);
+    /// let saved = iter.save_position().unwrap().encode();
+    /// drop(iter);
+    ///
+    /// // E.g. the next page of a scan, or the same scan after a restart.
+    /// sl.remove(&[3]);
+    /// let iter = sl.resume(&ResumeToken::decode(&saved).unwrap());
+    /// assert_eq!(iter.key(), Ok(&[5][..]));
+    /// ```
+    pub fn save_position(&self) -> Result<ResumeToken, IterError> {
+        Ok(ResumeToken {
+            key: self.current()?.data.clone(),
+            seq: self.list.last_seq(),
+        })
+    }
 
-        if self.node == self.list.get_head() {
-            self.node = null();
+    /// Returns a standard iterator over the keys from the current position
+    /// forward, as `Bytes` sharing the buffers of the nodes, for iterator
+    /// adapters. It yields nothing if the iterator isn't positioned.
+    /// # Examples
+    /// ```
+    /// use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, SkipList, SkipListIter};
+    ///
+    /// let mut sl = SkipList::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+    /// sl.extend(vec![1, 3, 5, 7]);
+    ///
+    /// let mut iter = SkipListIter::new(sl);
+    /// iter.seek(&[2]);
+    /// let keys: Vec<u8> = iter.into_std_iter().map(|key| key[0]).take_while(|&k| k < 7).collect();
+    /// assert_eq!(keys, vec![3, 5]);
+    /// ```
+    pub fn into_std_iter(self) -> StdIter<R, C, A> {
+        StdIter { iter: self }
+    }
+
+    /// Returns a standard iterator over the entries from the current position
+    /// forward, as pairs of a key and its value like `value`. It yields nothing
+    /// if the iterator isn't positioned.
+    pub fn into_std_entries(self) -> StdEntries<R, C, A> {
+        StdEntries { iter: self }
+    }
+
+    /// Returns the last live node up to `x`, null if there is none.
+    fn before_or_null(&self, x: Nav<'_>) -> *const Node {
+        let x = self.list.last_live_up_to(x);
+        if x == self.list.head() {
+            null()
+        } else {
+            x.as_ptr()
         }
     }
 
-    pub fn key(&self) -> &[u8] {
-        assert!(self.valid());
-        unsafe { (*self.node).data.as_ref() as _ }
+    fn current(&self) -> Result<Nav<'_>, IterError> {
+        // `node` is null or a node of the list owned by the iterator.
+        unsafe { Nav::from_ptr(self.node) }.ok_or(IterError::Invalid)
+    }
+}
+
+/// An `Iterator` over the keys of a `SkipListIter`, see
+/// `SkipListIter::into_std_iter`.
+pub struct StdIter<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    iter: SkipListIter<R, C, A>,
+}
+
+impl<R, C, A> StdIter<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    /// Returns the cursor, positioned on the key `next` would yield.
+    pub fn into_inner(self) -> SkipListIter<R, C, A> {
+        self.iter
+    }
+}
+
+impl<R, C, A> Iterator for StdIter<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    type Item = Bytes;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.iter.current().ok()?.data.clone();
+        self.iter.next().ok()?;
+        Some(key)
+    }
+}
+
+/// An `Iterator` over the entries of a `SkipListIter`, see
+/// `SkipListIter::into_std_entries`.
+pub struct StdEntries<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    iter: SkipListIter<R, C, A>,
+}
+
+impl<R, C, A> StdEntries<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    /// Returns the cursor, positioned on the entry `next` would yield.
+    pub fn into_inner(self) -> SkipListIter<R, C, A> {
+        self.iter
+    }
+}
+
+impl<R, C, A> Iterator for StdEntries<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    type Item = (Bytes, Bytes);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.iter.current().ok()?.node();
+        let entry = (node.data.clone(), node.value().clone());
+        self.iter.next().ok()?;
+        Some(entry)
+    }
+}
+
+/// The position of a `SkipListIter`, see `SkipListIter::save_position`.
+///
+/// The token only holds the key of the entry, not the entry itself: it stays
+/// usable after the entry is removed, with another handle or another list
+/// sorted the same way, e.g. one restored from a snapshot after a restart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumeToken {
+    /// The key of the entry the iterator was on.
+    pub key: Bytes,
+    /// The last sequence number of the list when the token was saved, see
+    /// `SkipList::last_seq`: entries stamped with a later one were inserted
+    /// since, e.g. reading at it with `SkipMap::get_at` skips them.
+    pub seq: u64,
+}
+
+impl ResumeToken {
+    /// Returns the token as bytes, to hand it to a client or store it, read
+    /// back by `decode`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut dst = Vec::with_capacity(self.key.len() + 15);
+        put_varint64(&mut dst, self.seq);
+        put_length_prefixed_slice(&mut dst, &self.key);
+        dst
+    }
+
+    /// Reads back a token written by `encode`, `None` if `src` isn't one.
+    pub fn decode(mut src: &[u8]) -> Option<Self> {
+        let seq = get_varint64(&mut src)?;
+        let key = get_length_prefixed_slice(&mut src)?;
+        if !src.is_empty() {
+            return None;
+        }
+        Some(ResumeToken {
+            key: Bytes::copy_from_slice(key),
+            seq,
+        })
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "arena"))]
 mod tests {
     use super::*;
     use crate::cmp::DefaultComparator;
-    use crate::{ArenaImpl, Random};
+    use crate::{ArenaImpl, Random, SkipMap};
 
     #[test]
     fn test_basic() {
-        let mut sl = SkipList::new(
+        let sl = SkipList::new(
             Random::new(0xdead_beef),
             DefaultComparator::default(),
             ArenaImpl::new(),
@@ -87,19 +318,183 @@ mod tests {
         assert!(!iter.valid());
         iter.seek_to_first();
         assert!(iter.valid());
-        assert_eq!(iter.key(), &[0]);
+        assert_eq!(iter.key().unwrap(), &[0]);
         iter.seek_to_last();
-        assert_eq!(iter.key(), &[99]);
+        assert_eq!(iter.key().unwrap(), &[99]);
 
         iter.seek(&[88]);
-        assert_eq!(iter.key(), &[88]);
+        assert_eq!(iter.key().unwrap(), &[88]);
 
-        iter.next();
-        assert_eq!(iter.key(), &[89]);
+        iter.next().unwrap();
+        assert_eq!(iter.key().unwrap(), &[89]);
 
         iter.seek(&[99]);
-        assert_eq!(iter.key(), &[99]);
-        iter.prev();
-        assert_eq!(iter.key(), &[98]);
+        assert_eq!(iter.key().unwrap(), &[99]);
+        iter.prev().unwrap();
+        assert_eq!(iter.key().unwrap(), &[98]);
+    }
+
+    #[test]
+    fn test_unwind_safe() {
+        fn assert_unwind_safe<T: UnwindSafe + RefUnwindSafe>() {}
+        assert_unwind_safe::<SkipListIter<Random, DefaultComparator, ArenaImpl>>();
+    }
+
+    #[test]
+    fn test_invalid() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        sl.insert(vec![1]);
+
+        let mut iter = SkipListIter::new(sl);
+        assert_eq!(iter.key(), Err(IterError::Invalid));
+        assert_eq!(iter.next(), Err(IterError::Invalid));
+        assert_eq!(iter.prev(), Err(IterError::Invalid));
+
+        iter.seek_to_first();
+        assert_eq!(iter.prev(), Ok(()));
+        assert!(!iter.valid());
+
+        iter.seek(&[2]);
+        assert_eq!(iter.key(), Err(IterError::Invalid));
+        assert_eq!(iter.save_position(), Err(IterError::Invalid));
+    }
+
+    #[test]
+    fn test_resume() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..100u8 {
+            sl.insert(vec![i]);
+        }
+        sl.next_seq();
+
+        // Read in pages of 10 entries, each through a new iterator.
+        let mut token: Option<ResumeToken> = None;
+        let mut keys = vec![];
+        loop {
+            let mut iter = match &token {
+                Some(token) => sl.resume(token),
+                None => {
+                    let mut iter = SkipListIter::new(sl.clone());
+                    iter.seek_to_first();
+                    iter
+                }
+            };
+            for _ in 0..10 {
+                match iter.key() {
+                    Ok(key) => keys.push(key[0]),
+                    Err(_) => break,
+                }
+                iter.next().unwrap();
+            }
+            match iter.save_position() {
+                Ok(saved) => token = Some(saved),
+                Err(_) => break,
+            }
+        }
+        assert_eq!(keys, (0..100).collect::<Vec<_>>());
+
+        let token = ResumeToken {
+            key: Bytes::from_static(&[50]),
+            seq: 1,
+        };
+        assert_eq!(token, ResumeToken::decode(&token.encode()).unwrap());
+        assert_eq!(ResumeToken::decode(&token.encode()[..2]), None);
+        assert_eq!(ResumeToken::decode(&[]), None);
+        let mut trailing = token.encode();
+        trailing.push(0);
+        assert_eq!(ResumeToken::decode(&trailing), None);
+
+        // The entry was removed: the iterator resumes at the next one.
+        sl.remove(&[50]);
+        assert_eq!(sl.resume(&token).key(), Ok(&[51][..]));
+        sl.remove(&[99]);
+        let past = ResumeToken {
+            key: Bytes::from_static(&[99]),
+            seq: 1,
+        };
+        assert!(!sl.resume(&past).valid());
+    }
+
+    #[test]
+    fn test_entries() {
+        let map = SkipMap::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..10u8 {
+            map.insert(vec![i], vec![i * 2]);
+        }
+        let set = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        set.insert(vec![1u8]);
+
+        let mut iter = SkipListIter::new(map.as_list().clone());
+        assert_eq!(iter.value(), Err(IterError::Invalid));
+        iter.seek(&[3]);
+        assert_eq!(iter.value(), Ok(&[6][..]));
+        iter.prev().unwrap();
+        assert_eq!(iter.value(), Ok(&[4][..]));
+
+        let mut entries = iter.into_std_entries();
+        assert_eq!(
+            entries.next(),
+            Some((Bytes::from_static(&[2]), Bytes::from_static(&[4])))
+        );
+        let rest: Vec<(u8, u8)> = entries
+            .by_ref()
+            .take(3)
+            .map(|(key, value)| (key[0], value[0]))
+            .collect();
+        assert_eq!(rest, vec![(3, 6), (4, 8), (5, 10)]);
+        assert_eq!(entries.into_inner().key(), Ok(&[6][..]));
+
+        let mut iter = SkipListIter::new(set);
+        iter.seek_to_first();
+        assert_eq!(iter.value(), Ok(&[][..]));
+        let entries: Vec<_> = iter.into_std_entries().collect();
+        assert_eq!(entries, vec![(Bytes::from_static(&[1]), Bytes::new())]);
+    }
+
+    #[test]
+    fn test_std_iter() {
+        let sl = SkipList::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..100u8 {
+            sl.insert(vec![i]);
+        }
+
+        assert_eq!(SkipListIter::new(sl.clone()).into_std_iter().next(), None);
+
+        let mut iter = SkipListIter::new(sl.clone());
+        iter.seek(&[90]);
+        let mut keys = iter.into_std_iter();
+        let first: Vec<u8> = keys.by_ref().take(5).map(|key| key[0]).collect();
+        assert_eq!(first, vec![90, 91, 92, 93, 94]);
+        let mut iter = keys.into_inner();
+        assert_eq!(iter.key(), Ok(&[95][..]));
+        iter.prev().unwrap();
+        assert_eq!(iter.into_std_iter().count(), 6);
+
+        let mut iter = SkipListIter::new(sl);
+        iter.seek_to_last();
+        let mut keys = iter.into_std_iter();
+        assert_eq!(keys.next().unwrap().as_ref(), &[99]);
+        assert_eq!(keys.next(), None);
+        assert_eq!(keys.next(), None);
     }
 }
@@ -0,0 +1,850 @@
+use crate::compress::ValueCodec;
+use crate::encoding;
+use crate::readahead::ReadAhead;
+use crate::skipnode::live_from;
+use crate::{
+    Arena, BaseComparator, CompressionError, DuplicateKeyError, InsertOutcome, Page,
+    RandomGenerator, SkipList, SkipListConfig, WriteBatch,
+};
+use bytes::Bytes;
+use std::iter;
+use std::mem;
+use std::ops::RangeBounds;
+#[cfg(feature = "timestamps")]
+use std::time::Instant;
+
+/// A skiplist storing a value along with every key, e.g. the memtable of a
+/// database.
+///
+/// The value lives in the node of its key, so a lookup costs a single search.
+/// With the default `DuplicatePolicy::AllowDuplicates`, inserting a key again
+/// shadows the previous value: `get` returns the value inserted last. With
+/// `DuplicatePolicy::Replace` the previous value is removed, with
+/// `DuplicatePolicy::Ignore` the first value is kept.
+///
+/// Values can be compressed transparently by a `ValueCodec`, set when the list
+/// is created. Reads return the values decompressed, or the
+/// `CompressionError` of a value the codec can't read back.
+/// # Examples
+#[cfg_attr(feature = "arena", doc = "```")]
+#[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+/// use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, SkipMap};
+///
+/// let map = SkipMap::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+/// map.insert(&b"key"[..], &b"old"[..]);
+/// map.insert(&b"key"[..], &b"new"[..]);
+/// assert_eq!(map.get(b"key").unwrap().unwrap().as_ref(), b"new");
+/// assert_eq!(map.get(b"missing"), Ok(None));
+/// ```
+pub struct SkipMap<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    list: SkipList<R, C, A>,
+}
+
+/// The value of an entry of a `SkipMap` along with its metadata, see
+/// `SkipMap::get_with_meta`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryMeta {
+    pub value: Bytes,
+    /// The byte tagging the entry, see `SkipList::meta`.
+    pub meta: u8,
+    /// When the entry was inserted, see `Node::inserted_at`.
+    #[cfg(feature = "timestamps")]
+    pub inserted_at: Instant,
+}
+
+impl<R, C, A> Clone for SkipMap<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    fn clone(&self) -> Self {
+        SkipMap {
+            list: self.list.clone(),
+        }
+    }
+}
+
+impl<R, C, A> SkipMap<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    pub fn new(rnd: R, cmp: C, arena: A) -> Self {
+        Self::from_list(SkipList::new(rnd, cmp, arena))
+    }
+
+    /// Store the values in `list`, e.g. one built with `SkipList::from_config`.
+    /// Values go through the `value_codec` of its config, if any.
+    pub fn from_list(list: SkipList<R, C, A>) -> Self {
+        SkipMap { list }
+    }
+
+    /// Create a map compressing its values with `codec`, see
+    /// `SkipListConfig::value_codec`.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::compress::{Compressor, ValueCodec};
+    /// use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, SkipMap};
+    ///
+    /// /// Stores zero-filled values as their length. Other values don't shrink, so
+    /// /// the codec keeps them as is.
+    /// struct Zeros;
+    ///
+    /// impl Compressor for Zeros {
+    ///     fn compress(&self, data: &[u8], dst: &mut Vec<u8>) {
+    ///         if data.iter().all(|&b| b == 0) {
+    ///             dst.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    ///         } else {
+    ///             dst.push(0xff);
+    ///             dst.extend_from_slice(data);
+    ///         }
+    ///     }
+    ///
+    ///     fn decompress(&self, data: &[u8]) -> Option<Vec<u8>> {
+    ///         let len = u32::from_le_bytes(std::convert::TryInto::try_into(data).ok()?);
+    ///         Some(vec![0; len as usize])
+    ///     }
+    /// }
+    ///
+    /// let map = SkipMap::with_value_codec(
+    ///     Random::new(0xdead_beef),
+    ///     DefaultComparator::default (),
+    ///     ArenaImpl::new(),
+    ///     ValueCodec::new(Zeros, 64),
+    /// );
+    /// let before = map.memory_usage();
+    /// map.insert(vec![1u8], vec![0u8; 4096]);
+    /// assert_eq!(map.get(&[1u8]).unwrap().unwrap(), vec![0u8; 4096]);
+    /// assert!(map.memory_usage() - before < 128);
+    /// ```
+    pub fn with_value_codec(rnd: R, cmp: C, arena: A, codec: ValueCodec) -> Self {
+        let config = SkipListConfig {
+            value_codec: Some(codec),
+            ..SkipListConfig::default()
+        };
+        Self::from_list(SkipList::with_config_unchecked(rnd, cmp, arena, config))
+    }
+
+    /// Returns the list holding the entries, for everything that only looks at
+    /// the keys: guards, iteration by level, freezing...
+    pub fn as_list(&self) -> &SkipList<R, C, A> {
+        &self.list
+    }
+
+    pub fn len(&self) -> u64 {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Returns an estimate of the memory used by the keys, the values as stored,
+    /// and the nodes, see `SkipList::memory_usage`.
+    pub fn memory_usage(&self) -> u64 {
+        self.list.memory_usage()
+    }
+
+    /// Insert `key` with `value`, see `SkipList::insert`.
+    pub fn insert(&self, key: impl Into<Bytes>, value: impl Into<Bytes>) -> InsertOutcome {
+        let value = self.encode(value.into());
+        self.list.insert_node(key.into(), value).1
+    }
+
+    /// Write the entries of `batch` with their values, see `SkipList::write`.
+    pub fn write(&self, mut batch: WriteBatch) -> Result<usize, DuplicateKeyError> {
+        for (_, value) in &mut batch.entries {
+            *value = self.encode(mem::take(value));
+        }
+        self.list.write(batch)
+    }
+
+    /// Returns the value of `key`, if any, or the error of the value codec
+    /// failing to decompress it.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>, CompressionError> {
+        self.list
+            .find_equal(key)
+            .map(|node| self.decode(node.value()))
+            .transpose()
+    }
+
+    /// Returns the value of `key` along with the metadata of its entry, if any,
+    /// see `get`.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, SkipMap};
+    ///
+    /// let map = SkipMap::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+    /// map.insert(&b"key"[..], &b"value"[..]);
+    /// let entry = map.get_with_meta(b"key").unwrap().unwrap();
+    /// assert_eq!(entry.value.as_ref(), b"value");
+    /// assert_eq!(entry.meta, 0);
+    /// ```
+    pub fn get_with_meta(&self, key: &[u8]) -> Result<Option<EntryMeta>, CompressionError> {
+        self.list
+            .find_equal(key)
+            .map(|node| {
+                Ok(EntryMeta {
+                    value: self.decode(node.value())?,
+                    meta: node.get_meta(),
+                    #[cfg(feature = "timestamps")]
+                    inserted_at: node.inserted_at(),
+                })
+            })
+            .transpose()
+    }
+
+    /// Insert `key` with `value`, stamped with a new sequence number from
+    /// `SkipList::next_seq`, and returns the number.
+    ///
+    /// The stored key is `key` followed by `u64::MAX - seq` as 8 big-endian
+    /// bytes, so with a bytewise comparator the versions of a key sort newest
+    /// first. `get_at` reads them back.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, SkipMap};
+    ///
+    /// let map = SkipMap::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+    /// let first = map.insert_seq(&b"key"[..], &b"old"[..]);
+    /// let second = map.insert_seq(&b"key"[..], &b"new"[..]);
+    /// assert_eq!((first, second), (1, 2));
+    /// assert_eq!(map.get_at(b"key", first), Ok(Some((1, "old".into()))));
+    /// assert_eq!(map.get_at(b"key", u64::MAX), Ok(Some((2, "new".into()))));
+    /// assert_eq!(map.get_at(b"key", 0), Ok(None));
+    /// ```
+    pub fn insert_seq(&self, key: impl AsRef<[u8]>, value: impl Into<Bytes>) -> u64 {
+        let seq = self.list.next_seq();
+        self.insert(stamp(key.as_ref(), seq), value);
+        seq
+    }
+
+    /// Returns the newest version of `key` inserted by `insert_seq` with a
+    /// sequence number up to `seq`, along with its number.
+    ///
+    /// Stamped keys aren't prefix free: versions of keys extending `key` can
+    /// sort between its own versions, and are skipped.
+    pub fn get_at(&self, key: &[u8], seq: u64) -> Result<Option<(u64, Bytes)>, CompressionError> {
+        let target = stamp(key, seq);
+        let mut x = live_from(self.list.seek(&target, &mut []));
+        while let Some(node) = x {
+            let stored = node.key();
+            if !stored.starts_with(key) {
+                break;
+            }
+            if stored.len() == target.len() {
+                let stamp = encoding::decode_fixed64_ordered(&stored[key.len()..]);
+                return Ok(Some((u64::MAX - stamp, self.decode(node.value())?)));
+            }
+            x = node.next_live();
+        }
+        Ok(None)
+    }
+
+    /// Replace the value of `key`, returns `false` if there is no such key.
+    ///
+    /// The value is swapped atomically in the entry: readers see either the old
+    /// or the new value, never a mix of both, and values already read stay
+    /// valid. Replaced values are only dropped along with the map, but stop
+    /// counting in `memory_usage` right away.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, SkipMap};
+    ///
+    /// let map = SkipMap::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+    /// map.insert(&b"key"[..], &b"old"[..]);
+    /// assert!(map.update(b"key", &b"new"[..]));
+    /// assert!(!map.update(b"missing", &b"new"[..]));
+    /// assert_eq!(map.get(b"key").unwrap().unwrap().as_ref(), b"new");
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    pub fn update(&self, key: &[u8], value: impl Into<Bytes>) -> bool {
+        match self.list.find_equal(key) {
+            Some(node) => self
+                .list
+                .update_value(node, self.encode(value.into()), |_| true),
+            None => false,
+        }
+    }
+
+    /// Replace the value of `key` with `new` if it is equal to `expected`, in a
+    /// single atomic step, see `update`. Returns `false` if there is no such key
+    /// or if its value differs, or the error of the value codec failing to
+    /// decompress the value.
+    /// # Examples
+    #[cfg_attr(feature = "arena", doc = "```")]
+    #[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+    /// use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, SkipMap};
+    ///
+    /// let map = SkipMap::new(Random::new(0xdead_beef), DefaultComparator::default (), ArenaImpl::new());
+    /// map.insert(&b"counter"[..], &b"1"[..]);
+    /// assert_eq!(map.compare_and_update(b"counter", b"0", &b"2"[..]), Ok(false));
+    /// assert_eq!(map.compare_and_update(b"counter", b"1", &b"2"[..]), Ok(true));
+    /// assert_eq!(map.get(b"counter").unwrap().unwrap().as_ref(), b"2");
+    /// ```
+    pub fn compare_and_update(
+        &self,
+        key: &[u8],
+        expected: &[u8],
+        new: impl Into<Bytes>,
+    ) -> Result<bool, CompressionError> {
+        let node = match self.list.find_equal(key) {
+            Some(node) => node,
+            None => return Ok(false),
+        };
+        let mut error = None;
+        let updated = self
+            .list
+            .update_value(node, self.encode(new.into()), |current| {
+                match self.decode(current) {
+                    Ok(current) => current.as_ref() == expected,
+                    Err(err) => {
+                        error = Some(err);
+                        false
+                    }
+                }
+            });
+        match error {
+            Some(err) => Err(err),
+            None => Ok(updated),
+        }
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.list.contains_key(key)
+    }
+
+    /// Remove `key` and its value, see `SkipList::remove`.
+    pub fn remove(&self, key: &[u8]) -> bool {
+        self.list.remove(key)
+    }
+
+    /// Remove the entries in `range` for which `keep`, given the key and the
+    /// value, returns `false`. See `SkipList::retain_range`.
+    ///
+    /// Entries whose value the value codec fails to decompress are kept, and
+    /// the first error is returned once the whole range was walked.
+    pub fn retain_range<'k, F>(
+        &self,
+        range: impl RangeBounds<&'k [u8]>,
+        mut keep: F,
+    ) -> Result<usize, CompressionError>
+    where
+        F: FnMut(&[u8], &[u8]) -> bool,
+    {
+        let mut error = None;
+        let removed = self
+            .list
+            .retain_range(range, |node| match self.decode(node.value()) {
+                Ok(value) => keep(&node.data, &value),
+                Err(err) => {
+                    error.get_or_insert(err);
+                    true
+                }
+            });
+        match error {
+            Some(err) => Err(err),
+            None => Ok(removed),
+        }
+    }
+
+    /// Iterate over the entries in key order. Values shadowed by a later insert
+    /// of the same key come right after the value shadowing them.
+    ///
+    /// Values are prefetched a few entries ahead of the scan, more as the scan
+    /// gets longer. An entry whose value the value codec fails to decompress
+    /// comes out as the error, and the scan can go on past it.
+    pub fn iter(&self) -> impl Iterator<Item = Result<(&[u8], Bytes), CompressionError>> {
+        ReadAhead::new((&self.list).into_iter())
+            .map(move |node| Ok((node.data.as_ref(), self.decode(node.value())?)))
+    }
+
+    /// Iterate over the entries in key order like `iter`, as `Bytes` sharing the
+    /// buffers of the nodes. The iterator holds a handle to the map instead of
+    /// borrowing it, see `SkipList::iter_bytes`.
+    pub fn iter_bytes(&self) -> impl Iterator<Item = Result<(Bytes, Bytes), CompressionError>> {
+        let map = self.clone();
+        let mut entries = self.list.iter_bytes();
+        iter::from_fn(move || {
+            let (key, value) = entries.next_entry()?;
+            Some(map.decode(&value).map(|value| (key, value)))
+        })
+    }
+
+    /// Returns the entries in `range` until their size, as stored, reaches
+    /// `max_bytes`, along with the key to resume from. See
+    /// `SkipList::collect_range_bounded`.
+    pub fn collect_range_bounded<'k>(
+        &self,
+        range: impl RangeBounds<&'k [u8]>,
+        max_bytes: usize,
+    ) -> Result<Page<(Bytes, Bytes)>, CompressionError> {
+        let page = self.list.collect_page(range, max_bytes, |node| {
+            (node.data.clone(), node.value().clone())
+        });
+        let entries = page
+            .entries
+            .into_iter()
+            .map(|(key, stored)| Ok((key, self.decode(&stored)?)))
+            .collect::<Result<_, _>>()?;
+        Ok(Page {
+            entries,
+            resume_key: page.resume_key,
+        })
+    }
+
+    fn codec(&self) -> Option<&ValueCodec> {
+        self.list.config().value_codec.as_ref()
+    }
+
+    fn encode(&self, value: Bytes) -> Bytes {
+        match self.codec() {
+            Some(codec) => codec.encode(&value),
+            None => value,
+        }
+    }
+
+    fn decode(&self, stored: &Bytes) -> Result<Bytes, CompressionError> {
+        match self.codec() {
+            Some(codec) => codec.decode(stored),
+            None => Ok(stored.clone()),
+        }
+    }
+}
+
+/// Returns `key` stamped with `seq`, see `SkipMap::insert_seq`.
+fn stamp(key: &[u8], seq: u64) -> Vec<u8> {
+    let mut stamped = Vec::with_capacity(key.len() + 8);
+    stamped.extend_from_slice(key);
+    encoding::put_fixed64_ordered(&mut stamped, u64::MAX - seq);
+    stamped
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use super::SkipMap;
+    use crate::compress::{Compressor, ValueCodec};
+    use crate::{
+        ArenaImpl, CompressionError, DefaultComparator, DuplicatePolicy, InsertOutcome, Random,
+        SkipList, SkipListConfig, WriteBatch,
+    };
+    use bytes::Bytes;
+    use std::convert::TryInto;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn map() -> SkipMap<Random, DefaultComparator, ArenaImpl> {
+        SkipMap::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        )
+    }
+
+    #[test]
+    fn test_insert_get() {
+        let map = map();
+        assert!(map.is_empty());
+        for i in (0..1000u32).rev() {
+            map.insert(i.to_be_bytes().to_vec(), i.to_string());
+        }
+        assert_eq!(map.len(), 1000);
+        for i in 0..1000u32 {
+            assert_eq!(map.get(&i.to_be_bytes()).unwrap().unwrap(), i.to_string());
+        }
+        assert_eq!(map.get(&1000u32.to_be_bytes()), Ok(None));
+        assert!(map.contains_key(&42u32.to_be_bytes()));
+
+        let entries: Vec<_> = map.iter().take(3).map(Result::unwrap).collect();
+        assert_eq!(entries[0].0, &0u32.to_be_bytes());
+        assert_eq!(entries[2].1, "2");
+    }
+
+    #[test]
+    fn test_overwrite() {
+        let map = map();
+        assert_eq!(map.insert(vec![1u8], vec![1u8]), InsertOutcome::Inserted);
+        assert_eq!(map.insert(vec![1u8], vec![2u8]), InsertOutcome::Duplicate);
+        assert_eq!(map.get(&[1]).unwrap().unwrap().as_ref(), &[2]);
+        let values: Vec<_> = map.iter().map(|entry| entry.unwrap().1).collect();
+        assert_eq!(values, vec![vec![2u8], vec![1u8]]);
+
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::Ignore,
+            ..Default::default()
+        };
+        let list = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        let map = SkipMap::from_list(list);
+        map.insert(vec![1u8], vec![1u8]);
+        assert_eq!(map.insert(vec![1u8], vec![2u8]), InsertOutcome::Ignored);
+        assert_eq!(map.get(&[1]).unwrap().unwrap().as_ref(), &[1]);
+        assert_eq!(map.len(), 1);
+
+        let config = SkipListConfig {
+            duplicate_policy: DuplicatePolicy::Replace,
+            ..Default::default()
+        };
+        let list = SkipList::from_config(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            config,
+        )
+        .unwrap();
+        let map = SkipMap::from_list(list);
+        map.insert(vec![1u8], vec![1u8]);
+        assert_eq!(map.insert(vec![1u8], vec![2u8]), InsertOutcome::Replaced);
+        let values: Vec<_> = map.iter().map(|entry| entry.unwrap().1).collect();
+        assert_eq!(values, vec![vec![2u8]]);
+    }
+
+    #[test]
+    fn test_remove() {
+        let map = map();
+        map.insert(vec![1u8], vec![1u8]);
+        map.insert(vec![1u8], vec![2u8]);
+        map.insert(vec![2u8], vec![2u8]);
+        assert!(map.remove(&[1]));
+        assert_eq!(map.get(&[1]), Ok(None));
+        assert_eq!(map.len(), 1);
+        map.insert(vec![1u8], vec![3u8]);
+        assert_eq!(map.get(&[1]).unwrap().unwrap().as_ref(), &[3]);
+        let entries: Vec<_> = map
+            .iter()
+            .map(|entry| {
+                let (k, v) = entry.unwrap();
+                (k.to_vec(), v)
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![(vec![1u8], vec![3u8].into()), (vec![2], vec![2].into())]
+        );
+    }
+
+    #[test]
+    fn test_update() {
+        let map = map();
+        map.insert(vec![1u8], vec![b'a'; 64]);
+        map.insert(vec![2u8], vec![]);
+        let empty = map.memory_usage();
+        assert!(map.update(&[2], vec![b'b'; 10]));
+        assert_eq!(map.get(&[2]).unwrap().unwrap(), vec![b'b'; 10]);
+        assert_eq!(map.memory_usage(), empty + 10);
+        assert!(map.update(&[2], vec![b'c'; 4]));
+        assert_eq!(map.memory_usage(), empty + 4);
+        assert!(!map.update(&[3], vec![]));
+        map.remove(&[2]);
+        assert!(!map.update(&[2], vec![]));
+
+        // Readers see whole values while they are being replaced.
+        let reader = map.clone();
+        let handle = thread::spawn(move || {
+            for _ in 0..1000 {
+                let value = reader.get(&[1]).unwrap().unwrap();
+                assert!(value == vec![b'a'; 64] || value == vec![b'b'; 64]);
+            }
+        });
+        for i in 0..1000 {
+            let byte = if i % 2 == 0 { b'b' } else { b'a' };
+            assert!(map.update(&[1], vec![byte; 64]));
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_drop_values() {
+        struct Tracked {
+            _owner: Arc<()>,
+        }
+
+        impl AsRef<[u8]> for Tracked {
+            fn as_ref(&self) -> &[u8] {
+                b"value"
+            }
+        }
+
+        let owner = Arc::new(());
+        let value = || {
+            Bytes::from_owner(Tracked {
+                _owner: owner.clone(),
+            })
+        };
+        let map = map();
+        map.insert(vec![1u8], value());
+        map.insert(vec![2u8], value());
+        assert!(map.update(&[1], value()));
+        assert_eq!(map.compare_and_update(&[1], b"other", value()), Ok(false));
+        assert!(map.remove(&[2]));
+        assert_eq!(Arc::strong_count(&owner), 4);
+        drop(map);
+        assert_eq!(Arc::strong_count(&owner), 1);
+    }
+
+    #[test]
+    fn test_compare_and_update() {
+        let map = map();
+        map.insert(vec![1u8], 0u32.to_be_bytes().to_vec());
+        assert_eq!(
+            map.compare_and_update(&[1], &1u32.to_be_bytes(), vec![]),
+            Ok(false)
+        );
+        assert_eq!(map.compare_and_update(&[2], &[], vec![]), Ok(false));
+
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let map = map.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        loop {
+                            let current = map.get(&[1]).unwrap().unwrap();
+                            let n = u32::from_be_bytes(current.as_ref().try_into().unwrap());
+                            let next = (n + 1).to_be_bytes().to_vec();
+                            if map.compare_and_update(&[1], &current, next).unwrap() {
+                                break;
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(
+            map.get(&[1]).unwrap().unwrap().as_ref(),
+            &400u32.to_be_bytes()
+        );
+    }
+
+    #[test]
+    fn test_insert_seq() {
+        let map = map();
+        let mut seqs = vec![];
+        for i in 0..10u8 {
+            seqs.push(map.insert_seq([i % 3], vec![i]));
+            // The key and a longer one starting with it don't mix.
+            map.insert_seq([i % 3, 0], vec![100 + i]);
+        }
+        assert_eq!(map.as_list().last_seq(), 20);
+        for (i, &seq) in seqs.iter().enumerate() {
+            let key = [i as u8 % 3];
+            assert_eq!(map.get_at(&key, seq), Ok(Some((seq, vec![i as u8].into()))));
+            // Between two versions, the older one is seen.
+            assert_eq!(
+                map.get_at(&key, seq + 1).unwrap().unwrap().1.as_ref(),
+                &[i as u8]
+            );
+        }
+        assert_eq!(map.get_at(&[0], 0), Ok(None));
+        assert_eq!(map.get_at(&[3], u64::MAX), Ok(None));
+        assert_eq!(
+            map.get_at(&[2], u64::MAX).unwrap().unwrap().1.as_ref(),
+            &[8]
+        );
+
+        // Handles share the counter.
+        let other = map.clone();
+        assert_eq!(other.as_list().next_seq(), 21);
+        assert_eq!(map.insert_seq([0], vec![]), 22);
+    }
+
+    #[test]
+    fn test_collect_range_bounded() {
+        let map = map();
+        for i in 0..10u8 {
+            map.insert(vec![i], vec![i; 9]);
+        }
+        let page = map.collect_range_bounded(&[5u8][..].., 30).unwrap();
+        assert_eq!(page.entries.len(), 3);
+        assert_eq!(page.entries[0], (vec![5u8].into(), vec![5u8; 9].into()));
+        assert_eq!(page.resume_key.unwrap().as_ref(), &[8]);
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let map = map();
+        let empty = map.memory_usage();
+        map.insert(vec![0u8; 10], vec![0u8; 100]);
+        assert_eq!(map.memory_usage(), empty + 110);
+    }
+
+    /// Keeps every other byte, only round trips values made of pairs.
+    struct Halve;
+
+    impl Compressor for Halve {
+        fn compress(&self, data: &[u8], dst: &mut Vec<u8>) {
+            dst.extend(data.iter().step_by(2));
+        }
+
+        fn decompress(&self, data: &[u8]) -> Option<Vec<u8>> {
+            Some(data.iter().flat_map(|&b| [b, b]).collect())
+        }
+    }
+
+    /// Compresses like `Halve`, and can't read values back.
+    struct Broken;
+
+    impl Compressor for Broken {
+        fn compress(&self, data: &[u8], dst: &mut Vec<u8>) {
+            Halve.compress(data, dst);
+        }
+
+        fn decompress(&self, _: &[u8]) -> Option<Vec<u8>> {
+            None
+        }
+    }
+
+    fn compressed_map<T: Compressor + 'static>(
+        compressor: T,
+    ) -> SkipMap<Random, DefaultComparator, ArenaImpl> {
+        SkipMap::with_value_codec(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+            ValueCodec::new(compressor, 8),
+        )
+    }
+
+    #[test]
+    fn test_value_codec() {
+        let map = compressed_map(Halve);
+        let empty = map.memory_usage();
+        map.insert(vec![1u8], vec![7u8; 1000]);
+        map.insert(vec![2u8], vec![7u8; 4]);
+        assert_eq!(map.memory_usage(), empty + 2 + 501 + 5);
+        assert_eq!(map.get(&[1]).unwrap().unwrap(), vec![7u8; 1000]);
+        assert_eq!(map.get(&[2]).unwrap().unwrap(), vec![7u8; 4]);
+
+        // Clones, and maps over the same list, share the entries and the codec.
+        let clone = map.clone();
+        assert_eq!(clone.iter().next().unwrap().unwrap().1, vec![7u8; 1000]);
+        let other = SkipMap::from_list(map.as_list().clone());
+        other.insert(vec![3u8], vec![9u8; 100]);
+        assert_eq!(map.get(&[3]).unwrap().unwrap(), vec![9u8; 100]);
+        assert_eq!(other.get(&[1]).unwrap().unwrap(), vec![7u8; 1000]);
+    }
+
+    #[test]
+    fn test_value_codec_errors() {
+        let map = compressed_map(Broken);
+        map.insert(vec![1u8], vec![7u8; 4]);
+        map.insert(vec![2u8], vec![7u8; 100]);
+        assert_eq!(map.get(&[1]).unwrap().unwrap(), vec![7u8; 4]);
+        assert_eq!(map.get(&[2]), Err(CompressionError::Corrupted));
+        assert!(map.get_with_meta(&[2]).is_err());
+        assert_eq!(
+            map.compare_and_update(&[2], &[], vec![]),
+            Err(CompressionError::Corrupted)
+        );
+        assert!(map.collect_range_bounded(.., 1000).is_err());
+        let entries: Vec<_> = map.iter().collect();
+        assert_eq!(entries[0], Ok((&[1u8][..], vec![7u8; 4].into())));
+        assert_eq!(entries[1], Err(CompressionError::Corrupted));
+
+        // The entries that can't be read are kept.
+        assert_eq!(
+            map.retain_range(.., |_, _| false),
+            Err(CompressionError::Corrupted)
+        );
+        assert_eq!(map.len(), 1);
+        assert!(map.contains_key(&[2]));
+    }
+
+    #[test]
+    fn test_write_batch() {
+        let map = compressed_map(Halve);
+        let empty = map.memory_usage();
+        let mut batch = WriteBatch::new();
+        batch.insert_value(vec![2u8], vec![7u8; 1000]);
+        batch.insert_value(vec![1u8], vec![8u8; 4]);
+        assert_eq!(map.write(batch), Ok(2));
+        assert_eq!(map.get(&[1]).unwrap().unwrap(), vec![8u8; 4]);
+        assert_eq!(map.get(&[2]).unwrap().unwrap(), vec![7u8; 1000]);
+        assert_eq!(map.memory_usage(), empty + 2 + 501 + 5);
+    }
+
+    #[test]
+    fn test_iter_bytes() {
+        let map = compressed_map(Halve);
+        map.insert(vec![1u8], vec![7u8; 1000]);
+        map.insert(vec![2u8], vec![8u8; 4]);
+        let stored = map.get(&[2]).unwrap().unwrap();
+        let entries = map.iter_bytes();
+        drop(map);
+        let entries: Vec<_> = thread::spawn(move || entries.map(Result::unwrap).collect())
+            .join()
+            .unwrap();
+        assert_eq!(entries[0], (vec![1u8].into(), vec![7u8; 1000].into()));
+        // Values not compressed aren't copied either.
+        assert_eq!(entries[1].1.as_ptr(), stored.as_ptr());
+        assert_eq!(entries.len(), 2);
+    }
+
+    #[test]
+    fn test_retain_range() {
+        let map = SkipMap::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        for i in 0..10u8 {
+            map.insert(vec![i], vec![i % 3]);
+        }
+        assert_eq!(
+            map.retain_range(&[3u8][..].., |_, value| value != [0]),
+            Ok(3)
+        );
+        let keys: Vec<u8> = map.iter().map(|entry| entry.unwrap().0[0]).collect();
+        assert_eq!(keys, vec![0, 1, 2, 4, 5, 7, 8]);
+    }
+
+    #[cfg(feature = "timestamps")]
+    #[test]
+    fn test_inserted_at() {
+        use crate::skipnode::Node;
+        use std::time::Instant;
+
+        let before = Instant::now();
+        let map = SkipMap::new(
+            Random::new(0xdead_beef),
+            DefaultComparator::default(),
+            ArenaImpl::new(),
+        );
+        map.insert(vec![2u8], vec![0u8]);
+        map.insert(vec![1u8], vec![0u8]);
+        let first = map.get_with_meta(&[2]).unwrap().unwrap();
+        let second = map.get_with_meta(&[1]).unwrap().unwrap();
+        assert!(before <= first.inserted_at);
+        assert!(first.inserted_at <= second.inserted_at);
+        assert!(second.inserted_at <= Instant::now());
+
+        // Updating the value keeps the time of the insert.
+        assert!(map.update(&[2], vec![1u8]));
+        let updated = map.get_with_meta(&[2]).unwrap().unwrap();
+        assert_eq!(updated.value.as_ref(), &[1]);
+        assert_eq!(updated.inserted_at, first.inserted_at);
+
+        // So does moving the entries to another arena.
+        let times: Vec<Instant> = map.as_list().into_iter().map(Node::inserted_at).collect();
+        let imm = map.as_list().clone();
+        drop(map);
+        let imm = imm.freeze().ok().unwrap().compact();
+        let moved: Vec<Instant> = (&imm).into_iter().map(Node::inserted_at).collect();
+        assert_eq!(moved, times);
+    }
+}
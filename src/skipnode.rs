@@ -1,44 +1,632 @@
 use crate::{Arena, K_MAX_HEIGHT};
 use bytes::Bytes;
+use std::convert::TryFrom;
 use std::fmt::{Error, Formatter};
-use std::sync::atomic::{AtomicPtr, Ordering};
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+#[cfg(feature = "metrics")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicPtr, AtomicU32, AtomicU8, AtomicUsize, Ordering};
+#[cfg(feature = "timestamps")]
+use std::sync::OnceLock;
+use std::sync::{Mutex, MutexGuard};
+#[cfg(feature = "timestamps")]
+use std::time::{Duration, Instant};
 use std::{fmt, mem, ptr};
 
+// The tower is truncated to the height of the node when allocating, so it has
+// to stay the last field. The spans of the links follow it, see `Node::span`.
+#[repr(C)]
 pub struct Node {
     pub data: Bytes,
-    pub forward: [AtomicPtr<Self>; K_MAX_HEIGHT],
+    /// The value of the entry in a `SkipMap`, see `Node::value`. Values live in
+    /// the arena and updates swap the pointer, null for an empty value.
+    value: AtomicPtr<Bytes>,
+    /// The node whose removal reveals this one while it is `PENDING`, see
+    /// `mark_pending`. Kept with the other pointers, the flags pack after it.
+    source: AtomicPtr<Node>,
+    /// A byte left to the user to tag the entry, see `SkipList::meta`.
+    pub meta: AtomicU8,
+    /// Internal flags, e.g. `DELETED`.
+    state: AtomicU8,
+    /// Number of levels the node is linked at, the length of its tower.
+    height: u8,
+    /// Times the key was inserted under `DuplicatePolicy::Count`, see
+    /// `Node::count`. Fits in the padding before the tower.
+    count: AtomicU32,
+    /// When the node was allocated, in nanoseconds since `clock_start`, see
+    /// `Node::inserted_at`.
+    #[cfg(feature = "timestamps")]
+    pub(crate) inserted: u64,
+    /// Sampled lookups that found the entry, see `SkipList::hot_keys`.
+    #[cfg(feature = "metrics")]
+    hits: AtomicU64,
+    pub(crate) forward: [AtomicPtr<Self>; K_MAX_HEIGHT],
 }
 
 impl Node {
-    #[allow(clippy::mut_from_ref)]
-    pub fn new<A: Arena>(data: Bytes, height: usize, arena: &A) -> &mut Self {
-        let size = mem::size_of::<Self>() /* 32 */
-                - (K_MAX_HEIGHT - height) * mem::size_of::<AtomicPtr<Self>>(); /* 8 * height*/
-
-        let ptr = arena.alloc(size) as *mut Node;
+    /// Allocate a node of `height` levels in `arena`, which owns it: the node is
+    /// valid as long as the arena, and nobody else sees it until it is linked.
+    pub fn new<A: Arena>(data: Bytes, height: usize, arena: &A) -> NonNull<Self> {
+        Self::with_value(data, Bytes::new(), height, arena)
+    }
 
+    pub fn with_value<A: Arena>(
+        data: Bytes,
+        value: Bytes,
+        height: usize,
+        arena: &A,
+    ) -> NonNull<Self> {
+        let node = arena.alloc(Self::size(height)).cast::<Node>();
+        let ptr = node.as_ptr();
+        // The fields are written in place, no reference to the node exists
+        // before it is whole. The tower is truncated to `height` levels, and the
+        // spans follow it.
         unsafe {
-            let node = &mut *ptr;
-            ptr::write(&mut node.data, data);
-            ptr::write_bytes(node.forward.as_mut_ptr(), 0, height);
-            node
+            ptr::addr_of_mut!((*ptr).data).write(data);
+            ptr::addr_of_mut!((*ptr).value).write(AtomicPtr::new(Self::alloc_value(value, arena)));
+            ptr::addr_of_mut!((*ptr).meta).write(AtomicU8::new(0));
+            ptr::addr_of_mut!((*ptr).state).write(AtomicU8::new(0));
+            ptr::addr_of_mut!((*ptr).source).write(AtomicPtr::new(ptr::null_mut()));
+            ptr::addr_of_mut!((*ptr).height).write(height as u8);
+            ptr::addr_of_mut!((*ptr).count).write(AtomicU32::new(1));
+            #[cfg(feature = "timestamps")]
+            ptr::addr_of_mut!((*ptr).inserted).write(clock_start().elapsed().as_nanos() as u64);
+            #[cfg(feature = "metrics")]
+            ptr::addr_of_mut!((*ptr).hits).write(AtomicU64::new(0));
+            let forward = ptr::addr_of_mut!((*ptr).forward) as *mut AtomicPtr<Node>;
+            ptr::write_bytes(forward, 0, height);
+            ptr::write_bytes(forward.add(height) as *mut AtomicUsize, 0, height);
         }
+        node
+    }
+
+    /// Returns the bytes allocated for a node of `height` levels.
+    #[inline]
+    pub(crate) fn size(height: usize) -> usize {
+        mem::size_of::<Self>() - (K_MAX_HEIGHT - height) * mem::size_of::<AtomicPtr<Self>>()
+            + height * mem::size_of::<AtomicUsize>()
+    }
+
+    fn spans(&self) -> *mut AtomicUsize {
+        unsafe { self.forward.as_ptr().add(self.height()) as *mut AtomicUsize }
+    }
+
+    /// Returns the number of live entries the link at `level` leads over, the
+    /// next node included, or up to the end of the list if it is the last one
+    /// of the level. Only kept by `SkipList`, see `SkipList::select`.
+    #[inline]
+    pub(crate) fn span(&self, level: usize) -> usize {
+        debug_assert!(level < self.height());
+        unsafe { (*self.spans().add(level)).load(Ordering::Acquire) }
     }
 
-    #[allow(clippy::mut_from_ref)]
-    pub fn head<A: Arena>(arena: &A) -> &mut Self {
+    #[inline]
+    pub(crate) fn set_span(&self, level: usize, span: usize) {
+        debug_assert!(level < self.height());
+        unsafe { (*self.spans().add(level)).store(span, Ordering::Release) }
+    }
+
+    #[inline]
+    pub(crate) fn add_span(&self, level: usize, delta: isize) {
+        debug_assert!(level < self.height());
+        unsafe { (*self.spans().add(level)).fetch_add(delta as usize, Ordering::AcqRel) };
+    }
+
+    /// Move `value` into the arena, to be stored by `with_value` or swapped in by
+    /// `compare_exchange_value`. Slots are freed with the arena, so readers can
+    /// keep the value they loaded while it gets replaced.
+    pub(crate) fn alloc_value<A: Arena>(value: Bytes, arena: &A) -> *mut Bytes {
+        if value.is_empty() {
+            return ptr::null_mut();
+        }
+        let slot = arena
+            .allocate_aligned(mem::size_of::<Bytes>())
+            .cast::<Bytes>()
+            .as_ptr();
+        unsafe { ptr::write(slot, value) };
+        slot
+    }
+
+    pub fn head<A: Arena>(arena: &A) -> NonNull<Self> {
         Self::new(Bytes::new(), K_MAX_HEIGHT, arena)
     }
 
+    /// The tower only has `height` levels, the slots above it hold the spans.
     #[inline]
-    pub fn set_next(&self, n: usize, node: *mut Node) {
+    pub(crate) fn set_next(&self, n: usize, node: *mut Node) {
+        debug_assert!(n < self.height());
         self.forward[n].store(node, Ordering::SeqCst);
     }
 
     #[inline]
-    pub fn get_next(&self, n: usize) -> *mut Node {
+    pub(crate) fn get_next(&self, n: usize) -> *mut Node {
+        debug_assert!(n < self.height());
         self.forward[n].load(Ordering::SeqCst)
     }
+
+    /// Returns the next node at `level`, `None` at the end of the level or if
+    /// the node isn't linked at `level`, see `height`.
+    #[inline]
+    pub fn next(&self, level: usize) -> Option<&Node> {
+        if level >= self.height() {
+            return None;
+        }
+        // Linked nodes belong to the list of this one, see `Nav`.
+        unsafe { self.get_next(level).as_ref() }
+    }
+
+    /// Returns the number of levels the node is linked at.
+    #[inline]
+    pub fn height(&self) -> usize {
+        usize::from(self.height)
+    }
+
+    /// Returns the value of the entry in a `SkipMap`, empty in a `SkipList`.
+    #[inline]
+    pub fn value(&self) -> &Bytes {
+        static EMPTY: Bytes = Bytes::new();
+        let value = self.value_ptr();
+        if value.is_null() {
+            &EMPTY
+        } else {
+            unsafe { &*value }
+        }
+    }
+
+    #[inline]
+    pub(crate) fn value_ptr(&self) -> *mut Bytes {
+        self.value.load(Ordering::Acquire)
+    }
+
+    /// Replace the value with `new` if it is still `current`, both allocated by
+    /// `alloc_value`.
+    #[inline]
+    pub(crate) fn compare_exchange_value(&self, current: *mut Bytes, new: *mut Bytes) -> bool {
+        self.value
+            .compare_exchange(current, new, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+    }
+
+    /// Returns when the entry was inserted, read from a monotonic clock. Updating
+    /// its value in place, or counting it again under `DuplicatePolicy::Count`,
+    /// keeps the time of the first insert.
+    #[cfg(feature = "timestamps")]
+    pub fn inserted_at(&self) -> Instant {
+        clock_start() + Duration::from_nanos(self.inserted)
+    }
+
+    /// Returns the number of sampled lookups that found the entry.
+    #[cfg(feature = "metrics")]
+    #[inline]
+    pub(crate) fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    #[cfg(feature = "metrics")]
+    #[inline]
+    pub(crate) fn add_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn get_meta(&self) -> u8 {
+        self.meta.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub fn set_meta(&self, meta: u8) {
+        self.meta.store(meta, Ordering::Release)
+    }
+
+    /// Returns the number of times the entry was inserted with
+    /// `DuplicatePolicy::Count`, always 1 with the other policies. 0 once
+    /// `SkipList::remove_one` took the last one, right before the entry is
+    /// removed.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Acquire) as usize
+    }
+
+    #[inline]
+    pub(crate) fn set_count(&self, count: usize) {
+        let count = u32::try_from(count).unwrap_or(u32::MAX);
+        self.count.store(count, Ordering::Release)
+    }
+
+    /// Add `n` to the count, saturating, unless it already dropped to 0.
+    /// Returns `false` in that case, the entry is on its way out.
+    #[inline]
+    pub(crate) fn add_count(&self, n: usize) -> bool {
+        let n = u32::try_from(n).unwrap_or(u32::MAX);
+        self.count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                (count > 0).then(|| count.saturating_add(n))
+            })
+            .is_ok()
+    }
+
+    /// Take one off the count and return the previous one, `None` if it was
+    /// already 0.
+    #[inline]
+    pub(crate) fn decrement_count(&self) -> Option<usize> {
+        self.count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                count.checked_sub(1)
+            })
+            .ok()
+            .map(|count| count as usize)
+    }
+
+    /// Returns `true` if the entry was removed. Removed nodes stay linked, every
+    /// read skips them. An entry being renamed into counts as removed until its
+    /// source is.
+    #[inline]
+    pub fn is_deleted(&self) -> bool {
+        let state = self.state.load(Ordering::Acquire);
+        if state & DELETED != 0 {
+            true
+        } else if state & PENDING != 0 {
+            // Both nodes live in the same arena, which outlives the rename or
+            // the batch.
+            let source = unsafe { &*self.source.load(Ordering::Acquire) };
+            source.state.load(Ordering::Acquire) & DELETED == 0
+        } else {
+            false
+        }
+    }
+
+    /// Mark the entry as removed, returns `false` if it already was or if it
+    /// wasn't visible yet.
+    #[inline]
+    pub(crate) fn mark_deleted(&self) -> bool {
+        self.state.fetch_or(DELETED, Ordering::AcqRel) & (DELETED | PENDING) == 0
+    }
+
+    /// Hide the entry until `source` is removed, see `SkipList::rename` and
+    /// `SkipList::write`. Must be called before the node is linked.
+    pub(crate) fn mark_pending(&self, source: &Node) {
+        self.source
+            .store(source as *const Node as *mut Node, Ordering::Release);
+        self.state.fetch_or(PENDING, Ordering::AcqRel);
+    }
+
+    /// Make the entry stand on its own once its source was removed. `source`
+    /// is left as it is, for readers that saw the flag.
+    pub(crate) fn clear_pending(&self) {
+        self.state.fetch_and(!PENDING, Ordering::AcqRel);
+    }
+}
+
+const DELETED: u8 = 1;
+const PENDING: u8 = 2;
+
+/// The instant the insert times of the nodes are counted from, the first time
+/// one is asked for in the process.
+#[cfg(feature = "timestamps")]
+fn clock_start() -> Instant {
+    static START: OnceLock<Instant> = OnceLock::new();
+    *START.get_or_init(Instant::now)
+}
+
+/// The nodes of a list, reached from its head. Arenas only give the memory back,
+/// so this drops the keys and values the nodes own: the ones of the nodes
+/// linked at level 0, of the runs of nodes unlinked since, see `retire`, and
+/// the values replaced in them, see `retire_value`.
+///
+/// Lists hold it before their arena, and before the lists they adopted, so that
+/// it drops while every node is still allocated.
+pub(crate) struct Nodes {
+    head: NodePtr,
+    /// The first node and the length of each run unlinked from the list, in
+    /// the order they were unlinked.
+    retired: Mutex<Vec<(NodePtr, usize)>>,
+    /// Values swapped out of the nodes by address, which readers may still be
+    /// on.
+    replaced: Mutex<Vec<usize>>,
+}
+
+impl Nodes {
+    pub(crate) fn new(head: NonNull<Node>) -> Self {
+        Nodes {
+            head: NodePtr::new(head),
+            retired: Mutex::default(),
+            replaced: Mutex::default(),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn head(&self) -> NodePtr {
+        self.head
+    }
+
+    /// Take over the `len` nodes following level 0 from `first`, just unlinked
+    /// from the list. Their own links are kept, so they lead to the same nodes
+    /// until the list drops.
+    pub(crate) fn retire(&self, first: Nav<'_>, len: usize) {
+        self.lock_retired()
+            .push((NodePtr::new(NonNull::from(first.node())), len));
+    }
+
+    /// Take over `value`, allocated by `Node::alloc_value` and just replaced in
+    /// its node.
+    pub(crate) fn retire_value(&self, value: *mut Bytes) {
+        if !value.is_null() {
+            self.replaced
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .push(value as usize);
+        }
+    }
+
+    /// Returns the number of runs retired so far, see `drop_retired_since`.
+    pub(crate) fn retired(&self) -> usize {
+        self.lock_retired().len()
+    }
+
+    /// Drop the keys and values of the runs retired after the first `len`, for a
+    /// caller about to give their memory back to the arena.
+    /// # Safety
+    /// No reader may be on the nodes of these runs.
+    pub(crate) unsafe fn drop_retired_since(&self, len: usize) {
+        for (first, len) in self.lock_retired().drain(len..) {
+            drop_run(first.as_ptr(), len);
+        }
+    }
+
+    fn lock_retired(&self) -> MutexGuard<'_, Vec<(NodePtr, usize)>> {
+        self.retired
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Drop for Nodes {
+    fn drop(&mut self) {
+        let head = self.head.as_ptr();
+        unsafe { drop_run((*head).get_next(0), usize::MAX) };
+        for &(first, len) in self.lock_retired().iter() {
+            unsafe { drop_run(first.as_ptr(), len) };
+        }
+        let replaced = self.replaced.get_mut();
+        for &value in replaced
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+        {
+            unsafe { ptr::drop_in_place(value as *mut Bytes) };
+        }
+    }
+}
+
+/// Drop the keys and values of up to `len` nodes, following level 0 from
+/// `node`. Their memory is left to the arena.
+/// # Safety
+/// The nodes must be alive, and neither used nor dropped again afterwards.
+unsafe fn drop_run(mut node: *mut Node, len: usize) {
+    for _ in 0..len {
+        if node.is_null() {
+            break;
+        }
+        let next = (*node).get_next(0);
+        let value = (*node).value_ptr();
+        if !value.is_null() {
+            ptr::drop_in_place(value);
+        }
+        ptr::drop_in_place(ptr::addr_of_mut!((*node).data));
+        node = next;
+    }
+}
+
+/// Returns the first node from `x` on, following level 0, that wasn't removed.
+#[inline]
+pub(crate) fn live_from(mut x: Option<Nav<'_>>) -> Option<Nav<'_>> {
+    while let Some(node) = x.filter(|node| node.is_deleted()) {
+        x = node.next(0);
+    }
+    x
+}
+
+/// A pointer to a node of a skiplist, e.g. its head.
+///
+/// This is the one place where the node graph is declared safe to share between
+/// threads. Nodes live in the arena of their list and are only freed with it, and
+/// every field written after a node is linked is atomic, so a `Node` is `Sync`: a
+/// pointer to one can be sent and shared as long as it is only dereferenced
+/// while its list is alive. Types holding a `NodePtr` derive `Send` and `Sync`
+/// from the rest of their fields.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct NodePtr(NonNull<Node>);
+
+unsafe impl Send for NodePtr {}
+unsafe impl Sync for NodePtr {}
+
+// Fails to build if a field added to `Node` makes the impls above unsound.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Node>();
+};
+
+impl NodePtr {
+    #[inline]
+    pub(crate) fn new(node: NonNull<Node>) -> Self {
+        NodePtr(node)
+    }
+
+    #[inline]
+    pub(crate) fn as_ptr(self) -> *mut Node {
+        self.0.as_ptr()
+    }
+
+    /// # Safety
+    /// The list owning the node must be alive for `'a`.
+    #[inline]
+    pub(crate) unsafe fn as_ref<'a>(&self) -> &'a Node {
+        self.0.as_ref()
+    }
+
+    /// # Safety
+    /// The list owning the node must be alive for `'a`.
+    #[inline]
+    pub(crate) unsafe fn nav<'a>(self) -> Nav<'a> {
+        Nav {
+            node: self.0,
+            list: PhantomData,
+        }
+    }
+}
+
+/// A node of a live list, through which the list code follows links and reads
+/// nodes without dereferencing raw pointers itself.
+///
+/// Invariant: the node belongs to a list alive for `'a`, its head included, or
+/// to a list kept alive by it, see `SkipList::merge`. Nodes are only freed with
+/// the arena of their list, and every node linked from one of a list belongs to
+/// it, so following a link keeps the invariant: `next` is safe, and only
+/// building a `Nav` from a raw pointer is not.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Nav<'a> {
+    node: NonNull<Node>,
+    list: PhantomData<&'a Node>,
+}
+
+impl<'a> Nav<'a> {
+    /// # Safety
+    /// `node` must be null or a node of a list alive for `'a`.
+    #[inline]
+    pub(crate) unsafe fn from_ptr(node: *const Node) -> Option<Self> {
+        NonNull::new(node as *mut Node).map(|node| Nav {
+            node,
+            list: PhantomData,
+        })
+    }
+
+    #[inline]
+    pub(crate) fn node(self) -> &'a Node {
+        unsafe { self.node.as_ref() }
+    }
+
+    #[inline]
+    pub(crate) fn as_ptr(self) -> *mut Node {
+        self.node.as_ptr()
+    }
+
+    /// Returns the next node at `level`, `None` at the end of the level.
+    #[inline]
+    pub(crate) fn next(self, level: usize) -> Option<Self> {
+        unsafe { Self::from_ptr(self.node().get_next(level)) }
+    }
+
+    /// Returns the first node after this one, following level 0, that wasn't
+    /// removed.
+    #[inline]
+    pub(crate) fn next_live(self) -> Option<Self> {
+        live_from(self.next(0))
+    }
+
+    #[inline]
+    pub(crate) fn set_next(self, level: usize, next: Option<Self>) {
+        self.node()
+            .set_next(level, next.map_or(ptr::null_mut(), Self::as_ptr));
+    }
+
+    #[inline]
+    pub(crate) fn key(self) -> &'a [u8] {
+        self.node().data.as_ref()
+    }
+
+    #[inline]
+    pub(crate) fn is(self, node: *const Node) -> bool {
+        ptr::eq(self.as_ptr(), node)
+    }
+}
+
+// Nodes are only ever allocated in the arena of a list.
+impl<'a> From<&'a Node> for Nav<'a> {
+    #[inline]
+    fn from(node: &'a Node) -> Self {
+        Nav {
+            node: NonNull::from(node),
+            list: PhantomData,
+        }
+    }
+}
+
+impl<'a> std::ops::Deref for Nav<'a> {
+    type Target = Node;
+
+    #[inline]
+    fn deref(&self) -> &Node {
+        self.node()
+    }
+}
+
+/// Returns the raw pointer of `node`, null for `None`.
+#[inline]
+pub(crate) fn raw(node: Option<Nav<'_>>) -> *mut Node {
+    node.map_or(ptr::null_mut(), Nav::as_ptr)
+}
+
+/// An opaque handle to an entry of a skiplist.
+///
+/// Handles are returned by `SkipList::insert_ref` and `SkipList::get_ref`, and
+/// let callers revisit an entry in O(1) through `SkipList::key_of` and
+/// `SkipList::next` instead of searching for it again. A handle remembers the list
+/// it came from: passing it to another live list returns `None`. A handle must
+/// not be used once every handle to its list has been dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeRef {
+    pub(crate) list: u64,
+    pub(crate) node: NodePtr,
+}
+
+impl NodeRef {
+    pub(crate) fn new(list: u64, node: NonNull<Node>) -> Self {
+        NodeRef {
+            list,
+            node: NodePtr::new(node),
+        }
+    }
+}
+
+/// The search path of an insert: the node before the inserted key on every level.
+///
+/// Every `SkipList` keeps one, shared by the writers of all its handles. Callers
+/// loading batches of keys can keep their own through
+/// `SkipList::insert_with_splice`: the next search resumes from the path of the
+/// previous insert instead of the head, which makes inserting nearby or sorted
+/// keys cheap. A splice remembers the list (and
+/// the `clear`) it was recorded for, and starts over from the head when used with
+/// another one.
+///
+/// Unlike the splice of RocksDB, only the nodes before the key are kept: the
+/// nodes after it are their next ones, read when checking that the path still
+/// brackets the key, so keeping them would only save a load per level.
+#[derive(Debug)]
+pub struct Splice {
+    /// Generation of the list the path was recorded in, 0 when empty.
+    pub(crate) generation: usize,
+    pub(crate) prev: [*mut Node; K_MAX_HEIGHT],
+}
+
+// The nodes are only ever dereferenced through the list owning them, once the
+// generation has been checked.
+unsafe impl Send for Splice {}
+unsafe impl Sync for Splice {}
+
+impl Splice {
+    pub fn new() -> Self {
+        Splice {
+            generation: 0,
+            prev: [ptr::null_mut(); K_MAX_HEIGHT],
+        }
+    }
+}
+
+impl Default for Splice {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl fmt::Display for Node {
@@ -47,7 +635,7 @@ impl fmt::Display for Node {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "arena"))]
 mod tests {
     use super::Node;
     use crate::ArenaImpl;
@@ -56,10 +644,10 @@ mod tests {
     fn test_new_node() {
         let arena = ArenaImpl::new();
 
-        let node = Node::head(&arena);
+        let node = unsafe { Node::head(&arena).as_ref() };
         assert_eq!(format!("{}", node), "[]");
 
-        let node = Node::new("da".into(), 0, &arena);
+        let node = unsafe { Node::new("da".into(), 0, &arena).as_ref() };
         assert_eq!(format!("{}", node), "[100, 97]");
     }
 
@@ -70,7 +658,9 @@ mod tests {
         let node = Node::new(vec![1].into(), 3, &arena);
         let next = Node::new(vec![2].into(), 4, &arena);
         let tail = Node::new(vec![3].into(), 1, &arena);
-        node.set_next(2, next);
+        let node = unsafe { node.as_ref() };
+        node.set_next(2, next.as_ptr());
+        let next = unsafe { next.as_ref() };
         let ret = node.get_next(1);
         assert!(ret.is_null());
         let ret = node.get_next(2);
@@ -79,10 +669,15 @@ mod tests {
             assert_eq!((*ret).data.as_ref(), &[2]);
         }
 
-        next.set_next(3, tail);
+        next.set_next(3, tail.as_ptr());
         let v = next.get_next(3);
         unsafe {
             assert_eq!((*v).data.as_ref(), &[3]);
         }
+
+        assert_eq!(node.next(2).unwrap().data.as_ref(), &[2]);
+        assert!(node.next(1).is_none());
+        // Above the tower.
+        assert!(node.next(3).is_none());
     }
 }
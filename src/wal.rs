@@ -0,0 +1,645 @@
+//! Write ahead log, in the LevelDB log format.
+//!
+//! The log is a sequence of 32KB blocks. A record is split into fragments that
+//! never cross a block boundary, each prefixed with a header:
+//!
+//! ```text
+//! checksum: u32 (masked crc32c of the type, log number and payload)
+//! length:   u16
+//! type:     u8  (full, first, middle or last fragment)
+//! log:      u64 (recyclable format only)
+//! ```
+//!
+//! A block trailer too small for a header is filled with zeros. The recyclable
+//! format stores the log number in every fragment, so a log file can be reused
+//! for a new log without being truncated: the reader stops at the first fragment
+//! left by the previous log. Unlike RocksDB, which keeps the low 32 bits, the
+//! whole log number is stored, so logs never wrap around to an older number.
+use crate::crc32c;
+use crate::encoding::{decode_fixed32, decode_fixed64, put_fixed64};
+use std::io::{self, Read, Write};
+
+pub const BLOCK_SIZE: usize = 32768;
+
+/// checksum (4 bytes), length (2 bytes), type (1 byte)
+pub const HEADER_SIZE: usize = 4 + 2 + 1;
+
+/// checksum (4 bytes), length (2 bytes), type (1 byte), log number (8 bytes)
+pub const RECYCLABLE_HEADER_SIZE: usize = 4 + 2 + 1 + 8;
+
+// Zero is reserved for preallocated files.
+const ZERO_TYPE: u8 = 0;
+const FULL_TYPE: u8 = 1;
+const FIRST_TYPE: u8 = 2;
+const MIDDLE_TYPE: u8 = 3;
+const LAST_TYPE: u8 = 4;
+// The recyclable types follow in the same order, from 5 (full) to 8 (last).
+const RECYCLABLE_FULL_TYPE: u8 = 5;
+const RECYCLABLE_LAST_TYPE: u8 = 8;
+
+pub struct LogWriter<W: Write> {
+    dest: W,
+    block_offset: usize,
+    /// Set for the recyclable format.
+    log_number: Option<u64>,
+}
+
+impl<W: Write> LogWriter<W> {
+    /// Create a writer appending records to `dest`, which must be empty.
+    pub fn new(dest: W) -> Self {
+        LogWriter {
+            dest,
+            block_offset: 0,
+            log_number: None,
+        }
+    }
+
+    /// Create a writer using the recyclable format. `dest` may hold an older log
+    /// written with a different `log_number`, which will be overwritten.
+    pub fn new_recyclable(dest: W, log_number: u64) -> Self {
+        LogWriter {
+            dest,
+            block_offset: 0,
+            log_number: Some(log_number),
+        }
+    }
+
+    pub fn add_record(&mut self, data: &[u8]) -> io::Result<()> {
+        let header_size = self.header_size();
+        let mut left = data;
+        let mut begin = true;
+        // Fragment the record if necessary and emit it. Note that if `data` is
+        // empty, we still want to emit a single zero-length record.
+        loop {
+            let leftover = BLOCK_SIZE - self.block_offset;
+            if leftover < header_size {
+                // Switch to a new block, filling the trailer with zeros.
+                if leftover > 0 {
+                    self.dest
+                        .write_all(&[0; RECYCLABLE_HEADER_SIZE][..leftover])?;
+                }
+                self.block_offset = 0;
+            }
+
+            let avail = BLOCK_SIZE - self.block_offset - header_size;
+            let fragment_length = left.len().min(avail);
+            let end = left.len() == fragment_length;
+            let record_type = match (begin, end) {
+                (true, true) => FULL_TYPE,
+                (true, false) => FIRST_TYPE,
+                (false, true) => LAST_TYPE,
+                (false, false) => MIDDLE_TYPE,
+            };
+            self.emit_physical_record(record_type, &left[..fragment_length])?;
+            left = &left[fragment_length..];
+            begin = false;
+            if left.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.dest.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.dest
+    }
+
+    fn header_size(&self) -> usize {
+        if self.log_number.is_some() {
+            RECYCLABLE_HEADER_SIZE
+        } else {
+            HEADER_SIZE
+        }
+    }
+
+    fn emit_physical_record(&mut self, record_type: u8, data: &[u8]) -> io::Result<()> {
+        debug_assert!(data.len() <= 0xffff);
+        let mut header = Vec::with_capacity(RECYCLABLE_HEADER_SIZE);
+        header.extend_from_slice(&[0; 4]);
+        header.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        match self.log_number {
+            Some(log_number) => {
+                header.push(record_type + RECYCLABLE_FULL_TYPE - FULL_TYPE);
+                put_fixed64(&mut header, log_number);
+            }
+            None => header.push(record_type),
+        }
+        // The checksum covers the type, the log number and the payload.
+        let crc = crc32c::extend(crc32c::value(&header[6..]), data);
+        header[..4].copy_from_slice(&crc32c::mask(crc).to_le_bytes());
+
+        self.dest.write_all(&header)?;
+        self.dest.write_all(data)?;
+        self.block_offset += header.len() + data.len();
+        Ok(())
+    }
+}
+
+/// A part of the log the reader had to skip.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corruption {
+    /// Approximate number of bytes dropped.
+    pub bytes: usize,
+    pub reason: &'static str,
+}
+
+enum Physical {
+    Record(u8, usize, usize),
+    Eof,
+    /// A fragment that was skipped, after reporting why if it was corrupted.
+    Bad,
+}
+
+pub struct LogReader<R: Read> {
+    src: R,
+    buffer: Vec<u8>,
+    /// Start of the unread part of `buffer`.
+    pos: usize,
+    eof: bool,
+    log_number: Option<u64>,
+    corruptions: Vec<Corruption>,
+}
+
+impl<R: Read> LogReader<R> {
+    pub fn new(src: R) -> Self {
+        LogReader {
+            src,
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+            pos: 0,
+            eof: false,
+            log_number: None,
+            corruptions: vec![],
+        }
+    }
+
+    /// Create a reader for a log written with `LogWriter::new_recyclable`.
+    /// Fragments of other logs mark the end of this one.
+    pub fn new_recyclable(src: R, log_number: u64) -> Self {
+        LogReader {
+            log_number: Some(log_number),
+            ..Self::new(src)
+        }
+    }
+
+    /// Everything skipped so far because it was corrupted.
+    pub fn corruptions(&self) -> &[Corruption] {
+        &self.corruptions
+    }
+
+    /// Read the next record, returning `None` at the end of the log.
+    ///
+    /// Corrupted records are skipped and reported in `corruptions`, like the
+    /// records of a recyclable log read with `new`, of an unknown type. A record cut
+    /// short at the end of the log, as left by a crash in the middle of a write,
+    /// is silently ignored. In the recyclable format a corrupted fragment can't be
+    /// told apart from the leftovers of an older log, so it ends the log instead.
+    pub fn read_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut record = vec![];
+        let mut in_fragmented_record = false;
+        loop {
+            match self.read_physical_record()? {
+                Physical::Record(record_type, start, end) => {
+                    let len = end - start;
+                    match record_type {
+                        FULL_TYPE => {
+                            if in_fragmented_record && !record.is_empty() {
+                                self.report(record.len(), "partial record without end(1)");
+                            }
+                            return Ok(Some(self.buffer[start..end].to_vec()));
+                        }
+                        FIRST_TYPE => {
+                            if in_fragmented_record && !record.is_empty() {
+                                self.report(record.len(), "partial record without end(2)");
+                            }
+                            record.clear();
+                            record.extend_from_slice(&self.buffer[start..end]);
+                            in_fragmented_record = true;
+                        }
+                        MIDDLE_TYPE => {
+                            if in_fragmented_record {
+                                record.extend_from_slice(&self.buffer[start..end]);
+                            } else {
+                                self.report(len, "missing start of fragmented record(1)");
+                            }
+                        }
+                        LAST_TYPE => {
+                            if in_fragmented_record {
+                                record.extend_from_slice(&self.buffer[start..end]);
+                                return Ok(Some(record));
+                            }
+                            self.report(len, "missing start of fragmented record(2)");
+                        }
+                        _ => {
+                            self.report(len + record.len(), "unknown record type");
+                            in_fragmented_record = false;
+                            record.clear();
+                        }
+                    }
+                }
+                Physical::Eof => {
+                    // A writer died in the middle of a fragmented record, drop
+                    // it without reporting a corruption.
+                    return Ok(None);
+                }
+                Physical::Bad => {
+                    if in_fragmented_record {
+                        self.report(record.len(), "error in middle of record");
+                        in_fragmented_record = false;
+                        record.clear();
+                    }
+                }
+            }
+        }
+    }
+
+    fn header_size(&self) -> usize {
+        if self.log_number.is_some() {
+            RECYCLABLE_HEADER_SIZE
+        } else {
+            HEADER_SIZE
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.buffer.len() - self.pos
+    }
+
+    /// Read the next fragment, returning its type (as a non recyclable type) and
+    /// its position in `buffer`.
+    fn read_physical_record(&mut self) -> io::Result<Physical> {
+        loop {
+            if self.remaining() < HEADER_SIZE
+                || (self.remaining() < self.header_size() && !self.is_zero_trailer())
+            {
+                if !self.eof {
+                    // Skip the trailer and read the next block.
+                    self.read_block()?;
+                    continue;
+                }
+                // A truncated header at the end of the log is the sign of a
+                // writer dying in the middle of a write, not a corruption.
+                self.pos = self.buffer.len();
+                return Ok(Physical::Eof);
+            }
+            if self.remaining() < self.header_size() {
+                // Zero filled trailer of a recyclable block.
+                self.pos = self.buffer.len();
+                continue;
+            }
+
+            let header = &self.buffer[self.pos..];
+            let length = u16::from_le_bytes([header[4], header[5]]) as usize;
+            let mut record_type = header[6];
+            let header_size = match record_type {
+                RECYCLABLE_FULL_TYPE..=RECYCLABLE_LAST_TYPE => RECYCLABLE_HEADER_SIZE,
+                _ => HEADER_SIZE,
+            };
+            if header_size + length > self.remaining() {
+                let drop_size = self.remaining();
+                self.pos = self.buffer.len();
+                if !self.eof && self.log_number.is_none() {
+                    self.report(drop_size, "bad record length");
+                    return Ok(Physical::Bad);
+                }
+                // The end of the log was reached without reading `length` bytes
+                // of payload: the writer died in the middle of the record. In a
+                // recycled file this may also be the leftover of an older log.
+                self.eof = true;
+                return Ok(Physical::Eof);
+            }
+
+            if record_type == ZERO_TYPE && length == 0 {
+                // Skip zero length records without reporting any drops, these
+                // are produced by preallocated files.
+                self.pos = self.buffer.len();
+                return Ok(Physical::Bad);
+            }
+
+            let expected_crc = crc32c::unmask(decode_fixed32(header));
+            let actual_crc = crc32c::value(&header[6..header_size + length]);
+            if actual_crc != expected_crc {
+                // Drop the rest of the buffer since `length` itself may have been
+                // corrupted: trusting it could make us read a fragment of some
+                // record that happens to look valid.
+                let drop_size = self.remaining();
+                self.pos = self.buffer.len();
+                if self.log_number.is_some() {
+                    // Past the end of a recycled log, fragments of the previous
+                    // log are not aligned with ours and look like garbage.
+                    self.eof = true;
+                    return Ok(Physical::Eof);
+                }
+                self.report(drop_size, "checksum mismatch");
+                return Ok(Physical::Bad);
+            }
+
+            // A legacy reader doesn't know the recyclable types, the record is
+            // reported as one of an unknown type.
+            if header_size == RECYCLABLE_HEADER_SIZE && self.log_number.is_some() {
+                let log_number = decode_fixed64(&header[7..]);
+                if self.log_number != Some(log_number) {
+                    // Left by a previous use of the file: the log ends here.
+                    self.pos = self.buffer.len();
+                    self.eof = true;
+                    return Ok(Physical::Eof);
+                }
+                record_type -= RECYCLABLE_FULL_TYPE - FULL_TYPE;
+            }
+
+            let start = self.pos + header_size;
+            self.pos = start + length;
+            return Ok(Physical::Record(record_type, start, start + length));
+        }
+    }
+
+    fn is_zero_trailer(&self) -> bool {
+        self.buffer[self.pos..].iter().all(|b| *b == 0)
+    }
+
+    fn read_block(&mut self) -> io::Result<()> {
+        self.buffer.clear();
+        self.buffer.resize(BLOCK_SIZE, 0);
+        self.pos = 0;
+        let mut read = 0;
+        while read < BLOCK_SIZE {
+            match self.src.read(&mut self.buffer[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.buffer.clear();
+                    return Err(e);
+                }
+            }
+        }
+        self.buffer.truncate(read);
+        if read < BLOCK_SIZE {
+            self.eof = true;
+        }
+        Ok(())
+    }
+
+    fn report(&mut self, bytes: usize, reason: &'static str) {
+        self.corruptions.push(Corruption { bytes, reason });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Random, RandomGenerator};
+    use std::io::Cursor;
+
+    // Construct a string of the specified length made out of the supplied
+    // partial string.
+    fn big_string(partial: &str, n: usize) -> Vec<u8> {
+        partial.bytes().cycle().take(n).collect()
+    }
+
+    fn number_string(n: usize) -> Vec<u8> {
+        format!("{}.", n).into_bytes()
+    }
+
+    fn random_skewed_string(i: usize, rnd: &Random) -> Vec<u8> {
+        big_string(
+            std::str::from_utf8(&number_string(i)).unwrap(),
+            rnd.skewed(17) as usize,
+        )
+    }
+
+    fn write(records: &[Vec<u8>]) -> Vec<u8> {
+        let mut writer = LogWriter::new(vec![]);
+        for r in records {
+            writer.add_record(r).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    fn read_all(log: &[u8]) -> (Vec<Vec<u8>>, Vec<Corruption>) {
+        let mut reader = LogReader::new(Cursor::new(log));
+        let mut records = vec![];
+        while let Some(r) = reader.read_record().unwrap() {
+            records.push(r);
+        }
+        (records, reader.corruptions().to_vec())
+    }
+
+    fn dropped(corruptions: &[Corruption]) -> usize {
+        corruptions.iter().map(|c| c.bytes).sum()
+    }
+
+    #[test]
+    fn test_empty() {
+        let (records, corruptions) = read_all(&[]);
+        assert!(records.is_empty());
+        assert!(corruptions.is_empty());
+    }
+
+    #[test]
+    fn test_read_write() {
+        let records = vec![b"foo".to_vec(), b"bar".to_vec(), vec![], b"xxxx".to_vec()];
+        let log = write(&records);
+        assert_eq!(read_all(&log), (records, vec![]));
+    }
+
+    #[test]
+    fn test_many_blocks() {
+        let records: Vec<_> = (0..100_000).map(number_string).collect();
+        let log = write(&records);
+        assert_eq!(read_all(&log), (records, vec![]));
+    }
+
+    #[test]
+    fn test_fragmentation() {
+        let records = vec![
+            b"small".to_vec(),
+            big_string("medium", 50000),
+            big_string("large", 100000),
+        ];
+        let log = write(&records);
+        assert_eq!(read_all(&log), (records, vec![]));
+    }
+
+    #[test]
+    fn test_marginal_trailer() {
+        // Make a trailer that is exactly the same length as an empty record.
+        let n = BLOCK_SIZE - 2 * HEADER_SIZE;
+        let records = vec![big_string("foo", n), vec![], b"bar".to_vec()];
+        let log = write(&records[..1]);
+        assert_eq!(log.len(), BLOCK_SIZE - HEADER_SIZE);
+        let log = write(&records);
+        assert_eq!(read_all(&log), (records, vec![]));
+    }
+
+    #[test]
+    fn test_short_trailer() {
+        let n = BLOCK_SIZE - 2 * HEADER_SIZE + 4;
+        let records = vec![big_string("foo", n), vec![], b"bar".to_vec()];
+        let log = write(&records);
+        assert_eq!(read_all(&log), (records, vec![]));
+    }
+
+    #[test]
+    fn test_aligned_eof() {
+        let n = BLOCK_SIZE - 2 * HEADER_SIZE + 4;
+        let records = vec![big_string("foo", n)];
+        let log = write(&records);
+        assert_eq!(log.len(), BLOCK_SIZE - HEADER_SIZE + 4);
+        assert_eq!(read_all(&log), (records, vec![]));
+    }
+
+    #[test]
+    fn test_random_read() {
+        let n = 500;
+        let rnd = Random::new(301);
+        let records: Vec<_> = (0..n).map(|i| random_skewed_string(i, &rnd)).collect();
+        let log = write(&records);
+        assert_eq!(read_all(&log), (records, vec![]));
+    }
+
+    #[test]
+    fn test_truncated_trailing_record_is_ignored() {
+        let mut log = write(&[b"foo".to_vec()]);
+        // Drop the payload and part of the header, as a torn write would.
+        log.truncate(HEADER_SIZE - 3);
+        let (records, corruptions) = read_all(&log);
+        assert!(records.is_empty());
+        // Truncated last record is ignored, not treated as an error.
+        assert!(corruptions.is_empty());
+    }
+
+    #[test]
+    fn test_bad_length_at_end_is_ignored() {
+        let mut log = write(&[b"foo".to_vec()]);
+        log.pop();
+        let (records, corruptions) = read_all(&log);
+        assert!(records.is_empty());
+        assert!(corruptions.is_empty());
+    }
+
+    #[test]
+    fn test_bad_length() {
+        let payload_size = BLOCK_SIZE - HEADER_SIZE;
+        let records = vec![big_string("bar", payload_size), b"foo".to_vec()];
+        let mut log = write(&records);
+        // Least significant size byte is stored in header[4].
+        log[4] += 1;
+        let (read, corruptions) = read_all(&log);
+        assert_eq!(read, vec![b"foo".to_vec()]);
+        assert_eq!(dropped(&corruptions), BLOCK_SIZE);
+        assert_eq!(corruptions[0].reason, "bad record length");
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let mut log = write(&[b"foooooo".to_vec(), b"bar".to_vec()]);
+        log[0] = log[0].wrapping_add(14);
+        let (read, corruptions) = read_all(&log);
+        // The whole block is dropped, including the valid record after it.
+        assert!(read.is_empty());
+        assert_eq!(dropped(&corruptions), log.len());
+        assert_eq!(corruptions[0].reason, "checksum mismatch");
+    }
+
+    #[test]
+    fn test_corrupt_tail_skipped() {
+        let first = b"first".to_vec();
+        let second = big_string("second", BLOCK_SIZE * 2);
+        let mut log = write(&[first.clone(), second.clone(), b"third".to_vec()]);
+        // Corrupt the last record, the records before it are still readable.
+        let n = log.len();
+        log[n - 2] ^= 0xff;
+        let (read, corruptions) = read_all(&log);
+        assert_eq!(read, vec![first, second]);
+        assert_eq!(corruptions.len(), 1);
+        assert_eq!(corruptions[0].reason, "checksum mismatch");
+    }
+
+    #[test]
+    fn test_missing_start_of_fragmented_record() {
+        let records = vec![big_string("foo", BLOCK_SIZE * 2), b"bar".to_vec()];
+        let mut log = write(&records);
+        // Drop the first block: the reader starts in the middle of a record.
+        let log = log.split_off(BLOCK_SIZE);
+        let (read, corruptions) = read_all(&log);
+        assert_eq!(read, vec![b"bar".to_vec()]);
+        assert!(corruptions[0].reason.starts_with("missing start"));
+    }
+
+    #[test]
+    fn test_recycled_log() {
+        let old: Vec<_> = (0..1000).map(|i| big_string("old", 100 + i)).collect();
+        let mut writer = LogWriter::new_recyclable(vec![], 1);
+        for r in &old {
+            writer.add_record(r).unwrap();
+        }
+        let mut file = writer.into_inner();
+
+        // Reuse the file for log 2, overwriting it from the start.
+        let new = vec![b"foo".to_vec(), big_string("bar", BLOCK_SIZE)];
+        let mut writer = LogWriter::new_recyclable(Cursor::new(&mut file), 2);
+        for r in &new {
+            writer.add_record(r).unwrap();
+        }
+
+        let mut reader = LogReader::new_recyclable(Cursor::new(&file), 2);
+        let mut read = vec![];
+        while let Some(r) = reader.read_record().unwrap() {
+            read.push(r);
+        }
+        assert_eq!(read, new);
+        assert!(reader.corruptions().is_empty());
+
+        let mut reader = LogReader::new_recyclable(Cursor::new(&file), 1);
+        assert_eq!(reader.read_record().unwrap(), None);
+
+        // A legacy reader skips the records and reports them.
+        let mut writer = LogWriter::new_recyclable(vec![], 2);
+        for r in &new {
+            writer.add_record(r).unwrap();
+        }
+        let log = writer.into_inner();
+        let mut reader = LogReader::new(Cursor::new(&log));
+        assert_eq!(reader.read_record().unwrap(), None);
+        let corruptions: Vec<_> = reader
+            .corruptions()
+            .iter()
+            .map(|c| (c.bytes, c.reason))
+            .collect();
+        assert_eq!(corruptions[0], (3, "unknown record type"));
+        assert!(corruptions.iter().all(|c| c.1 == "unknown record type"));
+        assert_eq!(
+            corruptions.iter().map(|c| c.0).sum::<usize>(),
+            3 + BLOCK_SIZE
+        );
+
+        // Log numbers are kept whole, past 32 bits too.
+        let mut writer = LogWriter::new_recyclable(Cursor::new(&mut file), (1 << 32) + 3);
+        writer.add_record(b"baz").unwrap();
+        let mut reader = LogReader::new_recyclable(Cursor::new(&file), (1 << 32) + 3);
+        assert_eq!(reader.read_record().unwrap(), Some(b"baz".to_vec()));
+        let mut reader = LogReader::new_recyclable(Cursor::new(&file), 3);
+        assert_eq!(reader.read_record().unwrap(), None);
+        let mut reader = LogReader::new_recyclable(Cursor::new(&file), (2 << 32) + 3);
+        assert_eq!(reader.read_record().unwrap(), None);
+    }
+
+    #[test]
+    fn test_recyclable_many_blocks() {
+        let records: Vec<_> = (0..20_000).map(number_string).collect();
+        let mut writer = LogWriter::new_recyclable(vec![], 7);
+        for r in &records {
+            writer.add_record(r).unwrap();
+        }
+        let log = writer.into_inner();
+        let mut reader = LogReader::new_recyclable(Cursor::new(&log), 7);
+        for r in &records {
+            assert_eq!(reader.read_record().unwrap().as_ref(), Some(r));
+        }
+        assert_eq!(reader.read_record().unwrap(), None);
+        assert!(reader.corruptions().is_empty());
+    }
+}
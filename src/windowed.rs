@@ -0,0 +1,199 @@
+use crate::{Arena, BaseComparator, InsertOutcome, RandomGenerator, SkipList};
+use bytes::Bytes;
+use std::collections::VecDeque;
+
+/// Entries tagged with the epoch they were inserted in, e.g. the time window of
+/// a sliding-window aggregate, kept in a skiplist per epoch.
+///
+/// Every epoch gets a new list from the function given to `new`, with an arena
+/// of its own: `drop_older_than` lets go of whole epochs at once, and their
+/// memory with them, instead of removing entries one by one. Reads go through
+/// the epochs from the newest to the oldest.
+/// # Examples
+/// ```
+/// use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, SkipList, WindowedSkipList};
+///
+/// let mut window = WindowedSkipList::new(|| {
+///     SkipList::new(
+///         Random::new(0xdead_beef),
+///         DefaultComparator::default (),
+///         ArenaImpl::new(),
+///     )
+/// });
+/// window.insert(1, vec![1u8]);
+/// window.insert(2, vec![2u8]);
+/// window.insert(2, vec![1u8]);
+/// assert_eq!(window.epoch_of(&[1]), Some(2));
+///
+/// assert_eq!(window.drop_older_than(2), 1);
+/// assert_eq!(window.epoch_of(&[1]), Some(2));
+/// assert_eq!(window.oldest_epoch(), Some(2));
+/// ```
+pub struct WindowedSkipList<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    /// The epochs and their lists, oldest first.
+    epochs: VecDeque<(u64, SkipList<R, C, A>)>,
+    new_list: Box<dyn FnMut() -> SkipList<R, C, A> + Send>,
+}
+
+impl<R, C, A> WindowedSkipList<R, C, A>
+where
+    R: RandomGenerator,
+    C: BaseComparator,
+    A: Arena,
+{
+    /// Returns an empty window, whose epochs get their lists from `new_list`.
+    pub fn new(new_list: impl FnMut() -> SkipList<R, C, A> + Send + 'static) -> Self {
+        WindowedSkipList {
+            epochs: VecDeque::new(),
+            new_list: Box::new(new_list),
+        }
+    }
+
+    /// Insert `key` in the list of `epoch`, created if it is the first entry of
+    /// the epoch. Epochs usually come in increasing order, late entries of older
+    /// ones still go to their own list.
+    pub fn insert(&mut self, epoch: u64, key: impl Into<Bytes>) -> InsertOutcome {
+        let i = match self.epochs.binary_search_by_key(&epoch, |(e, _)| *e) {
+            Ok(i) => i,
+            Err(i) => {
+                self.epochs.insert(i, (epoch, (self.new_list)()));
+                i
+            }
+        };
+        self.epochs[i].1.insert(key)
+    }
+
+    /// Returns the newest epoch holding `key`.
+    pub fn epoch_of(&self, key: &[u8]) -> Option<u64> {
+        self.epochs
+            .iter()
+            .rev()
+            .find(|(_, list)| list.contains_key(key))
+            .map(|(epoch, _)| *epoch)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.epoch_of(key).is_some()
+    }
+
+    /// Remove `key` from every epoch, returns `true` if one held it.
+    pub fn remove(&self, key: &[u8]) -> bool {
+        self.epochs
+            .iter()
+            .fold(false, |removed, (_, list)| list.remove(key) | removed)
+    }
+
+    /// Drop the epochs before `epoch`, returns the number of entries they held.
+    /// The memory of a list goes back when its last handle is dropped.
+    pub fn drop_older_than(&mut self, epoch: u64) -> u64 {
+        let mut dropped = 0;
+        while self.epochs.front().is_some_and(|(e, _)| *e < epoch) {
+            dropped += self.epochs.pop_front().unwrap().1.len();
+        }
+        dropped
+    }
+
+    /// Returns the list of `epoch`, `None` if it has no entry or was dropped.
+    pub fn epoch(&self, epoch: u64) -> Option<&SkipList<R, C, A>> {
+        self.epochs
+            .binary_search_by_key(&epoch, |(e, _)| *e)
+            .ok()
+            .map(|i| &self.epochs[i].1)
+    }
+
+    /// Returns the epochs and their lists, oldest first.
+    pub fn epochs(&self) -> impl Iterator<Item = (u64, &SkipList<R, C, A>)> {
+        self.epochs.iter().map(|(epoch, list)| (*epoch, list))
+    }
+
+    pub fn oldest_epoch(&self) -> Option<u64> {
+        self.epochs.front().map(|(epoch, _)| *epoch)
+    }
+
+    pub fn newest_epoch(&self) -> Option<u64> {
+        self.epochs.back().map(|(epoch, _)| *epoch)
+    }
+
+    /// Returns the number of entries of every epoch, an entry inserted in several
+    /// epochs counting once per epoch.
+    pub fn len(&self) -> u64 {
+        self.epochs.iter().map(|(_, list)| list.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the estimated memory used by the lists of every epoch.
+    pub fn memory_usage(&self) -> u64 {
+        self.epochs
+            .iter()
+            .map(|(_, list)| list.memory_usage())
+            .sum()
+    }
+}
+
+#[cfg(all(test, feature = "arena"))]
+mod tests {
+    use super::WindowedSkipList;
+    use crate::{ArenaImpl, DefaultComparator, Random, SkipList};
+
+    fn window() -> WindowedSkipList<Random, DefaultComparator, ArenaImpl> {
+        WindowedSkipList::new(|| {
+            SkipList::new(
+                Random::new(0xdead_beef),
+                DefaultComparator::default(),
+                ArenaImpl::new(),
+            )
+        })
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let mut window = window();
+        for epoch in 0..10u64 {
+            for i in 0..100u64 {
+                window.insert(epoch, (epoch * 100 + i).to_be_bytes().to_vec());
+            }
+            // Keep the last three epochs.
+            let dropped = window.drop_older_than(epoch.saturating_sub(2));
+            assert_eq!(dropped, if epoch >= 3 { 100 } else { 0 });
+        }
+        assert_eq!(window.len(), 300);
+        assert_eq!(window.oldest_epoch(), Some(7));
+        assert_eq!(window.newest_epoch(), Some(9));
+        assert_eq!(window.epochs().count(), 3);
+        assert!(!window.contains_key(&699u64.to_be_bytes()));
+        assert_eq!(window.epoch_of(&700u64.to_be_bytes()), Some(7));
+        assert!(window.epoch(6).is_none());
+
+        let memory = window.memory_usage();
+        window.drop_older_than(9);
+        assert!(window.memory_usage() < memory);
+        assert_eq!(window.drop_older_than(u64::MAX), 100);
+        assert!(window.is_empty());
+        assert_eq!(window.oldest_epoch(), None);
+    }
+
+    #[test]
+    fn test_late_entries() {
+        let mut window = window();
+        window.insert(5, vec![1u8]);
+        window.insert(3, vec![1u8]);
+        assert!(window.insert(3, vec![1u8]).existed());
+        window.insert(4, vec![2u8]);
+        let epochs: Vec<u64> = window.epochs().map(|(epoch, _)| epoch).collect();
+        assert_eq!(epochs, vec![3, 4, 5]);
+        assert_eq!(window.epoch_of(&[1]), Some(5));
+
+        assert!(window.remove(&[1]));
+        assert_eq!(window.epoch_of(&[1]), None);
+        assert!(!window.remove(&[1]));
+        assert_eq!(window.len(), 1);
+    }
+}
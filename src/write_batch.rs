@@ -0,0 +1,61 @@
+use bytes::Bytes;
+
+/// Keys, and the values of a `SkipMap`, staged to be written together with
+/// `SkipList::write` or `SkipMap::write`: readers find either none of them or
+/// all of them.
+/// # Examples
+#[cfg_attr(feature = "arena", doc = "```")]
+#[cfg_attr(not(feature = "arena"), doc = "```ignore")]
+/// use dakv_skiplist::{ArenaImpl, DefaultComparator, Random, SkipList, WriteBatch};
+///
+/// let sl = SkipList::new(
+///     Random::new(0xdead_beef),
+///     DefaultComparator::default (),
+///     ArenaImpl::new(),
+/// );
+/// let mut batch = WriteBatch::new();
+/// batch.insert(vec![2u8]);
+/// batch.insert(vec![1u8]);
+/// assert_eq!(batch.len(), 2);
+/// assert_eq!(sl.write(batch), Ok(2));
+/// assert_eq!(format!("{}", sl), "[[1] [2] ]");
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct WriteBatch {
+    pub(crate) entries: Vec<(Bytes, Bytes)>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        WriteBatch {
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Stage `key`. A key staged several times is written like as many inserts
+    /// in the order they were staged.
+    pub fn insert(&mut self, key: impl Into<Bytes>) {
+        self.insert_value(key, Bytes::new());
+    }
+
+    /// Stage `key` and its value, only read by `SkipMap::write`.
+    pub fn insert_value(&mut self, key: impl Into<Bytes>, value: impl Into<Bytes>) {
+        self.entries.push((key.into(), value.into()));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}